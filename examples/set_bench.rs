@@ -7,12 +7,14 @@ extern crate serde;
 use bellman_bignat::group::RsaQuotientGroup;
 use bellman_bignat::hash::circuit::CircuitHasher;
 use bellman_bignat::hash::hashes::{Mimc, Pedersen, Poseidon, Sha256};
+use bellman_bignat::hash::pocklington::CertificateKind;
 use bellman_bignat::hash::Hasher;
 use bellman_bignat::set::int_set::ExpSet;
 use bellman_bignat::set::int_set::exp::serial::SerialExp;
 use bellman_bignat::set::merkle::{MerkleSetBench, MerkleSetBenchInputs, MerkleSetBenchParams};
 use bellman_bignat::set::rsa::{SetBench, SetBenchInputs, SetBenchParams};
 use bellman_bignat::util::bench::{ConstraintCounter, ConstraintProfiler, WitnessTimer};
+use bellman_bignat::util::cancel::CancelToken;
 use docopt::Docopt;
 use sapling_crypto::bellman::pairing::bls12_381::Bls12;
 use sapling_crypto::bellman::pairing::Engine;
@@ -188,12 +190,19 @@ fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
             limb_width: 32,
             n_bits_elem: RSA_SIZE,
             n_bits_challenge: 256,
+            certificate: CertificateKind::Pocklington,
             n_bits_base: RSA_SIZE,
             item_size: ELEMENT_SIZE,
             n_inserts: t,
             n_removes: t,
             hasher: hash,
             verbose: false,
+            absorb_group: true,
+            canonical_order: false,
+            require_change: false,
+            blinding: None,
+            bind_epoch: false,
+            cancel: CancelToken::default(),
         },
     };
 
@@ -242,6 +251,7 @@ fn merkle_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
             n_swaps: t,
             hash,
             verbose: false,
+            require_change: false,
             depth: c,
         },
     };
@@ -4,25 +4,27 @@ extern crate rand;
 extern crate sapling_crypto;
 extern crate serde;
 
+use bellman_bignat::driver::{
+    create_proof, deterministic_rng, generate_parameters, Groth16, ProofSystem,
+};
 use bellman_bignat::group::RsaQuotientGroup;
 use bellman_bignat::hash::circuit::CircuitHasher;
 use bellman_bignat::hash::hashes::{Mimc, Pedersen, Poseidon, Sha256};
+use bellman_bignat::hash::pocklington::CertificateKind;
 use bellman_bignat::hash::Hasher;
-use bellman_bignat::mp::bignat::nat_to_limbs;
 use bellman_bignat::set::int_set::ExpSet;
 use bellman_bignat::set::int_set::exp::parallel::ParExpComb;
 use bellman_bignat::set::merkle::{MerkleSetBench, MerkleSetBenchInputs, MerkleSetBenchParams};
 use bellman_bignat::set::rsa::{SetBench, SetBenchInputs, SetBenchParams};
 use bellman_bignat::set::GenSet;
+use bellman_bignat::util::cancel::CancelToken;
 use bellman_bignat::util::verbose;
 use docopt::Docopt;
 use sapling_crypto::bellman::groth16::{
-    generate_random_parameters, prepare_prover, prepare_verifying_key, verify_proof,
-    ParameterSource, Parameters, Proof,
+    generate_random_parameters, prepare_verifying_key, Parameters,
 };
 use sapling_crypto::bellman::pairing::bls12_381::Bls12;
 use sapling_crypto::bellman::pairing::Engine;
-use sapling_crypto::bellman::{Circuit, SynthesisError};
 use serde::Deserialize;
 
 use rand::{thread_rng, Rng};
@@ -45,6 +47,7 @@ Options:
   --iparams FILE  Read parameters from FILE
   --hash HASH     The hash function to use [default: poseidon]
                   Valid values: poseidon, mimc, pedersen, babypedersen, sha
+  --seed SEED     Seed all randomness, making parameters and proofs reproducible
   --version       Show version.
 ";
 
@@ -70,6 +73,7 @@ struct Args {
     flag_verbose: bool,
     flag_oparams: Option<String>,
     flag_iparams: Option<String>,
+    flag_seed: Option<u64>,
     cmd_rsa: bool,
     cmd_merkle: bool,
 }
@@ -128,37 +132,15 @@ fn main() {
     );
 }
 
-/// A copy of the `create_random_proof` procedure of bellman.
-/// Returns the proof, the time spent synthesizing the circuit and its inputs, and the time spent
-/// doing cryptography.
-fn create_random_proof<E, C, R, P: ParameterSource<E>>(
-    circuit: C,
-    params: P,
-    rng: &mut R,
-) -> Result<(Proof<E>, Duration, Duration), SynthesisError>
-where
-    E: Engine,
-    C: Circuit<E>,
-    R: Rng,
-{
-    let synth_start = Instant::now();
-    let r = rng.gen();
-    let s = rng.gen();
-    let prover = prepare_prover(circuit)?;
-    let synth_end = Instant::now();
-
-    let crypto_start = Instant::now();
-    let proof = prover.create_proof(params, r, s)?;
-    let crypto_end = Instant::now();
-
-    Ok((proof, synth_end - synth_start, crypto_end - crypto_start))
-}
-
 fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
     args: &Args,
     hash: H,
 ) -> TimeReport {
-    let rng = &mut thread_rng();
+    let mut rng: Box<dyn Rng> = match args.flag_seed {
+        Some(seed) => Box::new(deterministic_rng(seed)),
+        None => Box::new(thread_rng()),
+    };
+    let rng = &mut rng;
 
     if args.flag_verbose {
         println!("Initializing accumulators, circuits");
@@ -176,15 +158,22 @@ fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
         limb_width: 32,
         n_bits_elem: RSA_SIZE,
         n_bits_challenge: 256,
+        certificate: CertificateKind::Pocklington,
         n_bits_base: RSA_SIZE,
         item_size: ELEMENT_SIZE,
         n_inserts: args.arg_transactions,
         n_removes: args.arg_transactions,
         hasher: hash.clone(),
         verbose: args.flag_verbose,
+        absorb_group: true,
+        canonical_order: false,
+        require_change: false,
+        blinding: None,
+        bind_epoch: false,
+        cancel: CancelToken::default(),
     };
 
-    let empty_circuit = SetBench::<_, ExpSet<_, ParExpComb>> {
+    let empty_circuit = || SetBench::<_, ExpSet<_, ParExpComb>> {
         inputs: None,
         params: params.clone(),
     };
@@ -202,25 +191,14 @@ fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
         group.clone(),
     );
 
-    let mut circuit = SetBench::<_, ExpSet<_, ParExpComb>> {
+    let circuit = SetBench::<_, ExpSet<_, ParExpComb>> {
         inputs: Some(inputs),
         params: params.clone(),
     };
-    let inputs = circuit.inputs.as_mut().unwrap();
     if args.flag_verbose {
         println!("Marshalling circuit inputs");
     }
-    let inputs = vec![
-        hash.hash(
-            &nat_to_limbs(&group.g, 32, 64)
-                .unwrap()
-                .into_iter()
-                .chain(nat_to_limbs(&group.m, 32, 64).unwrap().into_iter())
-                .collect::<Vec<E::Fr>>(),
-        ),
-        hash.hash(&nat_to_limbs(&inputs.initial_state.digest(), 32, 64).unwrap()),
-        hash.hash(&nat_to_limbs(&inputs.final_state.digest(), 32, 64).unwrap()),
-    ];
+    let inputs = circuit.public_inputs().unwrap();
 
     let init_end = Instant::now();
     if args.flag_verbose {
@@ -241,12 +219,15 @@ fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
             let p = Parameters::read(&mut f, false).expect("Error parsing params");
             p
         } else {
-            let p = generate_random_parameters(empty_circuit, rng);
+            let p = generate_parameters(empty_circuit, rng);
             if args.flag_verbose {
                 println!("Params gen is okay: {:#?}", p.is_ok());
             }
-            assert!(p.is_ok());
-            p.unwrap()
+            let (p, _, report) = p.unwrap();
+            if args.flag_verbose {
+                println!("Setup report: {:#?}", report);
+            }
+            p
         }
     };
     if let Some(ref path) = args.flag_oparams {
@@ -262,13 +243,13 @@ fn rsa_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
     }
 
     let (proof, prover_synth_time, prover_crypto_time) =
-        create_random_proof(circuit, &params, rng).unwrap();
+        create_proof(circuit, &params, rng).unwrap();
     if args.flag_verbose {
         println!("Proof generation successful? true");
     }
 
     let verifier_start = Instant::now();
-    let result = verify_proof(&pvk, &proof, &inputs);
+    let result = <Groth16 as ProofSystem<E>>::verify(&pvk, &proof, &inputs);
     let verifier_end = Instant::now();
     if args.flag_verbose {
         println!("Verified? {:?}", result.is_ok(),);
@@ -288,7 +269,11 @@ fn merkle_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
     args: &Args,
     hash: H,
 ) -> TimeReport {
-    let rng = &mut thread_rng();
+    let mut rng: Box<dyn Rng> = match args.flag_seed {
+        Some(seed) => Box::new(deterministic_rng(seed)),
+        None => Box::new(thread_rng()),
+    };
+    let rng = &mut rng;
 
     if args.flag_verbose {
         println!("Initializing accumulators, circuits");
@@ -300,6 +285,7 @@ fn merkle_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
         hash: hash.clone(),
         depth: args.arg_capacity,
         verbose: args.flag_verbose,
+        require_change: false,
     };
     let empty_circuit = MerkleSetBench {
         inputs: None,
@@ -355,13 +341,13 @@ fn merkle_bench<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
     }
 
     let (proof, prover_synth_time, prover_crypto_time) =
-        create_random_proof(circuit, &params, rng).unwrap();
+        create_proof(circuit, &params, rng).unwrap();
     if args.flag_verbose {
         println!("Proof generation successful? true");
     }
 
     let verifier_start = Instant::now();
-    let result = verify_proof(&pvk, &proof, &inputs);
+    let result = <Groth16 as ProofSystem<E>>::verify(&pvk, &proof, &inputs);
     let verifier_end = Instant::now();
     if args.flag_verbose {
         println!("Verified? {:?}", result.is_ok(),);
@@ -0,0 +1,219 @@
+extern crate bellman_bignat;
+extern crate docopt;
+extern crate rand;
+extern crate sapling_crypto;
+extern crate serde;
+
+use bellman_bignat::driver::{Groth16, ProofSystem};
+use bellman_bignat::group::RsaQuotientGroup;
+use bellman_bignat::hash::circuit::CircuitHasher;
+use bellman_bignat::hash::division_intractable as di;
+use bellman_bignat::hash::hashes::Poseidon;
+use bellman_bignat::hash::pocklington::CertificateKind;
+use bellman_bignat::hash::Hasher;
+use bellman_bignat::mp::bignat::nat_to_limbs;
+use bellman_bignat::set::int_set::exp::serial::SerialExp;
+use bellman_bignat::set::int_set::ExpSet;
+use bellman_bignat::set::rsa::{Set, SetBench, SetBenchParams};
+use bellman_bignat::set::witness::set_witnesses;
+use bellman_bignat::set::GenSet;
+use bellman_bignat::util::bench::ConstraintCounter;
+use bellman_bignat::util::cancel::CancelToken;
+use docopt::Docopt;
+use sapling_crypto::bellman::pairing::bls12_381::Bls12;
+use sapling_crypto::bellman::pairing::ff::PrimeField;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::Circuit;
+use serde::Deserialize;
+
+use rand::thread_rng;
+use std::time::Instant;
+
+const USAGE: &str = "
+Stateless Client Simulator
+
+Simulates a stateless chain backed by an RSA accumulator. Each client owns one coin (a set
+item). Each block, some clients spend their coin and receive a fresh one; the block producer
+proves the accumulator update, and every client updates its membership witness.
+
+Emits CSV: block, constraints, synth/prove seconds, witness update seconds, per-client seconds
+
+Usage:
+  stateless_sim [options] <blocks> <transactions> <clients>
+  stateless_sim (-h | --help)
+
+Options:
+  -h --help      Show this screen.
+  -p --prove     Produce and check a groth16 proof for each block (slow).
+";
+
+// From https://en.wikipedia.org/wiki/RSA_numbers#RSA-2048
+const RSA_2048: &str = "25195908475657893494027183240048398571429282126204032027777137836043662020707595556264018525880784406918290641249515082189298559149176184502808489120072844992687392807287776735971418347270261896375014971824691165077613379859095700097330459748808428401797429100642458691817195118746121515172654632282216869987549182422433637259085141865462043576798423387184774447920739934236584823824281198163815010674810451660377306056201619676256133844143603833904414952634432190114657544454178424020924616515723350778707749817125772467962926386356373289912154831438167899885040445364023527381951378636564391212010397122822120720357";
+const RSA_SIZE: usize = 2048;
+const ELEMENT_SIZE: usize = 5;
+const LIMB_WIDTH: usize = 32;
+
+/// The proving system used with `--prove`.
+type PS = Groth16;
+
+#[derive(Debug, Deserialize)]
+struct Args {
+    arg_blocks: usize,
+    arg_transactions: usize,
+    arg_clients: usize,
+    flag_prove: bool,
+}
+
+fn main() {
+    color_backtrace::install();
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.deserialize())
+        .unwrap_or_else(|e| e.exit());
+    assert!(
+        args.arg_transactions <= args.arg_clients,
+        "Each client may spend at most once per block"
+    );
+    simulate::<Bls12, _>(&args, Poseidon::default());
+}
+
+/// The coin owned by `client` after it has spent `generation` coins.
+fn coin<F: PrimeField>(client: usize, generation: usize) -> Vec<F> {
+    (0..ELEMENT_SIZE)
+        .map(|j| F::from_str(&format!("{}{:06}{:03}", generation + 1, client, j)).unwrap())
+        .collect()
+}
+
+/// The public inputs of a `SetBench` with the given initial and final states.
+fn public_inputs<E, H>(
+    hash: &H,
+    group: &RsaQuotientGroup,
+    initial: &mut Set<H, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>,
+    fin: &mut Set<H, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>,
+) -> Vec<E::Fr>
+where
+    E: Engine,
+    H: Hasher<F = E::Fr>,
+{
+    let n_limbs = RSA_SIZE / LIMB_WIDTH;
+    vec![
+        hash.hash(
+            &nat_to_limbs(&group.g, LIMB_WIDTH, n_limbs)
+                .unwrap()
+                .into_iter()
+                .chain(nat_to_limbs(&group.m, LIMB_WIDTH, n_limbs).unwrap())
+                .collect::<Vec<E::Fr>>(),
+        ),
+        hash.hash(&nat_to_limbs(&initial.digest(), LIMB_WIDTH, n_limbs).unwrap()),
+        hash.hash(&nat_to_limbs(&fin.digest(), LIMB_WIDTH, n_limbs).unwrap()),
+    ]
+}
+
+fn simulate<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(args: &Args, hash: H) {
+    let group = RsaQuotientGroup::from_strs("2", RSA_2048);
+    let params = SetBenchParams {
+        group: group.clone(),
+        limb_width: LIMB_WIDTH,
+        n_bits_elem: RSA_SIZE,
+        n_bits_challenge: 256,
+        certificate: CertificateKind::Pocklington,
+        n_bits_base: RSA_SIZE,
+        item_size: ELEMENT_SIZE,
+        n_inserts: args.arg_transactions,
+        n_removes: args.arg_transactions,
+        hasher: hash.clone(),
+        verbose: false,
+        absorb_group: true,
+        canonical_order: false,
+        require_change: false,
+        blinding: None,
+        bind_epoch: false,
+        cancel: CancelToken::default(),
+    };
+
+    let mut generations = vec![0; args.arg_clients];
+    let coins: Vec<Vec<E::Fr>> = (0..args.arg_clients).map(|c| coin(c, 0)).collect();
+    let mut state = Set::<H, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>::new_with(
+        group.clone(),
+        di::offset(RSA_SIZE),
+        hash.clone(),
+        RSA_SIZE,
+        LIMB_WIDTH,
+        &coins,
+    );
+
+    let keys: Option<(<PS as ProofSystem<E>>::ProvingKey, <PS as ProofSystem<E>>::VerifyingKey)> =
+        if args.flag_prove {
+            let empty_circuit = || SetBench::<H, ExpSet<_, SerialExp<_>>> {
+                inputs: None,
+                params: params.clone(),
+            };
+            Some(PS::setup(empty_circuit, &mut thread_rng()).unwrap())
+        } else {
+            None
+        };
+
+    println!("block,constraints,prover_s,witness_update_s,per_client_s");
+    let mut next_spender = 0;
+    for block in 0..args.arg_blocks {
+        // Choose the spenders, and build the block
+        let mut to_remove = Vec::new();
+        let mut to_insert = Vec::new();
+        for _ in 0..args.arg_transactions {
+            let c = next_spender;
+            next_spender = (next_spender + 1) % args.arg_clients;
+            to_remove.push(coin(c, generations[c]));
+            generations[c] += 1;
+            to_insert.push(coin(c, generations[c]));
+        }
+        let mut circuits = SetBench::plan(&params, state.clone(), to_remove, to_insert)
+            .expect("A block always fits in one batch");
+        assert_eq!(circuits.len(), 1);
+        let mut circuit = circuits.pop().unwrap();
+        let next_state = circuit.inputs.as_ref().unwrap().final_state.clone();
+
+        // Produce the block proof
+        let prover_start = Instant::now();
+        let n_constraints = match keys {
+            Some((ref p, ref pvk)) => {
+                let inputs = {
+                    let ins = circuit.inputs.as_mut().unwrap();
+                    public_inputs::<E, H>(
+                        &hash,
+                        &group,
+                        &mut ins.initial_state,
+                        &mut ins.final_state,
+                    )
+                };
+                let proof = PS::prove(p, circuit, &mut thread_rng()).unwrap();
+                assert!(PS::verify(pvk, &proof, &inputs).unwrap());
+                0
+            }
+            None => {
+                let mut cs = ConstraintCounter::new();
+                circuit.synthesize(&mut cs).expect("synthesis failed");
+                cs.num_constraints()
+            }
+        };
+        let prover_time = Instant::now() - prover_start;
+
+        // Every client refreshes their membership witness
+        state = next_state;
+        let witness_start = Instant::now();
+        let items: Vec<Vec<E::Fr>> = (0..args.arg_clients)
+            .map(|c| coin(c, generations[c]))
+            .collect();
+        let witnesses =
+            set_witnesses(&state, &items, |_, _| ()).expect("Each client owns a distinct coin");
+        assert!(witnesses.iter().all(Option::is_some));
+        let witness_time = Instant::now() - witness_start;
+
+        println!(
+            "{},{},{},{},{}",
+            block,
+            n_constraints,
+            prover_time.as_secs_f64(),
+            witness_time.as_secs_f64(),
+            witness_time.as_secs_f64() / args.arg_clients as f64,
+        );
+    }
+}
@@ -0,0 +1,61 @@
+/*
+ * C API for maintaining RSA sets and verifying proofs about them. See src/ffi.rs.
+ *
+ * Field elements are 32-byte little-endian representations, and an item of `k` elements is
+ * `32 * k` contiguous bytes. Integers are NUL-terminated decimal strings. Functions returning
+ * `int` return BB_OK or a negative BB_ERR_* code.
+ */
+#ifndef BELLMAN_BIGNAT_H
+#define BELLMAN_BIGNAT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BB_OK 0
+/* A required pointer was null. */
+#define BB_ERR_NULL (-1)
+/* An argument could not be decoded. */
+#define BB_ERR_ENCODING (-2)
+/* A removed item is not in the set. The set is unchanged. */
+#define BB_ERR_MISSING (-3)
+/* The output buffer is too small. */
+#define BB_ERR_BUFFER (-4)
+/* The library panicked. Any set passed in is unchanged. */
+#define BB_ERR_PANIC (-5)
+
+typedef struct BbSet BbSet;
+
+/* Returns NULL on bad arguments. Free the set with bb_set_free. */
+BbSet *bb_set_new(const char *g, const char *m, size_t n_bits_elem, size_t limb_width);
+
+void bb_set_free(BbSet *set);
+
+int bb_set_apply_batch(BbSet *set,
+                       const uint8_t *removals,
+                       size_t n_removals,
+                       const uint8_t *insertions,
+                       size_t n_insertions,
+                       size_t item_size);
+
+int bb_set_digest(BbSet *set, char *out, size_t out_len);
+
+/* Writes 32 bytes to `out`. */
+int bb_set_digest_commitment(BbSet *set, size_t n_limbs, uint8_t *out);
+
+/* Returns 1 if the proof is valid, 0 if it is not, and a negative code on bad arguments. */
+int bb_verify(const uint8_t *vk,
+              size_t vk_len,
+              const uint8_t *inputs,
+              size_t n_inputs,
+              const uint8_t *proof,
+              size_t proof_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BELLMAN_BIGNAT_H */
@@ -0,0 +1,134 @@
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
+
+use mp::bignat::BigNat;
+
+/// Verifies that `sig` is the unblinding of the blind signature `blinded_sig` under the blinding
+/// factor `r`, and that it is a valid RSA signature on `message`. That is:
+///
+///    * `r` is a unit modulo `modulus`,
+///    * `sig · r = blinded_sig (mod modulus)`, i.e. `sig = blinded_sig · r^{-1}`, and
+///    * `sig ^ public_exp = message (mod modulus)`.
+///
+/// Assumes that `blinded_sig` and `message` are reduced modulo `modulus`, and that all inputs
+/// are well-formed.
+pub fn verify_unblinding<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    modulus: &BigNat<E>,
+    public_exp: &BigNat<E>,
+    message: &BigNat<E>,
+    blinded_sig: &BigNat<E>,
+    r: &BigNat<E>,
+    sig: &BigNat<E>,
+) -> Result<(), SynthesisError> {
+    r.enforce_coprime(cs.namespace(|| "r is a unit"), modulus)?;
+    sig.assert_product_mod(cs.namespace(|| "unblind"), r, modulus, blinded_sig)?;
+    let signed = sig.pow_mod(cs.namespace(|| "sig ^ e"), public_exp, modulus)?;
+    signed.equal(cs.namespace(|| "signature valid"), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::test_helpers::*;
+    use OptionExt;
+
+    use rug::Integer;
+
+    use std::str::FromStr;
+
+    pub struct UnblindInputs<'a> {
+        pub modulus: &'a str,
+        pub public_exp: &'a str,
+        pub message: &'a str,
+        pub blinded_sig: &'a str,
+        pub r: &'a str,
+        pub sig: &'a str,
+    }
+
+    pub struct UnblindParams {
+        pub limb_width: usize,
+        pub n_limbs: usize,
+    }
+
+    pub struct Unblind<'a> {
+        inputs: Option<UnblindInputs<'a>>,
+        params: UnblindParams,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for Unblind<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let inputs = self.inputs.grab()?;
+            let mut alloc = |name: &str, value: &str| -> Result<BigNat<E>, SynthesisError> {
+                let n = BigNat::alloc_from_nat(
+                    cs.namespace(|| name.to_owned()),
+                    || Ok(Integer::from_str(value).unwrap()),
+                    self.params.limb_width,
+                    self.params.n_limbs,
+                )?;
+                n.assert_well_formed(cs.namespace(|| format!("{} rangecheck", name)))?;
+                Ok(n)
+            };
+            let modulus = alloc("modulus", inputs.modulus)?;
+            let public_exp = alloc("public_exp", inputs.public_exp)?;
+            let message = alloc("message", inputs.message)?;
+            let blinded_sig = alloc("blinded_sig", inputs.blinded_sig)?;
+            let r = alloc("r", inputs.r)?;
+            let sig = alloc("sig", inputs.sig)?;
+            verify_unblinding(
+                cs.namespace(|| "verify"),
+                &modulus,
+                &public_exp,
+                &message,
+                &blinded_sig,
+                &r,
+                &sig,
+            )
+        }
+    }
+
+    circuit_tests! {
+        unblind_143: (Unblind {
+            inputs: Some(UnblindInputs {
+                modulus: "143",
+                public_exp: "7",
+                message: "5",
+                blinded_sig: "107",
+                r: "2",
+                sig: "125",
+            }),
+            params: UnblindParams {
+                limb_width: 4,
+                n_limbs: 2,
+            },
+        }, true),
+        unblind_143_wrong_sig: (Unblind {
+            inputs: Some(UnblindInputs {
+                modulus: "143",
+                public_exp: "7",
+                message: "5",
+                blinded_sig: "107",
+                r: "2",
+                sig: "124",
+            }),
+            params: UnblindParams {
+                limb_width: 4,
+                n_limbs: 2,
+            },
+        }, false),
+        unblind_143_wrong_message: (Unblind {
+            inputs: Some(UnblindInputs {
+                modulus: "143",
+                public_exp: "7",
+                message: "6",
+                blinded_sig: "107",
+                r: "2",
+                sig: "125",
+            }),
+            params: UnblindParams {
+                limb_width: 4,
+                n_limbs: 2,
+            },
+        }, false),
+    }
+}
@@ -0,0 +1,547 @@
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rug::Integer;
+use sapling_crypto::bellman::groth16::{
+    generate_random_parameters, prepare_prover, prepare_verifying_key, verify_proof,
+    ParameterSource, Parameters, PreparedVerifyingKey, Proof, VerifyingKey,
+};
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{Circuit, SynthesisError};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use group::RsaQuotientGroup;
+use hash::circuit::CircuitHasher;
+use hash::Hasher;
+use set::int_set::IntSet;
+use set::rsa::{InputError, Set, SetBench, SetBenchInputs, SetBenchParams};
+use set::GenSet;
+use util::bench::{ConstraintCounter, MemoryReport};
+use util::verbose::in_verbose_mode;
+
+/// A copy of the `create_random_proof` procedure of bellman, with the randomness injected.
+/// Returns the proof, the time spent synthesizing the circuit and its inputs, and the time spent
+/// doing cryptography.
+pub fn create_proof<E, C, R, P>(
+    circuit: C,
+    params: P,
+    rng: &mut R,
+) -> Result<(Proof<E>, Duration, Duration), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+    R: Rng,
+    P: ParameterSource<E>,
+{
+    let synth_start = Instant::now();
+    let r = rng.gen();
+    let s = rng.gen();
+    let prover = prepare_prover(circuit)?;
+    let synth_end = Instant::now();
+
+    let crypto_start = Instant::now();
+    let proof = prover.create_proof(params, r, s)?;
+    let crypto_end = Instant::now();
+
+    Ok((proof, synth_end - synth_start, crypto_end - crypto_start))
+}
+
+/// Builds the RNG used for deterministic proving from `seed`.
+pub fn deterministic_rng(seed: u64) -> XorShiftRng {
+    // XorShift rejects the all-zero seed, so the high words are fixed and non-zero.
+    XorShiftRng::from_seed([
+        seed as u32,
+        (seed >> 32) as u32,
+        0x9e37_79b9,
+        0x7f4a_7c15,
+    ])
+}
+
+/// Proves `circuit` with blinding factors drawn from an RNG seeded with `seed`.
+///
+/// Given the same parameters, circuit and witness, this produces the same proof every time. Do
+/// not use this outside of testing and audits: a fixed seed destroys zero-knowledge.
+pub fn prove_deterministic<E, C, P>(
+    circuit: C,
+    params: P,
+    seed: u64,
+) -> Result<(Proof<E>, Duration, Duration), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+    P: ParameterSource<E>,
+{
+    create_proof(circuit, params, &mut deterministic_rng(seed))
+}
+
+/// Timing of each phase of parameter generation.
+#[derive(Clone, Debug)]
+pub struct SetupReport {
+    /// The number of constraints in the circuit.
+    pub n_constraints: usize,
+    /// The size of the circuit, for estimating the memory needed to prove it.
+    pub memory: MemoryReport,
+    /// Time spent synthesizing the circuit shape (without witnesses).
+    pub shape: Duration,
+    /// Time spent in bellman's parameter generation. This phase (the evaluation domain FFTs and
+    /// the multi-exponentiations for each query) is already spread over all cores by bellman's
+    /// worker pool.
+    pub param_gen: Duration,
+    /// Time spent preparing the verifying key.
+    pub vk_prep: Duration,
+}
+
+/// Generates groth16 parameters for the circuit built by `make_circuit`, reporting the time spent
+/// in each phase.
+///
+/// The circuit is first synthesized into a constraint counter, which is cheap and catches
+/// synthesis errors before the (much slower) parameter generation starts.
+pub fn generate_parameters<E, C, F, R>(
+    make_circuit: F,
+    rng: &mut R,
+) -> Result<(Parameters<E>, PreparedVerifyingKey<E>, SetupReport), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+    F: Fn() -> C,
+    R: Rng,
+{
+    let shape_start = Instant::now();
+    let mut counter = ConstraintCounter::new();
+    make_circuit().synthesize(&mut counter)?;
+    let memory = counter.report();
+    let n_constraints = memory.n_constraints;
+    let shape_end = Instant::now();
+    if in_verbose_mode() {
+        println!(
+            "Shape pass: {} constraints in {:?}, proving needs about {} MiB",
+            n_constraints,
+            shape_end - shape_start,
+            memory.estimated_prover_bytes::<E>() >> 20
+        );
+    }
+
+    let param_start = Instant::now();
+    let params = generate_random_parameters(make_circuit(), rng)?;
+    let param_end = Instant::now();
+    if in_verbose_mode() {
+        println!("Parameter generation: {:?}", param_end - param_start);
+    }
+
+    let vk_start = Instant::now();
+    let pvk = prepare_verifying_key(&params.vk);
+    let vk_end = Instant::now();
+
+    Ok((
+        params,
+        pvk,
+        SetupReport {
+            n_constraints,
+            memory,
+            shape: shape_end - shape_start,
+            param_gen: param_end - param_start,
+            vk_prep: vk_end - vk_start,
+        },
+    ))
+}
+
+/// A proving system over bellman circuits.
+///
+/// The C and Python bindings and the examples verify through this trait, and `stateless_sim`
+/// also sets up and proves through it. Two things remain groth16-specific: `create_proof` and
+/// `generate_parameters`, which split out timings for benchmarks such as `set_proof`, and
+/// `verifier`, whose batched check combines the curve points of the proofs.
+pub trait ProofSystem<E: Engine> {
+    type ProvingKey;
+    type VerifyingKey;
+    type Proof;
+
+    /// Generates keys for the circuit built by `make_circuit`, which must not depend on any
+    /// witness.
+    fn setup<C, F, R>(
+        make_circuit: F,
+        rng: &mut R,
+    ) -> Result<(Self::ProvingKey, Self::VerifyingKey), SynthesisError>
+    where
+        C: Circuit<E>,
+        F: Fn() -> C,
+        R: Rng;
+
+    fn prove<C: Circuit<E>, R: Rng>(
+        pk: &Self::ProvingKey,
+        circuit: C,
+        rng: &mut R,
+    ) -> Result<Self::Proof, SynthesisError>;
+
+    fn verify(
+        vk: &Self::VerifyingKey,
+        proof: &Self::Proof,
+        inputs: &[E::Fr],
+    ) -> Result<bool, SynthesisError>;
+
+    fn write_proof<W: Write>(proof: &Self::Proof, w: W) -> io::Result<()>;
+
+    fn read_proof<R: Read>(r: R) -> io::Result<Self::Proof>;
+
+    /// Reads a verifying key, as serialized by the backend, and prepares it for `verify`.
+    fn read_verifying_key<R: Read>(r: R) -> io::Result<Self::VerifyingKey>;
+}
+
+/// The groth16 proving system of bellman.
+#[derive(Clone, Copy, Debug)]
+pub struct Groth16;
+
+impl<E: Engine> ProofSystem<E> for Groth16 {
+    type ProvingKey = Parameters<E>;
+    type VerifyingKey = PreparedVerifyingKey<E>;
+    type Proof = Proof<E>;
+
+    fn setup<C, F, R>(
+        make_circuit: F,
+        rng: &mut R,
+    ) -> Result<(Parameters<E>, PreparedVerifyingKey<E>), SynthesisError>
+    where
+        C: Circuit<E>,
+        F: Fn() -> C,
+        R: Rng,
+    {
+        let (params, pvk, report) = generate_parameters(make_circuit, rng)?;
+        if in_verbose_mode() {
+            println!("Setup: {:?}", report);
+        }
+        Ok((params, pvk))
+    }
+
+    fn prove<C: Circuit<E>, R: Rng>(
+        pk: &Parameters<E>,
+        circuit: C,
+        rng: &mut R,
+    ) -> Result<Proof<E>, SynthesisError> {
+        create_proof(circuit, pk, rng).map(|(proof, _, _)| proof)
+    }
+
+    fn verify(
+        vk: &PreparedVerifyingKey<E>,
+        proof: &Proof<E>,
+        inputs: &[E::Fr],
+    ) -> Result<bool, SynthesisError> {
+        verify_proof(vk, proof, inputs)
+    }
+
+    fn write_proof<W: Write>(proof: &Proof<E>, w: W) -> io::Result<()> {
+        proof.write(w)
+    }
+
+    fn read_proof<R: Read>(r: R) -> io::Result<Proof<E>> {
+        Proof::read(r)
+    }
+
+    fn read_verifying_key<R: Read>(r: R) -> io::Result<PreparedVerifyingKey<E>> {
+        VerifyingKey::read(r).map(|vk| prepare_verifying_key(&vk))
+    }
+}
+
+/// A batch of changes to an RSA set, for `prove_set_update`.
+#[derive(Clone, Debug)]
+pub struct SetUpdate<F> {
+    /// The items to remove, which must be in the set once the insertions are made
+    pub to_remove: Vec<Vec<F>>,
+    pub to_insert: Vec<Vec<F>>,
+    /// The epoch to bind the proof to, if the parameters bind one
+    pub epoch: Option<F>,
+    /// The exponents blinding the initial and final digests, if the parameters blind them
+    pub blinders: Option<(Integer, Integer)>,
+}
+
+impl<F> SetUpdate<F> {
+    pub fn new(to_remove: Vec<Vec<F>>, to_insert: Vec<Vec<F>>) -> Self {
+        Self {
+            to_remove,
+            to_insert,
+            epoch: None,
+            blinders: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    /// The batch does not fit the state or the parameters.
+    Input(InputError),
+    Synthesis(SynthesisError),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateError::Input(e) => write!(f, "bad batch: {}", e),
+            UpdateError::Synthesis(e) => write!(f, "proving failed: {:?}", e),
+        }
+    }
+}
+
+impl From<InputError> for UpdateError {
+    fn from(e: InputError) -> Self {
+        UpdateError::Input(e)
+    }
+}
+
+impl From<SynthesisError> for UpdateError {
+    fn from(e: SynthesisError) -> Self {
+        UpdateError::Synthesis(e)
+    }
+}
+
+/// Proves that applying `batch` to `state` yields a new state, with a `SetBench` circuit
+/// described by `params`. Returns the proof, the public inputs a verifier checks it against, and
+/// the new state.
+///
+/// The batch is checked natively before proving (see `SetBench::check_inputs`), so that a bad
+/// batch fails fast with the reason, rather than as an unsatisfied constraint after synthesis.
+/// The proving key must come from `P::setup` over a `SetBench` with the same `params` and no
+/// inputs.
+pub fn prove_set_update<E, H, Inner, P, R>(
+    state: Set<H, Inner>,
+    batch: SetUpdate<E::Fr>,
+    params: &SetBenchParams<H>,
+    pk: &P::ProvingKey,
+    rng: &mut R,
+) -> Result<(P::Proof, Vec<E::Fr>, Set<H, Inner>), UpdateError>
+where
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    Inner: IntSet<G = RsaQuotientGroup>,
+    P: ProofSystem<E>,
+    R: Rng,
+{
+    // The checks and the circuit work on copies, which have no observers, so that those of `state`
+    // see the batch once, when it is applied after proving.
+    let mut state = state;
+    let mut initial_state = state.clone();
+    let mut final_state = state.clone();
+    final_state.insert_all(batch.to_insert.iter().cloned());
+    for (i, item) in batch.to_remove.iter().enumerate() {
+        if !final_state.remove(item) {
+            return Err(InputError::MissingItem(i).into());
+        }
+    }
+    // Forces the digests, so that neither is computed twice.
+    initial_state.digest();
+    final_state.digest();
+    let cache = initial_state.cache.clone();
+    let circuit = SetBench {
+        inputs: Some(SetBenchInputs {
+            initial_state,
+            final_state,
+            to_remove: batch.to_remove.clone(),
+            to_insert: batch.to_insert.clone(),
+            blinders: batch.blinders,
+            cache,
+            epoch: batch.epoch,
+        }),
+        params: params.clone(),
+    };
+    circuit.check_inputs()?;
+    let public_inputs = circuit.public_inputs()?;
+    let proof = P::prove(pk, circuit, rng)?;
+    state.insert_all(batch.to_insert);
+    state.remove_all(batch.to_remove.iter().map(Vec::as_slice));
+    Ok((proof, public_inputs, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::{Field, ScalarEngine};
+    use sapling_crypto::bellman::ConstraintSystem;
+    use sapling_crypto::circuit::num::AllocatedNum;
+    use hash::division_intractable::offset;
+    use hash::hashes::Poseidon;
+    use hash::pocklington::CertificateKind;
+    use set::int_set::exp::serial::SerialExp;
+    use set::int_set::ExpSet;
+    use util::cancel::CancelToken;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+    use OptionExt;
+
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    /// Knowledge of a square root of the input.
+    struct Square<E: Engine> {
+        root: Option<E::Fr>,
+    }
+
+    impl<E: Engine> Circuit<E> for Square<E> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let root = AllocatedNum::alloc(cs.namespace(|| "root"), || Ok(*self.root.grab()?))?;
+            let square = root.square(cs.namespace(|| "square"))?;
+            square.inputize(cs.namespace(|| "square input"))
+        }
+    }
+
+    #[test]
+    fn groth16_round_trip() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let rng = &mut deterministic_rng(1);
+        let (pk, vk) =
+            <Groth16 as ProofSystem<Bn256>>::setup(|| Square::<Bn256> { root: None }, rng)
+                .unwrap();
+        let root = F::from_str("5").unwrap();
+        let mut square = root;
+        square.square();
+        let proof = Groth16::prove(&pk, Square::<Bn256> { root: Some(root) }, rng).unwrap();
+        let mut bytes = Vec::new();
+        <Groth16 as ProofSystem<Bn256>>::write_proof(&proof, &mut bytes).unwrap();
+        let read = <Groth16 as ProofSystem<Bn256>>::read_proof(&bytes[..]).unwrap();
+        assert!(Groth16::verify(&vk, &read, &[square]).unwrap());
+        assert!(!Groth16::verify(&vk, &read, &[root]).unwrap());
+    }
+
+    #[test]
+    fn deterministic_rng_is_reproducible() {
+        let a: Vec<u64> = deterministic_rng(7).gen_iter().take(4).collect();
+        let b: Vec<u64> = deterministic_rng(7).gen_iter().take(4).collect();
+        let c: Vec<u64> = deterministic_rng(8).gen_iter().take(4).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    /// Proves by synthesizing into a `TestConstraintSystem`, which is kept as the proof.
+    struct Checked;
+
+    impl ProofSystem<Bn256> for Checked {
+        type ProvingKey = ();
+        type VerifyingKey = ();
+        type Proof = TestConstraintSystem<Bn256>;
+
+        fn setup<C, F, R>(_: F, _: &mut R) -> Result<((), ()), SynthesisError>
+        where
+            C: Circuit<Bn256>,
+            F: Fn() -> C,
+            R: Rng,
+        {
+            Ok(((), ()))
+        }
+
+        fn prove<C: Circuit<Bn256>, R: Rng>(
+            _: &(),
+            circuit: C,
+            _: &mut R,
+        ) -> Result<TestConstraintSystem<Bn256>, SynthesisError> {
+            let mut cs = TestConstraintSystem::new();
+            circuit.synthesize(&mut cs)?;
+            if cs.is_satisfied() {
+                Ok(cs)
+            } else {
+                Err(SynthesisError::Unsatisfiable)
+            }
+        }
+
+        fn verify(
+            _: &(),
+            proof: &TestConstraintSystem<Bn256>,
+            inputs: &[<Bn256 as ScalarEngine>::Fr],
+        ) -> Result<bool, SynthesisError> {
+            Ok(proof.verify(inputs))
+        }
+
+        fn write_proof<W: Write>(_: &TestConstraintSystem<Bn256>, _: W) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Other, "not serializable"))
+        }
+
+        fn read_proof<R: Read>(_: R) -> io::Result<TestConstraintSystem<Bn256>> {
+            Err(io::Error::new(io::ErrorKind::Other, "not serializable"))
+        }
+    }
+
+    type TestSet = Set<Poseidon<Bn256>, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>;
+
+    /// Parameters for single swaps of one-element items, and a set holding the item `1`.
+    fn single_swaps(bind_epoch: bool) -> (SetBenchParams<Poseidon<Bn256>>, TestSet) {
+        let group = RsaQuotientGroup::from_strs("2", RSA_512);
+        let hasher = Poseidon::<Bn256>::default();
+        let params = SetBenchParams {
+            group: group.clone(),
+            limb_width: 32,
+            n_bits_elem: 128,
+            n_bits_challenge: 128,
+            certificate: CertificateKind::Pocklington,
+            n_bits_base: 512,
+            item_size: 1,
+            n_inserts: 1,
+            n_removes: 1,
+            hasher: hasher.clone(),
+            verbose: false,
+            absorb_group: true,
+            canonical_order: false,
+            require_change: false,
+            blinding: None,
+            bind_epoch,
+            cancel: CancelToken::default(),
+        };
+        let state = Set::new_with(group, offset(128), hasher, 128, 32, &vec![vec![usize_to_f(1)]]);
+        (params, state)
+    }
+
+    #[test]
+    fn set_updates_chain() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |i: usize| vec![usize_to_f::<F>(i)];
+        let (params, state) = single_swaps(true);
+        let rng = &mut deterministic_rng(0);
+        let update = |from: usize, to: usize, epoch: usize| SetUpdate {
+            epoch: Some(usize_to_f(epoch)),
+            ..SetUpdate::new(vec![item(from)], vec![item(to)])
+        };
+
+        let (proof, first, state) =
+            prove_set_update::<_, _, _, Checked, _>(state, update(1, 2, 1), &params, &(), rng)
+                .unwrap();
+        assert!(Checked::verify(&(), &proof, &first).unwrap());
+        assert_eq!(first[1], usize_to_f(1));
+        let (proof, second, state) =
+            prove_set_update::<_, _, _, Checked, _>(state, update(2, 3, 2), &params, &(), rng)
+                .unwrap();
+        assert!(Checked::verify(&(), &proof, &second).unwrap());
+        assert!(!Checked::verify(&(), &proof, &first).unwrap());
+        assert_eq!(second[2], first[3]);
+
+        let missing = prove_set_update::<_, _, _, Checked, _>(
+            state.clone(),
+            update(2, 4, 3),
+            &params,
+            &(),
+            rng,
+        );
+        assert!(match missing.err() {
+            Some(UpdateError::Input(InputError::MissingItem(0))) => true,
+            _ => false,
+        });
+        let unbound = SetUpdate::new(vec![item(3)], vec![item(4)]);
+        let unbound = prove_set_update::<_, _, _, Checked, _>(state, unbound, &params, &(), rng);
+        assert!(match unbound.err() {
+            Some(UpdateError::Input(InputError::Epoch)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn observers_see_each_update_once() {
+        use std::sync::{Arc, Mutex};
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |i: usize| vec![usize_to_f::<F>(i)];
+        let (params, mut state) = single_swaps(false);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_in = seen.clone();
+        state.subscribe(move |_, digest| seen_in.lock().unwrap().push(digest.clone()));
+        let update = SetUpdate::new(vec![item(1)], vec![item(2)]);
+        let rng = &mut deterministic_rng(0);
+        let (_, _, mut state) =
+            prove_set_update::<_, _, _, Checked, _>(state, update, &params, &(), rng).unwrap();
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[1], state.digest());
+    }
+}
@@ -0,0 +1,386 @@
+//! A C API for maintaining RSA sets and verifying proofs about them, enabled by the `ffi`
+//! feature.
+//!
+//! Sets hash items with Poseidon over Bn256. Field elements cross the boundary as 32-byte
+//! little-endian representations, and an item of `k` elements is `32 * k` contiguous bytes.
+//! Integers cross as NUL-terminated decimal strings. Functions returning `c_int` return `BB_OK`
+//! or a negative `BB_ERR_*` code.
+//!
+//! Build a linkable library with `cargo rustc --release --features ffi --crate-type cdylib`;
+//! `include/bellman_bignat.h` declares it for C.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::bn256::Bn256;
+use sapling_crypto::bellman::pairing::ff::{PrimeField, PrimeFieldRepr, ScalarEngine};
+
+use driver::{Groth16, ProofSystem};
+use group::RsaQuotientGroup;
+use hash::division_intractable as di;
+use hash::hashes::Poseidon;
+use rollup::header::digest_commitment;
+use set::int_set::exp::serial::SerialExp;
+use set::int_set::ExpSet;
+use set::rsa::Set;
+use set::GenSet;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str::FromStr;
+
+type F = <Bn256 as ScalarEngine>::Fr;
+
+/// An RSA set, opaque to C.
+pub struct BbSet {
+    set: Set<Poseidon<Bn256>, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>,
+}
+
+pub const BB_OK: c_int = 0;
+/// A required pointer was null.
+pub const BB_ERR_NULL: c_int = -1;
+/// An argument could not be decoded.
+pub const BB_ERR_ENCODING: c_int = -2;
+/// A removed item is not in the set. The set is unchanged.
+pub const BB_ERR_MISSING: c_int = -3;
+/// The output buffer is too small.
+pub const BB_ERR_BUFFER: c_int = -4;
+/// The library panicked. Any set passed in is unchanged.
+pub const BB_ERR_PANIC: c_int = -5;
+
+const F_BYTES: usize = 32;
+
+/// Runs `f`, returning `on_panic` if it panics, since unwinding into C is undefined behavior.
+fn catch<T, F: FnOnce() -> T>(on_panic: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+unsafe fn read_str(s: *const c_char) -> Option<Integer> {
+    if s.is_null() {
+        return None;
+    }
+    let s = CStr::from_ptr(s).to_str().ok()?;
+    Integer::from_str(s).ok()
+}
+
+fn read_f(bytes: &[u8]) -> Option<F> {
+    let mut repr = <F as PrimeField>::Repr::default();
+    repr.read_le(bytes).ok()?;
+    F::from_repr(repr).ok()
+}
+
+fn write_f(f: &F, out: &mut [u8]) {
+    f.into_repr()
+        .write_le(out)
+        .expect("a field element fits in 32 bytes");
+}
+
+/// Reads `n_items` items of `item_size` elements each from `items`.
+unsafe fn read_items(items: *const u8, n_items: usize, item_size: usize) -> Option<Vec<Vec<F>>> {
+    if n_items == 0 {
+        return Some(Vec::new());
+    }
+    if items.is_null() || item_size == 0 {
+        return None;
+    }
+    let n_bytes = n_items.checked_mul(item_size)?.checked_mul(F_BYTES)?;
+    let bytes = slice::from_raw_parts(items, n_bytes);
+    bytes
+        .chunks(item_size * F_BYTES)
+        .map(|item| item.chunks(F_BYTES).map(read_f).collect::<Option<Vec<F>>>())
+        .collect()
+}
+
+/// Creates an empty set over the group generated by `g` modulo `m`, hashing items to
+/// `n_bits_elem`-bit elements. Returns null on bad arguments.
+///
+/// The set must be freed with `bb_set_free`.
+#[no_mangle]
+pub unsafe extern "C" fn bb_set_new(
+    g: *const c_char,
+    m: *const c_char,
+    n_bits_elem: usize,
+    limb_width: usize,
+) -> *mut BbSet {
+    catch(ptr::null_mut(), || {
+        let (g, m) = match (read_str(g), read_str(m)) {
+            (Some(g), Some(m)) => (g, m),
+            _ => return ptr::null_mut(),
+        };
+        if ![128, 512, 2048].contains(&n_bits_elem)
+            || limb_width == 0
+            || n_bits_elem % limb_width != 0
+        {
+            return ptr::null_mut();
+        }
+        let set = Set::new_with(
+            RsaQuotientGroup { g, m },
+            di::offset(n_bits_elem),
+            Poseidon::default(),
+            n_bits_elem,
+            limb_width,
+            &Vec::new(),
+        );
+        Box::into_raw(Box::new(BbSet { set }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bb_set_free(set: *mut BbSet) {
+    catch((), || {
+        if !set.is_null() {
+            drop(Box::from_raw(set));
+        }
+    })
+}
+
+/// Removes `n_removals` items and then inserts `n_insertions` items, all of `item_size`
+/// elements. If any removal is missing, the set is left unchanged.
+#[no_mangle]
+pub unsafe extern "C" fn bb_set_apply_batch(
+    set: *mut BbSet,
+    removals: *const u8,
+    n_removals: usize,
+    insertions: *const u8,
+    n_insertions: usize,
+    item_size: usize,
+) -> c_int {
+    catch(BB_ERR_PANIC, || {
+        let set = match set.as_mut() {
+            Some(s) => s,
+            None => return BB_ERR_NULL,
+        };
+        let (removals, insertions) = match (
+            read_items(removals, n_removals, item_size),
+            read_items(insertions, n_insertions, item_size),
+        ) {
+            (Some(r), Some(i)) => (r, i),
+            _ => return BB_ERR_ENCODING,
+        };
+        // Checked on a copy, so that a failure leaves the set (and its observers) untouched.
+        let mut next = set.set.clone();
+        if !next.remove_all(removals.iter().map(Vec::as_slice)) {
+            return BB_ERR_MISSING;
+        }
+        set.set.remove_all(removals.iter().map(Vec::as_slice));
+        set.set.insert_all(insertions);
+        BB_OK
+    })
+}
+
+/// Writes the digest of the set, as a NUL-terminated decimal string, to `out`.
+#[no_mangle]
+pub unsafe extern "C" fn bb_set_digest(set: *mut BbSet, out: *mut c_char, out_len: usize) -> c_int {
+    catch(BB_ERR_PANIC, || {
+        let set = match set.as_mut() {
+            Some(s) => s,
+            None => return BB_ERR_NULL,
+        };
+        if out.is_null() {
+            return BB_ERR_NULL;
+        }
+        let digest = set.set.digest().to_string();
+        if digest.len() + 1 > out_len {
+            return BB_ERR_BUFFER;
+        }
+        let out = slice::from_raw_parts_mut(out as *mut u8, out_len);
+        out[..digest.len()].copy_from_slice(digest.as_bytes());
+        out[digest.len()] = 0;
+        BB_OK
+    })
+}
+
+/// Writes the public input committing to the digest (the hash of its `n_limbs` limbs, see
+/// `rollup::header::digest_commitment`) to the 32 bytes at `out`.
+#[no_mangle]
+pub unsafe extern "C" fn bb_set_digest_commitment(
+    set: *mut BbSet,
+    n_limbs: usize,
+    out: *mut u8,
+) -> c_int {
+    catch(BB_ERR_PANIC, || {
+        let set = match set.as_mut() {
+            Some(s) => s,
+            None => return BB_ERR_NULL,
+        };
+        if out.is_null() {
+            return BB_ERR_NULL;
+        }
+        let digest = set.set.digest();
+        let commitment =
+            match digest_commitment(&set.set.hasher, &digest, set.set.limb_width, n_limbs) {
+                Ok(c) => c,
+                Err(_) => return BB_ERR_ENCODING,
+            };
+        write_f(&commitment, slice::from_raw_parts_mut(out, F_BYTES));
+        BB_OK
+    })
+}
+
+/// Verifies a serialized groth16 proof against a serialized verifying key and `n_inputs` public
+/// inputs. Returns 1 if the proof is valid, 0 if it is not, and a negative code on bad
+/// arguments.
+#[no_mangle]
+pub unsafe extern "C" fn bb_verify(
+    vk: *const u8,
+    vk_len: usize,
+    inputs: *const u8,
+    n_inputs: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> c_int {
+    catch(BB_ERR_PANIC, || {
+        if vk.is_null() || proof.is_null() || (inputs.is_null() && n_inputs > 0) {
+            return BB_ERR_NULL;
+        }
+        let vk = slice::from_raw_parts(vk, vk_len);
+        let vk = match <Groth16 as ProofSystem<Bn256>>::read_verifying_key(vk) {
+            Ok(vk) => vk,
+            Err(_) => return BB_ERR_ENCODING,
+        };
+        let proof = slice::from_raw_parts(proof, proof_len);
+        let proof = match <Groth16 as ProofSystem<Bn256>>::read_proof(proof) {
+            Ok(p) => p,
+            Err(_) => return BB_ERR_ENCODING,
+        };
+        let inputs = match read_items(inputs, n_inputs, 1) {
+            Some(is) => is.into_iter().map(|mut i| i.pop().unwrap()).collect::<Vec<F>>(),
+            None => return BB_ERR_ENCODING,
+        };
+        match <Groth16 as ProofSystem<Bn256>>::verify(&vk, &proof, &inputs) {
+            Ok(true) => 1,
+            Ok(false) => 0,
+            Err(_) => BB_ERR_ENCODING,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::Hasher;
+    use sapling_crypto::bellman::groth16::{create_random_proof, generate_random_parameters};
+    use sapling_crypto::bellman::pairing::ff::Field;
+    use sapling_crypto::bellman::{Circuit, ConstraintSystem, SynthesisError};
+    use std::ffi::CString;
+    use OptionExt;
+
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    #[test]
+    fn apply_batch_and_digest() {
+        let g = CString::new("2").unwrap();
+        let m = CString::new(RSA_512).unwrap();
+        let item = |s: &str| {
+            let mut bytes = vec![0u8; F_BYTES];
+            write_f(&F::from_str(s).unwrap(), &mut bytes);
+            bytes
+        };
+        unsafe {
+            let set = bb_set_new(g.as_ptr(), m.as_ptr(), 128, 32);
+            assert!(!set.is_null());
+            let one = item("1");
+            let two = item("2");
+            assert_eq!(
+                bb_set_apply_batch(set, ptr::null(), 0, one.as_ptr(), 1, 1),
+                BB_OK
+            );
+            assert_eq!(
+                bb_set_apply_batch(set, two.as_ptr(), 1, ptr::null(), 0, 1),
+                BB_ERR_MISSING
+            );
+            let mut out = vec![0 as c_char; 200];
+            assert_eq!(bb_set_digest(set, out.as_mut_ptr(), 2), BB_ERR_BUFFER);
+            assert_eq!(bb_set_digest(set, out.as_mut_ptr(), out.len()), BB_OK);
+            let digest = CStr::from_ptr(out.as_ptr()).to_str().unwrap();
+
+            let mut expected: Set<_, ExpSet<_, SerialExp<_>>> = Set::new_with(
+                RsaQuotientGroup::from_strs("2", RSA_512),
+                di::offset(128),
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                &vec![vec![F::from_str("1").unwrap()]],
+            );
+            assert_eq!(digest, expected.digest().to_string());
+
+            let mut commitment = [0u8; F_BYTES];
+            assert_eq!(bb_set_digest_commitment(set, 16, commitment.as_mut_ptr()), BB_OK);
+            let limbs = ::mp::bignat::nat_to_limbs(&expected.digest(), 32, 16).unwrap();
+            assert_eq!(read_f(&commitment), Some(expected.hasher.hash(&limbs)));
+            bb_set_free(set);
+        }
+    }
+
+    /// Proves knowledge of a square root of the public input.
+    struct Square {
+        root: Option<F>,
+    }
+
+    impl Circuit<Bn256> for Square {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let root = cs.alloc(|| "root", || Ok(*self.root.grab()?))?;
+            let square = cs.alloc_input(
+                || "square",
+                || {
+                    let mut square = *self.root.grab()?;
+                    square.square();
+                    Ok(square)
+                },
+            )?;
+            cs.enforce(
+                || "root * root = square",
+                |lc| lc + root,
+                |lc| lc + root,
+                |lc| lc + square,
+            );
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn verify_proof_bytes() {
+        let mut rng = rand::thread_rng();
+        let params = generate_random_parameters(Square { root: None }, &mut rng).unwrap();
+        let proof = create_random_proof(
+            Square {
+                root: Some(F::from_str("3").unwrap()),
+            },
+            &params,
+            &mut rng,
+        )
+        .unwrap();
+        let mut vk = Vec::new();
+        params.vk.write(&mut vk).unwrap();
+        let mut proof_bytes = Vec::new();
+        proof.write(&mut proof_bytes).unwrap();
+        let input = |s: &str| {
+            let mut bytes = vec![0u8; F_BYTES];
+            write_f(&F::from_str(s).unwrap(), &mut bytes);
+            bytes
+        };
+        let verify = |vk: &[u8], input: &[u8], n_inputs: usize, proof: &[u8]| unsafe {
+            bb_verify(
+                vk.as_ptr(),
+                vk.len(),
+                input.as_ptr(),
+                n_inputs,
+                proof.as_ptr(),
+                proof.len(),
+            )
+        };
+        assert_eq!(verify(&vk, &input("9"), 1, &proof_bytes), 1);
+        assert_eq!(verify(&vk, &input("10"), 1, &proof_bytes), 0);
+        assert_eq!(verify(&vk[..10], &input("9"), 1, &proof_bytes), BB_ERR_ENCODING);
+        assert_eq!(verify(&vk, &input("9"), 1, &proof_bytes[..10]), BB_ERR_ENCODING);
+        // The input length overflows rather than reading out of bounds.
+        assert_eq!(verify(&vk, &input("9"), usize::max_value(), &proof_bytes), BB_ERR_ENCODING);
+        assert_eq!(
+            unsafe { bb_verify(ptr::null(), 0, ptr::null(), 0, proof_bytes.as_ptr(), 1) },
+            BB_ERR_NULL
+        );
+    }
+}
@@ -1,15 +1,20 @@
 use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::Field;
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
 
-use std::cmp::{min, Eq, PartialEq};
+use std::cmp::{max, min, Eq, PartialEq};
 use std::fmt::{self, Debug, Display, Formatter};
 use std::str::FromStr;
 
+use mp::bigint::BigInt;
 use mp::bignat::{BigNat, BigNatParams};
-use mp::exp::optimal_k;
+use mp::exp::{optimal_k, simultaneous_power};
 use util::bit::{Bit, Bitvector};
+use util::convert::usize_to_f;
 use util::gadget::Gadget;
+use OptionExt;
 
 pub trait SemiGroup: Clone + Eq + Debug + Display {
     type Elem: Clone + Debug + Ord + Display;
@@ -32,6 +37,38 @@ pub trait SemiGroup: Clone + Eq + Debug + Display {
         }
         acc
     }
+    /// Computes the product of `bases[i]^exps[i]`.
+    fn multi_power(&self, bases: &[Self::Elem], exps: &[Integer]) -> Self::Elem {
+        assert_eq!(bases.len(), exps.len());
+        bases
+            .iter()
+            .zip(exps)
+            .fold(self.identity(), |acc, (b, e)| self.op(&acc, &self.power(b, e)))
+    }
+}
+
+/// A group together with a list of independent generators, used for commitments of the form
+/// `g_0^{e_0} · ... · g_n^{e_n}`.
+///
+/// The caller is responsible for ensuring that no relation between the generators is known
+/// (e.g. by deriving them from a hash).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Generators<G: SemiGroup> {
+    pub group: G,
+    pub generators: Vec<G::Elem>,
+}
+
+impl<G: SemiGroup> Generators<G> {
+    /// The generators `g` (the group generator) and `h`.
+    pub fn pair(group: G, h: G::Elem) -> Self {
+        let generators = vec![group.generator().clone(), h];
+        Self { group, generators }
+    }
+
+    /// Commits to `exps`, one per generator.
+    pub fn commit(&self, exps: &[Integer]) -> G::Elem {
+        self.group.multi_power(&self.generators, exps)
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -47,6 +84,14 @@ impl RsaGroup {
             m: Integer::from_str(m).unwrap(),
         }
     }
+
+    /// The same group, with generator `g`.
+    pub fn with_generator(&self, g: Integer) -> Self {
+        Self {
+            g,
+            m: self.m.clone(),
+        }
+    }
 }
 
 impl Display for RsaGroup {
@@ -94,6 +139,14 @@ impl RsaQuotientGroup {
             m: Integer::from_str(m).unwrap(),
         }
     }
+
+    /// The same group, with generator `g`.
+    pub fn with_generator(&self, g: Integer) -> Self {
+        Self {
+            g,
+            m: self.m.clone(),
+        }
+    }
 }
 
 impl Display for RsaQuotientGroup {
@@ -133,6 +186,189 @@ impl SemiGroup for RsaQuotientGroup {
     }
 }
 
+/// The binary quadratic form `a x^2 + b x y + c y^2`, an element of a `ClassGroup`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct QuadForm {
+    pub a: Integer,
+    pub b: Integer,
+    pub c: Integer,
+}
+
+impl QuadForm {
+    /// The form `(a, b, c)` of discriminant `discriminant`, if there is one: `b^2 - discriminant`
+    /// must be divisible by `4a`.
+    pub fn with_discriminant(a: Integer, b: Integer, discriminant: &Integer) -> Option<Self> {
+        let numerator = Integer::from(b.square_ref()) - discriminant;
+        let four_a = Integer::from(&a << 2);
+        if four_a == 0 || !numerator.is_divisible(&four_a) {
+            return None;
+        }
+        let c = numerator / four_a;
+        Some(QuadForm { a, b, c })
+    }
+
+    /// `b^2 - 4 a c`
+    pub fn discriminant(&self) -> Integer {
+        let mut ac = Integer::from(&self.a * &self.c);
+        ac <<= 2;
+        Integer::from(self.b.square_ref()) - ac
+    }
+
+    /// The reduced form equivalent to `self`: `|b| <= a <= c`, with `b >= 0` if `|b| = a` or
+    /// `a = c`. Each class of positive definite forms has exactly one.
+    pub fn reduce(self) -> Self {
+        self.reduce_with_transform().0
+    }
+
+    /// Like `reduce`, but also returns the transformation `[p, q, r, s]`, of determinant one,
+    /// which takes `self` to the reduced form: the latter is `self(p x + q y, r x + s y)`.
+    pub fn reduce_with_transform(mut self) -> (Self, [Integer; 4]) {
+        let discriminant = self.discriminant();
+        let mut p = Integer::from(1);
+        let mut q = Integer::from(0);
+        let mut r = Integer::from(0);
+        let mut s = Integer::from(1);
+        let t = self.normalize(&discriminant);
+        q += Integer::from(&p * &t);
+        s += Integer::from(&r * &t);
+        while self.a > self.c {
+            // (x, y) -> (-y, x)
+            std::mem::swap(&mut self.a, &mut self.c);
+            self.b = -self.b;
+            std::mem::swap(&mut p, &mut q);
+            std::mem::swap(&mut r, &mut s);
+            q = -q;
+            s = -s;
+            let t = self.normalize(&discriminant);
+            q += Integer::from(&p * &t);
+            s += Integer::from(&r * &t);
+        }
+        if self.a == self.c && self.b < 0 {
+            self.b = -self.b;
+            std::mem::swap(&mut p, &mut q);
+            std::mem::swap(&mut r, &mut s);
+            q = -q;
+            s = -s;
+        }
+        (self, [p, q, r, s])
+    }
+
+    /// Brings `b` into `(-a, a]` by an equivalence which fixes `a`, `(x, y) -> (x + t y, y)`,
+    /// recomputes `c`, and returns `t`.
+    fn normalize(&mut self, discriminant: &Integer) -> Integer {
+        let two_a = Integer::from(&self.a << 1);
+        let (t, _) = Integer::from(&self.a - &self.b).div_rem_floor(two_a.clone());
+        self.b += two_a * &t;
+        self.c = (Integer::from(self.b.square_ref()) - discriminant) / Integer::from(&self.a << 2);
+        t
+    }
+}
+
+impl Display for QuadForm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.a, self.b, self.c)
+    }
+}
+
+/// The class group of positive definite binary quadratic forms of a negative discriminant, with
+/// composition of forms as the operation.
+///
+/// Its order is hard to compute for large discriminants, as the order of an RSA group is, but a
+/// discriminant (e.g. `-p` for a random prime `p = 3 mod 4`) can be chosen publicly, with no
+/// trapdoor to forget. Elements are kept reduced, so that they are unique.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ClassGroup {
+    pub discriminant: Integer,
+    pub g: QuadForm,
+}
+
+impl ClassGroup {
+    /// The group of discriminant `discriminant`, which must be negative and `1 mod 4`, generated
+    /// by the form with leading coefficients `g_a` and `g_b`.
+    pub fn from_strs(discriminant: &str, g_a: &str, g_b: &str) -> Self {
+        let discriminant = Integer::from_str(discriminant).unwrap();
+        assert!(discriminant < 0 && discriminant.mod_u(4) == 1);
+        let g = QuadForm::with_discriminant(
+            Integer::from_str(g_a).unwrap(),
+            Integer::from_str(g_b).unwrap(),
+            &discriminant,
+        )
+        .expect("the generator has the discriminant");
+        Self {
+            g: g.reduce(),
+            discriminant,
+        }
+    }
+
+    /// The same group, with generator `g`.
+    pub fn with_generator(&self, g: QuadForm) -> Self {
+        assert_eq!(g.discriminant(), self.discriminant);
+        Self {
+            g: g.reduce(),
+            discriminant: self.discriminant.clone(),
+        }
+    }
+
+    /// Composes `f1` and `f2`, and reduces the result.
+    fn compose(&self, f1: &QuadForm, f2: &QuadForm) -> QuadForm {
+        self.compose_unreduced(f1, f2).reduce()
+    }
+
+    /// Composes `f1` and `f2`, as in Cohen's "A Course in Computational Algebraic Number Theory",
+    /// Algorithm 5.4.7, but without the final reduction. The result is `(a1 a2 / d^2, b, c)`,
+    /// where `d = gcd(a1, a2, (b1 + b2) / 2)`.
+    fn compose_unreduced(&self, f1: &QuadForm, f2: &QuadForm) -> QuadForm {
+        let (f1, f2) = if f1.a > f2.a { (f2, f1) } else { (f1, f2) };
+        let s = Integer::from(&f1.b + &f2.b) >> 1;
+        let n = Integer::from(&f2.b - &s);
+        let (d, y1) = if f2.a.is_divisible(&f1.a) {
+            (f1.a.clone(), Integer::from(0))
+        } else {
+            let (d, u, _) = f2.a.clone().gcd_cofactors(f1.a.clone(), Integer::new());
+            (d, u)
+        };
+        let (d1, x2, y2) = if s.is_divisible(&d) {
+            (d, Integer::from(0), Integer::from(-1))
+        } else {
+            let (d1, x2, y2) = s.clone().gcd_cofactors(d, Integer::new());
+            (d1, x2, -y2)
+        };
+        let v1 = Integer::from(&f1.a / &d1);
+        let v2 = Integer::from(&f2.a / &d1);
+        let (_, r) = (y1 * y2 * n - x2 * &f2.c).div_rem_floor(v1.clone());
+        let b = Integer::from(&v2 * &r) * 2 + &f2.b;
+        QuadForm::with_discriminant(v1 * v2, b, &self.discriminant)
+            .expect("composition preserves the discriminant")
+    }
+}
+
+impl Display for ClassGroup {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ClassGroup")
+            .field("discriminant", &format_args!("{}", &self.discriminant))
+            .field("g", &format_args!("{}", &self.g))
+            .finish()
+    }
+}
+
+impl SemiGroup for ClassGroup {
+    type Elem = QuadForm;
+
+    fn op(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem {
+        self.compose(a, b)
+    }
+
+    /// The principal form, `(1, 1, (1 - discriminant) / 4)`.
+    fn identity(&self) -> Self::Elem {
+        QuadForm::with_discriminant(Integer::from(1), Integer::from(1), &self.discriminant)
+            .expect("the discriminant is 1 mod 4")
+    }
+
+    fn generator(&self) -> &Self::Elem {
+        &self.g
+    }
+}
+
 pub trait CircuitSemiGroup: Gadget<Access = ()> + Eq {
     type Elem: Clone + Gadget<E = Self::E> + Eq + Display + Debug;
     type Group: SemiGroup;
@@ -152,6 +388,15 @@ pub trait CircuitSemiGroup: Gadget<Access = ()> + Eq {
     fn identity(&self) -> Self::Elem;
     fn group(&self) -> Option<&Self::Group>;
     fn elem_params(p: &<Self as Gadget>::Params) -> <Self::Elem as Gadget>::Params;
+    /// Maps the result of a sequence of `partial_op`s to the canonical representative that `op`
+    /// would produce.
+    fn normalize<CS: ConstraintSystem<Self::E>>(
+        &self,
+        _cs: CS,
+        x: &Self::Elem,
+    ) -> Result<Self::Elem, SynthesisError> {
+        Ok(x.clone())
+    }
     fn bauer_power_bin_rev_helper<'a, CS: ConstraintSystem<Self::E>>(
         &self,
         mut cs: CS,
@@ -223,9 +468,141 @@ pub trait CircuitSemiGroup: Gadget<Access = ()> + Eq {
         b: &Self::Elem,
         e: &BigNat<Self::E>,
     ) -> Result<Self::Elem, SynthesisError> {
-        let exp_bin_rev = e.decompose(cs.namespace(|| "exp decomp"))?.reversed();
+        let exp_bin_rev = e.decompose_bounded(cs.namespace(|| "exp decomp"))?.reversed();
         self.bauer_power_bin_rev(cs.namespace(|| "binary exp"), &b, exp_bin_rev)
     }
+    /// Computes the product of `bases[i]^exps[i]`, using simultaneous (Shamir's trick)
+    /// exponentiation (see `mp::exp::simultaneous_power`).
+    fn multi_power<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        bases: &[Self::Elem],
+        exps: &[BigNat<Self::E>],
+    ) -> Result<Self::Elem, SynthesisError> {
+        let bits = exps
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                Ok(e
+                    .decompose_bounded(cs.namespace(|| format!("exp decomp {}", i)))?
+                    .into_bits())
+            })
+            .collect::<Result<Vec<Vec<Bit<Self::E>>>, SynthesisError>>()?;
+        let acc = simultaneous_power(
+            cs.namespace(|| "ladder"),
+            bases,
+            bits,
+            self.identity(),
+            |cs, a, b| self.partial_op(cs, a, b),
+        )?;
+        match acc {
+            Some(a) => self.normalize(cs.namespace(|| "normalize"), &a),
+            None => Ok(self.identity()),
+        }
+    }
+
+    /// Verifies a Wesolowski proof of exponentiation, that `base^exp = result`: `proof` is
+    /// `base^floor(exp / l)` for the prime challenge `l`, so `proof^l base^(exp mod l) = result`.
+    /// This costs one exponentiation by two numbers of the challenge's size, sharing their
+    /// squarings, rather than one by `exp`.
+    ///
+    /// The challenge must be bound to the statement, e.g. by hashing it to a prime, for the proof
+    /// to be sound.
+    fn verify_poe<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        base: &Self::Elem,
+        exp: &BigNat<Self::E>,
+        result: &Self::Elem,
+        proof: &Self::Elem,
+        challenge: &BigNat<Self::E>,
+    ) -> Result<(), SynthesisError> {
+        let r = exp.red_mod(cs.namespace(|| "exp mod l"), challenge)?;
+        self.verify_reduced_poe(cs, base, &r, result, proof, challenge)
+    }
+
+    /// The check behind `verify_poe`, given `r = exp mod l` already reduced: that
+    /// `proof^l base^r = result`. `wesolowski::proof_of_exp`, whose `r` is an aggregated product,
+    /// and `verify_poke` share it.
+    fn verify_reduced_poe<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        base: &Self::Elem,
+        r: &BigNat<Self::E>,
+        result: &Self::Elem,
+        proof: &Self::Elem,
+        challenge: &BigNat<Self::E>,
+    ) -> Result<(), SynthesisError> {
+        let r = r.clone().with_max_bits(challenge.bit_bound());
+        let left = self.multi_power(
+            cs.namespace(|| "Q^l b^r"),
+            &[proof.clone(), base.clone()],
+            &[challenge.clone(), r],
+        )?;
+        Gadget::assert_equal(cs.namespace(|| "Q^l b^r == res"), &left, result)
+    }
+
+    /// Verifies a PoKE2 proof of knowledge of an exponent, from Boneh, Bünz and Fisch's "Batching
+    /// Techniques for Accumulators with Applications to IOPs and Stateless Blockchains": that the
+    /// prover knows an `x` with `u^x = w`. The prover commits to `z = g^x`, and proves with
+    /// `proof = (u g^alpha)^floor(x / l)` and `r = x mod l`, for the prime challenge `l` and the
+    /// challenge `alpha` (all in `poke`). Checks that `r < l` and
+    /// `proof^l (u g^alpha)^r = w z^alpha`.
+    ///
+    /// `x` never enters the circuit, so this costs exponentiations by `l`, `r` and `alpha`,
+    /// however large `x` is. As for `verify_poe`, the challenges must be bound to the statement:
+    /// `g` to `(u, w)`, `l` to `(u, w, z)`, e.g. by hashing them to a prime, and `alpha` to all of
+    /// these.
+    fn verify_poke<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        u: &Self::Elem,
+        w: &Self::Elem,
+        poke: &PoKEProof<Self>,
+    ) -> Result<(), SynthesisError> {
+        let PoKEProof {
+            g,
+            z,
+            proof,
+            r,
+            challenge,
+            alpha,
+        } = poke;
+        let reduced = r.is_less_than(cs.namespace(|| "r < l"), challenge)?;
+        Boolean::enforce_equal(
+            cs.namespace(|| "r is reduced"),
+            &reduced,
+            &Boolean::constant(true),
+        )?;
+        let g_alpha = self.power(cs.namespace(|| "g^alpha"), g, alpha)?;
+        let base = self.op(cs.namespace(|| "u g^alpha"), u, &g_alpha)?;
+        let z_alpha = self.power(cs.namespace(|| "z^alpha"), z, alpha)?;
+        let right = self.op(cs.namespace(|| "w z^alpha"), w, &z_alpha)?;
+        self.verify_reduced_poe(
+            cs.namespace(|| "Q^l (u g^alpha)^r == w z^alpha"),
+            &base,
+            r,
+            &right,
+            proof,
+            challenge,
+        )
+    }
+}
+
+/// A PoKE2 proof, and the challenges it answers (see `CircuitSemiGroup::verify_poke`).
+pub struct PoKEProof<G: CircuitSemiGroup + ?Sized> {
+    /// The generator, bound to `(u, w)`
+    pub g: G::Elem,
+    /// The commitment `g^x`
+    pub z: G::Elem,
+    /// `(u g^alpha)^floor(x / l)`
+    pub proof: G::Elem,
+    /// `x mod l`
+    pub r: BigNat<G::E>,
+    /// The prime challenge `l`, bound to `(u, w, z)`
+    pub challenge: BigNat<G::E>,
+    /// The challenge `alpha`, bound to `(u, w, z, l)`
+    pub alpha: BigNat<G::E>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -411,9 +788,7 @@ impl<E: Engine> CircuitSemiGroup for CircuitRsaQuotientGroup<E> {
         b: &BigNat<E>,
     ) -> Result<Self::Elem, SynthesisError> {
         let x = self.partial_op(cs.namespace(|| "mult"), a, b)?;
-        let y = self.m.sub(cs.namespace(|| "sub"), &x)?;
-        y.decompose(cs.namespace(|| "y decomp check"))?;
-        x.min(cs.namespace(|| "min"), &y)
+        self.normalize(cs, &x)
     }
     fn partial_op<CS: ConstraintSystem<E>>(
         &self,
@@ -429,9 +804,16 @@ impl<E: Engine> CircuitSemiGroup for CircuitRsaQuotientGroup<E> {
         b: &Self::Elem,
         e: &BigNat<Self::E>,
     ) -> Result<Self::Elem, SynthesisError> {
-        let exp_bin_rev = e.decompose(cs.namespace(|| "exp decomp"))?.reversed();
+        let exp_bin_rev = e.decompose_bounded(cs.namespace(|| "exp decomp"))?.reversed();
         let x = self.bauer_power_bin_rev(cs.namespace(|| "binary exp"), &b, exp_bin_rev)?;
-        let y = self.m.sub(cs.namespace(|| "sub"), &x)?;
+        self.normalize(cs, &x)
+    }
+    fn normalize<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        x: &BigNat<E>,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let y = self.m.sub(cs.namespace(|| "sub"), x)?;
         y.decompose(cs.namespace(|| "y decomp check"))?;
         x.min(cs.namespace(|| "min"), &y)
     }
@@ -449,6 +831,623 @@ impl<E: Engine> CircuitSemiGroup for CircuitRsaQuotientGroup<E> {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircuitClassGroupParams {
+    pub limb_width: usize,
+    /// The number of limbs in the magnitude of the discriminant
+    pub n_limbs: usize,
+    /// The discriminant. It is a constant of the circuit, so that every allocated form can be
+    /// checked against it.
+    pub discriminant: Integer,
+}
+
+impl CircuitClassGroupParams {
+    /// The parameters for `discriminant`, with as many limbs as its magnitude needs.
+    pub fn new(limb_width: usize, discriminant: Integer) -> Self {
+        let n_bits = Integer::from(discriminant.abs_ref()).significant_bits() as usize;
+        Self {
+            limb_width,
+            n_limbs: (max(n_bits, 1) - 1) / limb_width + 1,
+            discriminant,
+        }
+    }
+
+    /// `-discriminant`, as a constant.
+    fn neg_discriminant<E: Engine, CS: ConstraintSystem<E>>(
+        &self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        BigNat::constant::<CS>(
+            &Integer::from(-&self.discriminant),
+            self.limb_width,
+            self.n_limbs,
+        )
+    }
+
+    /// The number of limbs in `a` and `|b|` of a reduced form, which are at most
+    /// `sqrt(|discriminant| / 3)`.
+    fn half_limbs(&self) -> usize {
+        self.n_limbs / 2 + 1
+    }
+}
+
+/// A `QuadForm` in a circuit, with a signed `b`.
+///
+/// Allocating a form (`Gadget::alloc`) checks that it is a reduced form of the discriminant in its
+/// parameters, whose `b` is not a negative zero, so that each element of the group has exactly
+/// one assignment to the wires. `CircuitClassGroup`'s operations rely on this.
+#[derive(Clone)]
+pub struct CircuitQuadForm<E: Engine> {
+    pub a: BigNat<E>,
+    pub b: BigInt<E>,
+    pub c: BigNat<E>,
+    pub value: Option<QuadForm>,
+    pub params: CircuitClassGroupParams,
+    /// The sign of `b`, as a wire
+    sign: LinearCombination<E>,
+}
+
+impl<E: Engine> CircuitQuadForm<E> {
+    /// The form `(a, b, c)`, padded to the limb counts of `params`.
+    fn new<CS: ConstraintSystem<E>>(
+        a: BigNat<E>,
+        mut b: BigInt<E>,
+        c: BigNat<E>,
+        params: &CircuitClassGroupParams,
+    ) -> Self {
+        let half = params.half_limbs();
+        b.magnitude = b.magnitude.with_n_limbs::<CS>(half);
+        let value = a.value.as_ref().and_then(|a| {
+            b.value.as_ref().and_then(|b| {
+                c.value.as_ref().map(|c| QuadForm {
+                    a: a.clone(),
+                    b: b.clone(),
+                    c: c.clone(),
+                })
+            })
+        });
+        Self {
+            a: a.with_n_limbs::<CS>(half),
+            sign: b.sign.lc(CS::one(), E::Fr::one()),
+            b,
+            c: c.with_n_limbs::<CS>(params.n_limbs),
+            value,
+            params: params.clone(),
+        }
+    }
+
+    /// Allocates the form without any checks. Only for copies of checked forms, as in `mux`.
+    fn alloc_unchecked<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: Option<&QuadForm>,
+        params: &CircuitClassGroupParams,
+    ) -> Result<Self, SynthesisError> {
+        let half = params.half_limbs();
+        let a = BigNat::alloc_from_nat(
+            cs.namespace(|| "a"),
+            || Ok(value.grab()?.a.clone()),
+            params.limb_width,
+            half,
+        )?;
+        let sign = AllocatedBit::alloc(cs.namespace(|| "sign"), value.map(|v| v.b < 0))?;
+        let magnitude = BigNat::alloc_from_nat(
+            cs.namespace(|| "|b|"),
+            || Ok(Integer::from(value.grab()?.b.abs_ref())),
+            params.limb_width,
+            half,
+        )?;
+        let c = BigNat::alloc_from_nat(
+            cs.namespace(|| "c"),
+            || Ok(value.grab()?.c.clone()),
+            params.limb_width,
+            params.n_limbs,
+        )?;
+        let b = BigInt {
+            sign: Boolean::from(sign),
+            magnitude,
+            value: value.map(|v| v.b.clone()),
+        };
+        Ok(Self::new::<CS>(a, b, c, params))
+    }
+
+    /// Enforces that `self` is a reduced form of the discriminant, with well-formed limbs and a
+    /// canonical `b`.
+    fn enforce_reduced<CS: ConstraintSystem<E>>(&self, mut cs: CS) -> Result<(), SynthesisError> {
+        let neg_discriminant = self.params.neg_discriminant::<E, CS>()?;
+        self.a.assert_well_formed(cs.namespace(|| "a rangecheck"))?;
+        self.b
+            .magnitude
+            .assert_well_formed(cs.namespace(|| "b rangecheck"))?;
+        self.c.assert_well_formed(cs.namespace(|| "c rangecheck"))?;
+        self.b.enforce_canonical(cs.namespace(|| "b canonical"))?;
+        enforce_discriminant(
+            cs.namespace(|| "discriminant"),
+            &self.a,
+            &self.b,
+            &self.c,
+            &neg_discriminant,
+        )?;
+        // -a < b <= a <= c, and b >= 0 if a = c. Then a > 0, and since b is canonical, its sign
+        // is set iff b < 0.
+        let a = BigInt::from_nat(self.a.clone());
+        let c = BigInt::from_nat(self.c.clone());
+        let b_above = a.neg().is_less_than(cs.namespace(|| "-a < b"), &self.b)?;
+        let b_exceeds = a.is_less_than(cs.namespace(|| "a < b"), &self.b)?;
+        let c_below = c.is_less_than(cs.namespace(|| "c < a"), &a)?;
+        let a_below = a.is_less_than(cs.namespace(|| "a < c"), &c)?;
+        let tie_negative = Boolean::and(
+            cs.namespace(|| "a = c and b < 0"),
+            &a_below.not(),
+            &self.b.sign,
+        )?;
+        Boolean::enforce_equal(
+            cs.namespace(|| "-a < b holds"),
+            &b_above,
+            &Boolean::constant(true),
+        )?;
+        for (name, bad) in [
+            ("a < b fails", &b_exceeds),
+            ("c < a fails", &c_below),
+            ("tie is non-negative", &tie_negative),
+        ]
+        .iter()
+        {
+            Boolean::enforce_equal(cs.namespace(|| *name), bad, &Boolean::constant(false))?;
+        }
+        Ok(())
+    }
+}
+
+/// Enforces `b^2 - 4 a c = -neg_discriminant`, for well-formed `a`, `|b|` and `c`.
+fn enforce_discriminant<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    a: &BigNat<E>,
+    b: &BigInt<E>,
+    c: &BigNat<E>,
+    neg_discriminant: &BigNat<E>,
+) -> Result<(), SynthesisError> {
+    let b_squared = b
+        .magnitude
+        .mult(cs.namespace(|| "b^2"), &b.magnitude)?
+        .add::<CS>(neg_discriminant)?;
+    let four_ac = a
+        .mult(cs.namespace(|| "a * c"), c)?
+        .scale::<CS>(usize_to_f(4));
+    b_squared.equal_when_carried_regroup(cs.namespace(|| "b^2 - 4ac = D"), &four_ac)
+}
+
+impl<E: Engine> PartialEq for CircuitQuadForm<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value && self.params == other.params
+    }
+}
+
+impl<E: Engine> Eq for CircuitQuadForm<E> {}
+
+impl<E: Engine> Display for CircuitQuadForm<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.value.as_ref() {
+            Some(v) => write!(f, "CircuitQuadForm{}", v),
+            None => write!(f, "CircuitQuadForm(empty)"),
+        }
+    }
+}
+
+impl<E: Engine> Debug for CircuitQuadForm<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CircuitQuadForm")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("c", &self.c)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl<E: Engine> Gadget for CircuitQuadForm<E> {
+    type E = E;
+    type Value = QuadForm;
+    type Params = CircuitClassGroupParams;
+    type Access = ();
+    /// Allocates a form, checking that it is reduced.
+    fn alloc<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: Option<&Self::Value>,
+        _access: (),
+        params: &Self::Params,
+    ) -> Result<Self, SynthesisError> {
+        let form = Self::alloc_unchecked(cs.namespace(|| "form"), value, params)?;
+        form.enforce_reduced(cs.namespace(|| "is reduced"))?;
+        Ok(form)
+    }
+    /// As `Gadget::mux`, but the output is a copy of an input, so it is not checked again.
+    fn mux<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        s: &Bit<E>,
+        i0: &Self,
+        i1: &Self,
+    ) -> Result<Self, SynthesisError> {
+        if i0.params != i1.params {
+            eprintln!("Parameter mis-match in CircuitQuadForm mux");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let value = s
+            .value
+            .and_then(|b| if b { i1.value() } else { i0.value() });
+        let out = Self::alloc_unchecked(cs.namespace(|| "out"), value, &i0.params)?;
+        for (i, ((i0w, i1w), out_w)) in i0
+            .wires()
+            .into_iter()
+            .zip(i1.wires())
+            .zip(out.wires())
+            .enumerate()
+        {
+            cs.enforce(
+                || format!("{}", i),
+                |lc| lc + &s.bit,
+                |lc| lc + &i1w - &i0w,
+                |lc| lc + &out_w - &i0w,
+            );
+        }
+        Ok(out)
+    }
+    fn wires(&self) -> Vec<LinearCombination<E>> {
+        let mut wires = self.a.wires();
+        wires.push(self.sign.clone());
+        wires.extend(self.b.magnitude.wires());
+        wires.extend(self.c.wires());
+        wires
+    }
+    fn wire_values(&self) -> Option<Vec<E::Fr>> {
+        let mut vs = self.a.wire_values()?;
+        vs.push(if self.b.sign.get_value()? {
+            E::Fr::one()
+        } else {
+            E::Fr::zero()
+        });
+        vs.extend(self.b.magnitude.wire_values()?);
+        vs.extend(self.c.wire_values()?);
+        Some(vs)
+    }
+    fn value(&self) -> Option<&Self::Value> {
+        self.value.as_ref()
+    }
+    fn params(&self) -> &Self::Params {
+        &self.params
+    }
+    fn access(&self) -> &() {
+        &()
+    }
+}
+
+/// The witnesses with which `CircuitClassGroup::op` checks a composition.
+struct CompositionHint {
+    /// `(b1 + b2) / 2`
+    s: Integer,
+    /// `gcd(a1, a2, s)`
+    d: Integer,
+    /// `a1 / d`, `a2 / d` and `s / d`
+    quotients: [Integer; 3],
+    /// Coefficients which combine `a1`, `a2` and `s` into `d`
+    bezout: [Integer; 3],
+    /// The unreduced composition
+    composed: QuadForm,
+    /// `(b - b1) / (2 a1 / d)` and `(b - b2) / (2 a2 / d)`, for the composed `b`
+    steps: [Integer; 2],
+    /// The transformation which reduces the composition
+    transform: [Integer; 4],
+    reduced: QuadForm,
+}
+
+impl CompositionHint {
+    fn new(group: &ClassGroup, f1: &QuadForm, f2: &QuadForm) -> Self {
+        let s = Integer::from(&f1.b + &f2.b) >> 1;
+        let (d12, u, v) = f1.a.clone().gcd_cofactors(f2.a.clone(), Integer::new());
+        let (d, x, y) = d12.gcd_cofactors(s.clone(), Integer::new());
+        let quotients = [
+            Integer::from(&f1.a / &d),
+            Integer::from(&f2.a / &d),
+            Integer::from(&s / &d),
+        ];
+        let composed = group.compose_unreduced(f1, f2);
+        let steps = [
+            Integer::from(&composed.b - &f1.b) / Integer::from(&quotients[0] << 1),
+            Integer::from(&composed.b - &f2.b) / Integer::from(&quotients[1] << 1),
+        ];
+        let (reduced, transform) = composed.clone().reduce_with_transform();
+        Self {
+            bezout: [Integer::from(&x * &u), x * v, y],
+            s,
+            d,
+            quotients,
+            composed,
+            steps,
+            transform,
+            reduced,
+        }
+    }
+}
+
+/// The class group of a negative discriminant `D`, in a circuit.
+///
+/// `op` takes the composition and its reduction as witnesses, and checks them:
+///
+/// * `d = gcd(a1, a2, s)` for `s = (b1 + b2) / 2`: `d` divides each, and is an integer
+///   combination of them,
+/// * the composition is `(A, B, C)`, where `A = (a1 / d) (a2 / d)`, `B = b1 mod 2 a1 / d`,
+///   `B = b2 mod 2 a2 / d` and `B^2 - 4 A C = D`, which determine its class,
+/// * the result is `(A, B, C)` under a transformation of determinant one, and is reduced, so it
+///   is the unique reduced form of that class.
+///
+/// Forms are reduced by every operation, so `partial_op` is `op`.
+#[derive(Clone, Derivative)]
+#[derivative(PartialEq(bound = ""), Eq(bound = ""))]
+pub struct CircuitClassGroup<E: Engine> {
+    /// `-D`, a constant
+    pub neg_discriminant: BigNat<E>,
+    pub g: CircuitQuadForm<E>,
+    pub id: CircuitQuadForm<E>,
+    pub value: Option<ClassGroup>,
+    pub params: CircuitClassGroupParams,
+}
+
+impl<E: Engine> CircuitClassGroup<E> {
+    /// Composes `f1` and `f2`, which must be reduced, into a reduced form.
+    fn compose<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        f1: &CircuitQuadForm<E>,
+        f2: &CircuitQuadForm<E>,
+    ) -> Result<CircuitQuadForm<E>, SynthesisError> {
+        let hint = self.value.as_ref().and_then(|group| {
+            f1.value
+                .as_ref()
+                .and_then(|v1| f2.value.as_ref().map(|v2| CompositionHint::new(group, v1, v2)))
+        });
+        let limb_width = self.params.limb_width;
+        let half = self.params.half_limbs();
+        let wide = 2 * half + 1;
+        let a1 = BigInt::from_nat(f1.a.clone());
+        let a2 = BigInt::from_nat(f2.a.clone());
+
+        // d = gcd(a1, a2, s)
+        let s = BigInt::alloc_from_int(
+            cs.namespace(|| "s"),
+            || Ok(hint.grab()?.s.clone()),
+            limb_width,
+            half,
+        )?;
+        BigInt::enforce_sum(cs.namespace(|| "2s = b1 + b2"), &[&s, &s], &[&f1.b, &f2.b])?;
+        let d = BigNat::alloc_from_nat(
+            cs.namespace(|| "d"),
+            || Ok(hint.grab()?.d.clone()),
+            limb_width,
+            half,
+        )?;
+        d.assert_well_formed(cs.namespace(|| "d rangecheck"))?;
+        let d = BigInt::from_nat(d);
+        let mut quotients = Vec::new();
+        for (i, x) in [&a1, &a2, &s].iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("d divides {}", i));
+            let quotient = BigInt::alloc_from_int(
+                cs.namespace(|| "quotient"),
+                || Ok(hint.grab()?.quotients[i].clone()),
+                limb_width,
+                half,
+            )?;
+            d.mult(cs.namespace(|| "product"), &quotient)?
+                .equal(cs.namespace(|| "check"), x)?;
+            quotients.push(quotient);
+        }
+        let mut terms = Vec::new();
+        for (i, x) in [&a1, &a2, &s].iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("bezout {}", i));
+            let coefficient = BigInt::alloc_from_int(
+                cs.namespace(|| "coefficient"),
+                || Ok(hint.grab()?.bezout[i].clone()),
+                limb_width,
+                wide,
+            )?;
+            terms.push(coefficient.mult(cs.namespace(|| "term"), x)?);
+        }
+        BigInt::enforce_sum(
+            cs.namespace(|| "d is a combination"),
+            &terms.iter().collect::<Vec<_>>(),
+            &[&d],
+        )?;
+
+        // The composition (A, B, C)
+        let big_a = BigInt::from_nat(
+            quotients[0]
+                .magnitude
+                .mult(cs.namespace(|| "A"), &quotients[1].magnitude)?,
+        );
+        let big_b = BigInt::alloc_from_int(
+            cs.namespace(|| "B"),
+            || Ok(hint.grab()?.composed.b.clone()),
+            limb_width,
+            wide,
+        )?;
+        let big_c = BigNat::alloc_from_nat(
+            cs.namespace(|| "C"),
+            || Ok(hint.grab()?.composed.c.clone()),
+            limb_width,
+            wide,
+        )?;
+        big_c.assert_well_formed(cs.namespace(|| "C rangecheck"))?;
+        for (i, f) in [f1, f2].iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("B = b{} mod 2 a{} / d", i + 1, i + 1));
+            let step = BigInt::alloc_from_int(
+                cs.namespace(|| "step"),
+                || Ok(hint.grab()?.steps[i].clone()),
+                limb_width,
+                wide,
+            )?;
+            let half_stride = quotients[i].mult(cs.namespace(|| "half stride"), &step)?;
+            BigInt::enforce_sum(
+                cs.namespace(|| "check"),
+                &[&big_b],
+                &[&f.b, &half_stride, &half_stride],
+            )?;
+        }
+        enforce_discriminant(
+            cs.namespace(|| "composition discriminant"),
+            &big_a.magnitude,
+            &big_b,
+            &big_c,
+            &self.neg_discriminant,
+        )?;
+        let big_c = BigInt::from_nat(big_c);
+
+        // The reduction, by (x, y) -> (p x + q y, r x + s y)
+        let mut transform = Vec::new();
+        for (i, name) in ["p", "q", "r", "s"].iter().enumerate() {
+            transform.push(BigInt::alloc_from_int(
+                cs.namespace(|| *name),
+                || Ok(hint.grab()?.transform[i].clone()),
+                limb_width,
+                half,
+            )?);
+        }
+        let ps = transform[0].mult(cs.namespace(|| "p s"), &transform[3])?;
+        let qr = transform[1].mult(cs.namespace(|| "q r"), &transform[2])?;
+        let one = BigInt::from_nat(BigNat::one::<CS>(limb_width));
+        BigInt::enforce_sum(cs.namespace(|| "determinant"), &[&ps], &[&qr, &one])?;
+        // Allocating the result checks that it is reduced.
+        let reduced = CircuitQuadForm::alloc(
+            cs.namespace(|| "reduced"),
+            hint.as_ref().map(|h| &h.reduced),
+            (),
+            &self.params,
+        )?;
+        let a_p = big_a.mult(cs.namespace(|| "A p"), &transform[0])?;
+        let b_p = big_b.mult(cs.namespace(|| "B p"), &transform[0])?;
+        let c_r = big_c.mult(cs.namespace(|| "C r"), &transform[2])?;
+        // a' = A p^2 + B p r + C r^2
+        let a_p_p = a_p.mult(cs.namespace(|| "A p p"), &transform[0])?;
+        let b_p_r = b_p.mult(cs.namespace(|| "B p r"), &transform[2])?;
+        let c_r_r = c_r.mult(cs.namespace(|| "C r r"), &transform[2])?;
+        BigInt::enforce_sum(
+            cs.namespace(|| "reduced a"),
+            &[&BigInt::from_nat(reduced.a.clone())],
+            &[&a_p_p, &b_p_r, &c_r_r],
+        )?;
+        // b' = 2 A p q + B (p s + q r) + 2 C r s
+        let a_p_q = a_p.mult(cs.namespace(|| "A p q"), &transform[1])?;
+        let b_p_s = big_b.mult(cs.namespace(|| "B p s"), &ps)?;
+        let b_q_r = big_b.mult(cs.namespace(|| "B q r"), &qr)?;
+        let c_r_s = c_r.mult(cs.namespace(|| "C r s"), &transform[3])?;
+        BigInt::enforce_sum(
+            cs.namespace(|| "reduced b"),
+            &[&reduced.b],
+            &[&a_p_q, &a_p_q, &b_p_s, &b_q_r, &c_r_s, &c_r_s],
+        )?;
+        // c' follows from the discriminant.
+        Ok(reduced)
+    }
+}
+
+impl<E: Engine> Gadget for CircuitClassGroup<E> {
+    type E = E;
+    type Value = ClassGroup;
+    type Params = CircuitClassGroupParams;
+    type Access = ();
+    fn alloc<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: Option<&Self::Value>,
+        _access: (),
+        params: &Self::Params,
+    ) -> Result<Self, SynthesisError> {
+        let value = value.cloned();
+        if let Some(group) = value.as_ref() {
+            if group.discriminant != params.discriminant {
+                eprintln!(
+                    "Class group of discriminant {} allocated with parameters for {}",
+                    group.discriminant, params.discriminant
+                );
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+        if params.discriminant >= 0 || params.discriminant.mod_u(4) != 1 {
+            eprintln!(
+                "The discriminant {} is not negative and 1 mod 4",
+                params.discriminant
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let neg_discriminant = params.neg_discriminant::<E, CS>()?;
+        let g = CircuitQuadForm::alloc(
+            cs.namespace(|| "g"),
+            value.as_ref().map(|v| &v.g),
+            (),
+            params,
+        )?;
+
+        // The identity, (1, 1, (1 - D) / 4), is a constant.
+        let one = BigNat::one::<CS>(params.limb_width);
+        let id_c = BigNat::constant::<CS>(
+            &(Integer::from(1 - &params.discriminant) >> 2),
+            params.limb_width,
+            params.n_limbs,
+        )?;
+        let id = CircuitQuadForm::new::<CS>(one.clone(), BigInt::from_nat(one), id_c, params);
+        Ok(Self {
+            neg_discriminant,
+            g,
+            id,
+            value,
+            params: params.clone(),
+        })
+    }
+    fn wires(&self) -> Vec<LinearCombination<E>> {
+        self.g.wires()
+    }
+    fn wire_values(&self) -> Option<Vec<E::Fr>> {
+        self.g.wire_values()
+    }
+    fn value(&self) -> Option<&Self::Value> {
+        self.value.as_ref()
+    }
+    fn params(&self) -> &Self::Params {
+        &self.params
+    }
+    fn access(&self) -> &() {
+        &()
+    }
+}
+
+impl<E: Engine> CircuitSemiGroup for CircuitClassGroup<E> {
+    type Elem = CircuitQuadForm<E>;
+    type Group = ClassGroup;
+    fn op<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        a: &CircuitQuadForm<E>,
+        b: &CircuitQuadForm<E>,
+    ) -> Result<Self::Elem, SynthesisError> {
+        self.compose(cs, a, b)
+    }
+    fn partial_op<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        a: &CircuitQuadForm<E>,
+        b: &CircuitQuadForm<E>,
+    ) -> Result<Self::Elem, SynthesisError> {
+        self.compose(cs, a, b)
+    }
+    fn elem_params(p: &<Self as Gadget>::Params) -> <Self::Elem as Gadget>::Params {
+        p.clone()
+    }
+    fn group(&self) -> Option<&Self::Group> {
+        self.value.as_ref()
+    }
+    fn generator(&self) -> Self::Elem {
+        self.g.clone()
+    }
+    fn identity(&self) -> Self::Elem {
+        self.id.clone()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -809,4 +1808,416 @@ mod test {
             true,
         ),
     }
+
+    #[test]
+    fn dual_packed_inputs() {
+        use util::convert::pack_limbs;
+        use util::gadget::InputPacking;
+        let group = RsaGroup::from_strs("2", "140737488355333");
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let g = CircuitRsaGroup::alloc(
+            cs.namespace(|| "group"),
+            Some(&group),
+            (),
+            &CircuitRsaGroupParams {
+                limb_width: 16,
+                n_limbs: 3,
+            },
+        )
+        .unwrap();
+        g.inputize_with(
+            cs.namespace(|| "input"),
+            InputPacking::Dual { limb_width: 16 },
+        )
+        .unwrap();
+        assert!(cs.is_satisfied());
+        // One for the constant, and three for six limbs.
+        assert_eq!(cs.num_inputs(), 4);
+        let expected = pack_limbs(&g.wire_values().unwrap(), 16, 2);
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(cs.get_input(i + 1, &format!("input/{}/in", i)), *e);
+        }
+    }
+
+    #[test]
+    fn max_packed_inputs() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        use util::convert::pack_limbs;
+        use util::gadget::InputPacking;
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let value = (Integer::from(1) << 2047) + 12345;
+        let n = BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(value), 32, 64).unwrap();
+        let packing = InputPacking::Max { limb_width: 32 };
+        assert_eq!(packing.wires_per_input::<<Bn256 as ScalarEngine>::Fr>(), 7);
+        Gadget::inputize_with(&n, cs.namespace(|| "input"), packing).unwrap();
+        assert!(cs.is_satisfied());
+        // One for the constant, and ten for 64 limbs.
+        assert_eq!(cs.num_inputs(), 11);
+        let expected = pack_limbs(&n.wire_values().unwrap(), 32, 7);
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(cs.get_input(i + 1, &format!("input/{}/in", i)), *e);
+        }
+    }
+
+    #[test]
+    fn generators_commit() {
+        let group = RsaQuotientGroup::from_strs("2", "241");
+        let gens = Generators::pair(group, Integer::from(3));
+        assert_eq!(
+            gens.commit(&[Integer::from(5), Integer::from(7)]),
+            Integer::from(94)
+        );
+    }
+
+    #[test]
+    fn class_group_orders() {
+        // Class numbers 3 and 5.
+        for &(discriminant, g_b, order) in &[("-23", "1", 3), ("-47", "1", 5)] {
+            let group = ClassGroup::from_strs(discriminant, "2", g_b);
+            for k in 1..order {
+                assert_ne!(group.power(group.generator(), &Integer::from(k)), group.identity());
+            }
+            assert_eq!(
+                group.power(group.generator(), &Integer::from(order)),
+                group.identity()
+            );
+        }
+        let group = ClassGroup::from_strs("-47", "2", "1");
+        let g2 = group.op(group.generator(), group.generator());
+        assert_eq!(g2.to_string(), "(3, -1, 4)");
+    }
+
+    #[test]
+    fn class_group_powers() {
+        let group = ClassGroup::from_strs("-100000000000000000000000000000000000247", "2", "1");
+        let g = group.generator();
+        let a = Integer::from_str("123456789012345678901234567890").unwrap();
+        let b = Integer::from_str("987654321098765432109876543210").unwrap();
+        let ga = group.power(g, &a);
+        let gb = group.power(g, &b);
+        assert_eq!(group.op(&ga, &gb), group.power(g, &Integer::from(&a + &b)));
+        assert_eq!(group.op(&ga, &group.identity()), ga);
+        assert_eq!(
+            group.op(&group.op(&ga, g), &gb),
+            group.op(&ga, &group.op(g, &gb))
+        );
+        assert_eq!(ga.discriminant(), group.discriminant);
+        assert_eq!(
+            group.power(g, &Integer::from(1000)).to_string(),
+            "(2707762696202796574, 258366099672532139, 9238877651814502458)"
+        );
+    }
+
+    pub struct ClassGroupPower<'a> {
+        discriminant: &'a str,
+        e: &'a str,
+        /// The leading coefficients of `g^e`, for the generator `(2, 1, _)`
+        res: (&'a str, &'a str),
+        limb_width: usize,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for ClassGroupPower<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let native = ClassGroup::from_strs(self.discriminant, "2", "1");
+            let group = CircuitClassGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&native),
+                (),
+                &CircuitClassGroupParams::new(self.limb_width, native.discriminant.clone()),
+            )?;
+            let e = BigNat::alloc_from_nat(
+                cs.namespace(|| "e"),
+                || Ok(Integer::from_str(self.e).unwrap()),
+                4,
+                3,
+            )?;
+            let res = QuadForm::with_discriminant(
+                Integer::from_str(self.res.0).unwrap(),
+                Integer::from_str(self.res.1).unwrap(),
+                &native.discriminant,
+            )
+            .unwrap();
+            let expected =
+                CircuitQuadForm::alloc(cs.namespace(|| "expected"), Some(&res), (), &group.params)?;
+            let actual = group.power(cs.namespace(|| "pow"), &group.generator(), &e)?;
+            Gadget::assert_equal(cs.namespace(|| "check"), &actual, &expected)
+        }
+    }
+
+    circuit_tests! {
+        class_group_power_0: (
+            ClassGroupPower {
+                discriminant: "-47",
+                e: "0",
+                res: ("1", "1"),
+                limb_width: 4,
+            },
+            true,
+        ),
+        class_group_power_1: (
+            ClassGroupPower {
+                discriminant: "-47",
+                e: "1",
+                res: ("2", "1"),
+                limb_width: 4,
+            },
+            true,
+        ),
+        class_group_power_7: (
+            ClassGroupPower {
+                discriminant: "-47",
+                e: "7",
+                res: ("3", "-1"),
+                limb_width: 4,
+            },
+            true,
+        ),
+        class_group_power_13: (
+            ClassGroupPower {
+                discriminant: "-47",
+                e: "13",
+                res: ("3", "1"),
+                limb_width: 4,
+            },
+            true,
+        ),
+        class_group_power_wrong: (
+            ClassGroupPower {
+                discriminant: "-47",
+                e: "7",
+                res: ("3", "1"),
+                limb_width: 4,
+            },
+            false,
+        ),
+        class_group_power_127b: (
+            ClassGroupPower {
+                discriminant: "-100000000000000000000000000000000000247",
+                e: "1000",
+                res: ("2707762696202796574", "258366099672532139"),
+                limb_width: 32,
+            },
+            true,
+        ),
+    }
+
+    pub struct MultiPowerInputs<'a> {
+        pub h: &'a str,
+        pub a: &'a str,
+        pub b: &'a str,
+        pub res: &'a str,
+    }
+
+    pub struct MultiPower<'a> {
+        inputs: Option<MultiPowerInputs<'a>>,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for MultiPower<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let ins = self.inputs.grab()?;
+            let group = CircuitRsaQuotientGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&RsaQuotientGroup::from_strs("2", "241")),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width: 4,
+                    n_limbs: 2,
+                },
+            )?;
+            let h = BigNat::alloc_from_nat(
+                cs.namespace(|| "h"),
+                || Ok(Integer::from_str(ins.h).unwrap()),
+                4,
+                2,
+            )?;
+            let a = BigNat::alloc_from_nat(
+                cs.namespace(|| "a"),
+                || Ok(Integer::from_str(ins.a).unwrap()),
+                4,
+                2,
+            )?;
+            let b = BigNat::alloc_from_nat(
+                cs.namespace(|| "b"),
+                || Ok(Integer::from_str(ins.b).unwrap()),
+                4,
+                3,
+            )?;
+            let res = BigNat::alloc_from_nat(
+                cs.namespace(|| "res"),
+                || Ok(Integer::from_str(ins.res).unwrap()),
+                4,
+                2,
+            )?;
+            let actual =
+                group.multi_power(cs.namespace(|| "pow"), &[group.generator(), h], &[a, b])?;
+            actual.equal(cs.namespace(|| "check"), &res)
+        }
+    }
+
+    circuit_tests! {
+        multi_power_5_7: (
+            MultiPower {
+                inputs: Some(MultiPowerInputs { h: "3", a: "5", b: "7", res: "94" }),
+            },
+            true,
+        ),
+        multi_power_100_200: (
+            MultiPower {
+                inputs: Some(MultiPowerInputs { h: "3", a: "100", b: "200", res: "15" }),
+            },
+            true,
+        ),
+        multi_power_wrong: (
+            MultiPower {
+                inputs: Some(MultiPowerInputs { h: "3", a: "5", b: "7", res: "95" }),
+            },
+            false,
+        ),
+    }
+
+    pub struct VerifyPoE<'a> {
+        pub b: &'a str,
+        pub e: &'a str,
+        pub l: &'a str,
+        pub res: &'a str,
+        /// If missing, it is computed honestly
+        pub proof: Option<&'a str>,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for VerifyPoE<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let native = RsaGroup::from_strs("2", "241");
+            let group = CircuitRsaGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&native),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width: 4,
+                    n_limbs: 2,
+                },
+            )?;
+            let int = |s: &str| Integer::from_str(s).unwrap();
+            let proof = self
+                .proof
+                .map(&int)
+                .unwrap_or_else(|| native.power(&int(self.b), &(int(self.e) / int(self.l))));
+            let mut nat = |name: &str, n: Integer, n_limbs: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 4, n_limbs)
+            };
+            let b = nat("b", int(self.b), 2)?;
+            let e = nat("e", int(self.e), 4)?;
+            let l = nat("l", int(self.l), 2)?;
+            let res = nat("res", int(self.res), 2)?;
+            let proof = nat("proof", proof, 2)?;
+            group.verify_poe(cs.namespace(|| "poe"), &b, &e, &res, &proof, &l)
+        }
+    }
+
+    circuit_tests! {
+        verify_poe_5_12351: (
+            VerifyPoE { b: "5", e: "12351", l: "13", res: "162", proof: None },
+            true,
+        ),
+        verify_poe_explicit_proof: (
+            VerifyPoE { b: "5", e: "12351", l: "13", res: "162", proof: Some("177") },
+            true,
+        ),
+        verify_poe_wrong_result: (
+            VerifyPoE { b: "5", e: "12351", l: "13", res: "161", proof: None },
+            false,
+        ),
+        verify_poe_wrong_proof: (
+            VerifyPoE { b: "5", e: "12351", l: "13", res: "162", proof: Some("176") },
+            false,
+        ),
+    }
+
+    pub struct VerifyPoKE<'a> {
+        pub u: &'a str,
+        pub x: &'a str,
+        pub l: &'a str,
+        pub alpha: &'a str,
+        /// If missing, it is computed honestly
+        pub w: Option<&'a str>,
+        /// Whether to shift `l` from the quotient to the remainder, leaving `r >= l`
+        pub unreduced: bool,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for VerifyPoKE<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let native = RsaGroup::from_strs("2", "241");
+            let group = CircuitRsaGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&native),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width: 4,
+                    n_limbs: 2,
+                },
+            )?;
+            let int = |s: &str| Integer::from_str(s).unwrap();
+            let (u, x, l, alpha) = (int(self.u), int(self.x), int(self.l), int(self.alpha));
+            let g = native.generator().clone();
+            let w = self.w.map(&int).unwrap_or_else(|| native.power(&u, &x));
+            let z = native.power(&g, &x);
+            let (mut q, mut r) = x.div_rem(l.clone());
+            if self.unreduced {
+                q -= 1;
+                r += &l;
+            }
+            let base = native.op(&u, &native.power(&g, &alpha));
+            let proof = native.power(&base, &q);
+            let mut nat = |name: &str, n: Integer| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 4, 2)
+            };
+            let u = nat("u", u)?;
+            let w = nat("w", w)?;
+            let poke = PoKEProof {
+                g: group.generator(),
+                z: nat("z", z)?,
+                proof: nat("proof", proof)?,
+                r: nat("r", r)?,
+                challenge: nat("l", l)?,
+                alpha: nat("alpha", alpha)?,
+            };
+            group.verify_poke(cs.namespace(|| "poke"), &u, &w, &poke)
+        }
+    }
+
+    circuit_tests! {
+        verify_poke_5_12351: (
+            VerifyPoKE { u: "5", x: "12351", l: "13", alpha: "7", w: None, unreduced: false },
+            true,
+        ),
+        verify_poke_large_alpha: (
+            VerifyPoKE { u: "9", x: "99999", l: "31", alpha: "200", w: None, unreduced: false },
+            true,
+        ),
+        verify_poke_wrong_w: (
+            VerifyPoKE {
+                u: "5",
+                x: "12351",
+                l: "13",
+                alpha: "7",
+                w: Some("161"),
+                unreduced: false,
+            },
+            false,
+        ),
+        verify_poke_unreduced: (
+            VerifyPoKE { u: "5", x: "12351", l: "13", alpha: "7", w: None, unreduced: true },
+            false,
+        ),
+    }
+
+    shape_tests! {
+        multi_power_shape: (
+            MultiPower {
+                inputs: Some(MultiPowerInputs { h: "3", a: "5", b: "7", res: "94" }),
+            },
+            MultiPower {
+                inputs: Some(MultiPowerInputs { h: "3", a: "100", b: "200", res: "15" }),
+            },
+        ),
+    }
 }
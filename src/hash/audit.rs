@@ -0,0 +1,145 @@
+//! Audit mode: checking in-circuit hashes against their native counterparts.
+//!
+//! With the `audit` feature, every in-circuit hash (each `CircuitHasher` evaluation, the
+//! division-intractable hashes and `hash_to_integer`) is recomputed natively from its witness
+//! values as it is synthesized. A divergence is reported, and enforced as an unsatisfiable
+//! constraint named `audit: <what>` in the namespace of the hash, so that
+//! `TestConstraintSystem::which_is_unsatisfied` names the first place where the native helper and
+//! the circuit disagree. Without the feature, the checks compile to nothing.
+//!
+//! Audit constraints only exist on divergence, so the constraint count of a circuit depends on its
+//! witness in audit mode: it is for debugging, not for parameter generation or proving.
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::ConstraintSystem;
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use super::Hasher;
+
+use std::fmt::Debug;
+
+/// Whether the crate was built with the `audit` feature.
+pub const ENABLED: bool = cfg!(feature = "audit");
+
+/// In audit mode, checks that `circuit`, a value computed in-circuit, equals `native()`. Nothing
+/// is checked if either value is unknown, as when synthesizing without a witness.
+pub fn check<E, CS, T, N>(cs: &mut CS, what: &str, circuit: Option<T>, native: N)
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+    T: PartialEq + Debug,
+    N: FnOnce() -> Option<T>,
+{
+    if !ENABLED {
+        return;
+    }
+    if let (Some(circuit), Some(native)) = (circuit, native()) {
+        if circuit != native {
+            eprintln!(
+                "Audit: {} diverges from its native counterpart\n  circuit: {:?}\n  native:  {:?}",
+                what, circuit, native
+            );
+            cs.enforce(
+                || format!("audit: {}", what),
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + CS::one(),
+            );
+        }
+    }
+}
+
+/// Checks an allocated `hash2(a, b)`.
+pub fn check_hash2<E, H, CS>(
+    cs: &mut CS,
+    hasher: &H,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>,
+    output: &AllocatedNum<E>,
+) where
+    E: Engine,
+    H: Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    check(cs, "hash2", output.get_value(), || {
+        Some(hasher.hash2(a.get_value()?, b.get_value()?))
+    })
+}
+
+/// Checks an allocated `hash(inputs)`.
+pub fn check_hash<E, H, CS>(
+    cs: &mut CS,
+    hasher: &H,
+    inputs: &[AllocatedNum<E>],
+    output: &AllocatedNum<E>,
+) where
+    E: Engine,
+    H: Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    check(cs, "hash", output.get_value(), || {
+        let values = inputs
+            .iter()
+            .map(|i| i.get_value())
+            .collect::<Option<Vec<_>>>()?;
+        Some(hasher.hash(&values))
+    })
+}
+
+#[cfg(all(test, feature = "audit"))]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::{Field, ScalarEngine};
+    use sapling_crypto::bellman::SynthesisError;
+
+    use hash::circuit::CircuitHasher;
+    use hash::hashes::{Mimc, Poseidon};
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    /// Hashes `1` and `2`, then audits the output, against `hash2(1, 1)` if `diverge`.
+    pub struct Diverge<H> {
+        pub hasher: H,
+        pub diverge: bool,
+    }
+
+    impl<H: CircuitHasher<E = Bn256> + Hasher<F = <Bn256 as ScalarEngine>::Fr>> Circuit<Bn256>
+        for Diverge<H>
+    {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let a = AllocatedNum::alloc(cs.namespace(|| "a"), || Ok(usize_to_f(1)))?;
+            let b = AllocatedNum::alloc(cs.namespace(|| "b"), || Ok(usize_to_f(2)))?;
+            let out = self
+                .hasher
+                .allocate_hash2(cs.namespace(|| "hash"), &a, &b)?;
+            let mut cs = cs.namespace(|| "outer");
+            let b = if self.diverge { a.clone() } else { b };
+            check_hash2(&mut cs, &self.hasher, &a, &b, &out);
+            Ok(())
+        }
+    }
+
+    circuit_tests! {
+        audit_poseidon_agrees: (Diverge { hasher: Poseidon::<Bn256>::default(), diverge: false }, true),
+        audit_poseidon_diverges: (Diverge { hasher: Poseidon::<Bn256>::default(), diverge: true }, false),
+        audit_mimc_agrees: (Diverge { hasher: Mimc::<Bn256>::default(), diverge: false }, true),
+    }
+
+    #[test]
+    fn divergence_is_named() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        Diverge {
+            hasher: Poseidon::<Bn256>::default(),
+            diverge: true,
+        }
+        .synthesize(&mut cs)
+        .unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("outer/audit: hash2"));
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let one = <Bn256 as ScalarEngine>::Fr::one();
+        check(&mut cs, "value", Some(one), || None);
+        assert!(cs.is_satisfied());
+    }
+}
@@ -0,0 +1,199 @@
+//! A prover-side cache of per-item witness artifacts, shared across batches.
+//!
+//! Hot items (say, the accounts touched by every rollup batch) would otherwise be re-hashed from
+//! scratch each batch: their base hashes (which are also Merkle leaves), the integers they map to
+//! in RSA sets, and the Pocklington certificates of challenge transcripts which recur. A cache
+//! must only ever be used with one hasher, since entries are keyed by the items, not the hasher.
+//!
+//! Each table is cleared when it exceeds the capacity, which keeps memory bounded without the
+//! bookkeeping of a proper LRU.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::PrimeField;
+
+use super::division_intractable::Expansion;
+use super::pocklington::helper::{self as pocklington, PocklingtonCertificate};
+use super::{HashDomain, Hasher};
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The parameters which, with the base hash, determine an item's RSA set element.
+type ElementKey<R> = (R, Integer, usize, usize, Expansion);
+
+pub struct WitnessCache<F: PrimeField> {
+    capacity: usize,
+    item_hashes: Mutex<BTreeMap<Vec<F::Repr>, F>>,
+    elements: Mutex<BTreeMap<ElementKey<F::Repr>, Integer>>,
+    certificates: Mutex<BTreeMap<(F::Repr, usize), PocklingtonCertificate>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+fn get_or_insert<K: Ord, V: Clone>(
+    table: &Mutex<BTreeMap<K, V>>,
+    capacity: usize,
+    hits: &AtomicUsize,
+    misses: &AtomicUsize,
+    key: K,
+    compute: impl FnOnce() -> V,
+) -> V {
+    if let Some(v) = table.lock().unwrap().get(&key) {
+        hits.fetch_add(1, Ordering::Relaxed);
+        return v.clone();
+    }
+    misses.fetch_add(1, Ordering::Relaxed);
+    // Computed without the lock, so that other threads are not blocked on the hash.
+    let v = compute();
+    let mut table = table.lock().unwrap();
+    if table.len() >= capacity {
+        table.clear();
+    }
+    table.insert(key, v.clone());
+    v
+}
+
+impl<F: PrimeField> WitnessCache<F> {
+    /// A cache holding at most `capacity` entries of each kind.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            item_hashes: Mutex::new(BTreeMap::new()),
+            elements: Mutex::new(BTreeMap::new()),
+            certificates: Mutex::new(BTreeMap::new()),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// `hasher.hash(item)`.
+    pub fn hash_item<H: Hasher<F = F>>(&self, hasher: &H, item: &[F]) -> F {
+        let key = item.iter().map(PrimeField::into_repr).collect();
+        get_or_insert(
+            &self.item_hashes,
+            self.capacity,
+            &self.hits,
+            &self.misses,
+            key,
+            || hasher.hash(item),
+        )
+    }
+
+    /// The element `item` is represented by in an RSA set: `di::helper::di_hash_with`.
+    pub fn element<H: Hasher<F = F>>(
+        &self,
+        hasher: &H,
+        item: &[F],
+        offset: &Integer,
+        domain: &HashDomain,
+        expansion: &Expansion,
+    ) -> Integer {
+        let hash = self.hash_item(hasher, item);
+        let key = (
+            hash.into_repr(),
+            offset.clone(),
+            domain.n_bits,
+            domain.n_trailing_ones,
+            *expansion,
+        );
+        get_or_insert(
+            &self.elements,
+            self.capacity,
+            &self.hits,
+            &self.misses,
+            key,
+            || expansion.expand(hasher, &hash) + offset,
+        )
+    }
+
+    /// The certificate `pocklington::helper::hash_to_pocklington_prime` finds for `inputs`.
+    pub fn certificate<H: Hasher<F = F>>(
+        &self,
+        hasher: &H,
+        inputs: &[F],
+        entropy: usize,
+    ) -> Option<PocklingtonCertificate> {
+        let key = (hasher.hash(inputs).into_repr(), entropy);
+        if let Some(c) = self.certificates.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(c.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let cert = pocklington::hash_to_pocklington_prime(inputs, entropy, hasher)?;
+        let mut table = self.certificates.lock().unwrap();
+        if table.len() >= self.capacity {
+            table.clear();
+        }
+        table.insert(key, cert.clone());
+        Some(cert)
+    }
+
+    /// The number of lookups which were, and were not, answered from the cache.
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn clear(&self) {
+        self.item_hashes.lock().unwrap().clear();
+        self.elements.lock().unwrap().clear();
+        self.certificates.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::division_intractable::helper::{di_hash, di_hash_with};
+    use hash::division_intractable::offset;
+    use hash::hashes::Poseidon;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    #[test]
+    fn cached_artifacts_match() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let cache = WitnessCache::<F>::new(2);
+        let item = [usize_to_f::<F>(1), usize_to_f(2)];
+        let domain = HashDomain {
+            n_bits: 128,
+            n_trailing_ones: 1,
+        };
+        let off = offset(128);
+        let single = Expansion::single::<F>();
+        let expected = di_hash(&item, &off, &domain, 32, &hasher);
+        assert_eq!(cache.element(&hasher, &item, &off, &domain, &single), expected);
+        assert_eq!(cache.stats(), (0, 2));
+        assert_eq!(cache.element(&hasher, &item, &off, &domain, &single), expected);
+        assert_eq!(cache.stats(), (2, 2));
+        // Another expansion of the same hash is another element.
+        let split = Expansion {
+            n_chunks: 2,
+            chunk_bits: 60,
+        };
+        assert_eq!(
+            cache.element(&hasher, &item, &off, &domain, &split),
+            di_hash_with(&item, &off, &domain, 32, &split, &hasher)
+        );
+        assert_eq!(cache.stats(), (3, 3));
+        assert_eq!(cache.hash_item(&hasher, &item), hasher.hash(&item));
+
+        let cert = cache.certificate(&hasher, &item, 128);
+        assert_eq!(
+            cert,
+            pocklington::hash_to_pocklington_prime(&item, 128, &hasher)
+        );
+        assert_eq!(cache.certificate(&hasher, &item, 128), cert);
+        assert_eq!(cache.stats(), (5, 4));
+
+        for i in 3..6 {
+            cache.hash_item(&hasher, &[usize_to_f(i)]);
+        }
+        assert!(cache.item_hashes.lock().unwrap().len() <= 2);
+    }
+}
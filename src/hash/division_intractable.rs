@@ -1,5 +1,5 @@
 use rug::Integer;
-use sapling_crypto::bellman::pairing::ff::PrimeField;
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
 use sapling_crypto::circuit::boolean::Boolean;
@@ -7,11 +7,14 @@ use sapling_crypto::circuit::num::AllocatedNum;
 
 use std::str::FromStr;
 
+use super::audit;
 use super::circuit::{CircuitHasher, MaybeHashed};
+use super::low_k_bits;
 use super::HashDomain;
 use super::Hasher;
 use mp::bignat::BigNat;
 use util::bit::{Bit, Bitvector};
+use util::convert::{f_to_nat, usize_to_f};
 use wesolowski::Reduced;
 
 // Chosen randomly.
@@ -19,14 +22,109 @@ const OFFSET_2048: &str = "30731438344250145947882657666206403727243332864808664
 const OFFSET_512: &str = "12260090376946711734120031891656796026361161089996129826004640183990021905572572824302484514470302046195674460977677239638547760386187660404531883140339307";
 const OFFSET_128: &str = "320302797835264872593630364493262722277";
 
+/// The value of the tag the chunks of an expansion are hashed with, "di-expansion" read as a
+/// big-endian integer.
+const EXPANSION_TAG: &str = "31075652571485467123475115886";
+
+/// The tag chunk `i` of an expansion is hashed with: `EXPANSION_TAG + i`.
+fn chunk_tag<F: PrimeField>(i: usize) -> F {
+    let mut tag = F::from_str(EXPANSION_TAG).unwrap();
+    tag.add_assign(&usize_to_f(i));
+    tag
+}
+
+/// How the hash of the inputs is expanded into the integer which is added to the offset.
+///
+/// The first chunk is the hash `h` itself, and chunk `i > 0` is `hash2(h, EXPANSION_TAG + i)`, so
+/// that the chunks are as independent as the outputs of the hash. The low `chunk_bits` bits of
+/// each are concatenated, least significant first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Expansion {
+    pub n_chunks: usize,
+    /// At most the capacity of the field
+    pub chunk_bits: usize,
+}
+
+impl Expansion {
+    /// The low `CAPACITY` bits of a single hash.
+    pub fn single<F: PrimeField>() -> Self {
+        Self {
+            n_chunks: 1,
+            chunk_bits: F::CAPACITY as usize,
+        }
+    }
+
+    /// The width of the expanded integer.
+    pub fn n_bits(&self) -> usize {
+        self.n_chunks * self.chunk_bits
+    }
+
+    fn check<F: PrimeField>(&self, domain: &HashDomain) -> bool {
+        self.n_chunks > 0
+            && self.chunk_bits > 0
+            && self.chunk_bits <= F::CAPACITY as usize
+            && self.n_bits() < domain.n_bits
+    }
+
+    /// Expands `hash` into an integer of `self.n_bits()` bits.
+    pub fn expand<H: Hasher>(&self, hasher: &H, hash: &H::F) -> Integer {
+        let mut acc = Integer::from(0);
+        for i in 0..self.n_chunks {
+            let chunk = if i == 0 {
+                hash.clone()
+            } else {
+                hasher.hash2(hash.clone(), chunk_tag(i))
+            };
+            acc |= low_k_bits(&f_to_nat(&chunk), self.chunk_bits) << (self.chunk_bits * i) as u32;
+        }
+        acc
+    }
+
+    /// Expands `hash` in-circuit, returning the little-endian bits of the integer. Agrees with
+    /// `expand`.
+    pub fn allocate<E, H, CS>(
+        &self,
+        mut cs: CS,
+        hash: &AllocatedNum<E>,
+        hasher: &H,
+    ) -> Result<Vec<Boolean>, SynthesisError>
+    where
+        E: Engine,
+        H: CircuitHasher<E = E>,
+        CS: ConstraintSystem<E>,
+    {
+        let mut bits = Vec::with_capacity(self.n_bits());
+        for i in 0..self.n_chunks {
+            let chunk = if i == 0 {
+                hash.clone()
+            } else {
+                let tag_value: E::Fr = chunk_tag(i);
+                let tag = AllocatedNum::alloc(cs.namespace(|| format!("tag {}", i)), || {
+                    Ok(tag_value)
+                })?;
+                cs.enforce(
+                    || format!("tag {} is constant", i),
+                    |lc| lc,
+                    |lc| lc,
+                    |lc| lc + tag.get_variable() - (tag_value, CS::one()),
+                );
+                hasher.allocate_hash2(cs.namespace(|| format!("chunk {}", i)), hash, &tag)?
+            };
+            let mut chunk = chunk.into_bits_le_strict(cs.namespace(|| format!("bitify {}", i)))?;
+            chunk.truncate(self.chunk_bits);
+            bits.extend(chunk);
+        }
+        Ok(bits)
+    }
+}
+
 pub mod helper {
     use rug::Integer;
     use sapling_crypto::bellman::pairing::ff::PrimeField;
 
-    use super::super::low_k_bits;
     use super::super::HashDomain;
     use super::super::Hasher;
-    use util::convert::f_to_nat;
+    use super::Expansion;
 
     pub fn di_hash<H: Hasher>(
         inputs: &[H::F],
@@ -35,11 +133,33 @@ pub mod helper {
         limb_width: usize,
         hasher: &H,
     ) -> Integer {
-        let bits_per_hash = H::F::CAPACITY as usize;
+        di_hash_with(
+            inputs,
+            offset,
+            domain,
+            limb_width,
+            &Expansion::single::<H::F>(),
+            hasher,
+        )
+    }
+
+    /// `di_hash`, with the hash expanded according to `expansion`.
+    pub fn di_hash_with<H: Hasher>(
+        inputs: &[H::F],
+        offset: &Integer,
+        domain: &HashDomain,
+        limb_width: usize,
+        expansion: &Expansion,
+        hasher: &H,
+    ) -> Integer {
         assert!(domain.n_bits % limb_width == 0);
+        assert!(
+            expansion.check::<H::F>(domain),
+            "Invalid expansion {:?}",
+            expansion
+        );
         let hash = hasher.hash(inputs);
-        let x = low_k_bits(&f_to_nat(&hash), bits_per_hash);
-        x + offset
+        expansion.expand(hasher, &hash) + offset
     }
 }
 
@@ -72,12 +192,13 @@ pub fn allocate_offset<E: Engine, CS: ConstraintSystem<E>>(
     })
 }
 
-pub fn di_hash<E, H, CS>(
+/// Allocates the expansion of the hash of `input`, as a `BigNat`.
+fn allocate_expanded<E, H, CS>(
     mut cs: CS,
     input: &mut MaybeHashed<E>,
     limb_width: usize,
     domain: &HashDomain,
-    offset: Reduced<E>,
+    expansion: &Expansion,
     hasher: &H,
 ) -> Result<BigNat<E>, SynthesisError>
 where
@@ -85,28 +206,123 @@ where
     H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
     CS: ConstraintSystem<E>,
 {
-    let bits_per_hash = H::F::CAPACITY as usize;
     assert!(domain.n_bits % limb_width == 0);
+    if !expansion.check::<E::Fr>(domain) {
+        eprintln!("Invalid expansion {:?}", expansion);
+        return Err(SynthesisError::Unsatisfiable);
+    }
     let hash: AllocatedNum<E> =
         input.get_hash(|values| hasher.allocate_hash(cs.namespace(|| "inputs"), values))?;
-    let hash_bits = hash.into_bits_le_strict(cs.namespace(|| "bitify"))?;
-    let bits: Vec<Boolean> = hash_bits.into_iter().take(bits_per_hash).collect();
-    let x = BigNat::<E>::recompose(
+    let bits = expansion.allocate(cs.namespace(|| "expand"), &hash, hasher)?;
+    Ok(BigNat::<E>::recompose(
         &Bitvector::from_bits(
             bits.into_iter()
                 .map(|b| Bit::from_sapling::<CS>(b))
                 .collect(),
         ),
         limb_width,
-    );
-    x.add::<CS>(&offset.raw)
+    ))
+}
+
+pub fn di_hash<E, H, CS>(
+    cs: CS,
+    input: &mut MaybeHashed<E>,
+    limb_width: usize,
+    domain: &HashDomain,
+    offset: Reduced<E>,
+    hasher: &H,
+) -> Result<BigNat<E>, SynthesisError>
+where
+    E: Engine,
+    H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    di_hash_with(
+        cs,
+        input,
+        limb_width,
+        domain,
+        &Expansion::single::<E::Fr>(),
+        offset,
+        hasher,
+    )
+}
+
+/// `di_hash`, with the hash expanded according to `expansion`.
+pub fn di_hash_with<E, H, CS>(
+    mut cs: CS,
+    input: &mut MaybeHashed<E>,
+    limb_width: usize,
+    domain: &HashDomain,
+    expansion: &Expansion,
+    offset: Reduced<E>,
+    hasher: &H,
+) -> Result<BigNat<E>, SynthesisError>
+where
+    E: Engine,
+    H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    let x = allocate_expanded(
+        cs.namespace(|| "x"),
+        input,
+        limb_width,
+        domain,
+        expansion,
+        hasher,
+    )?;
+    let res = x.add::<CS>(&offset.raw)?;
+    audit::check(&mut cs, "di_hash", res.value.clone(), || {
+        let inputs = input
+            .values
+            .iter()
+            .map(|i| i.get_value())
+            .collect::<Option<Vec<_>>>()?;
+        Some(helper::di_hash_with(
+            &inputs,
+            offset.raw.value.as_ref()?,
+            domain,
+            limb_width,
+            expansion,
+            hasher,
+        ))
+    });
+    Ok(res)
 }
 
 pub fn modded_di_hash<E, H, CS>(
+    cs: CS,
+    input: &mut MaybeHashed<E>,
+    limb_width: usize,
+    domain: &HashDomain,
+    offset: &Reduced<E>,
+    challenge: &BigNat<E>,
+    hasher: &H,
+) -> Result<Reduced<E>, SynthesisError>
+where
+    E: Engine,
+    H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    modded_di_hash_with(
+        cs,
+        input,
+        limb_width,
+        domain,
+        &Expansion::single::<E::Fr>(),
+        offset,
+        challenge,
+        hasher,
+    )
+}
+
+/// `modded_di_hash`, with the hash expanded according to `expansion`.
+pub fn modded_di_hash_with<E, H, CS>(
     mut cs: CS,
     input: &mut MaybeHashed<E>,
     limb_width: usize,
     domain: &HashDomain,
+    expansion: &Expansion,
     offset: &Reduced<E>,
     challenge: &BigNat<E>,
     hasher: &H,
@@ -116,20 +332,14 @@ where
     H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
     CS: ConstraintSystem<E>,
 {
-    let bits_per_hash = H::F::CAPACITY as usize;
-    assert!(domain.n_bits % limb_width == 0);
-    let hash: AllocatedNum<E> =
-        input.get_hash(|values| hasher.allocate_hash(cs.namespace(|| "inputs"), values))?;
-    let hash_bits = hash.into_bits_le_strict(cs.namespace(|| "bitify"))?;
-    let bits: Vec<Boolean> = hash_bits.into_iter().take(bits_per_hash).collect();
-    let x = BigNat::<E>::recompose(
-        &Bitvector::from_bits(
-            bits.into_iter()
-                .map(|b| Bit::from_sapling::<CS>(b))
-                .collect(),
-        ),
+    let x = allocate_expanded(
+        cs.namespace(|| "x"),
+        input,
         limb_width,
-    );
+        domain,
+        expansion,
+        hasher,
+    )?;
     let r = x
         .add::<CS>(&offset.reduced)?
         .red_mod(cs.namespace(|| "x % l"), challenge)?;
@@ -273,4 +483,42 @@ mod test {
                     }
         }, true),
     }
+
+    #[test]
+    fn expansion_matches() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let hash = hasher.hash(&[F::from_str("7").unwrap()]);
+        let expansion = Expansion {
+            n_chunks: 3,
+            chunk_bits: 200,
+        };
+        let expected = expansion.expand(&hasher, &hash);
+        assert!(expected.significant_bits() as usize <= expansion.n_bits());
+        assert_eq!(
+            Expansion::single::<F>().expand(&hasher, &hash),
+            low_k_bits(&f_to_nat(&hash), F::CAPACITY as usize)
+        );
+        // Chunks are not related by the additive structure of the field.
+        let second = low_k_bits(&Integer::from(&expected >> 200), 200);
+        let mut next = hash.clone();
+        next.add_assign(&F::one());
+        assert_ne!(second, low_k_bits(&f_to_nat(&next), 200));
+
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let allocated = AllocatedNum::alloc(cs.namespace(|| "hash"), || Ok(hash)).unwrap();
+        let bits = expansion
+            .allocate(cs.namespace(|| "expand"), &allocated, &hasher)
+            .unwrap();
+        assert_eq!(bits.len(), expansion.n_bits());
+        let mut actual = Integer::from(0);
+        for (i, b) in bits.iter().enumerate() {
+            if b.get_value().unwrap() {
+                actual.set_bit(i as u32, true);
+            }
+        }
+        assert_eq!(actual, expected);
+        assert!(cs.is_satisfied());
+    }
 }
@@ -217,7 +217,7 @@ pub fn hash<E: Engine, CS: ConstraintSystem<E>>(
 #[cfg(test)]
 mod test {
     use super::*;
-    use sapling_crypto::circuit::boolean::Boolean;
+    use util::num::allocated_num::enforce_all_equal;
     use util::test_helpers::*;
 
     pub struct MimcInputs<'a> {
@@ -241,10 +241,7 @@ mod test {
             let allocated_input: AllocatedNum<E> =
                 AllocatedNum::alloc(cs.namespace(|| format!("input")), || Ok(input_value))?;
             let hash = permutation(cs.namespace(|| "hash"), allocated_input)?;
-            let eq =
-                AllocatedNum::equals(cs.namespace(|| "eq"), &hash, &allocated_expected_output)?;
-            Boolean::enforce_equal(cs.namespace(|| "eq2"), &eq, &Boolean::Constant(true))?;
-            Ok(())
+            enforce_all_equal(cs.namespace(|| "eq"), &[hash], &[allocated_expected_output])
         }
     }
 
@@ -15,6 +15,7 @@ use std::default::Default;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
+use super::audit;
 use super::circuit::CircuitHasher;
 use super::Hasher;
 
@@ -179,33 +180,35 @@ impl<E: Engine + Send + Sync> Hasher for Sha256<E> {
 
 impl<E> CircuitHasher for Poseidon<E>
 where
-    E: PoseidonEngine<SBox = QuinticSBox<E>>,
+    E: PoseidonEngine<SBox = QuinticSBox<E>> + Send + Sync,
 {
     type E = E;
     fn allocate_hash2<CS: ConstraintSystem<E>>(
         &self,
-        cs: CS,
+        mut cs: CS,
         a: &AllocatedNum<Self::E>,
         b: &AllocatedNum<Self::E>,
     ) -> CResult<AllocatedNum<E>> {
         use sapling_crypto::circuit::poseidon_hash::poseidon_hash;
         assert_eq!(self.params.output_len(), 1);
-        Ok(
-            poseidon_hash::<E, CS>(cs, &[a.clone(), b.clone()], &self.params)?
-                .pop()
-                .unwrap(),
-        )
+        let out = poseidon_hash::<E, _>(&mut cs, &[a.clone(), b.clone()], &self.params)?
+            .pop()
+            .unwrap();
+        audit::check_hash2(&mut cs, self, a, b, &out);
+        Ok(out)
     }
     fn allocate_hash<CS: ConstraintSystem<E>>(
         &self,
-        cs: CS,
+        mut cs: CS,
         inputs: &[AllocatedNum<Self::E>],
     ) -> CResult<AllocatedNum<E>> {
         use sapling_crypto::circuit::poseidon_hash::poseidon_hash;
         assert_eq!(self.params.output_len(), 1);
-        Ok(poseidon_hash::<E, CS>(cs, inputs, &self.params)?
+        let out = poseidon_hash::<E, _>(&mut cs, inputs, &self.params)?
             .pop()
-            .unwrap())
+            .unwrap();
+        audit::check_hash(&mut cs, self, inputs, &out);
+        Ok(out)
     }
 }
 
@@ -227,14 +230,16 @@ where
         for (i, in_) in [a, b].iter().enumerate() {
             bits.extend(in_.into_bits_le(cs.namespace(|| format!("bit split {}", i)))?);
         }
-        Ok(pedersen_hash::<E, _>(
+        let out = pedersen_hash::<E, _>(
             cs.namespace(|| "hash"),
             Personalization::NoteCommitment,
             &bits,
             &self.params,
         )?
         .get_x()
-        .clone())
+        .clone();
+        audit::check_hash2(&mut cs, self, a, b, &out);
+        Ok(out)
     }
 }
 
@@ -250,7 +255,9 @@ where
         b: &AllocatedNum<Self::E>,
     ) -> CResult<AllocatedNum<E>> {
         let num = mimc::compression(cs.namespace(|| "hash"), a.clone(), b.clone())?;
-        mimc::allocate_num(cs.namespace(|| "copy"), num)
+        let out = mimc::allocate_num(cs.namespace(|| "copy"), num)?;
+        audit::check_hash2(&mut cs, self, a, b, &out);
+        Ok(out)
     }
 }
 
@@ -261,18 +268,22 @@ where
     type E = E;
     fn allocate_hash2<CS: ConstraintSystem<E>>(
         &self,
-        cs: CS,
+        mut cs: CS,
         a: &AllocatedNum<Self::E>,
         b: &AllocatedNum<Self::E>,
     ) -> CResult<AllocatedNum<E>> {
-        sha::circuit::sha256(cs, &[a.clone(), b.clone()])
+        let out = sha::circuit::sha256(&mut cs, &[a.clone(), b.clone()])?;
+        audit::check_hash2(&mut cs, self, a, b, &out);
+        Ok(out)
     }
     fn allocate_hash<CS: ConstraintSystem<E>>(
         &self,
-        cs: CS,
+        mut cs: CS,
         inputs: &[AllocatedNum<Self::E>],
     ) -> CResult<AllocatedNum<E>> {
-        sha::circuit::sha256(cs, inputs)
+        let out = sha::circuit::sha256(&mut cs, inputs)?;
+        audit::check_hash(&mut cs, self, inputs, &out);
+        Ok(out)
     }
 }
 
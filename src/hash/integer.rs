@@ -5,8 +5,10 @@ use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
 use sapling_crypto::circuit::boolean::Boolean;
 use sapling_crypto::circuit::num::AllocatedNum;
 
+use super::audit;
 use super::circuit::CircuitHasher;
 use super::HashDomain;
+use super::Hasher;
 use mp::bignat::BigNat;
 use util::num::Num;
 use OptionExt;
@@ -19,13 +21,14 @@ pub mod helper {
     use super::super::low_k_bits;
     use super::super::HashDomain;
     use super::super::Hasher;
+    use super::SAMPLE_SLACK_BITS;
     use util::convert::f_to_nat;
 
-    pub fn hash_to_integer<H: Hasher>(inputs: &[H::F], domain: &HashDomain, hasher: &H) -> Integer {
+    /// Hashes `inputs` to an `n_bits`-bit integer: the low bits of the hash, followed by those of
+    /// the hash plus one, plus two, and so on.
+    pub fn hash_to_bits<H: Hasher>(inputs: &[H::F], n_bits: usize, hasher: &H) -> Integer {
         let bits_per_hash = <H::F as PrimeField>::CAPACITY as usize;
-
-        let bits_from_hash = domain.n_bits - 1 - domain.n_trailing_ones;
-        let n_hashes = (bits_from_hash - 1) / bits_per_hash + 1;
+        let n_hashes = (n_bits - 1) / bits_per_hash + 1;
 
         // First we hash the inputs
         let hash = hasher.hash(inputs);
@@ -38,30 +41,48 @@ pub mod helper {
             let low_bits = low_k_bits(&f_to_nat(&perm), bits_per_hash);
             sum_of_hashes += low_bits << (bits_per_hash * i) as u32;
         }
+        low_k_bits(&sum_of_hashes, n_bits)
+    }
+
+    pub fn hash_to_integer<H: Hasher>(inputs: &[H::F], domain: &HashDomain, hasher: &H) -> Integer {
+        let bits_from_hash = domain.n_bits - 1 - domain.n_trailing_ones;
 
         // Now we assemble the 1024b number. Notice the ORs are all disjoint.
         let mut acc = (Integer::from(1) << domain.n_trailing_ones as u32) - Integer::from(1usize);
-        acc |= low_k_bits(&sum_of_hashes, bits_from_hash) << domain.n_trailing_ones as u32;
+        acc |= hash_to_bits(inputs, bits_from_hash, hasher) << domain.n_trailing_ones as u32;
         acc |= Integer::from(1) << (domain.n_bits - 1) as u32;
         acc
     }
+
+    /// Hashes `inputs` to a number below `bound`, which has at most `bound_bits` bits.
+    pub fn hash_to_range<H: Hasher>(
+        inputs: &[H::F],
+        bound: &Integer,
+        bound_bits: usize,
+        hasher: &H,
+    ) -> Integer {
+        hash_to_bits(inputs, bound_bits + SAMPLE_SLACK_BITS, hasher) % bound
+    }
 }
 
-pub fn hash_to_integer<E, H, CS>(
+/// How many more bits than the bound `hash_to_range` reduces: the statistical distance of its
+/// output from uniform is below `2^-SAMPLE_SLACK_BITS`.
+pub const SAMPLE_SLACK_BITS: usize = 128;
+
+/// Hashes `input` to `n_bits` bits, low-order first, as `helper::hash_to_bits` does.
+pub fn hash_to_bits<E, H, CS>(
     mut cs: CS,
     input: &[AllocatedNum<E>],
-    limb_width: usize,
-    domain: &HashDomain,
+    n_bits: usize,
     hasher: &H,
-) -> Result<BigNat<E>, SynthesisError>
+) -> Result<Vec<Boolean>, SynthesisError>
 where
     E: Engine,
     H: CircuitHasher<E = E>,
     CS: ConstraintSystem<E>,
 {
     let bits_per_hash = E::Fr::CAPACITY as usize;
-    let bits_from_hash = domain.n_bits - 1 - domain.n_trailing_ones;
-    let n_hashes = (bits_from_hash - 1) / bits_per_hash + 1;
+    let n_hashes = (n_bits - 1) / bits_per_hash + 1;
 
     // First we hash the inputs, with poseidon
     let hash = hasher.allocate_hash(cs.namespace(|| "inputs"), &input)?;
@@ -93,14 +114,17 @@ where
         };
         hash_bits.extend(low_bits);
     }
+    hash_bits.truncate(n_bits);
+    Ok(hash_bits)
+}
 
-    let mut all_bits = Vec::new();
-    all_bits.extend(std::iter::repeat(Boolean::Constant(true)).take(domain.n_trailing_ones));
-    all_bits.extend(hash_bits.into_iter().take(bits_from_hash));
-    all_bits.push(Boolean::Constant(true));
-    let nat = BigNat::from_limbs(
-        all_bits
-            .into_iter()
+/// Packs bits, low-order first, into a `BigNat` with limbs of `limb_width`.
+fn bits_to_nat<E: Engine, CS: ConstraintSystem<E>>(
+    bits: Vec<Boolean>,
+    limb_width: usize,
+) -> BigNat<E> {
+    BigNat::from_limbs(
+        bits.into_iter()
             .map(|bit| {
                 let lc = bit.lc(CS::one(), E::Fr::one());
                 let val = bit
@@ -110,6 +134,110 @@ where
             })
             .collect(),
         1,
-    );
-    Ok(nat.group_limbs(limb_width))
+    )
+    .group_limbs(limb_width)
+}
+
+pub fn hash_to_integer<E, H, CS>(
+    mut cs: CS,
+    input: &[AllocatedNum<E>],
+    limb_width: usize,
+    domain: &HashDomain,
+    hasher: &H,
+) -> Result<BigNat<E>, SynthesisError>
+where
+    E: Engine,
+    H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    let bits_from_hash = domain.n_bits - 1 - domain.n_trailing_ones;
+    let hash_bits = hash_to_bits(&mut cs, input, bits_from_hash, hasher)?;
+
+    let mut all_bits = Vec::new();
+    all_bits.extend(std::iter::repeat(Boolean::Constant(true)).take(domain.n_trailing_ones));
+    all_bits.extend(hash_bits);
+    all_bits.push(Boolean::Constant(true));
+    let nat = bits_to_nat::<E, CS>(all_bits, limb_width);
+    audit::check(&mut cs, "hash_to_integer", nat.value.clone(), || {
+        let inputs = input
+            .iter()
+            .map(|i| i.get_value())
+            .collect::<Option<Vec<_>>>()?;
+        Some(helper::hash_to_integer(&inputs, domain, hasher))
+    });
+    Ok(nat)
+}
+
+/// Hashes `input` to a number below `bound`, which should have at most `bound_bits` bits.
+///
+/// Rather than rejecting out-of-range samples, this reduces `bound_bits + SAMPLE_SLACK_BITS`
+/// hashed bits modulo `bound`, so the output is within `2^-SAMPLE_SLACK_BITS` of uniform, and the
+/// cost is fixed: one reduction and one comparison with `bound`. A `bound` with more bits is
+/// still respected, but the output is then biased. Agrees with `helper::hash_to_range`.
+pub fn hash_to_range<E, H, CS>(
+    mut cs: CS,
+    input: &[AllocatedNum<E>],
+    bound: &BigNat<E>,
+    bound_bits: usize,
+    hasher: &H,
+) -> Result<BigNat<E>, SynthesisError>
+where
+    E: Engine,
+    H: CircuitHasher<E = E> + Hasher<F = E::Fr>,
+    CS: ConstraintSystem<E>,
+{
+    let n_bits = bound_bits + SAMPLE_SLACK_BITS;
+    let bits = hash_to_bits(cs.namespace(|| "hash"), input, n_bits, hasher)?;
+    let nat = bits_to_nat::<E, CS>(bits, bound.params.limb_width);
+    let sample = nat.red_mod(cs.namespace(|| "reduce"), bound)?;
+    let in_range = sample.is_less_than(cs.namespace(|| "below bound"), bound)?;
+    Boolean::enforce_equal(
+        cs.namespace(|| "in range"),
+        &in_range,
+        &Boolean::constant(true),
+    )?;
+    audit::check(&mut cs, "hash_to_range", sample.value.clone(), || {
+        let inputs = input
+            .iter()
+            .map(|i| i.get_value())
+            .collect::<Option<Vec<_>>>()?;
+        Some(helper::hash_to_range(
+            &inputs,
+            bound.value.as_ref()?,
+            bound_bits,
+            hasher,
+        ))
+    });
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rug::Integer;
+    use sapling_crypto::bellman::pairing::bn256::Bn256;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::hashes::Poseidon;
+    use util::test_helpers::*;
+
+    #[test]
+    fn hash_to_range_matches_native() {
+        let hasher = Poseidon::<Bn256>::default();
+        let bound = Integer::from(1_000_003);
+        for seed in 0..4u32 {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let value = <Bn256 as ScalarEngine>::Fr::from_str(&seed.to_string()).unwrap();
+            let input = AllocatedNum::alloc(cs.namespace(|| "seed"), || Ok(value)).unwrap();
+            let b = BigNat::alloc_from_nat(cs.namespace(|| "bound"), || Ok(bound.clone()), 32, 1)
+                .unwrap();
+            let sample =
+                hash_to_range(cs.namespace(|| "sample"), &[input], &b, 20, &hasher).unwrap();
+            let expected = helper::hash_to_range(&[value], &bound, 20, &hasher);
+            assert!(expected < bound);
+            assert_eq!(sample.value, Some(expected));
+            assert!(cs.is_satisfied());
+        }
+    }
 }
@@ -0,0 +1,202 @@
+//! Miller-Rabin primality checks in-circuit, with bases chosen by the verifier.
+//!
+//! Writing `n - 1 = d * 2^s` for an odd `d`, `n` passes the round with base `b` if `b^d = 1` or
+//! `b^(d * 2^i) = -1 (mod n)` for some `i < s`. A composite passes a round with a uniformly random
+//! base with probability at most 1/4, so `k` rounds with bases the prover cannot choose, such as
+//! ones derived from a challenge, are sound except with probability `4^-k`. Unlike Pocklington
+//! certificates (`hash::pocklington`), this needs no structure in `n`, but is only statistically
+//! sound, and a round costs a full exponentiation.
+//!
+//! `s` is a witness, bounded by `max_twos`, and each round costs `max_twos` squarings on top of the
+//! exponentiation by `d`. The rounds themselves are `BigNat::miller_rabin_round_with`, which
+//! `BigNat::miller_rabin` also uses, with fixed bases and `s = 1`.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::Field;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+
+use mp::bignat::{nat_to_limbs, BigNat, BigNatParams};
+use util::convert::{f_to_nat, nat_to_f};
+use util::num::Num;
+use OptionExt;
+
+use std::cmp::min;
+
+/// Returns a bit which is set iff `n` passes a Miller-Rabin round with each of `bases`, and
+/// constrains `n - 1` to have at most `max_twos` (and at least one) factors of two.
+///
+/// `n` and the bases must be well-formed. The bit is sound when set: a dishonest prover can only
+/// unset it, so callers checking primality should enforce it.
+pub fn miller_rabin<E, CS>(
+    mut cs: CS,
+    n: &BigNat<E>,
+    bases: &[BigNat<E>],
+    max_twos: usize,
+) -> Result<Boolean, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    if max_twos == 0 {
+        eprintln!("Miller-Rabin needs max_twos of at least 1");
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let limb_width = n.params.limb_width;
+    let n_limbs = n.limbs.len();
+    let twos_value = n
+        .value
+        .as_ref()
+        .map(|n| Integer::from(n - 1u32).find_one(0).unwrap_or(0) as usize);
+
+    // `below[i]` is set iff `i < s`, and `below[0]` is, so that `n` is odd.
+    let mut below = vec![Boolean::constant(true)];
+    for i in 1..max_twos {
+        let bit = Boolean::from(AllocatedBit::alloc(
+            cs.namespace(|| format!("below {}", i)),
+            twos_value.map(|s| i < s),
+        )?);
+        // below[i] * (1 - below[i - 1]) = 0
+        cs.enforce(
+            || format!("monotone {}", i),
+            |lc| lc + &bit.lc(CS::one(), E::Fr::one()),
+            |lc| lc + CS::one() - &below[i - 1].lc(CS::one(), E::Fr::one()),
+            |lc| lc,
+        );
+        below.push(bit);
+    }
+    // s = j iff below[j - 1] is set and below[j] is not, so the limbs of 2^s are linear in them.
+    let pow_limbs = max_twos / limb_width + 1;
+    let mut limbs = vec![LinearCombination::zero(); pow_limbs];
+    for j in 1..=max_twos {
+        let shift = nat_to_f(&(Integer::from(1) << (j % limb_width) as u32)).unwrap();
+        let mut indicator = below[j - 1].lc(CS::one(), shift);
+        if j < max_twos {
+            indicator = indicator - &below[j].lc(CS::one(), shift);
+        }
+        limbs[j / limb_width] = limbs[j / limb_width].clone() + &indicator;
+    }
+    // An `s` past `max_twos` is unsatisfiable, and the witness is then `2^max_twos`.
+    let pow_value = twos_value.map(|s| Integer::from(1) << min(s, max_twos) as u32);
+    let pow = BigNat {
+        limb_values: pow_value
+            .as_ref()
+            .map(|v| nat_to_limbs(v, limb_width, pow_limbs).unwrap()),
+        value: pow_value,
+        limbs,
+        params: BigNatParams::new(limb_width, pow_limbs),
+    };
+
+    let d = BigNat::alloc_from_nat(
+        cs.namespace(|| "d"),
+        || Ok(Integer::from(n.value.grab()? - 1u32) >> *twos_value.grab()? as u32),
+        limb_width,
+        n_limbs,
+    )?;
+    d.assert_well_formed(cs.namespace(|| "d rangecheck"))?;
+    // low limb of d = 2 * half + 1
+    let half = Num::alloc(cs.namespace(|| "half of low limb"), || {
+        let low = f_to_nat(&d.limb_values.grab()?[0]);
+        Ok(nat_to_f(&(low >> 1)).unwrap())
+    })?;
+    half.fits_in_bits(cs.namespace(|| "half rangecheck"), limb_width - 1)?;
+    cs.enforce(
+        || "d is odd",
+        |lc| lc,
+        |lc| lc,
+        |lc| lc + &d.limbs[0] - &half.num - &half.num - CS::one(),
+    );
+    let one = BigNat::one::<CS>(limb_width);
+    d.mult(cs.namespace(|| "d * 2^s"), &pow)?
+        .add::<CS>(&one)?
+        .equal_when_carried_regroup(cs.namespace(|| "n = d * 2^s + 1"), n)?;
+    let n_less_one = BigNat::alloc_from_nat(
+        cs.namespace(|| "n - 1"),
+        || Ok(Integer::from(n.value.grab()? - 1u32)),
+        limb_width,
+        n_limbs,
+    )?;
+    n_less_one.assert_well_formed(cs.namespace(|| "n - 1 rangecheck"))?;
+    n_less_one
+        .add::<CS>(&one)?
+        .equal_when_carried_regroup(cs.namespace(|| "n - 1 + 1 = n"), n)?;
+
+    let mut passes = Boolean::constant(true);
+    for (k, base) in bases.iter().enumerate() {
+        let round = n.miller_rabin_round_with(
+            cs.namespace(|| format!("round {}", k)),
+            base,
+            &n_less_one,
+            &d,
+            &below,
+        )?;
+        passes = Boolean::and(cs.namespace(|| format!("and {}", k)), &passes, &round)?;
+    }
+    Ok(passes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::miller_rabin_prime::helper::miller_rabin_round;
+    use util::test_helpers::*;
+
+    use std::str::FromStr;
+
+    pub struct MillerRabin {
+        n: &'static str,
+        bases: &'static [&'static str],
+        max_twos: usize,
+        passes: bool,
+    }
+
+    impl<E: Engine> Circuit<E> for MillerRabin {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: &Integer| {
+                let n = n.clone();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 16, 2)
+            };
+            let n_value = Integer::from_str(self.n).unwrap();
+            let n = nat(cs, "n", &n_value)?;
+            n.assert_well_formed(cs.namespace(|| "n rangecheck"))?;
+            let mut bases = Vec::new();
+            let mut native = true;
+            for (i, b) in self.bases.iter().enumerate() {
+                let b = Integer::from_str(b).unwrap();
+                native &= miller_rabin_round(&n_value, &b);
+                bases.push(nat(cs, &format!("base {}", i), &b)?);
+            }
+            let passes = miller_rabin(cs.namespace(|| "mr"), &n, &bases, self.max_twos)?;
+            let twos = Integer::from(&n_value - 1u32).find_one(0).unwrap() as usize;
+            if twos <= self.max_twos {
+                assert_eq!(passes.get_value(), Some(native));
+            }
+            Boolean::enforce_equal(
+                cs.namespace(|| "check"),
+                &passes,
+                &Boolean::constant(self.passes),
+            )
+        }
+    }
+
+    circuit_tests! {
+        mr_prime: (MillerRabin {
+            n: "998244353", bases: &["2", "3", "5"], max_twos: 23, passes: true,
+        }, true),
+        mr_carmichael: (MillerRabin {
+            n: "561", bases: &["2"], max_twos: 4, passes: false,
+        }, true),
+        mr_strong_pseudoprime: (MillerRabin {
+            n: "2047", bases: &["2"], max_twos: 3, passes: true,
+        }, true),
+        mr_strong_pseudoprime_caught: (MillerRabin {
+            n: "2047", bases: &["2", "3"], max_twos: 3, passes: false,
+        }, true),
+        mr_carmichael_claimed_prime: (MillerRabin {
+            n: "561", bases: &["2"], max_twos: 4, passes: true,
+        }, false),
+        mr_too_few_twos: (MillerRabin {
+            n: "998244353", bases: &["2"], max_twos: 8, passes: true,
+        }, false),
+    }
+}
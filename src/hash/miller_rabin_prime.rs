@@ -36,7 +36,7 @@ pub mod helper {
     }
 
     /// Returns whether `n` passes a Miller-Rabin check with base `b`.
-    fn miller_rabin_round(n: &Integer, b: &Integer) -> bool {
+    pub fn miller_rabin_round(n: &Integer, b: &Integer) -> bool {
         let n_less_one = Integer::from(n - 1);
         let mut d = Integer::from(n - 1);
         let d_bits = d.to_string_radix(2);
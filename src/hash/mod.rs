@@ -1,17 +1,22 @@
+pub mod audit;
+pub mod cache;
 pub mod division_intractable;
 pub mod hashes;
 pub mod integer;
+pub mod miller_rabin;
 pub mod miller_rabin_prime;
 pub mod pocklington;
+pub mod stats;
 
 use std::clone::Clone;
 
 use rug::Integer;
 use sapling_crypto::bellman::pairing::ff::Field;
 use sapling_crypto::bellman::pairing::ff::PrimeField;
+use serde::{Deserialize, Serialize};
 
 /// A representation of an integer domain to hash to
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HashDomain {
     pub n_bits: usize,
     pub n_trailing_ones: usize,
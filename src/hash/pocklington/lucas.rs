@@ -0,0 +1,335 @@
+//! Hashing to primes certified by Lucas's `n - 1` test, in Proth's form.
+//!
+//! The prime is `n = h * 2^k + 1`, where `h = (1 || r || nonce)` holds the random bits `r` and
+//! `2^k > h`. Lucas's test proves `n` prime given a base whose order modulo `n` is `n - 1`, which
+//! needs every prime factor of `n - 1`. Since `2^k > sqrt(n)`, the factor `2` alone suffices: if
+//! `a^((n - 1) / 2) = -1 (mod n)`, then every prime `p` dividing `n` has `2^k | p - 1`, so
+//! `p > sqrt(n)`, and `n` has no room for a second factor.
+//!
+//! Unlike a Pocklington chain, the certificate is a single exponentiation with no base prime, but
+//! the prime has about twice as many bits as it has entropy. Which is cheaper depends on the
+//! entropy and on what the prime is used for: `estimate_constraints` here and in
+//! `hash::pocklington` compare the certificates alone.
+use sapling_crypto::bellman::pairing::ff::Field;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use super::entropy::EntropySource;
+use super::Challenge;
+use hash::circuit::CircuitHasher;
+use hash::Hasher;
+use mp::bignat::BigNat;
+use util::bit::{Bit, Bitvector};
+use util::gadget::Gadget;
+use OptionExt;
+
+pub mod helper {
+    use rug::Integer;
+    use serde::{Deserialize, Serialize};
+
+    use super::super::entropy::helper::EntropySource;
+    use super::super::entropy::NatTemplate;
+    use super::super::helper::nonce_bits_needed;
+    use hash::miller_rabin_prime::helper::miller_rabin;
+    use hash::Hasher;
+
+    /// The shape of a Lucas prime: `h = (1 || r || nonce)` has `1 + random_bits + nonce_bits`
+    /// bits, and the prime is `h * 2^twos + 1`, for `twos` equal to that width.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct LucasPlan {
+        pub random_bits: usize,
+        pub nonce_bits: usize,
+        pub twos: usize,
+    }
+
+    impl LucasPlan {
+        /// The plan for a prime with `entropy` random bits, with enough nonce bits to find one
+        /// with all but 2**-64 probability.
+        pub fn new(entropy: usize) -> Self {
+            // The nonce bits needed grow with the width, which grows with the nonce bits.
+            let mut nonce_bits = 0;
+            loop {
+                let width = 1 + entropy + nonce_bits;
+                let needed = nonce_bits_needed(2 * width);
+                if needed <= nonce_bits {
+                    return Self {
+                        random_bits: entropy,
+                        nonce_bits,
+                        twos: width,
+                    };
+                }
+                nonce_bits = needed;
+            }
+        }
+
+        /// `h`, the factor of `n - 1` which is not a power of two by construction, for the given
+        /// random bits and nonce.
+        pub fn cofactor(&self, random: &Integer, nonce: u64) -> Integer {
+            assert!(self.nonce_bits <= 64);
+            let leading = Integer::from(1) << self.random_bits as u32;
+            (Integer::from(leading + random) << self.nonce_bits as u32) + nonce
+        }
+
+        pub fn evaluate(&self, random: &Integer, nonce: u64) -> Integer {
+            (self.cofactor(random, nonce) << self.twos as u32) + 1
+        }
+
+        pub fn max_bits(&self) -> usize {
+            1 + self.random_bits + self.nonce_bits + self.twos
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct LucasCertificate {
+        pub plan: LucasPlan,
+        pub random: Integer,
+        pub nonce: u64,
+        /// A base `a` with `a^((n - 1) / 2) = -1 (mod n)`
+        pub base: Integer,
+        pub number: Integer,
+    }
+
+    /// Returns whether `base` proves `number = h * 2^twos + 1` prime.
+    fn certifies(base: &Integer, number: &Integer) -> bool {
+        let n_less_one = Integer::from(number - 1);
+        let half = Integer::from(&n_less_one >> 1);
+        *base > 1 && *base < *number && base.clone().pow_mod(&half, number).unwrap() == n_less_one
+    }
+
+    pub fn hash_to_lucas_prime<H: Hasher>(
+        inputs: &[H::F],
+        entropy: usize,
+        base_hash: &H,
+    ) -> Option<LucasCertificate> {
+        let plan = LucasPlan::new(entropy);
+        let hash = base_hash.hash(inputs);
+        let mut entropy_source = EntropySource::new(hash, entropy);
+        let random = entropy_source.get_bits_as_nat(NatTemplate::with_random_bits(entropy));
+        for nonce in 0..(1u64 << plan.nonce_bits) {
+            let number = plan.evaluate(&random, nonce);
+            if !miller_rabin(&number, 30) {
+                continue;
+            }
+            // Half of the residues are non-residues, which are the bases that work.
+            let mut base = Integer::from(2);
+            while base.jacobi(&number) != -1 {
+                base += 1;
+            }
+            if certifies(&base, &number) {
+                return Some(LucasCertificate {
+                    plan,
+                    random,
+                    nonce,
+                    base,
+                    number,
+                });
+            }
+        }
+        None
+    }
+
+    /// Checks that `cert` is the certificate which `hash_to_lucas_prime` would accept for
+    /// `inputs`.
+    pub fn check_lucas_certificate<H: Hasher>(
+        inputs: &[H::F],
+        entropy: usize,
+        base_hash: &H,
+        cert: &LucasCertificate,
+    ) -> bool {
+        let plan = LucasPlan::new(entropy);
+        let hash = base_hash.hash(inputs);
+        let mut entropy_source = EntropySource::new(hash, entropy);
+        let random = entropy_source.get_bits_as_nat(NatTemplate::with_random_bits(entropy));
+        cert.plan == plan
+            && cert.random == random
+            && cert.nonce < 1u64 << plan.nonce_bits
+            && cert.number == plan.evaluate(&random, cert.nonce)
+            && certifies(&cert.base, &cert.number)
+    }
+}
+
+/// Hashes `input` to a prime with `entropy` random bits, certified by Lucas's test. The base of
+/// the certificate must fit in a limb of `limb_width` bits.
+pub fn hash_to_lucas_prime<
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+>(
+    mut cs: CS,
+    input: &[AllocatedNum<E>],
+    limb_width: usize,
+    entropy: usize,
+    base_hash: &H,
+) -> Result<Challenge<E>, SynthesisError> {
+    let hash = base_hash.allocate_hash(cs.namespace(|| "base hash"), &input)?;
+    let mut entropy_source =
+        EntropySource::alloc(cs.namespace(|| "entropy source"), Some(&()), hash, &entropy)?;
+
+    let plan = helper::LucasPlan::new(entropy);
+    let cert = input
+        .iter()
+        .map(|n| n.get_value())
+        .collect::<Option<Vec<E::Fr>>>()
+        .and_then(|is| helper::hash_to_lucas_prime(&is, entropy, base_hash));
+
+    // The bits of `n - 1`, most significant first.
+    let mut bits = vec![Bit::new_value::<CS>(true)];
+    for _ in 0..plan.random_bits {
+        bits.push(entropy_source.get_bit());
+    }
+    for i in (0..plan.nonce_bits).rev() {
+        let bit = AllocatedBit::alloc(
+            cs.namespace(|| format!("nonce bit {}", i)),
+            cert.as_ref().map(|c| (c.nonce >> i) & 1 == 1),
+        )?;
+        bits.push(Bit::from_sapling::<CS>(Boolean::from(bit)));
+    }
+    let cofactor = {
+        let mut bits = bits.clone();
+        bits.reverse();
+        BigNat::recompose(&Bitvector::from_bits(bits), limb_width)
+    };
+    bits.extend(std::iter::repeat(Bit::new_value::<CS>(false)).take(plan.twos));
+    bits.reverse();
+    let n_less_one = BigNat::recompose(&Bitvector::from_bits(bits), limb_width);
+    let n = n_less_one.shift::<CS>(E::Fr::one());
+
+    let base = BigNat::alloc_from_nat(
+        cs.namespace(|| "base"),
+        || Ok(cert.as_ref().grab()?.base.clone()),
+        limb_width,
+        1,
+    )?;
+    base.assert_well_formed(cs.namespace(|| "base rangecheck"))?;
+    // a^((n - 1) / 2) = (a^h)^(2^(k - 1))
+    let mut power = base.pow_mod(cs.namespace(|| "a^h"), &cofactor, &n)?;
+    for i in 1..plan.twos {
+        power = power
+            .mult_mod(cs.namespace(|| format!("square {}", i)), &power, &n)?
+            .1;
+    }
+    power.equal_when_carried(cs.namespace(|| "a^((n - 1) / 2) = -1"), &n_less_one)?;
+
+    let max_bits = plan.max_bits();
+    Ok(Challenge {
+        nat: n.with_max_bits(max_bits),
+        max_bits,
+        certificate: None,
+        plan: None,
+    })
+}
+
+/// Counts the constraints used by `hash_to_lucas_prime`, as `pocklington::estimate_constraints`
+/// does for Pocklington certificates.
+pub fn estimate_constraints<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
+    n_inputs: usize,
+    limb_width: usize,
+    entropy: usize,
+    base_hash: &H,
+) -> Result<usize, SynthesisError> {
+    use util::bench::ConstraintCounter;
+
+    let mut cs = ConstraintCounter::new();
+    let inputs = (0..n_inputs)
+        .map(|i| {
+            AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || {
+                Err(SynthesisError::AssignmentMissing)
+            })
+        })
+        .collect::<Result<Vec<AllocatedNum<E>>, _>>()?;
+    hash_to_lucas_prime(
+        cs.namespace(|| "hash"),
+        &inputs,
+        limb_width,
+        entropy,
+        base_hash,
+    )?;
+    Ok(cs.num_constraints())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::hashes::Poseidon;
+    use hash::miller_rabin_prime;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    #[test]
+    fn lucas_plan_shape() {
+        let plan = helper::LucasPlan::new(128);
+        assert_eq!(plan.twos, 1 + 128 + plan.nonce_bits);
+        assert_eq!(
+            plan.nonce_bits,
+            super::super::helper::nonce_bits_needed(2 * plan.twos)
+        );
+    }
+
+    #[test]
+    fn lucas_helper_finds_primes() {
+        let hash = Poseidon::<Bn256>::default();
+        for i in 1..5 {
+            let inputs = [usize_to_f::<<Bn256 as ScalarEngine>::Fr>(i)];
+            let cert = helper::hash_to_lucas_prime(&inputs, 128, &hash).unwrap();
+            assert!(miller_rabin_prime::helper::miller_rabin(&cert.number, 20));
+            assert!(cert.number.significant_bits() as usize <= cert.plan.max_bits());
+            assert!(helper::check_lucas_certificate(&inputs, 128, &hash, &cert));
+            let mut forged = cert.clone();
+            forged.nonce += 1;
+            forged.number = forged.plan.evaluate(&forged.random, forged.nonce);
+            assert!(!helper::check_lucas_certificate(&inputs, 128, &hash, &forged));
+        }
+    }
+
+    pub struct LucasHash<H> {
+        inputs: &'static [usize],
+        entropy: usize,
+        hash: H,
+    }
+
+    impl<E, H> Circuit<E> for LucasHash<H>
+    where
+        E: Engine,
+        H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let values: Vec<E::Fr> = self.inputs.iter().map(|i| usize_to_f(*i)).collect();
+            let cert = helper::hash_to_lucas_prime(&values, self.entropy, &self.hash).unwrap();
+            let inputs = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || Ok(*v))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let challenge = hash_to_lucas_prime(
+                cs.namespace(|| "hash"),
+                &inputs,
+                32,
+                self.entropy,
+                &self.hash,
+            )?;
+            assert_eq!(challenge.bit_bound(), cert.plan.max_bits());
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(cert.number.clone()),
+                32,
+                (cert.plan.max_bits() - 1) / 32 + 1,
+            )?;
+            challenge.nat().equal(cs.namespace(|| "eq"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        lucas_hash_30: (LucasHash {
+            inputs: &[1, 2, 3], entropy: 30, hash: Poseidon::<Bn256>::default(),
+        }, true),
+        lucas_hash_128: (LucasHash {
+            inputs: &[1, 2, 3], entropy: 128, hash: Poseidon::<Bn256>::default(),
+        }, true),
+    }
+}
@@ -1,18 +1,22 @@
 mod entropy;
+pub mod lucas;
 
 pub mod helper {
 
     use rug::Integer;
     use sapling_crypto::bellman::pairing::ff::PrimeField;
+    use serde::{Deserialize, Serialize};
 
     use super::entropy::helper::EntropySource;
     use super::entropy::NatTemplate;
+    use super::{lucas, CertificateKind};
     use hash::miller_rabin_prime::helper::miller_rabin_32b;
     use hash::Hasher;
 
     use std::cmp::min;
+    use std::collections::BTreeMap;
 
-    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub struct PocklingtonPlan {
         /// Number of nonce bits in the base prime
         pub base_nonce_bits: usize,
@@ -32,7 +36,7 @@ pub mod helper {
     /// such that `p'` is prime.
     ///
     /// This structure stores the plan: the size of `r` and `n`.
-    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub struct PlannedExtension {
         pub nonce_bits: usize,
         pub random_bits: usize,
@@ -72,42 +76,138 @@ pub mod helper {
         ((trials as f64).log2().ceil() + 0.1) as usize
     }
 
+    /// The cost of an extension of a `prime_bits`-bit prime into a `result_bits`-bit one: the
+    /// checks exponentiate by the extension and then by the prime, and each bit of an exponent
+    /// costs about a modular product, whose cost grows linearly with the width of the result.
+    fn extension_cost(
+        prime_bits: usize,
+        extension: &PlannedExtension,
+        result_bits: usize,
+    ) -> usize {
+        let extension_bits = extension.nonce_bits + extension.random_bits + 1;
+        (extension_bits + prime_bits) * result_bits
+    }
+
     impl PocklingtonPlan {
         /// Given a target entropy, constructs a plan for how to make a prime number of that
         /// bitwidth that can be certified using a recursive Pocklington test.
+        ///
+        /// Each extension is as large as possible, until the entropy is reached. This uses the
+        /// fewest extensions, but the last one is often small. It is the plan of
+        /// `CertificateKind::Pocklington`, which existing challenges are derived with.
         pub fn new(entropy: usize) -> Self {
+            let mut plan = Self::base(entropy);
+            while plan.entropy() < entropy {
+                let (nonce_bits, max_random_bits) = plan.next_extension_bounds();
+                let random_bits = min(entropy - plan.entropy(), max_random_bits);
+                // TODO we might be able to omit a nonce bit if we were to re-compute the nonce
+                // width using `random_bits`.
+                plan.extensions.push(PlannedExtension {
+                    nonce_bits,
+                    random_bits,
+                })
+            }
+            plan
+        }
+
+        /// Constructs the plan for a prime number of `entropy` random bits with the lowest
+        /// `cost`. It is the plan of `CertificateKind::TunedPocklington`.
+        ///
+        /// The plan is a function of `entropy` alone, so provers and verifiers outside the circuit
+        /// derive the same one.
+        pub fn tuned(entropy: usize) -> Self {
+            // Plans are searched in order of entropy, and every extension adds some, so each is
+            // final by the time it is reached. The rest of a plan only depends on the bit widths
+            // of its prime and its number of extensions, so of the plans which agree on those and
+            // their entropy, only the cheapest is kept. Each extension costs nonce bits, so no
+            // plan needs more of them than the greedy one, which bounds the search.
+            let max_extensions = Self::new(entropy).extensions.len();
+            let mut frontier: BTreeMap<_, (usize, Self)> = BTreeMap::new();
+            let base = Self::base(entropy);
+            frontier.insert(base.search_key(), (0, base));
+            let mut best: Option<(usize, Self)> = None;
+            while let Some(key) = frontier.keys().next().cloned() {
+                let (cost, plan) = frontier.remove(&key).unwrap();
+                if plan.entropy() == entropy {
+                    if best.as_ref().map_or(true, |&(c, _)| cost < c) {
+                        best = Some((cost, plan));
+                    }
+                    continue;
+                }
+                if plan.extensions.len() == max_extensions {
+                    continue;
+                }
+                let (nonce_bits, max_random_bits) = plan.next_extension_bounds();
+                let prime_bits = plan.max_bits();
+                for random_bits in 1..=min(entropy - plan.entropy(), max_random_bits) {
+                    let mut next = plan.clone();
+                    let extension = PlannedExtension {
+                        nonce_bits,
+                        random_bits,
+                    };
+                    next.extensions.push(extension.clone());
+                    let next_cost = cost + extension_cost(prime_bits, &extension, next.max_bits());
+                    let next_key = next.search_key();
+                    if frontier.get(&next_key).map_or(true, |&(c, _)| next_cost < c) {
+                        frontier.insert(next_key, (next_cost, next));
+                    }
+                }
+            }
+            best.expect("the greedy plan is always reachable").1
+        }
+
+        /// The base prime of a plan for `entropy` bits, with no extensions.
+        fn base(entropy: usize) -> Self {
             // Less than 31
             // Since we fix both low bits of the base prime to 1, we need an extra nonce bit, since
             // the 2's place bit is artificially fixed.
             let nonce_bits_needed_in_base = nonce_bits_needed(32) + 1;
-            let mut plan = Self {
+            Self {
                 base_nonce_bits: nonce_bits_needed_in_base,
                 // High bit is fixed to 1, so 31 bits for the nonce/random bits.
                 base_random_bits: min(entropy, 31 - nonce_bits_needed_in_base),
                 extensions: Vec::new(),
-            };
-
-            while plan.entropy() < entropy {
-                // If the extension has this many bits it is guaranteed to be less than the current
-                // base.
-                let max_extension_bits = plan.min_value().significant_bits() as usize - 1;
-                // Now, how many of those need to be nonce.
-                // TODO We could get a tighter bound by using max values, and not bits.
-                let max_nonce_bits_needed = nonce_bits_needed(max_extension_bits + plan.max_bits());
-                assert!(max_nonce_bits_needed < max_extension_bits);
-                let max_random_bits = max_extension_bits - max_nonce_bits_needed - 1;
-                let random_bits = min(entropy - plan.entropy(), max_random_bits);
-                // TODO we might be able to omit a nonce bit if we were to re-compute the nonce
-                // width using `random_bits`.
-                plan.extensions.push(PlannedExtension {
-                    nonce_bits: max_nonce_bits_needed,
-                    random_bits: random_bits,
-                })
-                // TODO these TODOs are worth looking at because we currently require 321 bits for
-                // a 256b nonce, which is just bigger than a multiple of 32.
             }
+        }
 
-            plan
+        /// The number of nonce bits of the next extension, and the most random bits it can have.
+        fn next_extension_bounds(&self) -> (usize, usize) {
+            // If the extension has this many bits it is guaranteed to be less than the current
+            // base.
+            let max_extension_bits = self.min_value().significant_bits() as usize - 1;
+            // Now, how many of those need to be nonce.
+            // TODO We could get a tighter bound by using max values, and not bits.
+            let max_nonce_bits_needed = nonce_bits_needed(max_extension_bits + self.max_bits());
+            assert!(max_nonce_bits_needed < max_extension_bits);
+            (
+                max_nonce_bits_needed,
+                max_extension_bits - max_nonce_bits_needed - 1,
+            )
+        }
+
+        fn search_key(&self) -> (usize, usize, usize, usize) {
+            (
+                self.entropy(),
+                self.extensions.len(),
+                self.min_value().significant_bits() as usize,
+                self.max_bits(),
+            )
+        }
+
+        /// An estimate of the constraints the extensions of this plan cost, in units of roughly
+        /// the constraints of one modular product of one-bit numbers.
+        ///
+        /// The base prime costs the same in every plan, so it is not counted.
+        pub fn cost(&self) -> usize {
+            let mut prime =
+                (Integer::from(1) << (self.base_random_bits + self.base_nonce_bits + 1) as u32) - 1;
+            let mut cost = 0;
+            for extension in &self.extensions {
+                let prime_bits = prime.significant_bits() as usize;
+                prime = prime * extension.max_value() + 1;
+                cost += extension_cost(prime_bits, extension, prime.significant_bits() as usize);
+            }
+            cost
         }
 
         pub fn entropy(&self) -> usize {
@@ -131,9 +231,22 @@ pub mod helper {
         pub fn max_bits(&self) -> usize {
             self.max_value().significant_bits() as usize
         }
+
+        /// The number of primes in the certification chain: the base prime, and one per
+        /// extension.
+        pub fn n_steps(&self) -> usize {
+            self.extensions.len() + 1
+        }
+
+        /// The nonce bit budget of each step of the chain, starting with the base prime.
+        pub fn nonce_bits(&self) -> Vec<usize> {
+            std::iter::once(self.base_nonce_bits)
+                .chain(self.extensions.iter().map(|e| e.nonce_bits))
+                .collect()
+        }
     }
 
-    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub struct PocklingtonExtension {
         pub plan: PlannedExtension,
         pub random: Integer,
@@ -142,7 +255,7 @@ pub mod helper {
         pub result: Integer,
     }
 
-    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub struct PocklingtonCertificate {
         pub base_prime: Integer,
         pub base_nonce: usize,
@@ -218,21 +331,134 @@ pub mod helper {
         entropy: usize,
         base_hash: &H,
     ) -> Option<PocklingtonCertificate> {
-        let plan = PocklingtonPlan::new(entropy);
+        hash_to_pocklington_prime_with_plan(inputs, &PocklingtonPlan::new(entropy), base_hash)
+    }
+
+    /// `hash_to_pocklington_prime`, following `plan` rather than `PocklingtonPlan::new`.
+    pub fn hash_to_pocklington_prime_with_plan<H: Hasher>(
+        inputs: &[H::F],
+        plan: &PocklingtonPlan,
+        base_hash: &H,
+    ) -> Option<PocklingtonCertificate> {
         let inputs: Vec<H::F> = inputs.iter().copied().collect();
         let hash = base_hash.hash(&inputs);
         let mut entropy_source = EntropySource::new(hash, plan.entropy());
-        let mut cert = attempt_pocklington_base(&plan, &mut entropy_source)?;
+        let mut cert = attempt_pocklington_base(plan, &mut entropy_source)?;
         for extension in &plan.extensions {
             cert = attempt_pocklington_extension::<H::F>(cert, extension, &mut entropy_source)?;
         }
         Some(cert)
     }
 
+    /// Checks that `cert` is the certificate which `hash_to_pocklington_prime` would accept for
+    /// `inputs`: its random bits are drawn from the hash of `inputs`, and each step satisfies the
+    /// same primality criteria. This is far cheaper than searching for the certificate.
+    pub fn check_pocklington_certificate<H: Hasher>(
+        inputs: &[H::F],
+        entropy: usize,
+        base_hash: &H,
+        cert: &PocklingtonCertificate,
+    ) -> bool {
+        let plan = PocklingtonPlan::new(entropy);
+        if cert.extensions.len() != plan.extensions.len()
+            || cert.base_nonce as u64 >= 1u64 << plan.base_nonce_bits
+            || cert.base_nonce & 0b11 != 0b11
+        {
+            return false;
+        }
+        let hash = base_hash.hash(inputs);
+        let mut entropy_source = EntropySource::new(hash, plan.entropy());
+        let random = entropy_source.get_bits_as_nat(
+            NatTemplate::with_random_bits(plan.base_random_bits).with_leading_ones(1),
+        );
+        let base = (random << plan.base_nonce_bits as u32) + cert.base_nonce as u64;
+        if base != cert.base_prime || !miller_rabin_32b(&base) {
+            return false;
+        }
+        let mut prime = base;
+        for (planned, ext) in plan.extensions.iter().zip(&cert.extensions) {
+            let random =
+                entropy_source.get_bits_as_nat(NatTemplate::with_random_bits(planned.random_bits));
+            if ext.plan != *planned
+                || ext.random != random
+                || ext.nonce >= 1u64 << planned.nonce_bits
+            {
+                return false;
+            }
+            let extension = planned.evaluate(&random, ext.nonce);
+            let number = Integer::from(&prime * &extension) + 1;
+            if number != ext.result || ext.checking_base <= 1 || ext.checking_base >= number {
+                return false;
+            }
+            let part = ext
+                .checking_base
+                .clone()
+                .pow_mod(&extension, &number)
+                .unwrap();
+            if part.clone().pow_mod(&prime, &number).unwrap() != 1
+                || Integer::from(&part - 1).gcd(&number) != 1
+            {
+                return false;
+            }
+            prime = number;
+        }
+        true
+    }
+
+    /// The prime which `super::hash_to_prime` hashes `inputs` to, certified as `kind` says.
+    pub fn hash_to_prime<H: Hasher>(
+        inputs: &[H::F],
+        entropy: usize,
+        kind: CertificateKind,
+        base_hash: &H,
+    ) -> Option<Integer> {
+        match kind {
+            CertificateKind::Pocklington => {
+                hash_to_pocklington_prime(inputs, entropy, base_hash).map(|c| c.number().clone())
+            }
+            CertificateKind::TunedPocklington => hash_to_pocklington_prime_with_plan(
+                inputs,
+                &PocklingtonPlan::tuned(entropy),
+                base_hash,
+            )
+            .map(|c| c.number().clone()),
+            CertificateKind::Lucas => {
+                lucas::helper::hash_to_lucas_prime(inputs, entropy, base_hash).map(|c| c.number)
+            }
+        }
+    }
+
     #[cfg(test)]
     mod test {
         use super::*;
 
+        #[test]
+        fn tuned_plan_is_valid() {
+            for &entropy in &[29, 30, 50, 80, 128, 256] {
+                let plan = PocklingtonPlan::tuned(entropy);
+                assert_eq!(plan.entropy(), entropy);
+                let mut prefix = PocklingtonPlan::base(entropy);
+                for extension in &plan.extensions {
+                    let (nonce_bits, max_random_bits) = prefix.next_extension_bounds();
+                    assert_eq!(extension.nonce_bits, nonce_bits);
+                    assert!(extension.random_bits <= max_random_bits);
+                    prefix.extensions.push(extension.clone());
+                }
+            }
+        }
+
+        #[test]
+        fn tuned_plan_is_cheaper() {
+            for &entropy in &[29, 30, 50, 80, 128] {
+                let tuned = PocklingtonPlan::tuned(entropy);
+                assert!(tuned.cost() <= PocklingtonPlan::new(entropy).cost());
+            }
+            let tuned = PocklingtonPlan::tuned(256);
+            let greedy = PocklingtonPlan::new(256);
+            assert!(tuned.cost() < greedy.cost());
+            assert!(tuned.max_bits() <= greedy.max_bits());
+        }
+
         #[test]
         fn prime_prob_64b() {
             let p = prime_density(64);
@@ -255,8 +481,10 @@ use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
 use sapling_crypto::circuit::boolean::Boolean;
 use sapling_crypto::circuit::num::AllocatedNum;
+use serde::{Deserialize, Serialize};
 
 use self::entropy::{EntropySource, NatTemplate};
+use hash::cache::WitnessCache;
 use hash::circuit::CircuitHasher;
 use hash::Hasher;
 use mp::bignat::{BigNat, BigNatParams};
@@ -265,31 +493,177 @@ use util::gadget::Gadget;
 use util::num::Num;
 use OptionExt;
 
+/// The wires of a Pocklington certificate, as allocated by `hash_to_pocklington_prime`.
+#[derive(Clone)]
+pub struct CircuitCertificate<E: Engine> {
+    pub base_nonce: BigNat<E>,
+    /// The nonce of each extension
+    pub nonces: Vec<BigNat<E>>,
+    /// The base used to check each extension
+    pub checking_bases: Vec<BigNat<E>>,
+}
+
+/// A prime challenge, together with its bit bound and (optionally) the wires of its certificate.
+///
+/// Set operations take a `Challenge` rather than a `BigNat`, so that an unverified number cannot
+/// be passed as the challenge by accident. The only ways to build one are
+/// `hash_to_pocklington_prime`, and the explicitly unchecked `Challenge::unchecked`.
+#[derive(Clone)]
+pub struct Challenge<E: Engine> {
+    nat: BigNat<E>,
+    max_bits: usize,
+    certificate: Option<CircuitCertificate<E>>,
+    plan: Option<helper::PocklingtonPlan>,
+}
+
+impl<E: Engine> Challenge<E> {
+    /// Wraps `nat` without checking that it is prime. The caller is responsible for the primality
+    /// of `nat`, e.g. because it is a trusted constant.
+    pub fn unchecked(nat: BigNat<E>) -> Self {
+        Self {
+            max_bits: nat.bit_bound(),
+            nat,
+            certificate: None,
+            plan: None,
+        }
+    }
+
+    pub fn nat(&self) -> &BigNat<E> {
+        &self.nat
+    }
+
+    /// An upper bound on the number of bits in the challenge.
+    pub fn bit_bound(&self) -> usize {
+        self.max_bits
+    }
+
+    pub fn certificate(&self) -> Option<&CircuitCertificate<E>> {
+        self.certificate.as_ref()
+    }
+
+    /// The plan the challenge was certified with, which is `helper::PocklingtonPlan::new` (or
+    /// `tuned`, per the certificate kind) of the entropy, so that provers can search for the
+    /// certificate natively.
+    pub fn plan(&self) -> Option<&helper::PocklingtonPlan> {
+        self.plan.as_ref()
+    }
+}
+
+/// How a challenge prime is certified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CertificateKind {
+    /// A chain of Pocklington extensions of a small base prime (`hash_to_pocklington_prime`)
+    Pocklington,
+    /// A Pocklington chain following `PocklingtonPlan::tuned`, whose extensions are sized to
+    /// minimize the estimated cost. It derives different challenges than `Pocklington`, so it
+    /// is for new parameter sets only.
+    TunedPocklington,
+    /// Lucas's test, in Proth's form (`lucas::hash_to_lucas_prime`)
+    Lucas,
+}
+
+impl Default for CertificateKind {
+    fn default() -> Self {
+        CertificateKind::Pocklington
+    }
+}
+
+/// Hashes `input` to a prime with `entropy` random bits, certified as `kind` says. Certificates
+/// of `CertificateKind::Pocklington` are looked up in `cache`, if given.
+pub fn hash_to_prime<
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+>(
+    cs: CS,
+    input: &[AllocatedNum<E>],
+    limb_width: usize,
+    entropy: usize,
+    kind: CertificateKind,
+    base_hash: &H,
+    cache: Option<&WitnessCache<E::Fr>>,
+) -> Result<Challenge<E>, SynthesisError> {
+    match kind {
+        CertificateKind::Pocklington => {
+            hash_to_pocklington_prime_cached(cs, input, limb_width, entropy, base_hash, cache)
+        }
+        CertificateKind::TunedPocklington => hash_to_pocklington_prime_with_plan(
+            cs,
+            input,
+            limb_width,
+            helper::PocklingtonPlan::tuned(entropy),
+            base_hash,
+            None,
+        ),
+        CertificateKind::Lucas => {
+            lucas::hash_to_lucas_prime(cs, input, limb_width, entropy, base_hash)
+        }
+    }
+}
+
 pub fn hash_to_pocklington_prime<
     E: Engine,
     H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
     CS: ConstraintSystem<E>,
 >(
-    mut cs: CS,
+    cs: CS,
     input: &[AllocatedNum<E>],
     limb_width: usize,
     entropy: usize,
     base_hash: &H,
-) -> Result<BigNat<E>, SynthesisError> {
-    use self::helper::{PocklingtonCertificate, PocklingtonPlan};
+) -> Result<Challenge<E>, SynthesisError> {
+    hash_to_pocklington_prime_cached(cs, input, limb_width, entropy, base_hash, None)
+}
 
+/// `hash_to_pocklington_prime`, looking the certificate up in `cache`, if given, rather than
+/// searching for it.
+pub fn hash_to_pocklington_prime_cached<
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+>(
+    cs: CS,
+    input: &[AllocatedNum<E>],
+    limb_width: usize,
+    entropy: usize,
+    base_hash: &H,
+    cache: Option<&WitnessCache<E::Fr>>,
+) -> Result<Challenge<E>, SynthesisError> {
+    let plan = helper::PocklingtonPlan::new(entropy);
+    hash_to_pocklington_prime_with_plan(cs, input, limb_width, plan, base_hash, cache)
+}
+
+/// `hash_to_pocklington_prime_cached`, following `plan`. The cache holds certificates for
+/// `PocklingtonPlan::new`, so it must only be given with that plan.
+fn hash_to_pocklington_prime_with_plan<
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+>(
+    mut cs: CS,
+    input: &[AllocatedNum<E>],
+    limb_width: usize,
+    plan: helper::PocklingtonPlan,
+    base_hash: &H,
+    cache: Option<&WitnessCache<E::Fr>>,
+) -> Result<Challenge<E>, SynthesisError> {
+    use self::helper::PocklingtonCertificate;
+
+    let entropy = plan.entropy();
     // Hash the inputs into an entropy pool.
     let hash = base_hash.allocate_hash(cs.namespace(|| "base hash"), &input)?;
     let mut entropy_source =
         EntropySource::alloc(cs.namespace(|| "entropy source"), Some(&()), hash, &entropy)?;
 
-    // Construct a pocklington plan, and a certificate if we actually have values.
-    let plan = PocklingtonPlan::new(entropy);
+    // Construct a certificate if we actually have values.
     let cert: Option<PocklingtonCertificate> = input
         .iter()
         .map(|n| n.get_value().clone())
         .collect::<Option<Vec<E::Fr>>>()
-        .and_then(|is| helper::hash_to_pocklington_prime(&is, entropy, base_hash));
+        .and_then(|is| match cache {
+            Some(cache) => cache.certificate(base_hash, &is, entropy),
+            None => helper::hash_to_pocklington_prime_with_plan(&is, &plan, base_hash),
+        });
 
     // Allocate the base nonce.
     let base_nonce = BigNat::from_num(
@@ -301,6 +675,7 @@ pub fn hash_to_pocklington_prime<
             limb_width: limb_width,
             max_word: Integer::from(1) << plan.base_nonce_bits as u32,
             min_bits: 0,
+            max_bits: None,
         },
     );
 
@@ -322,8 +697,15 @@ pub fn hash_to_pocklington_prime<
         &Boolean::constant(true),
     )?;
 
+    let max_bits = plan.max_bits();
+    let mut certificate = CircuitCertificate {
+        base_nonce,
+        nonces: Vec::new(),
+        checking_bases: Vec::new(),
+    };
+
     // For each extension...
-    for (i, extension) in plan.extensions.into_iter().enumerate() {
+    for (i, extension) in plan.extensions.iter().enumerate() {
         let mut cs = cs.namespace(|| format!("extension {}", i));
 
         // Allocate the nonce
@@ -338,6 +720,7 @@ pub fn hash_to_pocklington_prime<
                 limb_width: prime.params.limb_width,
                 max_word: Integer::from(1) << extension.nonce_bits as u32,
                 min_bits: 0,
+                max_bits: None,
             },
         );
 
@@ -377,14 +760,73 @@ pub fn hash_to_pocklington_prime<
 
         // NB: The "less than" condition is enforced by the bitwidths and the min-value analysis.
 
+        certificate.nonces.push(nonce);
+        certificate.checking_bases.push(base);
         prime = n;
     }
-    Ok(prime)
+    Ok(Challenge {
+        nat: prime.with_max_bits(max_bits),
+        max_bits,
+        certificate: Some(certificate),
+        plan: Some(plan),
+    })
+}
+
+/// Counts the constraints used by `hash_to_pocklington_prime` to hash `n_inputs` field elements
+/// into a prime with `entropy` bits of entropy.
+///
+/// This only synthesizes the shape of the circuit, so it is fast even for large `entropy`.
+pub fn estimate_constraints<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
+    n_inputs: usize,
+    limb_width: usize,
+    entropy: usize,
+    base_hash: &H,
+) -> Result<usize, SynthesisError> {
+    estimate_prime_constraints(
+        n_inputs,
+        limb_width,
+        entropy,
+        CertificateKind::Pocklington,
+        base_hash,
+    )
+}
+
+/// `estimate_constraints`, for `hash_to_prime` with certificates of kind `kind`.
+pub fn estimate_prime_constraints<E: Engine, H: Hasher<F = E::Fr> + CircuitHasher<E = E>>(
+    n_inputs: usize,
+    limb_width: usize,
+    entropy: usize,
+    kind: CertificateKind,
+    base_hash: &H,
+) -> Result<usize, SynthesisError> {
+    use util::bench::ConstraintCounter;
+
+    let mut cs = ConstraintCounter::new();
+    let inputs = (0..n_inputs)
+        .map(|i| {
+            AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || {
+                Err(SynthesisError::AssignmentMissing)
+            })
+        })
+        .collect::<Result<Vec<AllocatedNum<E>>, _>>()?;
+    hash_to_prime(
+        cs.namespace(|| "hash"),
+        &inputs,
+        limb_width,
+        entropy,
+        kind,
+        base_hash,
+        None,
+    )?;
+    Ok(cs.num_constraints())
 }
 
 #[cfg(test)]
 mod test {
-    use super::{hash_to_pocklington_prime, helper};
+    use super::{
+        estimate_constraints, estimate_prime_constraints, hash_to_pocklington_prime, helper,
+        CertificateKind,
+    };
     use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
     use sapling_crypto::bellman::pairing::Engine;
     use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
@@ -414,6 +856,38 @@ mod test {
         assert_eq!(p.entropy(), 256);
     }
 
+    #[test]
+    fn pocklington_plan_shape() {
+        let p = helper::PocklingtonPlan::new(256);
+        assert_eq!(p.n_steps(), p.extensions.len() + 1);
+        assert_eq!(p.nonce_bits().len(), p.n_steps());
+        assert_eq!(p.nonce_bits()[0], p.base_nonce_bits);
+    }
+
+    #[test]
+    fn pocklington_estimate_grows() {
+        let hash = Poseidon::<Bn256>::default();
+        let small = estimate_constraints::<Bn256, _>(5, 32, 30, &hash).unwrap();
+        let large = estimate_constraints::<Bn256, _>(5, 32, 128, &hash).unwrap();
+        assert!(small > 0);
+        assert!(small < large);
+    }
+
+    #[test]
+    fn tuned_plan_costs_fewer_constraints() {
+        let hash = Poseidon::<Bn256>::default();
+        for &entropy in &[128, 256] {
+            let count = |kind| estimate_prime_constraints::<Bn256, _>(5, 32, entropy, kind, &hash);
+            let greedy = count(CertificateKind::Pocklington).unwrap();
+            let tuned = count(CertificateKind::TunedPocklington).unwrap();
+            println!(
+                "{}-bit challenge: {} constraints greedy, {} tuned",
+                entropy, greedy, tuned
+            );
+            assert!(tuned <= greedy);
+        }
+    }
+
     macro_rules! pocklington_hash_tests {
         ($($name:ident: $value:expr,)*) => {
             $(
@@ -427,6 +901,8 @@ mod test {
                     let hash = Poseidon::<Bn256>::default();
                     let cert = helper::hash_to_pocklington_prime(&input_values, entropy, &hash).expect("pocklington generation failed");
                     assert!(miller_rabin_prime::helper::miller_rabin(cert.number(), 20));
+                    let max_bits = helper::PocklingtonPlan::new(entropy).max_bits();
+                    assert!(cert.number().significant_bits() as usize <= max_bits);
                 }
             )*
         }
@@ -484,13 +960,20 @@ mod test {
                     AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || Ok(value))
                 })
                 .collect::<Result<Vec<_>, _>>()?;
-            let hash = hash_to_pocklington_prime(
+            let challenge = hash_to_pocklington_prime(
                 cs.namespace(|| "hash"),
                 &allocated_inputs,
                 32,
                 self.params.entropy,
                 &self.params.hash,
             )?;
+            assert_eq!(challenge.bit_bound(), plan.max_bits());
+            assert_eq!(challenge.plan(), Some(&plan));
+            assert_eq!(
+                challenge.certificate().unwrap().nonces.len(),
+                plan.extensions.len()
+            );
+            let hash = challenge.nat();
             println!(
                 "Pocklington bits in: [{}, {}]",
                 hash.params.min_bits,
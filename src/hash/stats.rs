@@ -0,0 +1,280 @@
+//! Statistical sanity checks for the hashes to integers.
+//!
+//! These sample many items, hash them, and compare empirical properties of the outputs with what
+//! the `HashDomain` promises: the forced bits are forced, the free bits are set as often as they
+//! would be for a uniformly random field element, and pairs of outputs are coprime about as often
+//! as random integers (`6 / pi^2` of the time). They are meant to catch parameter changes which
+//! silently break the hash, not to certify it.
+//!
+//! The free bits are not all unbiased: each chunk of an expansion is the low bits of a field
+//! element, so its top bits are set less often than half the time.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
+
+use super::division_intractable::{helper::di_hash_with, Expansion};
+use super::integer::helper::hash_to_integer;
+use super::HashDomain;
+use super::Hasher;
+use util::convert::{f_to_nat, usize_to_f};
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// How many standard deviations from the expectation an empirical frequency may stray.
+const N_SIGMAS: f64 = 5.0;
+
+/// Empirical properties of a sample of hash outputs.
+#[derive(Clone, Debug)]
+pub struct HashStats {
+    pub n_samples: usize,
+    /// The fraction of samples with each free bit set, least significant first
+    pub bit_frequencies: Vec<f64>,
+    /// The fraction of pairs of consecutive samples which are coprime
+    pub coprime_fraction: f64,
+    /// The number of samples equal to an earlier one
+    pub n_duplicates: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatsError {
+    /// Sample `index` does not have the forced structure
+    Structure { index: usize },
+    /// A free bit is set with a frequency far from that of a random field element
+    BitBias { bit: usize, frequency: f64 },
+    /// Consecutive samples are coprime with a frequency far from `6 / pi^2`
+    Gcd { coprime_fraction: f64 },
+    /// Two samples collide
+    Duplicate,
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StatsError::Structure { index } => {
+                write!(f, "sample {} does not have the forced bits", index)
+            }
+            StatsError::BitBias { bit, frequency } => {
+                write!(f, "free bit {} is set with frequency {}", bit, frequency)
+            }
+            StatsError::Gcd { coprime_fraction } => write!(
+                f,
+                "consecutive samples are coprime with frequency {}",
+                coprime_fraction
+            ),
+            StatsError::Duplicate => write!(f, "two samples collide"),
+        }
+    }
+}
+
+/// The frequency with which bit `bit` is set in a uniformly random element of `F`.
+pub fn expected_bit_frequency<F: PrimeField>(bit: usize) -> f64 {
+    let mut minus_one = F::zero();
+    minus_one.sub_assign(&F::one());
+    let modulus = f_to_nat(&minus_one) + 1;
+    let period = Integer::from(1) << (bit + 1) as u32;
+    let half = Integer::from(1) << bit as u32;
+    let (n_periods, rem) = modulus.clone().div_rem_floor(period);
+    let tail = Integer::from(&rem - &half).max(Integer::from(0));
+    let n_set = n_periods * half + tail;
+    n_set.to_f64() / modulus.to_f64()
+}
+
+/// The expected frequencies of `n_chunks` chunks of the low `chunk_bits` bits of random field
+/// elements.
+fn expected_chunk_frequencies<F: PrimeField>(n_chunks: usize, chunk_bits: usize) -> Vec<f64> {
+    let chunk: Vec<f64> = (0..chunk_bits).map(expected_bit_frequency::<F>).collect();
+    (0..n_chunks).flat_map(|_| chunk.iter().cloned()).collect()
+}
+
+/// The `i`th sample item: `[i]`.
+fn item<H: Hasher>(i: usize) -> Vec<H::F> {
+    vec![usize_to_f(i)]
+}
+
+/// Computes the statistics of `free`, the free bits of each sample, which are `n_free_bits` wide.
+pub fn analyze(samples: &[Integer], free: &[Integer], n_free_bits: usize) -> HashStats {
+    let n = free.len() as f64;
+    let bit_frequencies = (0..n_free_bits)
+        .map(|b| free.iter().filter(|x| x.get_bit(b as u32)).count() as f64 / n)
+        .collect();
+    let n_coprime = samples
+        .windows(2)
+        .filter(|w| Integer::from(w[0].gcd_ref(&w[1])) == 1)
+        .count();
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    HashStats {
+        n_samples: samples.len(),
+        bit_frequencies,
+        coprime_fraction: n_coprime as f64 / (samples.len().max(2) - 1) as f64,
+        n_duplicates: samples.len() - sorted.len(),
+    }
+}
+
+/// Checks that the free bits in `stats` are set with about the `expected` frequencies. If
+/// `check_gcd` is set, also checks that samples are coprime as often as random integers are.
+pub fn check_stats(stats: &HashStats, expected: &[f64], check_gcd: bool) -> Result<(), StatsError> {
+    let n = stats.n_samples as f64;
+    for (bit, (&frequency, &p)) in stats.bit_frequencies.iter().zip(expected).enumerate() {
+        let bit_tolerance = N_SIGMAS * (p * (1.0 - p) / n).sqrt();
+        if (frequency - p).abs() > bit_tolerance {
+            return Err(StatsError::BitBias { bit, frequency });
+        }
+    }
+    if check_gcd {
+        let p = 6.0 / (PI * PI);
+        let gcd_tolerance = N_SIGMAS * (p * (1.0 - p) / (n - 1.0)).sqrt();
+        if (stats.coprime_fraction - p).abs() > gcd_tolerance {
+            return Err(StatsError::Gcd {
+                coprime_fraction: stats.coprime_fraction,
+            });
+        }
+    }
+    if stats.n_duplicates > 0 {
+        return Err(StatsError::Duplicate);
+    }
+    Ok(())
+}
+
+/// Samples `n_samples` division-intractable hashes and checks them.
+///
+/// Each output must be `offset` plus an `expansion.n_bits()`-bit integer, and must have exactly
+/// `domain.n_bits` bits. The outputs are shifted by a common offset, so their gcds are not those
+/// of random integers, and are not checked.
+pub fn check_di_hash<H: Hasher>(
+    hasher: &H,
+    offset: &Integer,
+    domain: &HashDomain,
+    expansion: &Expansion,
+    n_samples: usize,
+) -> Result<HashStats, StatsError> {
+    let samples: Vec<Integer> = (0..n_samples)
+        .map(|i| {
+            // The limb width does not affect the value.
+            di_hash_with(&item::<H>(i), offset, domain, 1, expansion, hasher)
+        })
+        .collect();
+    let mut free = Vec::with_capacity(n_samples);
+    for (index, s) in samples.iter().enumerate() {
+        let x = Integer::from(s - offset);
+        if x < 0
+            || x.significant_bits() as usize > expansion.n_bits()
+            || s.significant_bits() as usize != domain.n_bits
+        {
+            return Err(StatsError::Structure { index });
+        }
+        free.push(x);
+    }
+    let stats = analyze(&samples, &free, expansion.n_bits());
+    let expected = expected_chunk_frequencies::<H::F>(expansion.n_chunks, expansion.chunk_bits);
+    check_stats(&stats, &expected, false)?;
+    Ok(stats)
+}
+
+/// Samples `n_samples` outputs of `hash_to_integer` and checks them.
+///
+/// Each output must have its top bit (bit `domain.n_bits - 1`) and its low
+/// `domain.n_trailing_ones` bits set, and no higher bits.
+pub fn check_hash_to_integer<H: Hasher>(
+    hasher: &H,
+    domain: &HashDomain,
+    n_samples: usize,
+) -> Result<HashStats, StatsError> {
+    let samples: Vec<Integer> = (0..n_samples)
+        .map(|i| hash_to_integer(&item::<H>(i), domain, hasher))
+        .collect();
+    let n_free_bits = domain.n_bits - 1 - domain.n_trailing_ones;
+    let forced_low = (Integer::from(1) << domain.n_trailing_ones as u32) - 1;
+    let mut free = Vec::with_capacity(n_samples);
+    for (index, s) in samples.iter().enumerate() {
+        if s.significant_bits() as usize != domain.n_bits
+            || Integer::from(s & &forced_low) != forced_low
+        {
+            return Err(StatsError::Structure { index });
+        }
+        let mut x = Integer::from(s >> domain.n_trailing_ones as u32);
+        x.keep_bits_mut(n_free_bits as u32);
+        free.push(x);
+    }
+    let stats = analyze(&samples, &free, n_free_bits);
+    let bits_per_hash = H::F::CAPACITY as usize;
+    let mut expected =
+        expected_chunk_frequencies::<H::F>((n_free_bits - 1) / bits_per_hash + 1, bits_per_hash);
+    expected.truncate(n_free_bits);
+    // The forced low bits make every sample odd, which raises the chance of coprimality.
+    check_stats(&stats, &expected, domain.n_trailing_ones == 0)?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::division_intractable::offset;
+    use hash::hashes::Poseidon;
+    use util::test_helpers::*;
+
+    #[test]
+    fn di_hash_512_looks_random() {
+        let domain = HashDomain {
+            n_bits: 512,
+            n_trailing_ones: 1,
+        };
+        let expansion = Expansion::single::<<Bn256 as ScalarEngine>::Fr>();
+        let stats = check_di_hash(
+            &Poseidon::<Bn256>::default(),
+            &offset(512),
+            &domain,
+            &expansion,
+            1000,
+        )
+        .unwrap();
+        assert_eq!(stats.bit_frequencies.len(), expansion.n_bits());
+    }
+
+    #[test]
+    fn hash_to_integer_looks_random() {
+        let domain = HashDomain {
+            n_bits: 128,
+            n_trailing_ones: 2,
+        };
+        check_hash_to_integer(&Poseidon::<Bn256>::default(), &domain, 1000).unwrap();
+    }
+
+    #[test]
+    fn top_bits_of_field_elements_are_biased() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        assert!((expected_bit_frequency::<F>(0) - 0.5).abs() < 1e-9);
+        let top = expected_bit_frequency::<F>(F::CAPACITY as usize - 1);
+        assert!(top > 0.3 && top < 0.4);
+    }
+
+    #[test]
+    fn biased_bit_is_caught() {
+        let free: Vec<Integer> = (0..1000).map(|i| Integer::from(i) << 1).collect();
+        let samples: Vec<Integer> = free.iter().map(|x| Integer::from(x + 1)).collect();
+        let stats = analyze(&samples, &free, 8);
+        assert_eq!(
+            check_stats(&stats, &[0.5; 8], false),
+            Err(StatsError::BitBias {
+                bit: 0,
+                frequency: 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn shared_factor_is_caught() {
+        // Every sample is even, so no pair is coprime.
+        let samples: Vec<Integer> = (1..1001).map(|i| Integer::from(i) << 1).collect();
+        let stats = analyze(&samples, &samples, 0);
+        assert_eq!(
+            check_stats(&stats, &[], true),
+            Err(StatsError::Gcd {
+                coprime_fraction: 0.0
+            })
+        );
+    }
+}
@@ -5,6 +5,9 @@ extern crate bincode;
 extern crate flate2;
 extern crate fnv;
 extern crate gmp_mpfr_sys;
+extern crate hex;
+#[cfg(feature = "python")]
+extern crate pyo3;
 extern crate rand;
 extern crate rayon;
 extern crate sapling_crypto;
@@ -13,6 +16,7 @@ extern crate test;
 extern crate derivative;
 extern crate rug;
 extern crate serde;
+extern crate serde_json;
 extern crate sha2;
 
 #[cfg(test)]
@@ -23,11 +27,20 @@ extern crate quickcheck_macros;
 
 #[macro_use]
 pub mod util;
+pub mod blind_sig;
+pub mod driver;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod group;
 pub mod hash;
 pub mod mp;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod remote;
 pub mod rollup;
 pub mod set;
+pub mod testvectors;
+pub mod verifier;
 pub mod wesolowski;
 
 use sapling_crypto::bellman::SynthesisError;
@@ -0,0 +1,363 @@
+//! Signed integers, as a sign bit and a `BigNat` magnitude.
+//!
+//! Zero may be represented with either sign, unless `enforce_canonical` rules out the negative
+//! one. Relations between signed values are checked without branching on the signs: since
+//! `x = |x| - 2 * sign * |x|`, a signed sum `sum(xs) = sum(ys)` holds iff
+//! `sum(|xs|) + 2 * sum(sign * |ys|) = sum(|ys|) + 2 * sum(sign * |xs|)`, which has only
+//! non-negative terms, each costing one constraint per limb.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::Field;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+
+use super::bignat::{BigNat, BigNatParams};
+use OptionExt;
+
+use std::cmp::max;
+use std::fmt::{self, Debug, Formatter};
+
+#[derive(Clone)]
+pub struct BigInt<E: Engine> {
+    /// Set iff the value is negative
+    pub sign: Boolean,
+    /// The absolute value, whose limbs are assumed well-formed
+    pub magnitude: BigNat<E>,
+    /// The value (filled at witness-time)
+    pub value: Option<Integer>,
+}
+
+impl<E: Engine> BigInt<E> {
+    /// Allocates a signed integer with a magnitude of `n_limbs` well-formed limbs.
+    pub fn alloc_from_int<CS, F>(
+        mut cs: CS,
+        f: F,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Self, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+        F: FnOnce() -> Result<Integer, SynthesisError>,
+    {
+        let value = f().ok();
+        let sign = AllocatedBit::alloc(
+            cs.namespace(|| "sign"),
+            value.as_ref().map(|v| *v < 0),
+        )?;
+        let magnitude = BigNat::alloc_from_nat(
+            cs.namespace(|| "magnitude"),
+            || Ok(Integer::from(value.grab()?.abs_ref())),
+            limb_width,
+            n_limbs,
+        )?;
+        magnitude.assert_well_formed(cs.namespace(|| "magnitude rangecheck"))?;
+        Ok(Self {
+            sign: Boolean::from(sign),
+            magnitude,
+            value,
+        })
+    }
+
+    /// The non-negative integer `n`, which must be well-formed.
+    pub fn from_nat(n: BigNat<E>) -> Self {
+        Self {
+            sign: Boolean::Constant(false),
+            value: n.value.clone(),
+            magnitude: n,
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        Self {
+            sign: self.sign.not(),
+            magnitude: self.magnitude.clone(),
+            value: self.value.as_ref().map(|v| Integer::from(-v)),
+        }
+    }
+
+    /// `2 * |self|` if `self` is negative, and zero otherwise.
+    fn doubled_negative_part<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let magnitude = &self.magnitude;
+        let sign_value = self.sign.get_value();
+        let limb_values = sign_value.and_then(|negative| {
+            magnitude.limb_values.as_ref().map(|vs| {
+                vs.iter()
+                    .map(|v| {
+                        if negative {
+                            let mut t = *v;
+                            t.double();
+                            t
+                        } else {
+                            E::Fr::zero()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+        let mut limbs = Vec::new();
+        for (i, limb) in magnitude.limbs.iter().enumerate() {
+            let part = cs.alloc(
+                || format!("part {}", i),
+                || Ok(limb_values.grab()?[i]),
+            )?;
+            cs.enforce(
+                || format!("sign * limb {}", i),
+                |_| self.sign.lc(CS::one(), E::Fr::one()),
+                |lc| lc + limb + limb,
+                |lc| lc + part,
+            );
+            limbs.push(LinearCombination::zero() + part);
+        }
+        Ok(BigNat {
+            value: sign_value.and_then(|negative| {
+                magnitude.value.as_ref().map(|m| {
+                    if negative {
+                        Integer::from(m << 1)
+                    } else {
+                        Integer::from(0)
+                    }
+                })
+            }),
+            limb_values,
+            params: BigNatParams {
+                min_bits: 0,
+                max_bits: None,
+                max_word: Integer::from(&magnitude.params.max_word << 1),
+                n_limbs: limbs.len(),
+                limb_width: magnitude.params.limb_width,
+            },
+            limbs,
+        })
+    }
+
+    /// Enforces `sum(left) = sum(right)`. Both sides must be non-empty.
+    pub fn enforce_sum<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        left: &[&Self],
+        right: &[&Self],
+    ) -> Result<(), SynthesisError> {
+        // Each side gets its own magnitudes, and the other's doubled negative parts.
+        let mut sides = Vec::new();
+        for (name, own, other) in [("left", left, right), ("right", right, left)].iter() {
+            let mut cs = cs.namespace(|| *name);
+            let mut acc = own[0].magnitude.clone();
+            for x in &own[1..] {
+                acc = acc.add::<CS>(&x.magnitude)?;
+            }
+            for (i, x) in other.iter().enumerate() {
+                let part = x.doubled_negative_part(cs.namespace(|| format!("part {}", i)))?;
+                acc = acc.add::<CS>(&part)?;
+            }
+            sides.push(acc);
+        }
+        sides[0].equal_when_carried_regroup(cs.namespace(|| "equal"), &sides[1])
+    }
+
+    /// Compute `self + other`.
+    pub fn add<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let sum = BigInt::alloc_from_int(
+            cs.namespace(|| "sum"),
+            || Ok(Integer::from(self.value.grab()? + other.value.grab()?)),
+            self.magnitude.params.limb_width,
+            max(self.magnitude.limbs.len(), other.magnitude.limbs.len()) + 1,
+        )?;
+        Self::enforce_sum(cs.namespace(|| "check"), &[self, other], &[&sum])?;
+        Ok(sum)
+    }
+
+    /// Compute `self - other`.
+    pub fn sub<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let diff = BigInt::alloc_from_int(
+            cs.namespace(|| "diff"),
+            || Ok(Integer::from(self.value.grab()? - other.value.grab()?)),
+            self.magnitude.params.limb_width,
+            max(self.magnitude.limbs.len(), other.magnitude.limbs.len()) + 1,
+        )?;
+        Self::enforce_sum(cs.namespace(|| "check"), &[self], &[other, &diff])?;
+        Ok(diff)
+    }
+
+    /// Compute `self * other`.
+    pub fn mult<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let magnitude = self
+            .magnitude
+            .mult(cs.namespace(|| "magnitude"), &other.magnitude)?;
+        let sign = Boolean::xor(cs.namespace(|| "sign"), &self.sign, &other.sign)?;
+        Ok(Self {
+            sign,
+            magnitude,
+            value: self
+                .value
+                .as_ref()
+                .and_then(|a| other.value.as_ref().map(|b| Integer::from(a * b))),
+        })
+    }
+
+    /// Constrain `self` to be equal to `other`.
+    pub fn equal<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+    ) -> Result<(), SynthesisError> {
+        Self::enforce_sum(cs, &[self], &[other])
+    }
+
+    /// Returns a bit which is set iff `self < other`.
+    pub fn is_less_than<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Boolean, SynthesisError> {
+        let limb_width = self.magnitude.params.limb_width;
+        // gap = other - self - 1, which is non-negative iff self < other
+        let gap = BigInt::alloc_from_int(
+            cs.namespace(|| "gap"),
+            || Ok(Integer::from(other.value.grab()? - self.value.grab()?) - 1),
+            limb_width,
+            max(self.magnitude.limbs.len(), other.magnitude.limbs.len()) + 1,
+        )?;
+        let one = Self::from_nat(BigNat::one::<CS>(limb_width));
+        Self::enforce_sum(cs.namespace(|| "check"), &[other], &[self, &one, &gap])?;
+        // A negative gap must not be a negative zero.
+        gap.enforce_canonical(cs.namespace(|| "canonical gap"))?;
+        Ok(gap.sign.not())
+    }
+
+    /// Enforces that `self` is not a negative zero, so that each value has one representation: a
+    /// negative value must have a non-zero magnitude. The limbs are well-formed, so their sum is
+    /// zero iff they all are.
+    pub fn enforce_canonical<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<(), SynthesisError> {
+        let inverse = cs.alloc(
+            || "inverse",
+            || {
+                let negative = *self.sign.get_value().grab()?;
+                let mut sum = E::Fr::zero();
+                for v in self.magnitude.limb_values.grab()? {
+                    sum.add_assign(v);
+                }
+                Ok(if negative {
+                    sum.inverse().ok_or(SynthesisError::Unsatisfiable)?
+                } else {
+                    E::Fr::zero()
+                })
+            },
+        )?;
+        cs.enforce(
+            || "negative is non-zero",
+            |lc| {
+                self.magnitude
+                    .limbs
+                    .iter()
+                    .fold(lc, |lc, limb| lc + limb)
+            },
+            |lc| lc + inverse,
+            |_| self.sign.lc(CS::one(), E::Fr::one()),
+        );
+        Ok(())
+    }
+}
+
+impl<E: Engine> Debug for BigInt<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("BigInt")
+            .field("sign", &self.sign.get_value())
+            .field("magnitude", &self.magnitude)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::test_helpers::*;
+
+    use std::str::FromStr;
+
+    pub struct SignedOps {
+        a: &'static str,
+        b: &'static str,
+        /// `a + b`, `a - b` and `a * b`
+        expected: [&'static str; 3],
+    }
+
+    impl<E: Engine> Circuit<E> for SignedOps {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let mut int = |name: &str, n: &str, n_limbs: usize| {
+                let n = Integer::from_str(n).unwrap();
+                BigInt::alloc_from_int(cs.namespace(|| name), || Ok(n), 8, n_limbs)
+            };
+            let a = int("a", self.a, 2)?;
+            let b = int("b", self.b, 2)?;
+            let sum = int("sum", self.expected[0], 3)?;
+            let diff = int("diff", self.expected[1], 3)?;
+            let product = int("product", self.expected[2], 4)?;
+            let a_value = a.value.clone().unwrap();
+            let b_value = b.value.clone().unwrap();
+            let lt = a.is_less_than(cs.namespace(|| "a < b"), &b)?;
+            assert_eq!(lt.get_value(), Some(a_value < b_value));
+            a.add(cs.namespace(|| "a + b"), &b)?
+                .equal(cs.namespace(|| "sum check"), &sum)?;
+            a.sub(cs.namespace(|| "a - b"), &b)?
+                .equal(cs.namespace(|| "diff check"), &diff)?;
+            a.mult(cs.namespace(|| "a * b"), &b)?
+                .equal(cs.namespace(|| "product check"), &product)?;
+            a.neg()
+                .neg()
+                .equal(cs.namespace(|| "double negation"), &a)
+        }
+    }
+
+    circuit_tests! {
+        signed_pos_pos: (
+            SignedOps { a: "1000", b: "24", expected: ["1024", "976", "24000"] },
+            true,
+        ),
+        signed_pos_neg: (
+            SignedOps { a: "1000", b: "-24", expected: ["976", "1024", "-24000"] },
+            true,
+        ),
+        signed_neg_pos: (
+            SignedOps { a: "-1000", b: "24", expected: ["-976", "-1024", "-24000"] },
+            true,
+        ),
+        signed_neg_neg: (
+            SignedOps { a: "-1000", b: "-24", expected: ["-1024", "-976", "24000"] },
+            true,
+        ),
+        signed_equal: (
+            SignedOps { a: "-7", b: "-7", expected: ["-14", "0", "49"] },
+            true,
+        ),
+        signed_adjacent: (
+            SignedOps { a: "6", b: "7", expected: ["13", "-1", "42"] },
+            true,
+        ),
+        signed_zero: (
+            SignedOps { a: "0", b: "-5", expected: ["-5", "5", "0"] },
+            true,
+        ),
+        signed_wrong_sign: (
+            SignedOps { a: "1000", b: "-24", expected: ["976", "-1024", "-24000"] },
+            false,
+        ),
+    }
+}
@@ -2,7 +2,8 @@ use rug::Integer;
 use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
-use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::num::AllocatedNum;
 
 use std::borrow::Borrow;
 use std::cmp::{max, min, Ordering};
@@ -10,17 +11,22 @@ use std::convert::From;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::rc::Rc;
 
-use super::exp::optimal_k;
-use super::poly::Polynomial;
+use super::exp::{optimal_k, simultaneous_power};
+use super::poly::{MultStrategy, Polynomial};
 use util::bit::{Bit, Bitvector};
-use util::convert::{f_to_nat, nat_to_f};
-use util::gadget::Gadget;
+use util::convert::{f_to_nat, nat_to_f, pack_limbs, usize_to_f};
+use util::encoding::{BitOrder, Encoding, LimbOrder};
+use util::gadget::{Gadget, InputPacking};
 use util::lazy::LazyCell;
 use util::num::Num;
 use OptionExt;
 
 /// Compute the natural number represented by an array of limbs.
-/// The limbs are assumed to be based the `limb_width` power of 2.
+/// The limbs are assumed to be based the `limb_width` power of 2, least significant first.
+///
+/// Limbs wider than `limb_width` are not an error: the uncarried limbs of a product (see
+/// `BigNat::from_poly`) are summed with their weights. To decode limbs that should be well-formed,
+/// use `limbs_to_nat_with`, which checks their widths.
 pub fn limbs_to_nat<F: PrimeField, B: Borrow<F>, I: DoubleEndedIterator<Item = B>>(
     limbs: I,
     limb_width: usize,
@@ -32,6 +38,28 @@ pub fn limbs_to_nat<F: PrimeField, B: Borrow<F>, I: DoubleEndedIterator<Item = B
     })
 }
 
+/// Checks that limbs of `limb_width` bits are field elements of `F`.
+fn enforce_limb_width_fits<F: PrimeField>(limb_width: usize) -> Result<(), SynthesisError> {
+    if limb_width == 0 || limb_width > F::CAPACITY as usize {
+        eprintln!(
+            "limbs of width {} are not field elements of capacity {}",
+            limb_width,
+            F::CAPACITY
+        );
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    Ok(())
+}
+
+fn usize_gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
 fn int_with_n_ones(n: usize) -> Integer {
     let mut m = Integer::from(1);
     m <<= n as u32;
@@ -40,15 +68,21 @@ fn int_with_n_ones(n: usize) -> Integer {
 }
 
 /// Compute the limbs encoding a natural number.
-/// The limbs are assumed to be based the `limb_width` power of 2.
+/// The limbs are assumed to be based the `limb_width` power of 2, and follow
+/// `Encoding::NATIVE`.
+///
+/// Fails if `nat` is negative or has more than `n_limbs * limb_width` bits, or if limbs of
+/// `limb_width` bits do not fit in `F`. Otherwise `limbs_to_nat_with` (or `limbs_to_nat`) with
+/// the same width recovers `nat`.
 pub fn nat_to_limbs<'a, F: PrimeField>(
     nat: &Integer,
     limb_width: usize,
     n_limbs: usize,
 ) -> Result<Vec<F>, SynthesisError> {
+    enforce_limb_width_fits::<F>(limb_width)?;
     let mask = int_with_n_ones(limb_width);
     let mut nat = nat.clone();
-    if nat.significant_bits() as usize <= n_limbs * limb_width {
+    if nat >= 0 && nat.significant_bits() as usize <= n_limbs * limb_width {
         Ok((0..n_limbs)
             .map(|_| {
                 let r = Integer::from(&nat & &mask);
@@ -65,9 +99,83 @@ pub fn nat_to_limbs<'a, F: PrimeField>(
     }
 }
 
+/// Compute the limbs encoding a natural number under `encoding`. `nat_to_limbs` is this, with
+/// `Encoding::NATIVE`; with `LimbOrder::MostSignificantFirst`, the most significant limb comes
+/// first. The byte order of `encoding` is irrelevant.
+///
+/// Fails as `nat_to_limbs` does; in particular, limbs wider than the capacity of `F` (such as
+/// the 256-bit words of EVM contracts) are rejected. For `nat` that fits, `limbs_to_nat_with`
+/// under the same width and encoding recovers it.
+pub fn nat_to_limbs_with<F: PrimeField>(
+    nat: &Integer,
+    limb_width: usize,
+    n_limbs: usize,
+    encoding: &Encoding,
+) -> Result<Vec<F>, SynthesisError> {
+    enforce_limb_width_fits::<F>(limb_width)?;
+    Ok(encoding
+        .split_nat(nat, limb_width, n_limbs)?
+        .iter()
+        .map(|l| nat_to_f(l).unwrap())
+        .collect())
+}
+
+/// Compute the natural number represented by limbs under `encoding`.
+///
+/// Fails if a limb has more than `limb_width` bits, as limbs of another width would: so the
+/// limbs of `nat_to_limbs_with` decode to the number they encode, and nothing else decodes.
+pub fn limbs_to_nat_with<F: PrimeField>(
+    limbs: &[F],
+    limb_width: usize,
+    encoding: &Encoding,
+) -> Result<Integer, SynthesisError> {
+    enforce_limb_width_fits::<F>(limb_width)?;
+    let limbs: Vec<Integer> = limbs.iter().map(f_to_nat).collect();
+    if let Some((i, limb)) = limbs
+        .iter()
+        .enumerate()
+        .find(|(_, l)| l.significant_bits() as usize > limb_width)
+    {
+        eprintln!("limb {} ({}) is wider than {} bits", i, limb, limb_width);
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    Ok(encoding.join_nat(&limbs, limb_width))
+}
+
+/// The public inputs which `Gadget::inputize_with` exposes for a `BigNat` of `n_limbs` limbs of
+/// `limb_width` bits, carrying `nat`, under `packing`: what a verifier should supply.
+///
+/// Fails if `nat` does not fit, if the limb width of `packing` is not `limb_width`, or if the
+/// packed limbs do not fit in `F`, as synthesis would.
+pub fn public_inputs_for<F: PrimeField>(
+    nat: &Integer,
+    limb_width: usize,
+    n_limbs: usize,
+    packing: InputPacking,
+) -> Result<Vec<F>, SynthesisError> {
+    let limbs = nat_to_limbs::<F>(nat, limb_width, n_limbs)?;
+    match packing {
+        InputPacking::Unpacked => Ok(limbs),
+        InputPacking::Dual { limb_width: w } | InputPacking::Max { limb_width: w } => {
+            let wires_per_input = packing.wires_per_input::<F>();
+            if w != limb_width || wires_per_input * w > F::CAPACITY as usize {
+                eprintln!(
+                    "Cannot pack {} limbs of {} bits into one input, as {:?}",
+                    wires_per_input, limb_width, packing
+                );
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            Ok(pack_limbs(&limbs, limb_width, wires_per_input))
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct BigNatParams {
     pub min_bits: usize,
+    /// If known, a bound on the bit-length of the value. Exponentiation ladders are sized from
+    /// this, rather than from the limbs, and enforce it.
+    pub max_bits: Option<usize>,
     pub max_word: Integer,
     pub limb_width: usize,
     pub n_limbs: usize,
@@ -82,8 +190,99 @@ impl BigNatParams {
             n_limbs,
             limb_width,
             min_bits: 0,
+            max_bits: None,
+        }
+    }
+}
+
+/// Records how a `BigNat` was lowered into a vector of field elements by `as_allocated_nums`:
+/// limb `i` holds bits `[i * limb_width, (i + 1) * limb_width)`, and each limb is at most
+/// `max_word`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LimbSchema {
+    pub limb_width: usize,
+    pub n_limbs: usize,
+    pub max_word: Integer,
+}
+
+impl LimbSchema {
+    /// The field elements that `n` lowers to under this schema, if it is carried.
+    pub fn limbs_of<F: PrimeField>(&self, n: &Integer) -> Result<Vec<F>, SynthesisError> {
+        nat_to_limbs(n, self.limb_width, self.n_limbs)
+    }
+
+    /// The schema of numbers of up to `n_bits` bits in `limb_width`-bit limbs, when they are to be
+    /// multiplied modulo one another. Its `max_word` bounds the limbs of such a product plus a
+    /// remainder, which is what `mult_mod` and `red_mod` carry.
+    ///
+    /// Errors if that bound is too wide to carry in `F`, in which case those gadgets would fail.
+    pub fn for_products<F: PrimeField>(
+        limb_width: usize,
+        n_bits: usize,
+    ) -> Result<Self, SynthesisError> {
+        enforce_limb_width_fits::<F>(limb_width)?;
+        if n_bits == 0 {
+            eprintln!("No limb schema holds numbers of 0 bits");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let schema = Self::products_unchecked(limb_width, n_bits);
+        if !schema.is_carryable::<F>() {
+            eprintln!(
+                "Products of {}-bit numbers in {}-bit limbs are too wide to carry",
+                n_bits, limb_width
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        Ok(schema)
+    }
+
+    /// The schema of `for_products` with the widest limbs, and so the fewest, that `F` allows.
+    pub fn recommend<F: PrimeField>(n_bits: usize) -> Result<Self, SynthesisError> {
+        let widest = (1..F::CAPACITY as usize / 2 + 1)
+            .rev()
+            .find(|&w| n_bits == 0 || Self::products_unchecked(w, n_bits).is_carryable::<F>());
+        match widest {
+            Some(limb_width) => Self::for_products::<F>(limb_width, n_bits),
+            None => {
+                eprintln!("No limb width can multiply {}-bit numbers", n_bits);
+                Err(SynthesisError::Unsatisfiable)
+            }
+        }
+    }
+
+    fn products_unchecked(limb_width: usize, n_bits: usize) -> Self {
+        let n_limbs = (n_bits - 1) / limb_width + 1;
+        let limb_max = (Integer::from(1) << limb_width as u32) - 1;
+        let max_word = Integer::from(n_limbs) * Integer::from(limb_max.square_ref()) + limb_max;
+        LimbSchema {
+            limb_width,
+            n_limbs,
+            max_word,
         }
     }
+
+    /// As in `BigNat::enforce_carryable`.
+    fn is_carryable<F: PrimeField>(&self) -> bool {
+        self.max_word.significant_bits() + 1 <= F::CAPACITY
+    }
+}
+
+/// How `BigNat::pow_mod_with` reduces each product modulo the modulus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reduction {
+    /// The quotient is a free witness, and `a * b = q * m + r` is checked, as in `mult_mod`.
+    Quotient,
+    /// The quotient is Barrett's estimate, computed in-circuit from the top limbs of the product
+    /// and `floor(2^(w * (2n + 2)) / m)`, which is allocated once per exponentiation. Remainders
+    /// are below `3m` until a final reduction. Since R1CS lets the prover supply any quotient,
+    /// this costs more constraints than `Quotient`; it is here for comparison.
+    Barrett,
+    /// Square-and-multiply, one exponent bit at a time, with each intermediate power allocated
+    /// as a hint. A step from `a` to `b` checks `a * a * f = q * m + b` directly (see
+    /// `mulmod_fused`), for `f` the base or one, so a bit costs one range-checked hint and one
+    /// quotient, where `Quotient` range-checks a quotient and a remainder for the square and
+    /// again for the product.
+    Hinted,
 }
 
 /// A representation of a large natural number (a member of {0, 1, 2, ... })
@@ -115,6 +314,53 @@ impl<E: Engine> From<BigNat<E>> for Polynomial<E> {
     }
 }
 
+/// A square root of `x` modulo the odd prime `p`, by Tonelli-Shanks, or `None` if `x` is not a
+/// residue. For a composite `p`, the result is `None` or a valid root.
+fn sqrt_mod_prime(x: &Integer, p: &Integer) -> Option<Integer> {
+    let x = Integer::from(x % p);
+    if x == 0 {
+        return Some(x);
+    }
+    if x.jacobi(p) != 1 {
+        return None;
+    }
+    // p - 1 = q * 2^s
+    let p_minus_one = Integer::from(p - 1u32);
+    let s = p_minus_one.find_one(0)?;
+    let q = Integer::from(&p_minus_one >> s);
+    let mut z = Integer::from(2);
+    while z.jacobi(p) != -1 {
+        z += 1;
+    }
+    let mut c = z.pow_mod(&q, p).ok()?;
+    let mut root = x.clone().pow_mod(&(Integer::from(&q + 1u32) >> 1), p).ok()?;
+    let mut t = x.clone().pow_mod(&q, p).ok()?;
+    let mut m = s;
+    while t != 1 {
+        // The least `i` with `t^(2^i) = 1`
+        let mut i = 0;
+        let mut t_pow = t.clone();
+        while t_pow != 1 {
+            t_pow.square_mut();
+            t_pow %= p;
+            i += 1;
+            if i == m {
+                return None;
+            }
+        }
+        let b = c.pow_mod(&(Integer::from(1) << (m - i - 1)), p).ok()?;
+        root = root * &b % p;
+        c = Integer::from(&b * &b) % p;
+        t = t * &c % p;
+        m = i;
+    }
+    if Integer::from(root.square_ref()) % p == x {
+        Some(root)
+    } else {
+        None
+    }
+}
+
 impl<E: Engine> BigNat<E> {
     /// Allocates a `BigNat` in the circuit with `n_limbs` limbs of width `limb_width` each.
     /// If `max_word` is missing, then it is assumed to be `(2 << limb_width) - 1`.
@@ -167,6 +413,7 @@ impl<E: Engine> BigNat<E> {
             limbs,
             params: BigNatParams {
                 min_bits: 0,
+                max_bits: None,
                 n_limbs,
                 max_word: max_word.unwrap_or_else(|| int_with_n_ones(limb_width)),
                 limb_width,
@@ -187,6 +434,7 @@ impl<E: Engine> BigNat<E> {
         Self {
             params: BigNatParams {
                 min_bits: 0,
+                max_bits: None,
                 n_limbs: limbs.len(),
                 max_word,
                 limb_width,
@@ -273,6 +521,52 @@ impl<E: Engine> BigNat<E> {
         limbs
     }
 
+    /// Allocates a copy of each limb as an `AllocatedNum`, for use with gadgets (e.g. hashes)
+    /// which consume allocated field elements. Returns the limbs and the schema they follow.
+    pub fn as_allocated_nums<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<(Vec<AllocatedNum<E>>, LimbSchema), SynthesisError> {
+        let nums = self
+            .as_limbs::<CS>()
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| n.as_sapling_allocated_num(cs.namespace(|| format!("limb {}", i))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let schema = LimbSchema {
+            limb_width: self.params.limb_width,
+            n_limbs: self.params.n_limbs,
+            max_word: self.params.max_word.clone(),
+        };
+        Ok((nums, schema))
+    }
+
+    /// Allocates `self % p`, where `p` is the order of the scalar field, e.g. to absorb a digest
+    /// into a field-native hash as one element rather than one per limb.
+    ///
+    /// The weighted sum of the limbs is `self` over the integers, so in the field it is already
+    /// `self % p`: the reduction is proven by the single constraint equating the sum with the
+    /// result. The limbs need not be carried.
+    pub fn reduce_to_field<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<AllocatedNum<E>, SynthesisError> {
+        let base = usize_to_f::<E::Fr>(2).pow(&[self.params.limb_width as u64]);
+        let mut coeff = E::Fr::one();
+        let mut lc = LinearCombination::zero();
+        let mut value = self.limb_values.as_ref().map(|_| E::Fr::zero());
+        for (i, limb) in self.limbs.iter().enumerate() {
+            lc = lc + (coeff, limb);
+            if let (Some(v), Some(vs)) = (value.as_mut(), self.limb_values.as_ref()) {
+                let mut term = vs[i];
+                term.mul_assign(&coeff);
+                v.add_assign(&term);
+            }
+            coeff.mul_assign(&base);
+        }
+        Num::new(value, lc).as_sapling_allocated_num(cs.namespace(|| "reduced"))
+    }
+
     pub fn inputize<CS: ConstraintSystem<E>>(&self, mut cs: CS) -> Result<(), SynthesisError> {
         for (i, l) in self.limbs.iter().enumerate() {
             let mut c = cs.namespace(|| format!("limb {}", i));
@@ -282,6 +576,51 @@ impl<E: Engine> BigNat<E> {
         Ok(())
     }
 
+    /// Exposes the limbs as inputs, laid out as `nat_to_limbs_with` lays them out under
+    /// `encoding`. The byte order is irrelevant. `self` must be well-formed if the bit order is
+    /// not `LsbFirst`, since then each limb is decomposed into `limb_width` bits.
+    pub fn inputize_encoded<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        encoding: &Encoding,
+    ) -> Result<(), SynthesisError> {
+        let mut order: Vec<usize> = (0..self.limbs.len()).collect();
+        if encoding.limb_order == LimbOrder::MostSignificantFirst {
+            order.reverse();
+        }
+        for (j, i) in order.into_iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("limb {}", j));
+            let limb_value = self.limb_values.as_ref().map(|vs| vs[i]);
+            let (lc, value) = match encoding.bit_order {
+                BitOrder::LsbFirst => (self.limbs[i].clone(), limb_value),
+                BitOrder::MsbFirst => {
+                    let bits = Num::new(limb_value, self.limbs[i].clone())
+                        .decompose(cs.namespace(|| "bits"), self.params.limb_width)?;
+                    // The first (least significant) bit gets the highest weight.
+                    let mut lc = LinearCombination::zero();
+                    let mut coeff = E::Fr::one();
+                    for bit in bits.bits.iter().rev() {
+                        lc = lc + (coeff, bit);
+                        coeff.double();
+                    }
+                    let value = bits.values.map(|vs| {
+                        vs.iter().fold(E::Fr::zero(), |mut acc, b| {
+                            acc.double();
+                            if *b {
+                                acc.add_assign(&E::Fr::one());
+                            }
+                            acc
+                        })
+                    });
+                    (lc, value)
+                }
+            };
+            let v = cs.alloc_input(|| "alloc", || Ok(*value.grab()?))?;
+            cs.enforce(|| "eq", |lc| lc, |lc| lc, |l| l + v - &lc);
+        }
+        Ok(())
+    }
+
     /// Constrain `self` to be equal to `other`, assuming that they're both properly carried.
     pub fn equal<CS: ConstraintSystem<E>>(
         &self,
@@ -362,6 +701,20 @@ impl<E: Engine> BigNat<E> {
         &self,
         mut cs: CS,
     ) -> Result<(), SynthesisError> {
+        // A constant has nothing to range-check, so it is checked now.
+        if let Some(limbs) = Polynomial::from(self.clone()).constant_coefficients::<CS>() {
+            let max_word = int_with_n_ones(self.params.limb_width);
+            return match limbs.iter().map(f_to_nat).find(|l| *l > max_word) {
+                Some(limb) => {
+                    eprintln!(
+                        "Constant limb {} is wider than {} bits",
+                        limb, self.params.limb_width
+                    );
+                    Err(SynthesisError::Unsatisfiable)
+                }
+                None => Ok(()),
+            };
+        }
         // swap the option and iterator
         let limb_values_split =
             (0..self.limbs.len()).map(|i| self.limb_values.as_ref().map(|vs| vs[i]));
@@ -401,6 +754,42 @@ impl<E: Engine> BigNat<E> {
         Ok(Bitvector { bits, values })
     }
 
+    /// Records that the value has at most `max_bits` bits. This is not checked here: consumers
+    /// which rely on it (e.g. `decompose_bounded`) enforce it.
+    pub fn with_max_bits(mut self, max_bits: usize) -> Self {
+        self.params.max_bits = Some(max_bits);
+        self
+    }
+
+    /// The number of bits needed to hold the value: the tracked bound, if any, or else the width
+    /// of the limbs.
+    pub fn bit_bound(&self) -> usize {
+        let limb_bits = self.params.limb_width * self.params.n_limbs;
+        self.params
+            .max_bits
+            .map(|b| min(b, limb_bits))
+            .unwrap_or(limb_bits)
+    }
+
+    /// Decomposes into `bit_bound()` little-endian bits, constraining the remaining high bits to
+    /// be zero.
+    pub fn decompose_bounded<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<Bitvector<E>, SynthesisError> {
+        let n_bits = self.bit_bound();
+        let bv = self.decompose(cs.namespace(|| "decomp"))?;
+        for (i, bit) in bv.bits.iter().enumerate().skip(n_bits) {
+            cs.enforce(
+                || format!("high bit {} is zero", i),
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + bit,
+            );
+        }
+        Ok(bv.truncate(n_bits))
+    }
+
     pub fn recompose(bv: &Bitvector<E>, limb_width: usize) -> Self {
         let nat = BigNat::from_limbs(
             bv.bits
@@ -419,6 +808,76 @@ impl<E: Engine> BigNat<E> {
         nat.group_limbs(limb_width)
     }
 
+    /// The number with little-endian `bits`, in limbs of `limb_width` bits. The limbs are linear
+    /// combinations of the bits, so this costs no constraints.
+    pub fn from_bits<CS: ConstraintSystem<E>>(bits: &[Boolean], limb_width: usize) -> Self {
+        let mut bits: Vec<Bit<E>> = bits.iter().cloned().map(Bit::from_sapling::<CS>).collect();
+        if bits.is_empty() {
+            bits.push(Bit::new_false::<CS>());
+        }
+        let n_bits = bits.len();
+        BigNat::recompose(&Bitvector::from_bits(bits), limb_width).with_max_bits(n_bits)
+    }
+
+    /// The value of the field element `num`, in limbs of `limb_width` bits.
+    ///
+    /// `num` is decomposed strictly, so the limbs hold its canonical representative, below the
+    /// field modulus, and not that plus the modulus.
+    pub fn from_allocated_num<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        num: &AllocatedNum<E>,
+        limb_width: usize,
+    ) -> Result<Self, SynthesisError> {
+        enforce_limb_width_fits::<E::Fr>(limb_width)?;
+        let bits = num.into_bits_le_strict(cs.namespace(|| "decomp"))?;
+        Ok(Self::from_bits::<CS>(&bits, limb_width))
+    }
+
+    /// The little-endian bits of `self`, `limb_width` per limb, allocated as sapling `Boolean`s
+    /// so that they can feed its gadgets (SHA-256, Pedersen hashes, ...). The limbs are carried
+    /// first if they may be wider than the limb width.
+    pub fn into_bits<CS: ConstraintSystem<E>>(
+        self,
+        mut cs: CS,
+    ) -> Result<Vec<Boolean>, SynthesisError> {
+        let limb_width = self.params.limb_width;
+        let carried = if self.params.max_word >= Integer::from(1) << limb_width as u32 {
+            let carried = BigNat::alloc_from_nat(
+                cs.namespace(|| "carried"),
+                || Ok(self.value.grab()?.clone()),
+                limb_width,
+                (self.value_bits() - 1) / limb_width + 1,
+            )?;
+            carried.equal_when_carried_regroup(cs.namespace(|| "carry check"), &self)?;
+            carried
+        } else {
+            self
+        };
+        let mut bits = Vec::new();
+        for (i, limb) in carried.limbs.iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("limb {}", i));
+            let value = carried.limb_values.as_ref().map(|vs| f_to_nat(&vs[i]));
+            let mut sum = LinearCombination::zero();
+            let mut coeff = E::Fr::one();
+            for j in 0..limb_width {
+                let bit = AllocatedBit::alloc(
+                    cs.namespace(|| format!("bit {}", j)),
+                    value.as_ref().map(|v| v.get_bit(j as u32)),
+                )?;
+                sum = sum + (coeff, bit.get_variable());
+                coeff.double();
+                bits.push(Boolean::from(bit));
+            }
+            cs.enforce(
+                || "recompose",
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + &sum - limb,
+            );
+        }
+        Ok(bits)
+    }
+
     pub fn enforce_full_bits<CS: ConstraintSystem<E>>(
         &mut self,
         mut cs: CS,
@@ -476,6 +935,43 @@ impl<E: Engine> BigNat<E> {
         Ok(())
     }
 
+    /// Enforces that `self` has exactly `n_bits` bits: bit `n_bits - 1` is set and every higher
+    /// bit is clear. Unlike `decompose_bounded`, which only bounds the value from above, this pins
+    /// down its size, so both `min_bits` and `max_bits` become `n_bits`.
+    ///
+    /// The limbs must be carried.
+    pub fn assert_bit_length<CS: ConstraintSystem<E>>(
+        &mut self,
+        mut cs: CS,
+        n_bits: usize,
+    ) -> Result<(), SynthesisError> {
+        let limb_width = self.params.limb_width;
+        if n_bits == 0 || n_bits > limb_width * self.params.n_limbs {
+            eprintln!(
+                "Cannot fix the length of a {}x{}-bit BigNat at {} bits",
+                self.params.n_limbs, limb_width, n_bits
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        if self.params.max_word >= Integer::from(1) << limb_width as u32 {
+            eprintln!("assert_bit_length needs carried limbs");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let bits = self.decompose(cs.namespace(|| "decomp"))?.into_bits();
+        bits[n_bits - 1].constrain_value(cs.namespace(|| "top bit is one"), true);
+        for (i, bit) in bits.iter().enumerate().skip(n_bits) {
+            cs.enforce(
+                || format!("high bit {} is zero", i),
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + bit,
+            );
+        }
+        self.params.min_bits = n_bits;
+        self.params.max_bits = Some(n_bits);
+        Ok(())
+    }
+
     pub fn enforce_limb_width_agreement(
         &self,
         other: &Self,
@@ -517,6 +1013,7 @@ impl<E: Engine> BigNat<E> {
         Ok(Self {
             params: BigNatParams {
                 min_bits,
+                max_bits: None,
                 max_word: max(&self.params.max_word, &other.params.max_word).clone(),
                 n_limbs: self.params.n_limbs + other.params.n_limbs,
                 limb_width,
@@ -546,10 +1043,33 @@ impl<E: Engine> BigNat<E> {
         new
     }
 
+    /// Produces the natural number with the limbs of `self` above the low-order `n_limbs`, which
+    /// is `floor(self / 2^(n_limbs * limb_width))` if `self` is carried.
+    fn drop_limbs(&self, n_limbs: usize) -> Self {
+        let limb_width = self.params.limb_width;
+        let limbs = self.limbs[n_limbs..].to_vec();
+        let limb_values = self.limb_values.as_ref().map(|vs| vs[n_limbs..].to_vec());
+        BigNat {
+            value: limb_values
+                .as_ref()
+                .map(|vs| limbs_to_nat::<E::Fr, _, _>(vs.iter(), limb_width)),
+            params: BigNatParams {
+                min_bits: 0,
+                max_bits: None,
+                max_word: self.params.max_word.clone(),
+                n_limbs: limbs.len(),
+                limb_width,
+            },
+            limb_values,
+            limbs,
+        }
+    }
+
     pub fn from_poly(poly: Polynomial<E>, limb_width: usize, max_word: Integer) -> Self {
         Self {
             params: BigNatParams {
                 min_bits: 0,
+                max_bits: None,
                 max_word,
                 n_limbs: poly.coefficients.len(),
                 limb_width,
@@ -684,7 +1204,94 @@ impl<E: Engine> BigNat<E> {
         )?;
         // Verify that factor is in bounds
         factor.assert_well_formed(cs.namespace(|| "rangecheck"))?;
-        self.verify_mult(cs.namespace(|| "multcheck"), &factor, &other)
+        self.verify_mult(
+            cs.namespace(|| "multcheck"),
+            &factor,
+            &other,
+            MultStrategy::Schoolbook,
+        )
+    }
+
+    /// Compute `self / k`, enforcing that the small constant `k` divides `self`.
+    ///
+    /// `k` times the quotient is a linear combination of the quotient's limbs, so beyond the
+    /// quotient's range check this costs only a carry check.
+    pub fn div_const_exact<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        k: usize,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let quotient = self.alloc_const_quotient(cs.namespace(|| "quotient"), k)?;
+        self.equal_when_carried_regroup(
+            cs.namespace(|| "eq"),
+            &quotient.scale::<CS>(usize_to_f(k)),
+        )?;
+        Ok(quotient)
+    }
+
+    /// Compute `(self / k, self % k)` for a small constant `k`, rounding the quotient down.
+    ///
+    /// The remainder is a single limb, checked to be below `k` with two bit decompositions of the
+    /// width of `k - 1`.
+    pub fn div_const_floor<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        k: usize,
+    ) -> Result<(BigNat<E>, BigNat<E>), SynthesisError> {
+        let quotient = self.alloc_const_quotient(cs.namespace(|| "quotient"), k)?;
+        let remainder = BigNat::alloc_from_nat(
+            cs.namespace(|| "remainder"),
+            || Ok(self.value.grab()?.clone() % Integer::from(k)),
+            self.params.limb_width,
+            1,
+        )?;
+        let r = Num::new(
+            remainder.limb_values.as_ref().map(|vs| vs[0]),
+            remainder.limbs[0].clone(),
+        );
+        let r_bits = Integer::from(k - 1).significant_bits() as usize;
+        if r_bits == 0 {
+            cs.enforce(|| "remainder is zero", |lc| lc, |lc| lc, |lc| lc + &r.num);
+        } else {
+            // r < 2^b and r + 2^b - k < 2^b, so r < k.
+            r.fits_in_bits(cs.namespace(|| "remainder rangecheck"), r_bits)?;
+            let gap = (1usize << r_bits) - k;
+            Num::new(
+                r.value.map(|mut v| {
+                    v.add_assign(&usize_to_f(gap));
+                    v
+                }),
+                r.num.clone() + (usize_to_f(gap), CS::one()),
+            )
+            .fits_in_bits(cs.namespace(|| "remainder below k"), r_bits)?;
+        }
+        let recomposed = quotient.scale::<CS>(usize_to_f(k)).add::<CS>(&remainder)?;
+        self.equal_when_carried_regroup(cs.namespace(|| "eq"), &recomposed)?;
+        Ok((quotient, remainder))
+    }
+
+    /// Allocates and range-checks `floor(self / k)`, for `div_const_exact` and `div_const_floor`.
+    fn alloc_const_quotient<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        k: usize,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let limb_width = self.params.limb_width;
+        if k == 0 || Integer::from(k) >= Integer::from(1) << limb_width as u32 {
+            eprintln!("Cannot divide {}-bit limbs by the constant {}", limb_width, k);
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        self.enforce_carryable("div_const")?;
+        let quotient_bits =
+            (self.value_bits() + 1).saturating_sub(Integer::from(k).significant_bits() as usize);
+        let quotient = BigNat::alloc_from_nat(
+            cs.namespace(|| "quotient"),
+            || Ok(self.value.grab()?.clone() / Integer::from(k)),
+            limb_width,
+            quotient_bits.saturating_sub(1) / limb_width + 1,
+        )?;
+        quotient.assert_well_formed(cs.namespace(|| "rangecheck"))?;
+        Ok(quotient)
     }
 
     pub fn shift<CS: ConstraintSystem<E>>(&self, constant: E::Fr) -> BigNat<E> {
@@ -722,6 +1329,7 @@ impl<E: Engine> BigNat<E> {
         new
     }
 
+    /// Compute `self - other`, enforcing `self >= other`.
     pub fn sub<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
@@ -737,39 +1345,239 @@ impl<E: Engine> BigNat<E> {
             self.params.limb_width,
             self.params.n_limbs,
         )?;
+        // Without this, negative limbs would let `diff` stand for a negative number.
+        diff.assert_well_formed(cs.namespace(|| "diff rangecheck"))?;
         let sum = other.add::<CS>(&diff)?;
         self.equal_when_carried_regroup(cs.namespace(|| "eq"), &sum)?;
         Ok(diff)
     }
 
-    pub fn mult<CS: ConstraintSystem<E>>(
+    /// Compute `self - other` wrapped modulo `2^(limb_width * n_limbs)`, where `n_limbs` is the
+    /// larger limb count, and a bit which is set iff it wrapped (`self < other`).
+    pub fn sub_with_underflow<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
         other: &Self,
-    ) -> Result<BigNat<E>, SynthesisError> {
-        self.enforce_limb_width_agreement(other, "mult")?;
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        self.enforce_limb_width_agreement(other, "sub_with_underflow")?;
+        let limb_width = self.params.limb_width;
+        let n_limbs = max(self.params.n_limbs, other.params.n_limbs);
+        let wrap = Integer::from(1) << (limb_width * n_limbs) as u32;
+        let underflow = AllocatedBit::alloc(
+            cs.namespace(|| "underflow"),
+            self.value
+                .as_ref()
+                .and_then(|a| other.value.as_ref().map(|b| a < b)),
+        )?;
+        let diff = BigNat::alloc_from_nat(
+            cs.namespace(|| "diff"),
+            || {
+                let mut d = self.value.grab()?.clone();
+                d -= other.value.grab()?;
+                if d < 0 {
+                    d += &wrap;
+                }
+                Ok(d)
+            },
+            limb_width,
+            n_limbs,
+        )?;
+        diff.assert_well_formed(cs.namespace(|| "diff rangecheck"))?;
+        // self + underflow * 2^(limb_width * n_limbs) = other + diff
+        let underflow = Boolean::from(underflow);
+        let borrowed = BigNat::wrapped_bit::<CS>(&underflow, limb_width, n_limbs);
+        let left = self.add::<CS>(&borrowed)?;
+        let right = other.add::<CS>(&diff)?.with_n_limbs::<CS>(n_limbs + 1);
+        left.equal_when_carried_regroup(cs.namespace(|| "eq"), &right)?;
+        Ok((underflow, diff))
+    }
 
-        let mut prod = BigNat::alloc_from_nat(
-            cs.namespace(|| "product"),
+    /// Compute `self + other` wrapped modulo `2^(limb_width * n_limbs)`, where `n_limbs` is the
+    /// larger limb count, and a bit which is set iff it wrapped. Both must be carried.
+    pub fn add_with_overflow<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        self.enforce_limb_width_agreement(other, "add_with_overflow")?;
+        let limb_width = self.params.limb_width;
+        let n_limbs = max(self.params.n_limbs, other.params.n_limbs);
+        let wrap = Integer::from(1) << (limb_width * n_limbs) as u32;
+        let overflow = AllocatedBit::alloc(
+            cs.namespace(|| "overflow"),
+            self.value
+                .as_ref()
+                .and_then(|a| other.value.as_ref().map(|b| Integer::from(a + b) >= wrap)),
+        )?;
+        let sum = BigNat::alloc_from_nat(
+            cs.namespace(|| "sum"),
             || {
                 let mut s = self.value.grab()?.clone();
-                s *= other.value.grab()?;
+                s += other.value.grab()?;
+                if s >= wrap {
+                    s -= &wrap;
+                }
                 Ok(s)
             },
-            other.params.limb_width,
-            other.params.n_limbs + self.params.n_limbs,
+            limb_width,
+            n_limbs,
         )?;
-        if self.params.min_bits > 0 && other.params.min_bits > 0 {
-            prod.params.min_bits = self.params.min_bits + other.params.min_bits - 1;
-        }
+        sum.assert_well_formed(cs.namespace(|| "sum rangecheck"))?;
+        // self + other = sum + overflow * 2^(limb_width * n_limbs)
+        let overflow = Boolean::from(overflow);
+        let carried = BigNat::wrapped_bit::<CS>(&overflow, limb_width, n_limbs);
+        let left = self.add::<CS>(other)?.with_n_limbs::<CS>(n_limbs + 1);
+        let right = sum.add::<CS>(&carried)?;
+        left.equal_when_carried_regroup(cs.namespace(|| "eq"), &right)?;
+        Ok((overflow, sum))
+    }
 
-        prod.assert_well_formed(cs.namespace(|| "rangecheck"))?;
+    /// Compute `self * other` wrapped modulo `2^(limb_width * n_limbs)`, where `n_limbs` is the
+    /// larger limb count, and a bit which is set iff it wrapped. Both must be carried.
+    ///
+    /// The full product is range-checked, so the split into low and high limbs is free, and the
+    /// bit costs a comparison of the high limbs with zero.
+    pub fn mult_with_overflow<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        let n_limbs = max(self.params.n_limbs, other.params.n_limbs);
+        let product = self.mult(cs.namespace(|| "product"), other)?;
+        let high = product.drop_limbs(n_limbs);
+        let zero = BigNat::constant::<CS>(
+            &Integer::from(0),
+            self.params.limb_width,
+            high.limbs.len(),
+        )?;
+        let fits = high.is_equal(cs.namespace(|| "high is zero"), &zero)?;
+        let mut low = product.truncate_limbs(n_limbs);
+        low.params.min_bits = 0;
+        Ok((fits.not(), low))
+    }
 
-        // Verify that factor is in bounds
-        self.verify_mult(cs.namespace(|| "multcheck"), &other, &prod)?;
-        Ok(prod)
+    /// Like `add_with_overflow`, but gives `2^(limb_width * n_limbs) - 1` when it would wrap.
+    pub fn saturating_add<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        let (overflow, sum) = self.add_with_overflow(cs.namespace(|| "add"), other)?;
+        let saturated = sum.saturate(cs.namespace(|| "saturate"), &overflow)?;
+        Ok((overflow, saturated))
     }
 
+    /// Like `sub_with_underflow`, but gives zero when it would wrap.
+    pub fn saturating_sub<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        let (underflow, diff) = self.sub_with_underflow(cs.namespace(|| "sub"), other)?;
+        let zero = BigNat::constant::<CS>(&Integer::from(0), diff.params.limb_width, 1)?;
+        let saturated =
+            BigNat::conditionally_select(cs.namespace(|| "saturate"), &diff, &zero, &underflow)?;
+        Ok((underflow, saturated))
+    }
+
+    /// Like `mult_with_overflow`, but gives `2^(limb_width * n_limbs) - 1` when it would wrap.
+    pub fn saturating_mult<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        let (overflow, product) = self.mult_with_overflow(cs.namespace(|| "mult"), other)?;
+        let saturated = product.saturate(cs.namespace(|| "saturate"), &overflow)?;
+        Ok((overflow, saturated))
+    }
+
+    /// Replaces `self` by the largest number of its limbs if `condition`.
+    fn saturate<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        condition: &Boolean,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let limb_width = self.params.limb_width;
+        let n_limbs = self.params.n_limbs;
+        let largest = BigNat::constant::<CS>(
+            &int_with_n_ones(limb_width * n_limbs),
+            limb_width,
+            n_limbs,
+        )?;
+        BigNat::conditionally_select(cs.namespace(|| "select"), self, &largest, condition)
+    }
+
+    /// `bit * 2^(limb_width * n_limbs)`, as `n_limbs + 1` limbs.
+    fn wrapped_bit<CS: ConstraintSystem<E>>(
+        bit: &Boolean,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Self {
+        let bit = Bit::from_sapling::<CS>(bit.clone());
+        let mut wrapped = BigNat::from_bit(&bit, limb_width);
+        wrapped.limbs.splice(0..0, vec![LinearCombination::zero(); n_limbs]);
+        if let Some(vs) = wrapped.limb_values.as_mut() {
+            vs.splice(0..0, vec![E::Fr::zero(); n_limbs]);
+        }
+        if let Some(v) = wrapped.value.as_mut() {
+            *v <<= (limb_width * n_limbs) as u32;
+        }
+        wrapped.params.n_limbs += n_limbs;
+        wrapped
+    }
+
+    pub fn mult<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.mult_with(cs, other, MultStrategy::Schoolbook)
+    }
+
+    /// Like `mult`, but checks the product with `Polynomial::alloc_product_karatsuba`, which
+    /// splits operands of more than `threshold` limbs.
+    pub fn mult_karatsuba<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+        threshold: usize,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.mult_with(cs, other, MultStrategy::Karatsuba { threshold })
+    }
+
+    /// Like `mult`, but checks the product as `strategy` says.
+    pub fn mult_with<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        strategy: MultStrategy,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(other, "mult")?;
+
+        let mut prod = BigNat::alloc_from_nat(
+            cs.namespace(|| "product"),
+            || {
+                let mut s = self.value.grab()?.clone();
+                s *= other.value.grab()?;
+                Ok(s)
+            },
+            other.params.limb_width,
+            other.params.n_limbs + self.params.n_limbs,
+        )?;
+        if self.params.min_bits > 0 && other.params.min_bits > 0 {
+            prod.params.min_bits = self.params.min_bits + other.params.min_bits - 1;
+        }
+
+        prod.assert_well_formed(cs.namespace(|| "rangecheck"))?;
+
+        // Verify that factor is in bounds
+        self.verify_mult(cs.namespace(|| "multcheck"), &other, &prod, strategy)?;
+        Ok(prod)
+    }
+
+    /// Compute `self + other` limb-wise, at no cost. Nothing is carried, so the limbs may exceed
+    /// the limb width, as `max_word` tracks: sums of many terms, such as `mult_lazy` products, are
+    /// carried once, by `carry`, `red_mod` or an `equal_when_carried` comparison.
     pub fn add<CS: ConstraintSystem<E>>(&self, other: &Self) -> Result<BigNat<E>, SynthesisError> {
         self.enforce_limb_width_agreement(other, "add")?;
         let n_limbs = max(self.params.n_limbs, other.params.n_limbs);
@@ -808,6 +1616,7 @@ impl<E: Engine> BigNat<E> {
             limbs,
             params: BigNatParams {
                 min_bits: max(self.params.min_bits, other.params.min_bits),
+                max_bits: None,
                 n_limbs,
                 max_word,
                 limb_width: self.params.limb_width,
@@ -831,11 +1640,320 @@ impl<E: Engine> BigNat<E> {
         Ok(lesser)
     }
 
+    /// The number `bit`, as a single limb of `limb_width` bits.
+    pub fn from_bit(bit: &Bit<E>, limb_width: usize) -> Self {
+        BigNat {
+            limb_values: bit
+                .value
+                .map(|b| vec![if b { E::Fr::one() } else { E::Fr::zero() }]),
+            value: bit.value.map(|b| Integer::from(b as u8)),
+            limbs: vec![bit.bit.clone()],
+            params: BigNatParams {
+                min_bits: 0,
+                max_bits: None,
+                n_limbs: 1,
+                limb_width,
+                max_word: Integer::from(1),
+            },
+        }
+    }
+
+    /// Returns a bit which is set iff `self < other`.
+    ///
+    /// Both numbers must be well-formed (see `assert_well_formed`), since they are muxed.
+    pub fn is_less_than<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Boolean, SynthesisError> {
+        self.enforce_limb_width_agreement(other, "is_less_than")?;
+        let n_limbs = max(self.params.n_limbs, other.params.n_limbs);
+        let a = self.with_n_limbs::<CS>(n_limbs);
+        let b = other.with_n_limbs::<CS>(n_limbs);
+        let lt = AllocatedBit::alloc(
+            cs.namespace(|| "lt"),
+            self.value
+                .as_ref()
+                .and_then(|a| other.value.as_ref().map(|b| a < b)),
+        )?;
+        let bit = Bit::from_sapling::<CS>(Boolean::from(lt.clone()));
+        // If `self < other`, then `other >= self + 1`, and otherwise `self >= other`.
+        let (lesser, greater) = Gadget::switch(cs.namespace(|| "switch"), &bit, &b, &a)?;
+        let lesser = lesser.add::<CS>(&BigNat::from_bit(&bit, self.params.limb_width))?;
+        greater.sub(cs.namespace(|| "difference"), &lesser)?;
+        Ok(Boolean::from(lt))
+    }
+
+    /// Compute `self * 2^bits`, at no cost: whole limbs are shifted in as zeros, and the limbs are
+    /// scaled by the remaining power of two, so they may exceed the limb width.
+    pub fn shl_const<CS: ConstraintSystem<E>>(&self, bits: usize) -> Self {
+        let limb_width = self.params.limb_width;
+        let n_zeros = bits / limb_width;
+        let scale = Integer::from(1) << (bits % limb_width) as u32;
+        let mut new = self.scale::<CS>(nat_to_f(&scale).unwrap());
+        new.limbs.splice(0..0, vec![LinearCombination::zero(); n_zeros]);
+        if let Some(vs) = new.limb_values.as_mut() {
+            vs.splice(0..0, vec![E::Fr::zero(); n_zeros]);
+        }
+        if let Some(v) = new.value.as_mut() {
+            *v <<= (n_zeros * limb_width) as u32;
+        }
+        new.params.n_limbs += n_zeros;
+        if new.params.min_bits > 0 {
+            new.params.min_bits += bits;
+        }
+        new.params.max_bits = new.params.max_bits.map(|b| b + bits);
+        new
+    }
+
+    /// Compute `floor(self / 2^bits)`. Unless `bits` is zero, the quotient is allocated
+    /// well-formed, along with the shifted-out bits, which are constrained to be less than
+    /// `2^bits`.
+    pub fn shr_const<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        bits: usize,
+    ) -> Result<Self, SynthesisError> {
+        if bits == 0 {
+            return Ok(self.clone());
+        }
+        let limb_width = self.params.limb_width;
+        let quotient = BigNat::alloc_from_nat(
+            cs.namespace(|| "quotient"),
+            || Ok(Integer::from(self.value.grab()? >> bits as u32)),
+            limb_width,
+            max(self.value_bits().saturating_sub(bits), 1).saturating_sub(1) / limb_width + 1,
+        )?;
+        quotient.assert_well_formed(cs.namespace(|| "quotient rangecheck"))?;
+        let low = BigNat::alloc_from_nat(
+            cs.namespace(|| "low bits"),
+            || Ok(self.value.grab()?.clone().keep_bits(bits as u32)),
+            limb_width,
+            (bits - 1) / limb_width + 1,
+        )?;
+        low.assert_well_formed(cs.namespace(|| "low rangecheck"))?;
+        if bits % limb_width != 0 {
+            let top = low.limbs.len() - 1;
+            Num::new(
+                low.limb_values.as_ref().map(|vs| vs[top]),
+                low.limbs[top].clone(),
+            )
+            .fits_in_bits(cs.namespace(|| "low top limb"), bits % limb_width)?;
+        }
+        let recomposed = quotient.shl_const::<CS>(bits).add::<CS>(&low)?;
+        self.equal_when_carried_regroup(cs.namespace(|| "eq"), &recomposed)?;
+        Ok(quotient)
+    }
+
+    /// Compute `2^amount`, for an allocated `amount` of at most `max_amount`.
+    ///
+    /// The amount is decomposed into `max_amount + 1` one-hot bits, from which the limbs of the
+    /// power are linear combinations. An amount past `max_amount` is unsatisfiable.
+    pub fn pow2<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        amount: &AllocatedNum<E>,
+        max_amount: usize,
+        limb_width: usize,
+    ) -> Result<Self, SynthesisError> {
+        let amount_value = amount.get_value().map(|a| f_to_nat(&a).to_usize());
+        let n_limbs = max_amount / limb_width + 1;
+        let mut limbs = vec![LinearCombination::zero(); n_limbs];
+        let mut sum = LinearCombination::zero();
+        let mut weighted_sum = LinearCombination::zero();
+        for j in 0..=max_amount {
+            let bit = Bit::alloc(
+                cs.namespace(|| format!("one hot {}", j)),
+                amount_value.map(|a| a == Some(j)),
+            )?;
+            let shift = nat_to_f(&(Integer::from(1) << (j % limb_width) as u32)).unwrap();
+            limbs[j / limb_width] = limbs[j / limb_width].clone() + (shift, &bit.bit);
+            sum = sum + &bit.bit;
+            weighted_sum = weighted_sum + (usize_to_f(j), &bit.bit);
+        }
+        cs.enforce(
+            || "one hot",
+            |lc| lc,
+            |lc| lc,
+            |lc| lc + &sum - CS::one(),
+        );
+        cs.enforce(
+            || "amount",
+            |lc| lc,
+            |lc| lc,
+            |lc| lc + &weighted_sum - amount.get_variable(),
+        );
+        let value = amount_value.map(|a| match a {
+            Some(a) if a <= max_amount => Integer::from(1) << a as u32,
+            _ => Integer::from(0),
+        });
+        Ok(BigNat {
+            limb_values: value
+                .as_ref()
+                .map(|v| nat_to_limbs(v, limb_width, n_limbs).unwrap()),
+            value,
+            limbs,
+            params: BigNatParams {
+                min_bits: 0,
+                max_bits: Some(max_amount + 1),
+                max_word: int_with_n_ones(limb_width),
+                n_limbs,
+                limb_width,
+            },
+        })
+    }
+
+    /// Compute `self * 2^amount`, for an allocated `amount` of at most `max_amount`.
+    pub fn shl_var<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        amount: &AllocatedNum<E>,
+        max_amount: usize,
+    ) -> Result<Self, SynthesisError> {
+        let pow = Self::pow2(
+            cs.namespace(|| "2^amount"),
+            amount,
+            max_amount,
+            self.params.limb_width,
+        )?;
+        self.mult(cs.namespace(|| "shift"), &pow)
+    }
+
+    /// Compute `floor(self / 2^amount)`, for an allocated `amount` of at most `max_amount`.
+    pub fn shr_var<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        amount: &AllocatedNum<E>,
+        max_amount: usize,
+    ) -> Result<Self, SynthesisError> {
+        let limb_width = self.params.limb_width;
+        let pow = Self::pow2(cs.namespace(|| "2^amount"), amount, max_amount, limb_width)?;
+        let shift = || -> Result<u32, SynthesisError> {
+            let a = f_to_nat(amount.get_value().grab()?);
+            a.to_u32().ok_or_else(|| {
+                eprintln!("Shift amount {} is too large", a);
+                SynthesisError::Unsatisfiable
+            })
+        };
+        let quotient = BigNat::alloc_from_nat(
+            cs.namespace(|| "quotient"),
+            || Ok(Integer::from(self.value.grab()? >> shift()?)),
+            limb_width,
+            (self.value_bits() - 1) / limb_width + 1,
+        )?;
+        quotient.assert_well_formed(cs.namespace(|| "quotient rangecheck"))?;
+        let low = BigNat::alloc_from_nat(
+            cs.namespace(|| "low bits"),
+            || Ok(self.value.grab()?.clone().keep_bits(shift()?)),
+            limb_width,
+            pow.params.n_limbs,
+        )?;
+        low.assert_well_formed(cs.namespace(|| "low rangecheck"))?;
+        // low < 2^amount
+        let one = Self::one::<CS>(limb_width);
+        pow.sub(cs.namespace(|| "low is low"), &low.add::<CS>(&one)?)?;
+        let shifted = quotient.mult(cs.namespace(|| "shift back"), &pow)?;
+        let recomposed = shifted.add::<CS>(&low)?;
+        self.equal_when_carried_regroup(cs.namespace(|| "eq"), &recomposed)?;
+        Ok(quotient)
+    }
+
+    /// Returns `b` if `condition`, and `a` otherwise.
+    ///
+    /// Unlike `Gadget::mux`, this selects limb by limb, with one constraint per limb, so neither
+    /// input needs to be well-formed.
+    pub fn conditionally_select<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        a: &Self,
+        b: &Self,
+        condition: &Boolean,
+    ) -> Result<Self, SynthesisError> {
+        a.enforce_limb_width_agreement(b, "conditionally_select")?;
+        let n_limbs = max(a.params.n_limbs, b.params.n_limbs);
+        let a = a.with_n_limbs::<CS>(n_limbs);
+        let b = b.with_n_limbs::<CS>(n_limbs);
+        let limb_values = condition.get_value().and_then(|c| {
+            if c {
+                b.limb_values.clone()
+            } else {
+                a.limb_values.clone()
+            }
+        });
+        let condition_lc = condition.lc(CS::one(), E::Fr::one());
+        let mut limbs = Vec::with_capacity(n_limbs);
+        for i in 0..n_limbs {
+            let limb = cs.alloc(
+                || format!("limb {}", i),
+                || Ok(limb_values.grab()?[i]),
+            )?;
+            // c * (b - a) = out - a
+            cs.enforce(
+                || format!("select {}", i),
+                |lc| lc + &condition_lc,
+                |lc| lc + &b.limbs[i] - &a.limbs[i],
+                |lc| lc + limb - &a.limbs[i],
+            );
+            limbs.push(LinearCombination::zero() + limb);
+        }
+        Ok(BigNat {
+            value: condition
+                .get_value()
+                .and_then(|c| if c { b.value.clone() } else { a.value.clone() }),
+            limb_values,
+            limbs,
+            params: BigNatParams {
+                min_bits: min(a.params.min_bits, b.params.min_bits),
+                max_bits: match (a.params.max_bits, b.params.max_bits) {
+                    (Some(x), Some(y)) => Some(max(x, y)),
+                    _ => None,
+                },
+                max_word: max(&a.params.max_word, &b.params.max_word).clone(),
+                n_limbs,
+                limb_width: a.params.limb_width,
+            },
+        })
+    }
+
+    /// Returns `(b, a)` if `condition`, and `(a, b)` otherwise, like
+    /// `AllocatedNum::conditionally_reverse`.
+    ///
+    /// The first output is selected as in `conditionally_select`; the second is the limb-wise sum
+    /// of the inputs less the first, so the swap costs one constraint per limb in all.
+    pub fn conditionally_swap<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        a: &Self,
+        b: &Self,
+        condition: &Boolean,
+    ) -> Result<(Self, Self), SynthesisError> {
+        let first = Self::conditionally_select(cs.namespace(|| "first"), a, b, condition)?;
+        let n_limbs = first.params.n_limbs;
+        let a = a.with_n_limbs::<CS>(n_limbs);
+        let b = b.with_n_limbs::<CS>(n_limbs);
+        let limbs = (0..n_limbs)
+            .map(|i| a.limbs[i].clone() + &b.limbs[i] - &first.limbs[i])
+            .collect();
+        let second = BigNat {
+            value: condition
+                .get_value()
+                .and_then(|c| if c { a.value.clone() } else { b.value.clone() }),
+            limb_values: condition.get_value().and_then(|c| {
+                if c {
+                    a.limb_values.clone()
+                } else {
+                    b.limb_values.clone()
+                }
+            }),
+            limbs,
+            params: first.params.clone(),
+        };
+        Ok((first, second))
+    }
+
     fn verify_mult<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
         other: &Self,
         prod: &Self,
+        strategy: MultStrategy,
     ) -> Result<(), SynthesisError> {
         self.enforce_limb_width_agreement(other, "verify_mult, other")?;
         self.enforce_limb_width_agreement(prod, "verify_mult, prod")?;
@@ -846,9 +1964,10 @@ impl<E: Engine> BigNat<E> {
             x *= &other.params.max_word;
             x
         };
-        let poly_prod = Polynomial::from(self.clone()).alloc_product(
+        let poly_prod = Polynomial::from(self.clone()).alloc_product_with(
             cs.namespace(|| "poly product"),
             &Polynomial::from(other.clone()),
+            strategy,
         )?;
         BigNat::from_poly(poly_prod, other.params.limb_width, max_word)
             .equal_when_carried_regroup(cs.namespace(|| "equal"), prod)?;
@@ -860,8 +1979,37 @@ impl<E: Engine> BigNat<E> {
         cs: CS,
         other: &Self,
     ) -> Result<(), SynthesisError> {
-        let one = Self::one::<CS>(other.params.limb_width);
-        self.enforce_gcd(cs, other, &one)
+        self.bezout(cs, other).map(|_| ())
+    }
+
+    /// Proves that `self` and `other` are coprime by exhibiting Bezout coefficients: returns
+    /// `(x, y)` constrained by `self * x = other * y + 1`.
+    ///
+    /// The coefficients of `a x + b y = 1` have opposite signs, so `y` is the negation of the
+    /// negative one. The witness `x` is in `(0, other]`, and `y` in `[0, self)`. If the numbers
+    /// are not coprime, the constraints are unsatisfiable.
+    pub fn bezout<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(BigNat<E>, BigNat<E>), SynthesisError> {
+        self.enforce_limb_width_agreement(other, "bezout")?;
+        let x = BigNat::alloc_from_nat(
+            cs.namespace(|| "x"),
+            || {
+                let (a, b) = (self.value.grab()?, other.value.grab()?);
+                let s = a.clone().gcd_cofactors(b.clone(), Integer::new()).1;
+                let x = (s % b + b) % b;
+                // Only when `other` is one, in which case `x = 1, y = self - 1`.
+                Ok(if x == 0 { b.clone() } else { x })
+            },
+            self.params.limb_width,
+            other.limbs.len(),
+        )?;
+        x.assert_well_formed(cs.namespace(|| "x rangecheck"))?;
+        let one = Self::one::<CS>(self.params.limb_width);
+        let y = self.assert_product_mod(cs.namespace(|| "a x = b y + 1"), &x, other, &one)?;
+        Ok((x, y))
     }
 
     fn enforce_gcd<CS: ConstraintSystem<E>>(
@@ -916,6 +2064,7 @@ impl<E: Engine> BigNat<E> {
             (),
             &BigNatParams {
                 min_bits: 1,
+                max_bits: None,
                 max_word: int_with_n_ones(limb_width),
                 n_limbs,
                 limb_width,
@@ -926,6 +2075,108 @@ impl<E: Engine> BigNat<E> {
         Ok(gcd)
     }
 
+    /// Compute a `BigNat` constrained to be an inverse of `self` modulo `modulus`:
+    /// `self * inverse = 1 (mod modulus)`.
+    ///
+    /// The witness is the reduced inverse, but the circuit only enforces the congruence, so the
+    /// result is not constrained to be less than `modulus`. Synthesis fails if `self` is not
+    /// invertible.
+    pub fn mod_inverse<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(modulus, "mod_inverse")?;
+        let inverse = BigNat::alloc_from_nat(
+            cs.namespace(|| "inverse"),
+            || {
+                let (a, m) = (self.value.grab()?, modulus.value.grab()?);
+                a.clone().invert(m).map_err(|_| {
+                    eprintln!("{} has no inverse modulo {}", a, m);
+                    SynthesisError::Unsatisfiable
+                })
+            },
+            self.params.limb_width,
+            modulus.limbs.len(),
+        )?;
+        inverse.assert_well_formed(cs.namespace(|| "inverse rangecheck"))?;
+        let one = Self::one::<CS>(self.params.limb_width);
+        self.assert_product_mod(cs.namespace(|| "product is one"), &inverse, modulus, &one)?;
+        Ok(inverse)
+    }
+
+    /// Allocates a square root of `x` modulo `modulus`, constrained by `root^2 = x (mod modulus)`.
+    /// `x` must be less than the modulus.
+    ///
+    /// The witness is computed for a prime modulus, and synthesis fails if there is no root. For
+    /// a composite modulus, compute the root from its factors and use `assert_sqrt_with`.
+    pub fn assert_sqrt<CS: ConstraintSystem<E>>(
+        cs: CS,
+        x: &Self,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        Self::assert_sqrt_with(cs, x, modulus, || {
+            let (x, m) = (x.value.grab()?, modulus.value.grab()?);
+            sqrt_mod_prime(x, m).ok_or_else(|| {
+                eprintln!("No square root of {} modulo {} found", x, m);
+                SynthesisError::Unsatisfiable
+            })
+        })
+    }
+
+    /// As `assert_sqrt`, with the witness `root`, which must be less than the modulus.
+    pub fn assert_sqrt_with<CS, F>(
+        mut cs: CS,
+        x: &Self,
+        modulus: &Self,
+        root: F,
+    ) -> Result<BigNat<E>, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+        F: FnOnce() -> Result<Integer, SynthesisError>,
+    {
+        x.enforce_limb_width_agreement(modulus, "assert_sqrt")?;
+        let root = BigNat::alloc_from_nat(
+            cs.namespace(|| "root"),
+            root,
+            modulus.params.limb_width,
+            modulus.limbs.len(),
+        )?;
+        root.assert_well_formed(cs.namespace(|| "root rangecheck"))?;
+        root.assert_product_mod(cs.namespace(|| "root^2 = x"), &root, modulus, x)?;
+        Ok(root)
+    }
+
+    /// Decides whether `x` is a quadratic residue modulo `modulus`, given a `nonresidue`. Returns
+    /// a bit which is set iff it is, and a root of `x` if so, or of `nonresidue * x` otherwise.
+    ///
+    /// If `x` were a residue, so would be `nonresidue * x` if it had a root and `x` is
+    /// invertible, so the bit is sound when the caller ensures that `nonresidue` is not a residue
+    /// and that `x` is coprime to the modulus. The witnesses are computed for a prime modulus,
+    /// for which one of the two roots always exists. `x` must be less than the modulus.
+    pub fn sqrt_or_nonresidue<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        x: &Self,
+        modulus: &Self,
+        nonresidue: &Self,
+    ) -> Result<(Boolean, BigNat<E>), SynthesisError> {
+        x.enforce_limb_width_agreement(nonresidue, "sqrt_or_nonresidue")?;
+        let is_residue = Boolean::from(AllocatedBit::alloc(
+            cs.namespace(|| "is residue"),
+            x.value.as_ref().and_then(|x| {
+                modulus
+                    .value
+                    .as_ref()
+                    .map(|m| sqrt_mod_prime(x, m).is_some())
+            }),
+        )?);
+        let (_, shifted) = nonresidue.mult_mod(cs.namespace(|| "nonresidue * x"), x, modulus)?;
+        let target =
+            Self::conditionally_select(cs.namespace(|| "target"), &shifted, x, &is_residue)?;
+        let root = Self::assert_sqrt(cs.namespace(|| "sqrt"), &target, modulus)?;
+        Ok((is_residue, root))
+    }
+
     pub fn assert_product_mod<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
@@ -984,6 +2235,58 @@ impl<E: Engine> BigNat<E> {
         Ok(quotient)
     }
 
+    /// Enforces `self = other (mod modulus)` directly, with neither side reduced. The quotient of
+    /// the difference may be negative, so it is offset by `2^k`, for `k` bits bounding both
+    /// sides: the witness `q = (self - other) / modulus + 2^k` is never negative, and
+    /// `self + 2^k * modulus = other + q * modulus` is enforced. Returns `q`.
+    ///
+    /// The modulus must be non-zero.
+    pub fn assert_congruent<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(other, "assert_congruent, other")?;
+        self.enforce_limb_width_agreement(modulus, "assert_congruent, modulus")?;
+        let limb_width = self.params.limb_width;
+        let offset_bits = max(self.value_bits(), other.value_bits());
+        // q <= self + 2^k < 2^(k + 1)
+        let quotient = BigNat::alloc_from_nat(
+            cs.namespace(|| "quotient"),
+            || {
+                let difference = Integer::from(self.value.grab()? - other.value.grab()?);
+                let (q, r) = difference.div_rem_floor(modulus.value.grab()?.clone());
+                if r != 0 {
+                    eprintln!("assert_congruent: the sides differ by {} modulo the modulus", r);
+                }
+                Ok(q + (Integer::from(1) << offset_bits as u32))
+            },
+            limb_width,
+            offset_bits / limb_width + 1,
+        )?;
+        quotient.assert_well_formed(cs.namespace(|| "quotient rangecheck"))?;
+
+        // self + 2^k * m
+        let left = self.add::<CS>(&modulus.shl_const::<CS>(offset_bits))?;
+        // q * m + other
+        let right_product = Polynomial::from(quotient.clone()).alloc_product(
+            cs.namespace(|| "right_product"),
+            &Polynomial::from(modulus.clone()),
+        )?;
+        let right = right_product.sum(&Polynomial::from(other.clone()));
+        let right_max_word = {
+            let mut x = Integer::from(min(quotient.limbs.len(), modulus.limbs.len()));
+            x *= &quotient.params.max_word;
+            x *= &modulus.params.max_word;
+            x += &other.params.max_word;
+            x
+        };
+        let right_int = BigNat::from_poly(right, limb_width, right_max_word);
+        left.equal_when_carried_regroup(cs.namespace(|| "carry"), &right_int)?;
+        Ok(quotient)
+    }
+
     /// Compute a `BigNat` contrained to be equal to `self * other % modulus`.
     pub fn mult_mod<CS: ConstraintSystem<E>>(
         &self,
@@ -1056,15 +2359,236 @@ impl<E: Engine> BigNat<E> {
         Ok((quotient, remainder))
     }
 
-    /// Compute a `BigNat` contrained to be equal to `self * other % modulus`.
-    pub fn red_mod<CS: ConstraintSystem<E>>(
+    /// Compute `self * other` without carrying it: the limbs are the coefficients of the product
+    /// polynomial, checked with one constraint each, with no allocated product nor range checks.
+    /// They exceed the limb width, so the product is for accumulating with `add`, and carrying
+    /// once at the end.
+    pub fn mult_lazy<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
-        modulus: &Self,
+        other: &Self,
     ) -> Result<BigNat<E>, SynthesisError> {
-        self.enforce_limb_width_agreement(modulus, "red_mod")?;
+        self.enforce_limb_width_agreement(other, "mult_lazy")?;
+        let max_word = {
+            let mut x = Integer::from(min(self.limbs.len(), other.limbs.len()));
+            x *= &self.params.max_word;
+            x *= &other.params.max_word;
+            x
+        };
+        let product = Polynomial::from(self.clone())
+            .alloc_product(cs.namespace(|| "product"), &Polynomial::from(other.clone()))?;
+        Ok(BigNat::from_poly(product, self.params.limb_width, max_word))
+    }
+
+    /// Compute a well-formed `BigNat` equal to `self`, whose limbs may exceed the limb width.
+    pub fn carry<CS: ConstraintSystem<E>>(&self, mut cs: CS) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_carryable("carry")?;
+        let limb_width = self.params.limb_width;
+        let carried = BigNat::alloc_from_nat(
+            cs.namespace(|| "carried"),
+            || Ok(self.value.grab()?.clone()),
+            limb_width,
+            (self.value_bits() - 1) / limb_width + 1,
+        )?;
+        carried.assert_well_formed(cs.namespace(|| "rangecheck"))?;
+        self.equal_when_carried_regroup(cs.namespace(|| "carry check"), &carried)?;
+        Ok(carried)
+    }
+
+    /// Compute `sum(a * b) % modulus` over the pairs of `products`.
+    ///
+    /// The products are accumulated without carrying (see `mult_lazy`) and reduced once, so there
+    /// is a single quotient, remainder and carry check, where a chain of `mult_mod`s has one each
+    /// per product.
+    pub fn sum_of_products_mod<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        products: &[(&Self, &Self)],
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let mut sum: Option<BigNat<E>> = None;
+        for (i, &(a, b)) in products.iter().enumerate() {
+            let product = a.mult_lazy(cs.namespace(|| format!("product {}", i)), b)?;
+            sum = Some(match sum {
+                Some(sum) => sum.add::<CS>(&product)?,
+                None => product,
+            });
+        }
+        match sum {
+            Some(sum) => sum.red_mod(cs.namespace(|| "reduce"), modulus),
+            None => {
+                eprintln!("sum_of_products_mod of no products");
+                Err(SynthesisError::Unsatisfiable)
+            }
+        }
+    }
+
+    /// Compute `prod(factors) % modulus`, multiplying without carrying (see `mult_lazy`) and
+    /// reducing only when the next product's limbs would be too wide to carry.
+    ///
+    /// Each reduction has one quotient, remainder and carry check, where a chain of `mult_mod`s
+    /// has one of each per product. With 64 limbs of 32 bits, up to six factors share one.
+    pub fn mulmod_fused<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        factors: &[Self],
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let (first, rest) = match factors.split_first() {
+            Some(split) => split,
+            None => {
+                eprintln!("mulmod_fused of no factors");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        };
+        let mut acc = LazyMod::new(first.clone(), modulus)?;
+        for (i, factor) in rest.iter().enumerate() {
+            let factor = LazyMod::new(factor.clone(), modulus)?;
+            acc = acc.mult(cs.namespace(|| format!("product {}", i)), &factor)?;
+        }
+        acc.finish(cs.namespace(|| "reduce"))
+    }
+
+    /// Compute `prod(factors) % modulus` with a balanced product tree: each level multiplies
+    /// adjacent pairs of the level below, and an odd one out moves up unchanged.
+    ///
+    /// This has the same `len - 1` multiplications as a left fold, but the tree has depth
+    /// `log2(len)`, and the value of each subtree is a standalone product, which a prover can
+    /// compute (or cache) independently of its siblings. The multiplications are lazy (see
+    /// `LazyMod`), so the low levels of the tree, whose limbs are still narrow, are not reduced.
+    pub fn product_tree_mod<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        factors: &[Self],
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        if factors.is_empty() {
+            eprintln!("product_tree_mod of no factors");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let mut level = factors
+            .iter()
+            .map(|f| LazyMod::new(f.clone(), modulus))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut depth = 0;
+        while level.len() > 1 {
+            let mut cs = cs.namespace(|| format!("level {}", depth));
+            let mut next = Vec::with_capacity((level.len() + 1) / 2);
+            let mut pairs = level.into_iter();
+            let mut i = 0;
+            while let Some(a) = pairs.next() {
+                next.push(match pairs.next() {
+                    Some(b) => a.mult(cs.namespace(|| format!("node {}", i)), &b)?,
+                    None => a,
+                });
+                i += 1;
+            }
+            level = next;
+            depth += 1;
+        }
+        level.pop().unwrap().finish(cs.namespace(|| "reduce"))
+    }
+
+    /// Compute `sum(coefficients[i] * point^i) % modulus`, by Horner's rule.
+    ///
+    /// Each step multiplies by `point` and adds the next coefficient without carrying (see
+    /// `mult_lazy`), and then reduces once, so a step costs a single `red_mod`.
+    pub fn eval_poly_mod<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        coefficients: &[Self],
+        point: &Self,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let (leading, rest) = match coefficients.split_last() {
+            Some(split) => split,
+            None => {
+                eprintln!("eval_poly_mod of no coefficients");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        };
+        let mut acc = leading.red_mod(cs.namespace(|| "leading"), modulus)?;
+        for (i, c) in rest.iter().enumerate().rev() {
+            let mut cs = cs.namespace(|| format!("coefficient {}", i));
+            acc = acc
+                .mult_lazy(cs.namespace(|| "product"), point)?
+                .add::<CS>(c)?
+                .red_mod(cs.namespace(|| "reduce"), modulus)?;
+        }
+        Ok(acc)
+    }
+
+    /// Reconstructs the number below `M = prod(moduli)` which is congruent to each of `residues`
+    /// modulo the corresponding one of `moduli`, which must be pairwise coprime constants above
+    /// one.
+    ///
+    /// The number is `sum(r_i * c_i) % M`, for the constants `c_i = (M / m_i) * ((M / m_i)^-1 %
+    /// m_i)`. The products with the constants `c_i` go through `mult_lazy`, whose
+    /// `Polynomial::alloc_product` scales by constant limbs rather than allocating, so they cost no
+    /// constraints: however many residues there are, this costs one reduction modulo `M` and one
+    /// comparison with it. The residues need not be reduced.
+    pub fn crt_recompose<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        residues: &[Self],
+        moduli: &[Integer],
+    ) -> Result<BigNat<E>, SynthesisError> {
+        if residues.is_empty() || residues.len() != moduli.len() {
+            eprintln!(
+                "crt_recompose of {} residues with {} moduli",
+                residues.len(),
+                moduli.len()
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        for (i, m) in moduli.iter().enumerate() {
+            if *m <= 1 || moduli[..i].iter().any(|n| Integer::from(m.gcd_ref(n)) != 1) {
+                eprintln!("CRT modulus {} is not coprime to the others, or below two", m);
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+        let limb_width = residues[0].params.limb_width;
+        let product = moduli.iter().fold(Integer::from(1), |acc, m| acc * m);
+        let n_limbs = (product.significant_bits() as usize - 1) / limb_width + 1;
+        let modulus = Self::constant::<CS>(&product, limb_width, n_limbs)?;
+        let coefficients = moduli
+            .iter()
+            .map(|m| {
+                let cofactor = Integer::from(&product / m);
+                let inverse = cofactor.clone().invert(m).unwrap();
+                Self::constant::<CS>(&(cofactor * inverse), limb_width, n_limbs)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let products: Vec<(&Self, &Self)> = residues.iter().zip(&coefficients).collect();
+        let recomposed = Self::sum_of_products_mod(cs.namespace(|| "sum"), &products, &modulus)?;
+        let in_range = recomposed.is_less_than(cs.namespace(|| "below M"), &modulus)?;
+        Boolean::enforce_equal(
+            cs.namespace(|| "in range"),
+            &in_range,
+            &Boolean::constant(true),
+        )?;
+        Ok(recomposed)
+    }
+
+    /// Checks that the limbs of `self` are narrow enough to be carried in the field.
+    fn enforce_carryable(&self, what: &str) -> Result<(), SynthesisError> {
+        let max_word_bits = self.params.max_word.significant_bits() as usize;
+        // Carries are checked limb by limb, and each check adds twice `max_word` to a limb.
+        if max_word_bits + 1 > E::Fr::CAPACITY as usize {
+            eprintln!(
+                "Limbs of up to {} bits are too wide to carry ({})",
+                max_word_bits, what
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        Ok(())
+    }
+
+    /// Compute a `BigNat` constrained to be equal to `self % modulus`.
+    pub fn red_mod<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(modulus, "red_mod")?;
+        self.enforce_carryable("red_mod")?;
         let limb_width = self.params.limb_width;
-        let quotient_bits = self.n_bits().saturating_sub(modulus.params.min_bits);
+        let quotient_bits = self.value_bits().saturating_sub(modulus.params.min_bits);
         let quotient_limbs = quotient_bits.saturating_sub(1) / limb_width + 1;
         let quotient = BigNat::alloc_from_nat(
             cs.namespace(|| "quotient"),
@@ -1073,9 +2597,34 @@ impl<E: Engine> BigNat<E> {
             quotient_limbs,
         )?;
         quotient.assert_well_formed(cs.namespace(|| "quotient rangecheck"))?;
+        self.reduce_with_quotient(&mut cs, modulus, &quotient)
+    }
+
+    /// Compute a `BigNat` constrained to be equal to `self - quotient * modulus`, checking only
+    /// that it is well-formed and that `self = quotient * modulus + remainder`.
+    ///
+    /// This is the tail of `red_mod`, for callers which already have the quotient, e.g. because it
+    /// is shared with another reduction. `quotient` is not range-checked here: it must already be
+    /// well-formed. As with `red_mod`, the remainder is not checked to be below `modulus`.
+    pub fn reduce_with_quotient<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        modulus: &Self,
+        quotient: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(modulus, "reduce_with_quotient, modulus")?;
+        self.enforce_limb_width_agreement(quotient, "reduce_with_quotient, quotient")?;
+        self.enforce_carryable("reduce_with_quotient")?;
+        let limb_width = self.params.limb_width;
         let remainder = BigNat::alloc_from_nat(
             cs.namespace(|| "remainder"),
-            || Ok(Integer::from(self.value.grab()? % modulus.value.grab()?)),
+            || {
+                Ok({
+                    let mut x = quotient.value.grab()?.clone();
+                    x *= modulus.value.grab()?;
+                    Integer::from(self.value.grab()? - x)
+                })
+            },
             self.params.limb_width,
             modulus.limbs.len(),
         )?;
@@ -1101,6 +2650,108 @@ impl<E: Engine> BigNat<E> {
         Ok(remainder)
     }
 
+    /// Allocates `floor(2^(w * (2n + 2)) / self)`, the factor of Barrett reduction modulo `self`,
+    /// for `self` of `n` limbs of width `w`, the top one non-zero.
+    fn barrett_factor<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let limb_width = self.params.limb_width;
+        let n_limbs = self.limbs.len();
+        let shift = (limb_width * (2 * n_limbs + 2)) as u32;
+        let factor = BigNat::alloc_from_nat(
+            cs.namespace(|| "factor"),
+            || Ok((Integer::from(1) << shift) / self.value.grab()?),
+            limb_width,
+            n_limbs + 4,
+        )?;
+        factor.assert_well_formed(cs.namespace(|| "factor rangecheck"))?;
+        let remainder = BigNat::alloc_from_nat(
+            cs.namespace(|| "remainder"),
+            || Ok((Integer::from(1) << shift) % self.value.grab()?),
+            limb_width,
+            n_limbs,
+        )?;
+        remainder.assert_well_formed(cs.namespace(|| "remainder rangecheck"))?;
+        let one = Self::one::<CS>(limb_width);
+        self.sub(
+            cs.namespace(|| "remainder is less"),
+            &remainder.add::<CS>(&one)?,
+        )?;
+        // factor * m + remainder = 2^shift
+        let product = factor.mult(cs.namespace(|| "factor * m"), self)?;
+        let power = one.shl_const::<CS>(shift as usize);
+        product
+            .add::<CS>(&remainder)?
+            .equal_when_carried_regroup(cs.namespace(|| "division"), &power)?;
+        Ok(factor)
+    }
+
+    /// Reduces `self`, which must be carried and below `2^(w * (2n + 2))`, modulo `modulus`, by
+    /// Barrett's method with the `factor` from `barrett_factor`. The result is congruent to
+    /// `self`, and below `3 * modulus`.
+    fn barrett_reduce<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        modulus: &Self,
+        factor: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let limb_width = modulus.params.limb_width;
+        let n_limbs = modulus.limbs.len();
+        // floor(floor(x / b^(n - 1)) * factor / b^(n + 3))
+        let estimate = self
+            .drop_limbs(n_limbs - 1)
+            .mult(cs.namespace(|| "estimate"), factor)?
+            .drop_limbs(n_limbs + 3);
+        let remainder = BigNat::alloc_from_nat(
+            cs.namespace(|| "remainder"),
+            || {
+                let mut r = self.value.grab()?.clone();
+                r -= Integer::from(estimate.value.grab()? * modulus.value.grab()?);
+                Ok(r)
+            },
+            limb_width,
+            n_limbs + 1,
+        )?;
+        remainder.assert_well_formed(cs.namespace(|| "remainder rangecheck"))?;
+        let e_poly = Polynomial::from(estimate.clone());
+        let mod_poly = Polynomial::from(modulus.clone());
+        let r_poly = Polynomial::from(remainder.clone());
+
+        // e * m + r
+        let right_product = e_poly.alloc_product(cs.namespace(|| "right_product"), &mod_poly)?;
+        let right = right_product.sum(&r_poly);
+        let right_max_word = {
+            let mut x = Integer::from(min(estimate.limbs.len(), modulus.limbs.len()));
+            x *= &estimate.params.max_word;
+            x *= &modulus.params.max_word;
+            x += &remainder.params.max_word;
+            x
+        };
+        let right_int = BigNat::from_poly(right, limb_width, right_max_word);
+        self.equal_when_carried_regroup(cs.namespace(|| "carry"), &right_int)?;
+        Ok(remainder)
+    }
+
+    /// Compute a `BigNat` congruent to `self * other` modulo `modulus`: the remainder of
+    /// `mult_mod` without a Barrett `factor`, and `barrett_reduce` with one.
+    fn mult_mod_reduced<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        modulus: &Self,
+        factor: Option<&Self>,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        match factor {
+            None => Ok(self.mult_mod(cs, other, modulus)?.1),
+            Some(factor) => self.mult(cs.namespace(|| "product"), other)?.barrett_reduce(
+                cs.namespace(|| "barrett"),
+                modulus,
+                factor,
+            ),
+        }
+    }
+
     /// Combines limbs into groups.
     pub fn group_limbs(&self, limbs_per_group: usize) -> BigNat<E> {
         let n_groups = (self.limbs.len() - 1) / limbs_per_group + 1;
@@ -1147,6 +2798,7 @@ impl<E: Engine> BigNat<E> {
         BigNat {
             params: BigNatParams {
                 min_bits: self.params.min_bits,
+                max_bits: self.params.max_bits,
                 limb_width: self.params.limb_width * limbs_per_group,
                 n_limbs: limbs.len(),
                 max_word,
@@ -1163,6 +2815,8 @@ impl<E: Engine> BigNat<E> {
         mut cs: CS,
         exp: Bitvector<E>,
         modulus: &Self,
+        reduction: Reduction,
+        k: usize,
     ) -> Result<BigNat<E>, SynthesisError> {
         fn bauer_power_bin_rev_helper<'a, E: Engine, CS: ConstraintSystem<E>>(
             mut cs: CS,
@@ -1170,6 +2824,7 @@ impl<E: Engine> BigNat<E> {
             k: usize,
             mut exp_chunks: std::slice::Chunks<'a, Bit<E>>,
             modulus: &BigNat<E>,
+            factor: Option<&BigNat<E>>,
         ) -> Result<BigNat<E>, SynthesisError> {
             if let Some(chunk) = exp_chunks.next_back() {
                 let chunk_len = chunk.len();
@@ -1181,12 +2836,16 @@ impl<E: Engine> BigNat<E> {
                         k,
                         exp_chunks,
                         modulus,
+                        factor,
                     )?;
                     // Square once, for each bit in the chunk
                     for j in 0..chunk_len {
-                        acc = acc
-                            .mult_mod(cs.namespace(|| format!("square {}", j)), &acc, &modulus)?
-                            .1;
+                        acc = acc.mult_mod_reduced(
+                            cs.namespace(|| format!("square {}", j)),
+                            &acc,
+                            &modulus,
+                            factor,
+                        )?;
                     }
                     // Select the correct base power
                     let base_power = Gadget::mux_tree(
@@ -1194,9 +2853,7 @@ impl<E: Engine> BigNat<E> {
                         chunk.into_iter(),
                         &base_powers[..(1 << chunk_len)],
                     )?;
-                    Ok(acc
-                        .mult_mod(cs.namespace(|| "prod"), &base_power, &modulus)?
-                        .1)
+                    acc.mult_mod_reduced(cs.namespace(|| "prod"), &base_power, &modulus, factor)
                 } else {
                     Gadget::mux_tree(
                         cs.namespace(|| "select"),
@@ -1208,52 +2865,210 @@ impl<E: Engine> BigNat<E> {
                 Ok(BigNat::one::<CS>(modulus.params.limb_width))
             }
         }
-        let k = optimal_k(exp.bits.len());
+        let factor = match reduction {
+            Reduction::Quotient | Reduction::Hinted => None,
+            Reduction::Barrett => Some(modulus.barrett_factor(cs.namespace(|| "barrett factor"))?),
+        };
         let base_powers = {
             let mut base_powers = vec![BigNat::one::<CS>(modulus.params.limb_width), self.clone()];
             for i in 2..(1 << k) {
                 base_powers.push(
-                    base_powers
-                        .last()
-                        .unwrap()
-                        .mult_mod(cs.namespace(|| format!("base {}", i)), self, modulus)?
-                        .1,
+                    base_powers.last().unwrap().mult_mod_reduced(
+                        cs.namespace(|| format!("base {}", i)),
+                        self,
+                        modulus,
+                        factor.as_ref(),
+                    )?,
                 );
             }
             base_powers
         };
-        bauer_power_bin_rev_helper(
+        let power = bauer_power_bin_rev_helper(
             cs.namespace(|| "helper"),
             &base_powers,
             k,
             exp.into_bits().chunks(k),
             modulus,
-        )
+            factor.as_ref(),
+        )?;
+        match factor {
+            None => Ok(power),
+            Some(_) => power.red_mod(cs.namespace(|| "final reduction"), modulus),
+        }
     }
 
     /// Computes a `BigNat` constrained to be equal to `self ** exp % modulus`.
     pub fn pow_mod<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        exp: &Self,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.pow_mod_with(cs, exp, modulus, Reduction::Quotient)
+    }
+
+    /// Like `pow_mod`, but reduces products as `reduction` says.
+    pub fn pow_mod_with<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        exp: &Self,
+        modulus: &Self,
+        reduction: Reduction,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.pow_mod_inner(cs, exp, modulus, reduction, None)
+    }
+
+    /// Like `pow_mod`, but with a fixed window of `window` exponent bits, rather than the one
+    /// `optimal_k` picks for the length of the exponent.
+    ///
+    /// The `2^window` powers of the base are computed once, and each window of the exponent costs
+    /// `window` squarings, a selection with a mux tree, and one multiplication.
+    pub fn pow_mod_windowed<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        exp: &Self,
+        modulus: &Self,
+        window: usize,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        if window == 0 {
+            eprintln!("pow_mod_windowed needs a window of at least one bit");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        self.pow_mod_inner(cs, exp, modulus, Reduction::Quotient, Some(window))
+    }
+
+    /// Computes a `BigNat` constrained to be equal to `self ** exponent % modulus`, for an
+    /// exponent known when the circuit is built.
+    ///
+    /// Square-and-multiply is unrolled over the bits of the exponent, so nothing about the
+    /// exponent is allocated or selected: a `b`-bit exponent with `k` set bits costs `b - 1`
+    /// squarings and `k - 1` multiplications. An exponent of zero gives the constant one.
+    pub fn pow_const<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        exponent: &Integer,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(modulus, "pow_const")?;
+        if *exponent < 0 {
+            eprintln!("pow_const with negative exponent {}", exponent);
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        if *exponent == 0 {
+            return Ok(BigNat::one::<CS>(self.params.limb_width));
+        }
+        let n_bits = exponent.significant_bits();
+        if n_bits == 1 {
+            return self.red_mod(cs.namespace(|| "reduce"), modulus);
+        }
+        let mut acc = self.clone();
+        for i in (0..n_bits - 1).rev() {
+            acc = acc
+                .mult_mod(cs.namespace(|| format!("square {}", i)), &acc, modulus)?
+                .1;
+            if exponent.get_bit(i) {
+                acc = acc
+                    .mult_mod(cs.namespace(|| format!("multiply {}", i)), self, modulus)?
+                    .1;
+            }
+        }
+        Ok(acc)
+    }
+
+    fn pow_mod_inner<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
         exp: &Self,
         modulus: &Self,
+        reduction: Reduction,
+        window: Option<usize>,
     ) -> Result<BigNat<E>, SynthesisError> {
-        let exp_bin_rev = if exp.params.max_word >= Integer::from(1) << exp.params.limb_width as u32
-        {
-            let exp_carried = BigNat::alloc_from_nat(
+        if reduction == Reduction::Hinted {
+            let exp_bits = exp.exponent_bits(&mut cs)?;
+            return self.pow_mod_hinted(cs.namespace(|| "hinted exp"), exp_bits, modulus);
+        }
+        let exp_bin_rev = exp.exponent_bits(&mut cs)?.reversed();
+        let k = window.unwrap_or_else(|| optimal_k(exp_bin_rev.bits.len()));
+        self.pow_mod_bin_rev(
+            cs.namespace(|| "binary exp"),
+            exp_bin_rev,
+            modulus,
+            reduction,
+            k,
+        )
+    }
+
+    // NB: `exp` has its low-order bit at index 0.
+    fn pow_mod_hinted<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        exp: Bitvector<E>,
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.enforce_limb_width_agreement(modulus, "pow_mod_hinted")?;
+        let one = BigNat::one::<CS>(modulus.params.limb_width);
+        let mut acc: Option<BigNat<E>> = None;
+        for (i, bit) in exp.into_bits().iter().enumerate().rev() {
+            let mut cs = cs.namespace(|| format!("bit {}", i));
+            let factor = Gadget::mux(cs.namespace(|| "select"), bit, &one, self)?;
+            let factors = match acc {
+                None => vec![factor],
+                Some(a) => vec![a.clone(), a, factor],
+            };
+            acc = Some(Self::mulmod_fused(cs.namespace(|| "step"), &factors, modulus)?);
+        }
+        Ok(acc.unwrap_or(one))
+    }
+
+    /// Decomposes `self` into bits, low-order first, as an exponent: carrying it first if its
+    /// limbs may be wider than the limb width.
+    fn exponent_bits<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+    ) -> Result<Bitvector<E>, SynthesisError> {
+        if self.params.max_word >= Integer::from(1) << self.params.limb_width as u32 {
+            let mut exp_carried = BigNat::alloc_from_nat(
                 cs.namespace(|| "exp carried"),
-                || Ok(exp.value.grab()?.clone()),
-                exp.params.limb_width,
-                exp.params.n_limbs,
+                || Ok(self.value.grab()?.clone()),
+                self.params.limb_width,
+                self.params.n_limbs,
             )?;
-            exp_carried.equal_when_carried_regroup(cs.namespace(|| "carry check"), &exp)?;
-            exp_carried
-                .decompose(cs.namespace(|| "exp decomp"))?
-                .reversed()
+            exp_carried.equal_when_carried_regroup(cs.namespace(|| "carry check"), self)?;
+            exp_carried.params.max_bits = self.params.max_bits;
+            exp_carried.decompose_bounded(cs.namespace(|| "exp decomp"))
         } else {
-            exp.decompose(cs.namespace(|| "exp decomp"))?.reversed()
-        };
-        self.pow_mod_bin_rev(cs.namespace(|| "binary exp"), exp_bin_rev, modulus)
+            self.decompose_bounded(cs.namespace(|| "exp decomp"))
+        }
+    }
+
+    /// Computes a `BigNat` constrained to be equal to `prod(bases[i] ** exps[i]) % modulus`.
+    ///
+    /// This uses simultaneous (Shamir's trick) exponentiation, as `CircuitSemiGroup::multi_power`
+    /// does (see `mp::exp::simultaneous_power`).
+    pub fn multi_pow_mod<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        bases: &[Self],
+        exps: &[Self],
+        modulus: &Self,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let one = BigNat::one::<CS>(modulus.params.limb_width);
+        let bits = exps
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                Ok(e
+                    .exponent_bits(cs.namespace(|| format!("exp {}", i)))?
+                    .into_bits())
+            })
+            .collect::<Result<Vec<Vec<Bit<E>>>, SynthesisError>>()?;
+        let acc = simultaneous_power(
+            cs.namespace(|| "ladder"),
+            bases,
+            bits,
+            one.clone(),
+            |cs, a, b| Ok(a.mult_mod(cs, b, modulus)?.1),
+        )?;
+        Ok(acc.unwrap_or(one))
     }
 
     /// Assuming that the input is equivalent to 3 modulo 4, does a round of Miller-Rabin to check
@@ -1276,20 +3091,49 @@ impl<E: Engine> BigNat<E> {
             .unwrap()
             .constrain_value(cs.namespace(|| "= 3 mod 4"), true);
         let n_less_one = BigNat::recompose(&bits.clone().shr(1).shl(1), self.params.limb_width);
-        let one = BigNat::alloc_from_nat(
-            cs.namespace(|| "1"),
-            || Ok(Integer::from(1)),
-            self.params.limb_width,
-            self.limbs.len(),
-        )?;
-        // 2a + 1 == n
+        // 2a + 1 == n, so n - 1 has one factor of two
         let a = BigNat::recompose(&bits.shr(1), self.params.limb_width);
-        // Check that b^a == 1 (mod self) OR
-        //            b^a == -1 (mod self)
-        let pow = base.pow_mod(cs.namespace(|| "b^a"), &a, self)?;
-        let is_1 = pow.is_equal(cs.namespace(|| "=1"), &n_less_one)?;
-        let is_neg_1 = pow.is_equal(cs.namespace(|| "=-1"), &one)?;
-        Ok(Boolean::and(cs.namespace(|| "or"), &is_1.not(), &is_neg_1.not())?.not())
+        self.miller_rabin_round_with(cs, base, &n_less_one, &a, &[Boolean::constant(true)])
+    }
+
+    /// Given `n - 1 = d * 2^s` for the input `n`, does a round of Miller-Rabin: whether `b^d = 1`
+    /// or `b^(d * 2^i) = n - 1 (mod n)` for some `i` with `below[i]` set.
+    ///
+    /// `below[i]` should be set iff `i < s`. The caller constrains `n_less_one`, `d` and `below`.
+    pub fn miller_rabin_round_with<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        base: &Self,
+        n_less_one: &Self,
+        d: &Self,
+        below: &[Boolean],
+    ) -> Result<Boolean, SynthesisError> {
+        // `is_equal` needs matching limb counts
+        let is_equal = |cs: &mut CS, name: String, a: &Self, b: &Self| {
+            let n_limbs = max(a.params.n_limbs, b.params.n_limbs);
+            a.with_n_limbs::<CS>(n_limbs)
+                .is_equal(cs.namespace(|| name), &b.with_n_limbs::<CS>(n_limbs))
+        };
+        let one = BigNat::one::<CS>(self.params.limb_width);
+        let mut x = base.pow_mod(cs.namespace(|| "b^d"), d, self)?;
+        let mut passes = is_equal(&mut cs, "b^d = 1".into(), &x, &one)?;
+        for (i, below) in below.iter().enumerate() {
+            if i > 0 {
+                x = x
+                    .mult_mod(cs.namespace(|| format!("square {}", i)), &x, self)?
+                    .1;
+            }
+            let is_minus_one = is_equal(&mut cs, format!("-1 at {}", i), &x, n_less_one)?;
+            let hit = Boolean::and(cs.namespace(|| format!("hit {}", i)), &is_minus_one, below)?;
+            // a or b = not (not a and not b)
+            passes = Boolean::and(
+                cs.namespace(|| format!("or {}", i)),
+                &passes.not(),
+                &hit.not(),
+            )?
+            .not();
+        }
+        Ok(passes)
     }
 
     fn miller_rabin_rounds<CS: ConstraintSystem<E>>(
@@ -1344,6 +3188,7 @@ impl<E: Engine> BigNat<E> {
             limbs: { vec![LinearCombination::zero() + CS::one()] },
             params: BigNatParams {
                 min_bits: 1,
+                max_bits: None,
                 n_limbs: 1,
                 limb_width: limb_width,
                 max_word: Integer::from(1),
@@ -1351,6 +3196,36 @@ impl<E: Engine> BigNat<E> {
         }
     }
 
+    /// A constant, such as a fixed modulus or generator. Its limbs are multiples of `CS::one()`,
+    /// so it allocates nothing, is well-formed without range checks, and its products (in `mult`,
+    /// `mult_mod` and the like) are linear combinations rather than multiplication constraints.
+    pub fn constant<CS: ConstraintSystem<E>>(
+        value: &Integer,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Self, SynthesisError> {
+        let limb_values = nat_to_limbs::<E::Fr>(value, limb_width, n_limbs)?;
+        let limbs = limb_values
+            .iter()
+            .map(|v| LinearCombination::zero() + (*v, CS::one()))
+            .collect();
+        let mut params = BigNatParams::new(limb_width, n_limbs);
+        params.min_bits = value.significant_bits() as usize;
+        Ok(BigNat {
+            value: Some(value.clone()),
+            limb_values: Some(limb_values),
+            limbs,
+            params,
+        })
+    }
+
+    /// Whether every limb is a constant, as for `BigNat::constant` and `BigNat::one`.
+    pub fn is_constant<CS: ConstraintSystem<E>>(&self) -> bool {
+        Polynomial::from(self.clone())
+            .constant_coefficients::<CS>()
+            .is_some()
+    }
+
     pub fn miller_rabin<CS: ConstraintSystem<E>>(
         &self,
         cs: CS,
@@ -1383,31 +3258,285 @@ impl<E: Engine> BigNat<E> {
         self.params.limb_width * (self.params.n_limbs - 1)
             + self.params.max_word.significant_bits() as usize
     }
-}
 
-impl<E: Engine> Gadget for BigNat<E> {
-    type E = E;
-    type Value = Integer;
-    type Params = BigNatParams;
-    type Access = ();
-    fn alloc<CS: ConstraintSystem<E>>(
-        cs: CS,
-        value: Option<&Self::Value>,
-        _access: (),
-        params: &Self::Params,
-    ) -> Result<Self, SynthesisError> {
-        BigNat::alloc_from_nat(
-            cs,
-            || Ok(value.grab()?.clone().clone()),
-            params.limb_width,
-            params.n_limbs,
-        )
-    }
-    fn value(&self) -> Option<&Integer> {
-        self.value.as_ref()
+    /// A bound on the number of bits of the value, which unlike `n_bits` accounts for the carries
+    /// of limbs past the limb width.
+    fn value_bits(&self) -> usize {
+        if self.params.max_word.significant_bits() as usize > self.params.limb_width {
+            self.n_bits() + 1
+        } else {
+            self.n_bits()
+        }
     }
-    fn wire_values(&self) -> Option<Vec<E::Fr>> {
-        self.limb_values.clone()
+
+    /// Re-expresses `limbs` (each of width `from_width`) as `n_limbs` limbs of width `to_width`.
+    ///
+    /// If `from_width` divides `to_width`, adjacent limbs are combined linearly, at no cost.
+    /// Otherwise each limb is split into range-checked pieces of width `gcd(from_width,
+    /// to_width)` first. Pieces which do not fit in the output limbs are constrained to zero.
+    ///
+    /// Assumes that each input limb fits in `from_width` bits.
+    fn relimb_nums<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        limbs: Vec<Num<E>>,
+        from_width: usize,
+        to_width: usize,
+        n_limbs: usize,
+    ) -> Result<Vec<Num<E>>, SynthesisError> {
+        let piece_width = usize_gcd(from_width, to_width);
+        let pieces_per_limb = from_width / piece_width;
+        let mask = int_with_n_ones(piece_width);
+        let mut pieces: Vec<Num<E>> = Vec::new();
+        for (i, limb) in limbs.into_iter().enumerate() {
+            if pieces_per_limb == 1 {
+                pieces.push(limb);
+                continue;
+            }
+            let mut cs = cs.namespace(|| format!("split {}", i));
+            let nat = limb.value.as_ref().map(|v| f_to_nat(v));
+            let mut sum = LinearCombination::zero();
+            let mut shift = E::Fr::one();
+            for j in 0..pieces_per_limb {
+                let piece = Num::alloc(cs.namespace(|| format!("piece {}", j)), || {
+                    let v = Integer::from(nat.grab()? >> (piece_width * j) as u32) & &mask;
+                    Ok(nat_to_f(&v).unwrap())
+                })?;
+                piece.fits_in_bits(cs.namespace(|| format!("range {}", j)), piece_width)?;
+                sum = sum + (shift, &piece.num);
+                for _ in 0..piece_width {
+                    shift.double();
+                }
+                pieces.push(piece);
+            }
+            cs.enforce(
+                || "recompose",
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + &limb.num - &sum,
+            );
+        }
+        let pieces_per_out = to_width / piece_width;
+        let out = (0..n_limbs)
+            .map(|i| {
+                let group = pieces
+                    .iter()
+                    .skip(i * pieces_per_out)
+                    .take(pieces_per_out)
+                    .collect::<Vec<_>>();
+                let mut shift = E::Fr::one();
+                let mut num = LinearCombination::zero();
+                let mut value = Some(E::Fr::zero());
+                for p in group {
+                    num = num + (shift, &p.num);
+                    value = value.and_then(|mut acc| {
+                        let mut t = p.value?;
+                        t.mul_assign(&shift);
+                        acc.add_assign(&t);
+                        Some(acc)
+                    });
+                    for _ in 0..piece_width {
+                        shift.double();
+                    }
+                }
+                Num::new(value, num)
+            })
+            .collect();
+        for (i, p) in pieces.iter().enumerate().skip(n_limbs * pieces_per_out) {
+            cs.enforce(
+                || format!("high piece {} is zero", i),
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + &p.num,
+            );
+        }
+        Ok(out)
+    }
+
+    /// Re-expresses `self` as `n_limbs` allocated limbs of width `limb_width`.
+    /// Assumes that `self` is well-formed.
+    pub fn to_allocated_limbs<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Vec<AllocatedNum<E>>, SynthesisError> {
+        let nums = Self::relimb_nums(
+            cs.namespace(|| "relimb"),
+            self.as_limbs::<CS>(),
+            self.params.limb_width,
+            limb_width,
+            n_limbs,
+        )?;
+        nums.into_iter()
+            .enumerate()
+            .map(|(i, n)| n.as_sapling_allocated_num(cs.namespace(|| format!("alloc {}", i))))
+            .collect()
+    }
+
+    /// Re-expresses `self` in limbs of width `limb_width`, as many as its value needs, so that it
+    /// can be combined with numbers of that width, such as hash outputs in wider limbs.
+    ///
+    /// As in `to_allocated_limbs`, this is free if the current width divides the new one, and
+    /// otherwise costs a range check per piece of the gcd of the widths. Limbs wider than the
+    /// limb width, as in sums, are carried first.
+    pub fn relimb<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        limb_width: usize,
+    ) -> Result<Self, SynthesisError> {
+        let well_formed = self.params.max_word <= int_with_n_ones(self.params.limb_width);
+        let carried = if well_formed {
+            self.clone()
+        } else {
+            self.carry(cs.namespace(|| "carry"))?
+        };
+        let n_bits = carried.params.limb_width * carried.limbs.len();
+        let nums = Self::relimb_nums(
+            cs.namespace(|| "relimb"),
+            carried.as_limbs::<CS>(),
+            carried.params.limb_width,
+            limb_width,
+            (n_bits - 1) / limb_width + 1,
+        )?;
+        let mut relimbed = Self::from_limbs(nums, limb_width);
+        relimbed.params.min_bits = self.params.min_bits;
+        Ok(relimbed)
+    }
+
+    /// Builds a `BigNat` with `n_limbs` limbs of width `limb_width` from `limbs`, which have width
+    /// `from_width`. Assumes that each of `limbs` fits in `from_width` bits.
+    pub fn from_allocated_limbs<CS: ConstraintSystem<E>>(
+        cs: CS,
+        limbs: &[AllocatedNum<E>],
+        from_width: usize,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Self, SynthesisError> {
+        let nums = limbs.iter().map(|l| Num::from(l.clone())).collect();
+        let nums = Self::relimb_nums(cs, nums, from_width, limb_width, n_limbs)?;
+        Ok(Self::from_limbs(nums, limb_width))
+    }
+}
+
+/// A value modulo `modulus` whose reduction is deferred: sums and products are taken limb-wise
+/// without carrying (see `BigNat::mult_lazy`), and the value is reduced only when the next
+/// operation's limbs would be too wide to carry, and once by `finish`.
+///
+/// Each reduction brings a quotient, a remainder, their range checks and a carry check, so where a
+/// chain of `mult_mod`s pays for one per product, this pays for one per run of products which fit
+/// in the field together. `mulmod_fused` and `product_tree_mod` (and so the exponent aggregation
+/// of the set circuits, see `wesolowski::aggregate_exponent`) build on it. The
+/// `lazy_aggregation_in_set_bench` test of `set::rsa` prints the constraint count of a `SetBench`
+/// of 8 swaps over a 2048-bit group, and what it would be with a chain of `mult_mod`s.
+#[derive(Clone)]
+pub struct LazyMod<E: Engine> {
+    value: BigNat<E>,
+    modulus: BigNat<E>,
+}
+
+impl<E: Engine> LazyMod<E> {
+    /// `value`, which is not reduced yet, modulo `modulus`.
+    pub fn new(value: BigNat<E>, modulus: &BigNat<E>) -> Result<Self, SynthesisError> {
+        value.enforce_limb_width_agreement(modulus, "LazyMod::new")?;
+        value.enforce_carryable("LazyMod::new")?;
+        Ok(LazyMod {
+            value,
+            modulus: modulus.clone(),
+        })
+    }
+
+    /// The value, congruent to the result but possibly unreduced and uncarried.
+    pub fn value(&self) -> &BigNat<E> {
+        &self.value
+    }
+
+    pub fn add<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let (a, b) = self.make_room(cs.namespace(|| "make room"), other, |a, b| {
+            Integer::from(&a.params.max_word + &b.params.max_word)
+        })?;
+        Ok(LazyMod {
+            value: a.add::<CS>(&b)?,
+            modulus: self.modulus.clone(),
+        })
+    }
+
+    pub fn mult<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<Self, SynthesisError> {
+        let (a, b) = self.make_room(cs.namespace(|| "make room"), other, |a, b| {
+            let mut x = Integer::from(min(a.limbs.len(), b.limbs.len()));
+            x *= &a.params.max_word;
+            x *= &b.params.max_word;
+            x
+        })?;
+        Ok(LazyMod {
+            value: a.mult_lazy(cs.namespace(|| "product"), &b)?,
+            modulus: self.modulus.clone(),
+        })
+    }
+
+    /// Reduces the value, enforcing its congruence with the result.
+    pub fn finish<CS: ConstraintSystem<E>>(self, cs: CS) -> Result<BigNat<E>, SynthesisError> {
+        self.value.red_mod(cs, &self.modulus)
+    }
+
+    /// The operands of an operation whose limbs are at most `max_word(a, b)`, reduced (the wider
+    /// first) until those limbs can be carried.
+    fn make_room<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        max_word: impl Fn(&BigNat<E>, &BigNat<E>) -> Integer,
+    ) -> Result<(BigNat<E>, BigNat<E>), SynthesisError> {
+        let mut a = self.value.clone();
+        let mut b = other.value.clone();
+        let mut reduced = (false, false);
+        // As in `BigNat::enforce_carryable`.
+        while max_word(&a, &b).significant_bits() + 1 > E::Fr::CAPACITY {
+            if !reduced.0 && (reduced.1 || a.params.max_word >= b.params.max_word) {
+                a = a.red_mod(cs.namespace(|| "reduce self"), &self.modulus)?;
+                reduced.0 = true;
+            } else if !reduced.1 {
+                b = b.red_mod(cs.namespace(|| "reduce other"), &self.modulus)?;
+                reduced.1 = true;
+            } else {
+                eprintln!("Reduced operands are still too wide to combine lazily");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+        Ok((a, b))
+    }
+}
+
+impl<E: Engine> Gadget for BigNat<E> {
+    type E = E;
+    type Value = Integer;
+    type Params = BigNatParams;
+    type Access = ();
+    fn alloc<CS: ConstraintSystem<E>>(
+        cs: CS,
+        value: Option<&Self::Value>,
+        _access: (),
+        params: &Self::Params,
+    ) -> Result<Self, SynthesisError> {
+        BigNat::alloc_from_nat(
+            cs,
+            || Ok(value.grab()?.clone().clone()),
+            params.limb_width,
+            params.n_limbs,
+        )
+    }
+    fn value(&self) -> Option<&Integer> {
+        self.value.as_ref()
+    }
+    fn wire_values(&self) -> Option<Vec<E::Fr>> {
+        self.limb_values.clone()
     }
     fn params(&self) -> &BigNatParams {
         &self.params
@@ -1442,6 +3571,10 @@ impl<E: Engine> Gadget for BigNat<E> {
             (),
             &BigNatParams {
                 min_bits: min(i0.params.min_bits, i1.params.min_bits),
+                max_bits: match (i0.params.max_bits, i1.params.max_bits) {
+                    (Some(a), Some(b)) => Some(max(a, b)),
+                    _ => None,
+                },
                 max_word: max(i0.params.max_word.clone(), i1.params.max_word.clone()),
                 limb_width: i0.params.limb_width,
                 n_limbs: i0.params.n_limbs,
@@ -1463,6 +3596,28 @@ impl<E: Engine> Gadget for BigNat<E> {
         }
         Ok(out)
     }
+
+    /// Enforces that the values of `i0` and `i1` differ. Both are carried first, since limbs
+    /// which are not well-formed may differ while the values are equal.
+    fn assert_not_equal<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        i0: &Self,
+        i1: &Self,
+    ) -> Result<(), SynthesisError> {
+        i0.enforce_limb_width_agreement(i1, "assert_not_equal")?;
+        let i0 = i0.carry(cs.namespace(|| "carry i0"))?;
+        let i1 = i1.carry(cs.namespace(|| "carry i1"))?;
+        let n_limbs = max(i0.params.n_limbs, i1.params.n_limbs);
+        let equal = i0.with_n_limbs::<CS>(n_limbs).is_equal(
+            cs.namespace(|| "is equal"),
+            &i1.with_n_limbs::<CS>(n_limbs),
+        )?;
+        Boolean::enforce_equal(
+            cs.namespace(|| "not equal"),
+            &equal,
+            &Boolean::constant(false),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -1476,6 +3631,36 @@ mod tests {
     use std::str::FromStr;
     use util::convert::usize_to_f;
 
+    pub struct NotEqual {
+        /// Doubled without carrying, so that the limbs of the left side are not canonical
+        pub half_a: usize,
+        pub b: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for NotEqual {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let half_a = BigNat::alloc_from_nat(
+                cs.namespace(|| "half a"),
+                || Ok(Integer::from(self.half_a)),
+                4,
+                2,
+            )?;
+            let a = half_a.add::<CS>(&half_a)?;
+            let b = BigNat::alloc_from_nat(
+                cs.namespace(|| "b"),
+                || Ok(Integer::from(self.b)),
+                4,
+                2,
+            )?;
+            Gadget::assert_not_equal(cs.namespace(|| "not equal"), &a, &b)
+        }
+    }
+
+    circuit_tests! {
+        not_equal_uncarried_same_value: (NotEqual { half_a: 8, b: 16 }, false),
+        not_equal_uncarried_other_value: (NotEqual { half_a: 8, b: 17 }, true),
+    }
+
     pub struct CarrierInputs {
         pub a: Vec<usize>,
         pub b: Vec<usize>,
@@ -1983,6 +4168,7 @@ mod tests {
         pub limb_width: usize,
         pub n_limbs_b: usize,
         pub n_limbs_e: usize,
+        pub reduction: Reduction,
     }
 
     pub struct PowMod<'a> {
@@ -2016,7 +4202,7 @@ mod tests {
                 self.params.limb_width,
                 self.params.n_limbs_b,
             )?;
-            let actual = b.pow_mod(cs.namespace(|| "pow"), &e, &m)?;
+            let actual = b.pow_mod_with(cs.namespace(|| "pow"), &e, &m, self.params.reduction)?;
             actual.equal(cs.namespace(|| "check"), &res)?;
             Ok(())
         }
@@ -2029,6 +4215,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "1",
@@ -2045,6 +4232,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "1",
@@ -2061,6 +4249,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "1",
@@ -2077,6 +4266,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 3,
                                     n_limbs_e: 1,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "2",
@@ -2093,6 +4283,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "16",
@@ -2109,6 +4300,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "16",
@@ -2125,6 +4317,7 @@ mod tests {
                                     limb_width: 4,
                                     n_limbs_b: 2,
                                     n_limbs_e: 2,
+                                    reduction: Reduction::Quotient,
                                 },
                                 inputs: Some(PowModInputs {
                                     b: "16",
@@ -2143,6 +4336,7 @@ mod tests {
                         //                    limb_width: 32,
                         //                    n_limbs_b: 64,
                         //                    n_limbs_e: 4,
+                        //                    reduction: Reduction::Quotient,
                         //                },
                         //                inputs: Some(PowModInputs {
                         //                    b: "16",
@@ -2155,6 +4349,342 @@ mod tests {
                         //        ),
     }
 
+    circuit_tests! {
+        pow_mod_barrett_2_to_2: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 3,
+                    n_limbs_e: 1,
+                    reduction: Reduction::Barrett,
+                },
+                inputs: Some(PowModInputs {
+                    b: "2",
+                    e: "2",
+                    m: "1255",
+                    res: "4",
+                }),
+            },
+            true
+        ),
+        pow_mod_barrett_16_to_5: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Barrett,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "5",
+                    m: "255",
+                    res: "16",
+                }),
+            },
+            true
+        ),
+        pow_mod_barrett_16_to_255: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Barrett,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "254",
+                    m: "255",
+                    res: "1",
+                }),
+            },
+            true
+        ),
+        pow_mod_barrett_16_to_255_wrong: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Barrett,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "254",
+                    m: "255",
+                    res: "16",
+                }),
+            },
+            false
+        ),
+    }
+
+    circuit_tests! {
+        pow_mod_hinted_16_to_0: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Hinted,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "0",
+                    m: "255",
+                    res: "1",
+                }),
+            },
+            true
+        ),
+        pow_mod_hinted_2_to_2: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 3,
+                    n_limbs_e: 1,
+                    reduction: Reduction::Hinted,
+                },
+                inputs: Some(PowModInputs {
+                    b: "2",
+                    e: "2",
+                    m: "1255",
+                    res: "4",
+                }),
+            },
+            true
+        ),
+        pow_mod_hinted_16_to_255: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Hinted,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "254",
+                    m: "255",
+                    res: "1",
+                }),
+            },
+            true
+        ),
+        pow_mod_hinted_16_to_255_wrong: (
+            PowMod {
+                params: PowModParams {
+                    limb_width: 4,
+                    n_limbs_b: 2,
+                    n_limbs_e: 2,
+                    reduction: Reduction::Hinted,
+                },
+                inputs: Some(PowModInputs {
+                    b: "16",
+                    e: "254",
+                    m: "255",
+                    res: "16",
+                }),
+            },
+            false
+        ),
+    }
+
+    #[test]
+    fn hinted_pow_mod_is_cheaper_than_binary() {
+        let count = |hinted: bool| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let mut nat = |name: &str, n_limbs: usize| {
+                BigNat::<Bn256>::alloc_from_nat(
+                    cs.namespace(|| name),
+                    || Err(SynthesisError::AssignmentMissing),
+                    32,
+                    n_limbs,
+                )
+                .unwrap()
+            };
+            let b = nat("b", 64);
+            let e = nat("e", 4);
+            let m = nat("m", 64);
+            let before = cs.num_constraints();
+            if hinted {
+                b.pow_mod_with(cs.namespace(|| "pow"), &e, &m, Reduction::Hinted).unwrap();
+            } else {
+                b.pow_mod_windowed(cs.namespace(|| "pow"), &e, &m, 1).unwrap();
+            }
+            cs.num_constraints() - before
+        };
+        let hinted = count(true);
+        let binary = count(false);
+        println!(
+            "2048b modulus, 128b exponent: {} constraints hinted, {} with one-bit windows",
+            hinted, binary
+        );
+        assert!(hinted < binary);
+    }
+
+    #[test]
+    fn pow_mod_reduction_constraints_2048() {
+        let count = |reduction| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let circuit = PowMod {
+                params: PowModParams {
+                    limb_width: 32,
+                    n_limbs_b: 64,
+                    n_limbs_e: 4,
+                    reduction,
+                },
+                inputs: None,
+            };
+            Circuit::<Bn256>::synthesize(circuit, &mut cs).unwrap();
+            cs.num_constraints()
+        };
+        let quotient = count(Reduction::Quotient);
+        let barrett = count(Reduction::Barrett);
+        println!(
+            "2048b modulus, 128b exponent: {} constraints with quotients, {} with Barrett",
+            quotient, barrett
+        );
+        // Each Barrett reduction range-checks the full product and the product with the factor,
+        // where a free quotient needs only itself and the remainder checked.
+        assert!(quotient < barrett);
+    }
+
+    #[test]
+    fn fixed_window_pow_mod() {
+        for window in 1..6 {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: &str, n: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(Integer::from(n)), 4, 2)
+                    .unwrap()
+            };
+            let b = nat("b", 7);
+            let e = nat("e", 203);
+            let m = nat("m", 255);
+            let pow = b
+                .pow_mod_windowed(cs.namespace(|| "pow"), &e, &m, window)
+                .unwrap();
+            assert_eq!(pow.value, Some(Integer::from(133)));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn pow_const_matches_native() {
+        for &e in &[0u32, 1, 2, 3, 203, 255] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: &str, n: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(Integer::from(n)), 4, 2)
+                    .unwrap()
+            };
+            let b = nat("b", 7);
+            let m = nat("m", 255);
+            let pow = b
+                .pow_const(cs.namespace(|| "pow"), &Integer::from(e), &m)
+                .unwrap();
+            let expected = Integer::from(7).pow_mod(&Integer::from(e), &Integer::from(255));
+            assert_eq!(pow.value, Some(expected.unwrap()));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn pow_const_is_cheaper_than_pow_mod() {
+        let exponent = Integer::from(65537);
+        let count = |constant: bool| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let mut nat = |name: &str, n: &Integer| {
+                let n = n.clone();
+                BigNat::<Bn256>::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4).unwrap()
+            };
+            let b = nat("b", &Integer::from(3));
+            let m = nat("m", &Integer::from_str("298937084241820869743410128427022097023").unwrap());
+            let e = nat("e", &exponent);
+            let before = cs.num_constraints();
+            if constant {
+                b.pow_const(cs.namespace(|| "pow"), &exponent, &m).unwrap();
+            } else {
+                b.pow_mod(cs.namespace(|| "pow"), &e, &m).unwrap();
+            }
+            cs.num_constraints() - before
+        };
+        assert!(count(true) < count(false));
+    }
+
+    pub struct BooleanBits {
+        n: &'static str,
+        doubled: bool,
+        expected: &'static str,
+    }
+
+    impl<E: Engine> Circuit<E> for BooleanBits {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let n = Integer::from_str(self.n).unwrap();
+            let n = BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(n), 8, 4)?;
+            let x = if self.doubled {
+                n.add::<CS>(&n)?
+            } else {
+                n
+            };
+            let bits = x.clone().into_bits(cs.namespace(|| "into"))?;
+            let value = x.value.clone().unwrap();
+            for (i, b) in bits.iter().enumerate() {
+                assert_eq!(b.get_value(), Some(value.get_bit(i as u32)));
+            }
+            let y = BigNat::from_bits::<CS>(&bits, 8);
+            assert_eq!(y.value, Some(value));
+            let expected = Integer::from_str(self.expected).unwrap();
+            let expected =
+                BigNat::alloc_from_nat(cs.namespace(|| "expected"), || Ok(expected), 8, 5)?;
+            y.equal_when_carried_regroup(cs.namespace(|| "round trip"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        boolean_bits: (BooleanBits {
+            n: "3735928559",
+            doubled: false,
+            expected: "3735928559",
+        }, true),
+        boolean_bits_doubled: (BooleanBits {
+            n: "3735928559",
+            doubled: true,
+            expected: "7471857118",
+        }, true),
+        boolean_bits_wrong: (BooleanBits {
+            n: "3735928559",
+            doubled: false,
+            expected: "3735928558",
+        }, false),
+    }
+
+    pub struct MultiPowMod {
+        exps: [usize; 2],
+        res: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for MultiPowMod {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let mut nat = |name: &str, n: usize, n_limbs: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(Integer::from(n)), 4, n_limbs)
+            };
+            let bases = [nat("b0", 3, 3)?, nat("b1", 5, 3)?];
+            let exps = [nat("e0", self.exps[0], 2)?, nat("e1", self.exps[1], 2)?];
+            let m = nat("m", 1255, 3)?;
+            let res = nat("res", self.res, 3)?;
+            let actual = BigNat::multi_pow_mod(cs.namespace(|| "pow"), &bases, &exps, &m)?;
+            actual.equal(cs.namespace(|| "check"), &res)
+        }
+    }
+
+    circuit_tests! {
+        multi_pow_mod_5_7: (MultiPowMod { exps: [5, 7], res: 1245 }, true),
+        multi_pow_mod_100_200: (MultiPowMod { exps: [100, 200], res: 20 }, true),
+        multi_pow_mod_0_0: (MultiPowMod { exps: [0, 0], res: 1 }, true),
+        multi_pow_mod_wrong: (MultiPowMod { exps: [5, 7], res: 1244 }, false),
+    }
+
     #[derive(Debug)]
     pub struct MillerRabinRoundInputs<'a> {
         pub b: &'a str,
@@ -2486,43 +5016,1496 @@ mod tests {
                                  true),
     }
 
-    #[derive(Debug)]
-    pub struct MillerRabin32Inputs<'a> {
-        pub n: &'a str,
-        pub result: bool,
+    pub struct CoprimeInputs<'a> {
+        pub a: &'a str,
+        pub b: &'a str,
     }
 
-    pub struct MillerRabin32<'a> {
-        inputs: Option<MillerRabin32Inputs<'a>>,
+    pub struct Coprime<'a> {
+        inputs: Option<CoprimeInputs<'a>>,
+        params: GcdParams,
     }
 
-    impl<'a, E: Engine> Circuit<E> for MillerRabin32<'a> {
+    impl<'a, E: Engine> Circuit<E> for Coprime<'a> {
         fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-            use sapling_crypto::circuit::boolean::AllocatedBit;
-            let n = BigNat::alloc_from_nat(
-                cs.namespace(|| "n"),
-                || Ok(Integer::from_str(self.inputs.grab()?.n).unwrap()),
-                32,
-                1,
+            let a = BigNat::alloc_from_nat(
+                cs.namespace(|| "a"),
+                || Ok(Integer::from_str(self.inputs.grab()?.a).unwrap()),
+                self.params.limb_width,
+                self.params.n_limbs_a,
             )?;
-            let expected_res = Boolean::Is(AllocatedBit::alloc(
-                cs.namespace(|| "bit"),
-                self.inputs.map(|o| o.result),
-            )?);
-            let actual_res = n.miller_rabin_32b(cs.namespace(|| "mr"))?;
-            Boolean::enforce_equal(cs.namespace(|| "eq"), &expected_res, &actual_res)?;
+            let b = BigNat::alloc_from_nat(
+                cs.namespace(|| "b"),
+                || Ok(Integer::from_str(self.inputs.grab()?.b).unwrap()),
+                self.params.limb_width,
+                self.params.n_limbs_b,
+            )?;
+            let (x, y) = a.bezout(cs.namespace(|| "bezout"), &b)?;
+            let values = (a.value(), b.value(), x.value(), y.value());
+            if let (Some(a), Some(b), Some(x), Some(y)) = values {
+                assert!(x <= b && y < a);
+            }
             Ok(())
         }
     }
+
     circuit_tests! {
-        mr_32: (
-                          MillerRabin32 {
-                              inputs: Some(MillerRabin32Inputs {
-                                  n: "251",
-                                  result: true,
-                              }),
-                          },
-                          true),
+        coprime_4_5: (Coprime {
+            inputs: Some(CoprimeInputs { a: "4", b: "5" }),
+            params: GcdParams { limb_width: 4, n_limbs_a: 1, n_limbs_b: 1 },
+        }, true),
+        coprime_5_4: (Coprime {
+            inputs: Some(CoprimeInputs { a: "5", b: "4" }),
+            params: GcdParams { limb_width: 4, n_limbs_a: 2, n_limbs_b: 1 },
+        }, true),
+        coprime_7_1: (Coprime {
+            inputs: Some(CoprimeInputs { a: "7", b: "1" }),
+            params: GcdParams { limb_width: 4, n_limbs_a: 1, n_limbs_b: 1 },
+        }, true),
+        coprime_128b: (Coprime {
+            inputs: Some(CoprimeInputs {
+                a: "311515013647097972396078794914139832177",
+                b: "298937084241820869743410128427022097023",
+            }),
+            params: GcdParams { limb_width: 32, n_limbs_a: 4, n_limbs_b: 4 },
+        }, true),
+        coprime_30_24: (Coprime {
+            inputs: Some(CoprimeInputs { a: "30", b: "24" }),
+            params: GcdParams { limb_width: 4, n_limbs_a: 2, n_limbs_b: 2 },
+        }, false),
+    }
+
+    pub struct LessThan<'a> {
+        a: &'a str,
+        b: &'a str,
+        limb_width: usize,
+        n_limbs_a: usize,
+        n_limbs_b: usize,
+        expected: bool,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for LessThan<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let a = BigNat::alloc_from_nat(
+                cs.namespace(|| "a"),
+                || Ok(Integer::from_str(self.a).unwrap()),
+                self.limb_width,
+                self.n_limbs_a,
+            )?;
+            let b = BigNat::alloc_from_nat(
+                cs.namespace(|| "b"),
+                || Ok(Integer::from_str(self.b).unwrap()),
+                self.limb_width,
+                self.n_limbs_b,
+            )?;
+            let lt = a.is_less_than(cs.namespace(|| "a < b"), &b)?;
+            Boolean::enforce_equal(cs.namespace(|| "eq"), &lt, &Boolean::constant(self.expected))
+        }
+    }
+
+    circuit_tests! {
+        less_than_3_5: (LessThan {
+            a: "3", b: "5", limb_width: 4, n_limbs_a: 1, n_limbs_b: 1, expected: true,
+        }, true),
+        less_than_5_3: (LessThan {
+            a: "5", b: "3", limb_width: 4, n_limbs_a: 1, n_limbs_b: 1, expected: false,
+        }, true),
+        less_than_equal: (LessThan {
+            a: "200", b: "200", limb_width: 4, n_limbs_a: 2, n_limbs_b: 2, expected: false,
+        }, true),
+        less_than_wider_other: (LessThan {
+            a: "255", b: "256", limb_width: 4, n_limbs_a: 2, n_limbs_b: 3, expected: true,
+        }, true),
+        less_than_128b: (LessThan {
+            a: "298937084241820869743410128427022097023",
+            b: "311515013647097972396078794914139832177",
+            limb_width: 32,
+            n_limbs_a: 4,
+            n_limbs_b: 4,
+            expected: true,
+        }, true),
+        less_than_wrong: (LessThan {
+            a: "5", b: "3", limb_width: 4, n_limbs_a: 1, n_limbs_b: 1, expected: true,
+        }, false),
+    }
+
+    pub struct SubUnderflow<'a> {
+        a: &'a str,
+        b: &'a str,
+        diff: &'a str,
+        underflow: bool,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for SubUnderflow<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let alloc = |cs: &mut CS, name: &str, n: &str| {
+                BigNat::alloc_from_nat(
+                    cs.namespace(|| name.to_owned()),
+                    || Ok(Integer::from_str(n).unwrap()),
+                    4,
+                    2,
+                )
+            };
+            let a = alloc(cs, "a", self.a)?;
+            let b = alloc(cs, "b", self.b)?;
+            let expected = alloc(cs, "expected", self.diff)?;
+            let (underflow, diff) = a.sub_with_underflow(cs.namespace(|| "sub"), &b)?;
+            Gadget::assert_equal(cs.namespace(|| "diff eq"), &diff, &expected)?;
+            Boolean::enforce_equal(
+                cs.namespace(|| "underflow eq"),
+                &underflow,
+                &Boolean::constant(self.underflow),
+            )
+        }
+    }
+
+    circuit_tests! {
+        sub_no_underflow: (SubUnderflow { a: "200", b: "13", diff: "187", underflow: false }, true),
+        sub_equal: (SubUnderflow { a: "13", b: "13", diff: "0", underflow: false }, true),
+        sub_underflow: (SubUnderflow { a: "13", b: "200", diff: "69", underflow: true }, true),
+        sub_unflagged: (SubUnderflow { a: "13", b: "200", diff: "69", underflow: false }, false),
+        sub_wrong_diff: (SubUnderflow { a: "200", b: "13", diff: "186", underflow: false }, false),
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum FixedWidthOp {
+        Add,
+        Mult,
+        SaturatingAdd,
+        SaturatingSub,
+        SaturatingMult,
+    }
+
+    pub struct FixedWidth {
+        op: FixedWidthOp,
+        a: usize,
+        b: usize,
+        result: usize,
+        flag: bool,
+    }
+
+    impl<E: Engine> Circuit<E> for FixedWidth {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let alloc = |cs: &mut CS, name: &str, n: usize| {
+                BigNat::alloc_from_nat(
+                    cs.namespace(|| name.to_owned()),
+                    || Ok(Integer::from(n)),
+                    4,
+                    2,
+                )
+            };
+            let a = alloc(cs, "a", self.a)?;
+            let b = alloc(cs, "b", self.b)?;
+            let expected = alloc(cs, "expected", self.result)?;
+            let mut cs = cs.namespace(|| "op");
+            let (flag, result) = match self.op {
+                FixedWidthOp::Add => a.add_with_overflow(&mut cs, &b)?,
+                FixedWidthOp::Mult => a.mult_with_overflow(&mut cs, &b)?,
+                FixedWidthOp::SaturatingAdd => a.saturating_add(&mut cs, &b)?,
+                FixedWidthOp::SaturatingSub => a.saturating_sub(&mut cs, &b)?,
+                FixedWidthOp::SaturatingMult => a.saturating_mult(&mut cs, &b)?,
+            };
+            assert_eq!(result.params.n_limbs, 2);
+            result.equal_when_carried(cs.namespace(|| "result eq"), &expected)?;
+            Boolean::enforce_equal(
+                cs.namespace(|| "flag eq"),
+                &flag,
+                &Boolean::constant(self.flag),
+            )
+        }
+    }
+
+    circuit_tests! {
+        add_no_overflow: (FixedWidth { op: FixedWidthOp::Add, a: 200, b: 55, result: 255, flag: false }, true),
+        add_overflow: (FixedWidth { op: FixedWidthOp::Add, a: 200, b: 60, result: 4, flag: true }, true),
+        add_unflagged: (FixedWidth { op: FixedWidthOp::Add, a: 200, b: 60, result: 4, flag: false }, false),
+        mult_no_overflow: (FixedWidth { op: FixedWidthOp::Mult, a: 15, b: 17, result: 255, flag: false }, true),
+        mult_overflow: (FixedWidth { op: FixedWidthOp::Mult, a: 16, b: 17, result: 16, flag: true }, true),
+        mult_unflagged: (FixedWidth { op: FixedWidthOp::Mult, a: 16, b: 17, result: 16, flag: false }, false),
+        saturating_add: (FixedWidth { op: FixedWidthOp::SaturatingAdd, a: 200, b: 60, result: 255, flag: true }, true),
+        saturating_add_fits: (FixedWidth { op: FixedWidthOp::SaturatingAdd, a: 20, b: 60, result: 80, flag: false }, true),
+        saturating_sub: (FixedWidth { op: FixedWidthOp::SaturatingSub, a: 13, b: 200, result: 0, flag: true }, true),
+        saturating_sub_fits: (FixedWidth { op: FixedWidthOp::SaturatingSub, a: 200, b: 13, result: 187, flag: false }, true),
+        saturating_mult: (FixedWidth { op: FixedWidthOp::SaturatingMult, a: 16, b: 17, result: 255, flag: true }, true),
+        saturating_mult_wrapped: (FixedWidth { op: FixedWidthOp::SaturatingMult, a: 16, b: 17, result: 16, flag: true }, false),
+    }
+
+    pub struct BitLength {
+        n: usize,
+        n_bits: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for BitLength {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let mut n =
+                BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(Integer::from(self.n)), 4, 3)?;
+            n.assert_bit_length(cs.namespace(|| "length"), self.n_bits)?;
+            assert_eq!(n.params.min_bits, self.n_bits);
+            assert_eq!(n.params.max_bits, Some(self.n_bits));
+            Ok(())
+        }
+    }
+
+    circuit_tests! {
+        bit_length_exact: (BitLength { n: 0b1001_0110, n_bits: 8 }, true),
+        bit_length_limb_boundary: (BitLength { n: 0b1_0000, n_bits: 5 }, true),
+        bit_length_full: (BitLength { n: 0xfff, n_bits: 12 }, true),
+        bit_length_one: (BitLength { n: 1, n_bits: 1 }, true),
+        bit_length_too_short: (BitLength { n: 0b0101_0110, n_bits: 8 }, false),
+        bit_length_too_long: (BitLength { n: 0b1_0110_0000, n_bits: 8 }, false),
+        bit_length_zero: (BitLength { n: 0, n_bits: 1 }, false),
+    }
+
+    pub struct Select {
+        a: usize,
+        b: usize,
+        condition: bool,
+    }
+
+    impl<E: Engine> Circuit<E> for Select {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let a =
+                BigNat::alloc_from_nat(cs.namespace(|| "a"), || Ok(Integer::from(self.a)), 4, 2)?;
+            // Not well-formed: one limb is past the limb width.
+            let b =
+                BigNat::alloc_from_nat(cs.namespace(|| "b"), || Ok(Integer::from(self.b)), 4, 3)?
+                    .scale::<CS>(usize_to_f(3));
+            let condition = Boolean::from(AllocatedBit::alloc(
+                cs.namespace(|| "condition"),
+                Some(self.condition),
+            )?);
+            let out =
+                BigNat::conditionally_select(cs.namespace(|| "select"), &a, &b, &condition)?;
+            let expected = if self.condition { 3 * self.b } else { self.a };
+            assert_eq!(out.value, Some(Integer::from(expected)));
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(Integer::from(expected)),
+                4,
+                3,
+            )?;
+            out.equal_when_carried(cs.namespace(|| "eq"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        select_a: (Select { a: 200, b: 1000, condition: false }, true),
+        select_b: (Select { a: 200, b: 1000, condition: true }, true),
+    }
+
+    pub struct Swap {
+        a: usize,
+        b: usize,
+        condition: bool,
+        first: usize,
+        second: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for Swap {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let alloc = |cs: &mut CS, name: &str, n: usize, n_limbs: usize| {
+                BigNat::alloc_from_nat(
+                    cs.namespace(|| name.to_owned()),
+                    || Ok(Integer::from(n)),
+                    4,
+                    n_limbs,
+                )
+            };
+            let a = alloc(cs, "a", self.a, 2)?;
+            let b = alloc(cs, "b", self.b, 3)?;
+            let condition = Boolean::from(AllocatedBit::alloc(
+                cs.namespace(|| "condition"),
+                Some(self.condition),
+            )?);
+            let (first, second) =
+                BigNat::conditionally_swap(cs.namespace(|| "swap"), &a, &b, &condition)?;
+            let expected_first = alloc(cs, "expected first", self.first, 3)?;
+            let expected_second = alloc(cs, "expected second", self.second, 3)?;
+            first.equal(cs.namespace(|| "first eq"), &expected_first)?;
+            second.equal(cs.namespace(|| "second eq"), &expected_second)
+        }
+    }
+
+    circuit_tests! {
+        swap_off: (Swap { a: 200, b: 1000, condition: false, first: 200, second: 1000 }, true),
+        swap_on: (Swap { a: 200, b: 1000, condition: true, first: 1000, second: 200 }, true),
+        swap_on_unswapped: (Swap { a: 200, b: 1000, condition: true, first: 200, second: 1000 }, false),
+        swap_off_half_swapped: (Swap { a: 200, b: 1000, condition: false, first: 200, second: 200 }, false),
+    }
+
+    pub struct Shift {
+        n: &'static str,
+        bits: usize,
+        /// Whether to scale the input by 3 first, so that its limbs are past the limb width
+        scaled: bool,
+        shifted_right: &'static str,
+    }
+
+    impl<E: Engine> Circuit<E> for Shift {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let mut n = BigNat::alloc_from_nat(
+                cs.namespace(|| "n"),
+                || Ok(Integer::from_str(self.n).unwrap()),
+                8,
+                4,
+            )?;
+            if self.scaled {
+                n = n.scale::<CS>(usize_to_f(3));
+            }
+            let left = n.shl_const::<CS>(self.bits);
+            assert_eq!(left.value, n.value.clone().map(|v| v << self.bits as u32));
+            let doubled = n.add::<CS>(&n)?;
+            let back = left.shr_const(cs.namespace(|| "back"), self.bits)?;
+            back.equal_when_carried(cs.namespace(|| "round trip"), &n)?;
+            let right = n.shr_const(cs.namespace(|| "right"), self.bits)?;
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(Integer::from_str(self.shifted_right).unwrap()),
+                8,
+                right.params.n_limbs,
+            )?;
+            Gadget::assert_equal(cs.namespace(|| "eq"), &right, &expected)?;
+            doubled.equal_when_carried(cs.namespace(|| "double"), &n.shl_const::<CS>(1))
+        }
+    }
+
+    circuit_tests! {
+        shift_limbs: (Shift {
+            n: "4000000000", bits: 16, scaled: false, shifted_right: "61035",
+        }, true),
+        shift_bits: (Shift {
+            n: "4000000000", bits: 13, scaled: false, shifted_right: "488281",
+        }, true),
+        shift_scaled: (Shift {
+            n: "4000000000", bits: 13, scaled: true, shifted_right: "1464843",
+        }, true),
+        shift_wrong: (Shift {
+            n: "4000000000", bits: 13, scaled: false, shifted_right: "488282",
+        }, false),
+    }
+
+    pub struct DivConst {
+        n: &'static str,
+        k: usize,
+        /// Whether to use `div_const_exact`, rather than `div_const_floor`
+        exact: bool,
+        quotient: &'static str,
+        remainder: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for DivConst {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: Integer, n_limbs: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 8, n_limbs)
+            };
+            let n = nat(cs, "n", Integer::from_str(self.n).unwrap(), 4)?;
+            let expected = nat(cs, "quotient", Integer::from_str(self.quotient).unwrap(), 4)?;
+            let quotient = if self.exact {
+                n.div_const_exact(cs.namespace(|| "div"), self.k)?
+            } else {
+                let (quotient, remainder) = n.div_const_floor(cs.namespace(|| "div"), self.k)?;
+                let expected = nat(cs, "remainder", Integer::from(self.remainder), 1)?;
+                remainder.equal(cs.namespace(|| "remainder eq"), &expected)?;
+                quotient
+            };
+            quotient.equal_when_carried(cs.namespace(|| "quotient eq"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        div_const_exact: (DivConst {
+            n: "4000000000", k: 5, exact: true, quotient: "800000000", remainder: 0,
+        }, true),
+        div_const_inexact: (DivConst {
+            n: "4000000001", k: 5, exact: true, quotient: "800000000", remainder: 0,
+        }, false),
+        div_const_floor: (DivConst {
+            n: "4000000001", k: 7, exact: false, quotient: "571428571", remainder: 4,
+        }, true),
+        div_const_floor_by_one: (DivConst {
+            n: "4000000001", k: 1, exact: false, quotient: "4000000001", remainder: 0,
+        }, true),
+        div_const_floor_widest: (DivConst {
+            n: "4000000000", k: 255, exact: false, quotient: "15686274", remainder: 130,
+        }, true),
+        div_const_floor_wrong: (DivConst {
+            n: "4000000001", k: 7, exact: false, quotient: "571428571", remainder: 5,
+        }, false),
+    }
+
+    pub struct VarShift {
+        n: &'static str,
+        amount: usize,
+        max_amount: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for VarShift {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let value = Integer::from_str(self.n).unwrap();
+            let n = BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(value.clone()), 8, 4)?;
+            let amount =
+                AllocatedNum::alloc(cs.namespace(|| "amount"), || Ok(usize_to_f(self.amount)))?;
+            let left = n.shl_var(cs.namespace(|| "left"), &amount, self.max_amount)?;
+            let right = n.shr_var(cs.namespace(|| "right"), &amount, self.max_amount)?;
+            if self.amount <= self.max_amount {
+                let shift = self.amount as u32;
+                assert_eq!(left.value, Some(Integer::from(&value << shift)));
+                assert_eq!(right.value, Some(Integer::from(&value >> shift)));
+            }
+            let back = right.shl_var(cs.namespace(|| "back"), &amount, self.max_amount)?;
+            let low = value.keep_bits(self.amount as u32);
+            let low = BigNat::alloc_from_nat(cs.namespace(|| "low"), || Ok(low), 8, 4)?;
+            back.add::<CS>(&low)?
+                .equal_when_carried_regroup(cs.namespace(|| "round trip"), &n)
+        }
+    }
+
+    circuit_tests! {
+        var_shift_0: (VarShift { n: "4000000000", amount: 0, max_amount: 20 }, true),
+        var_shift_13: (VarShift { n: "4000000000", amount: 13, max_amount: 20 }, true),
+        var_shift_max: (VarShift { n: "4000000000", amount: 20, max_amount: 20 }, true),
+        var_shift_past_max: (VarShift { n: "4000000000", amount: 21, max_amount: 20 }, false),
+    }
+
+    pub struct ModInverseInputs<'a> {
+        pub a: &'a str,
+        pub m: &'a str,
+        pub inverse: &'a str,
+    }
+
+    pub struct ModInverseParams {
+        pub limb_width: usize,
+        pub n_limbs: usize,
+    }
+
+    pub struct ModInverse<'a> {
+        inputs: Option<ModInverseInputs<'a>>,
+        params: ModInverseParams,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for ModInverse<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let a = BigNat::alloc_from_nat(
+                cs.namespace(|| "a"),
+                || Ok(Integer::from_str(self.inputs.grab()?.a).unwrap()),
+                self.params.limb_width,
+                self.params.n_limbs,
+            )?;
+            let m = BigNat::alloc_from_nat(
+                cs.namespace(|| "m"),
+                || Ok(Integer::from_str(self.inputs.grab()?.m).unwrap()),
+                self.params.limb_width,
+                self.params.n_limbs,
+            )?;
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(Integer::from_str(self.inputs.grab()?.inverse).unwrap()),
+                self.params.limb_width,
+                self.params.n_limbs,
+            )?;
+            let inverse = a.mod_inverse(cs.namespace(|| "inverse"), &m)?;
+            Gadget::assert_equal(cs.namespace(|| "eq"), &inverse, &expected)
+        }
+    }
+
+    circuit_tests! {
+        mod_inverse_3_7: (ModInverse {
+            inputs: Some(ModInverseInputs { a: "3", m: "7", inverse: "5" }),
+            params: ModInverseParams { limb_width: 4, n_limbs: 2 },
+        }, true),
+        mod_inverse_128b: (ModInverse {
+            inputs: Some(ModInverseInputs {
+                a: "311515013647097972396078794914139832177",
+                m: "298937084241820869743410128427022097023",
+                inverse: "297164117655481686272311289548977043167",
+            }),
+            params: ModInverseParams { limb_width: 32, n_limbs: 4 },
+        }, true),
+        mod_inverse_wrong: (ModInverse {
+            inputs: Some(ModInverseInputs { a: "3", m: "7", inverse: "4" }),
+            params: ModInverseParams { limb_width: 4, n_limbs: 2 },
+        }, false),
+    }
+
+    #[test]
+    fn mod_inverse_requires_coprimality() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let circuit = ModInverse {
+            inputs: Some(ModInverseInputs { a: "6", m: "9", inverse: "0" }),
+            params: ModInverseParams { limb_width: 4, n_limbs: 2 },
+        };
+        assert!(circuit.synthesize(&mut cs).is_err());
+    }
+
+    #[test]
+    fn mod_inverse_rejects_forged_witness() {
+        // The honest inverse of 3 modulo 7 is 5. A prover claiming 4 overwrites the low limb of
+        // the inverse, whose high bits (and so its range check) are unchanged, and the product of
+        // 3 and the inverse to match. Then only the congruence catches it.
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let circuit = ModInverse {
+            inputs: Some(ModInverseInputs { a: "3", m: "7", inverse: "4" }),
+            params: ModInverseParams { limb_width: 4, n_limbs: 2 },
+        };
+        circuit.synthesize(&mut cs).unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("eq/0"));
+        cs.set("inverse/inverse/limb 0", usize_to_f(4));
+        cs.set("inverse/product is one/left/prod 0", usize_to_f(12));
+        let unsatisfied = cs.which_is_unsatisfied().unwrap();
+        assert!(unsatisfied.starts_with("inverse/product is one/"), "{}", unsatisfied);
+    }
+
+    pub struct Congruent {
+        a: &'static str,
+        b: &'static str,
+        m: &'static str,
+        /// Limbs of `a`, which may differ from those of `b` and `m`
+        a_limbs: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for Congruent {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: &str, n_limbs: usize| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, n_limbs)
+            };
+            let a = nat(cs, "a", self.a, self.a_limbs)?;
+            let b = nat(cs, "b", self.b, 4)?;
+            let m = nat(cs, "m", self.m, 4)?;
+            a.assert_congruent(cs.namespace(|| "a = b mod m"), &b, &m)?;
+            Ok(())
+        }
+    }
+
+    circuit_tests! {
+        congruent_small: (Congruent { a: "17", b: "3", m: "7", a_limbs: 1 }, true),
+        congruent_negative_quotient: (Congruent { a: "3", b: "17", m: "7", a_limbs: 1 }, true),
+        congruent_equal: (Congruent { a: "5", b: "5", m: "7", a_limbs: 1 }, true),
+        congruent_wide: (Congruent {
+            a: "98765432109876543210987654321098765432109876543210987654321098765432109876",
+            b: "172750895257590401689206260252134498552",
+            m: "298937084241820869743410128427022097023",
+            a_limbs: 8,
+        }, true),
+        congruent_wrong: (Congruent { a: "18", b: "3", m: "7", a_limbs: 1 }, false),
+        congruent_wrong_negative: (Congruent { a: "3", b: "18", m: "7", a_limbs: 1 }, false),
+    }
+
+    pub struct ReduceWithQuotient {
+        a: usize,
+        m: usize,
+        quotient: usize,
+        expected: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for ReduceWithQuotient {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: usize, n_limbs: usize| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(Integer::from(n)), 4, n_limbs)
+            };
+            let a = nat(cs, "a", self.a, 3)?;
+            let m = nat(cs, "m", self.m, 2)?;
+            let quotient = nat(cs, "quotient", self.quotient, 2)?;
+            quotient.assert_well_formed(cs.namespace(|| "quotient rangecheck"))?;
+            let expected = nat(cs, "expected", self.expected, 2)?;
+            let remainder = a.reduce_with_quotient(cs.namespace(|| "reduce"), &m, &quotient)?;
+            remainder.equal(cs.namespace(|| "eq"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        reduce_with_quotient: (ReduceWithQuotient { a: 1000, m: 77, quotient: 12, expected: 76 }, true),
+        // Only the relation is checked, so a smaller quotient leaves an unreduced remainder.
+        reduce_with_small_quotient: (ReduceWithQuotient { a: 1000, m: 77, quotient: 11, expected: 153 }, true),
+        reduce_with_quotient_wrong: (ReduceWithQuotient { a: 1000, m: 77, quotient: 12, expected: 75 }, false),
+    }
+
+    pub struct ModSqrt {
+        x: &'static str,
+        m: &'static str,
+        /// A non-residue to decide the residuosity of `x` with, and the expected residuosity
+        nonresidue: Option<(&'static str, bool)>,
+        /// A root to use in place of the computed one
+        root: Option<&'static str>,
+        limb_width: usize,
+        n_limbs: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for ModSqrt {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let (limb_width, n_limbs) = (self.limb_width, self.n_limbs);
+            let nat = |cs: &mut CS, name: &str, n: &str| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), limb_width, n_limbs)
+            };
+            let x = nat(cs, "x", self.x)?;
+            let m = nat(cs, "m", self.m)?;
+            match (self.nonresidue, self.root) {
+                (Some((w, residue)), _) => {
+                    let w = nat(cs, "nonresidue", w)?;
+                    let (is_residue, _) =
+                        BigNat::sqrt_or_nonresidue(cs.namespace(|| "sqrt"), &x, &m, &w)?;
+                    Boolean::enforce_equal(
+                        cs.namespace(|| "residuosity"),
+                        &is_residue,
+                        &Boolean::constant(residue),
+                    )
+                }
+                (None, Some(root)) => {
+                    let root = Integer::from_str(root).unwrap();
+                    BigNat::assert_sqrt_with(cs.namespace(|| "sqrt"), &x, &m, || Ok(root))
+                        .map(|_| ())
+                }
+                (None, None) => BigNat::assert_sqrt(cs.namespace(|| "sqrt"), &x, &m).map(|_| ()),
+            }
+        }
+    }
+
+    circuit_tests! {
+        sqrt_3_mod_4: (ModSqrt {
+            x: "2", m: "7", nonresidue: None, root: None, limb_width: 4, n_limbs: 2,
+        }, true),
+        sqrt_tonelli_shanks: (ModSqrt {
+            x: "642754969",
+            m: "998244353",
+            nonresidue: None,
+            root: None,
+            limb_width: 16,
+            n_limbs: 2,
+        }, true),
+        sqrt_given_root: (ModSqrt {
+            x: "642754969",
+            m: "998244353",
+            nonresidue: None,
+            root: Some("123456789"),
+            limb_width: 16,
+            n_limbs: 2,
+        }, true),
+        sqrt_wrong_root: (ModSqrt {
+            x: "642754969",
+            m: "998244353",
+            nonresidue: None,
+            root: Some("123456788"),
+            limb_width: 16,
+            n_limbs: 2,
+        }, false),
+        sqrt_residue: (ModSqrt {
+            x: "642754969",
+            m: "998244353",
+            nonresidue: Some(("3", true)),
+            root: None,
+            limb_width: 16,
+            n_limbs: 2,
+        }, true),
+        sqrt_nonresidue: (ModSqrt {
+            x: "5",
+            m: "998244353",
+            nonresidue: Some(("3", false)),
+            root: None,
+            limb_width: 16,
+            n_limbs: 2,
+        }, true),
+        sqrt_nonresidue_claimed_residue: (ModSqrt {
+            x: "5",
+            m: "998244353",
+            nonresidue: Some(("3", true)),
+            root: None,
+            limb_width: 16,
+            n_limbs: 2,
+        }, false),
+    }
+
+    pub struct ConstantModulus {
+        x: &'static str,
+        y: &'static str,
+        m: &'static str,
+        product: &'static str,
+    }
+
+    impl<E: Engine> Circuit<E> for ConstantModulus {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: &str| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4)
+            };
+            let x = nat(cs, "x", self.x)?;
+            let y = nat(cs, "y", self.y)?;
+            let expected = nat(cs, "product", self.product)?;
+            let m = BigNat::constant::<CS>(&Integer::from_str(self.m).unwrap(), 32, 4)?;
+            assert!(m.is_constant::<CS>() && !x.is_constant::<CS>());
+            m.assert_well_formed(cs.namespace(|| "m rangecheck"))?;
+            let (_, product) = x.mult_mod(cs.namespace(|| "x * y"), &y, &m)?;
+            product.equal(cs.namespace(|| "check"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        constant_modulus: (ConstantModulus {
+            x: "123456789012345678901234567890123456",
+            y: "98765432109876543210987654321098765",
+            m: "321521701247558003770554266015257202945",
+            product: "1589412592759522345575468551327511700",
+        }, true),
+        constant_modulus_wrong: (ConstantModulus {
+            x: "123456789012345678901234567890123456",
+            y: "98765432109876543210987654321098765",
+            m: "321521701247558003770554266015257202945",
+            product: "1589412592759522345575468551327511701",
+        }, false),
+    }
+
+    #[test]
+    fn constant_products_are_free() {
+        let m = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let count = |constant: bool| {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let x = BigNat::alloc_from_nat(cs.namespace(|| "x"), || Ok(Integer::from(3)), 32, 4)
+                .unwrap();
+            let m = if constant {
+                BigNat::constant::<TestConstraintSystem<Bn256>>(&m, 32, 4).unwrap()
+            } else {
+                BigNat::alloc_from_nat(cs.namespace(|| "m"), || Ok(m.clone()), 32, 4).unwrap()
+            };
+            let before = cs.num_constraints();
+            x.mult(cs.namespace(|| "x * m"), &m).unwrap();
+            assert!(cs.is_satisfied());
+            cs.num_constraints() - before
+        };
+        // The allocated product costs 7 constraints, one per coefficient.
+        assert_eq!(count(false), count(true) + 7);
+
+        // An uncarried product with a constant, as in `crt_recompose`, is free too.
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let x = BigNat::alloc_from_nat(cs.namespace(|| "x"), || Ok(Integer::from(3)), 32, 4)
+            .unwrap();
+        let c = BigNat::constant::<TestConstraintSystem<Bn256>>(&m, 32, 4).unwrap();
+        let before = cs.num_constraints();
+        x.mult_lazy(cs.namespace(|| "x * c"), &c).unwrap();
+        assert_eq!(cs.num_constraints(), before);
+    }
+
+    pub struct SumOfProducts {
+        factors: [&'static str; 4],
+        m: &'static str,
+        /// `(a * b + c * d) % m`, for the four factors
+        expected: &'static str,
+    }
+
+    impl<E: Engine> Circuit<E> for SumOfProducts {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: &str| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4)
+            };
+            let mut fs = Vec::new();
+            for (i, f) in self.factors.iter().enumerate() {
+                fs.push(nat(cs, &format!("factor {}", i), f)?);
+            }
+            let m = nat(cs, "m", self.m)?;
+            let expected = nat(cs, "expected", self.expected)?;
+            let sum = BigNat::sum_of_products_mod(
+                cs.namespace(|| "sum"),
+                &[(&fs[0], &fs[1]), (&fs[2], &fs[3])],
+                &m,
+            )?;
+            let carried = fs[0]
+                .mult_lazy(cs.namespace(|| "lazy"), &fs[1])?
+                .carry(cs.namespace(|| "carry"))?;
+            let product = fs[0].mult(cs.namespace(|| "eager"), &fs[1])?;
+            carried.equal(cs.namespace(|| "carried"), &product)?;
+            sum.equal(cs.namespace(|| "check"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        sum_of_products: (SumOfProducts {
+            factors: [
+                "123456789012345678901234567890123456",
+                "98765432109876543210987654321098765",
+                "31415926535897932384626433832795028",
+                "271828182845904523536028747135266249",
+            ],
+            m: "321521701247558003770554266015257202945",
+            expected: "162625734674505548968570394818526043237",
+        }, true),
+        sum_of_products_wrong: (SumOfProducts {
+            factors: [
+                "123456789012345678901234567890123456",
+                "98765432109876543210987654321098765",
+                "31415926535897932384626433832795028",
+                "271828182845904523536028747135266249",
+            ],
+            m: "321521701247558003770554266015257202945",
+            expected: "162625734674505548968570394818526043238",
+        }, false),
+    }
+
+    #[test]
+    fn sum_of_products_is_cheaper_than_mult_mods() {
+        let m = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let count = |lazy: bool| {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nats = Vec::new();
+            for i in 0..5 {
+                let n = if i == 4 { m.clone() } else { Integer::from(1000 + i) };
+                let name = format!("nat {}", i);
+                nats.push(BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4).unwrap());
+            }
+            let before = cs.num_constraints();
+            if lazy {
+                let products = [(&nats[0], &nats[1]), (&nats[2], &nats[3])];
+                BigNat::sum_of_products_mod(cs.namespace(|| "sum"), &products, &nats[4]).unwrap();
+            } else {
+                let (_, ab) = nats[0].mult_mod(cs.namespace(|| "ab"), &nats[1], &nats[4]).unwrap();
+                let (_, cd) = nats[2].mult_mod(cs.namespace(|| "cd"), &nats[3], &nats[4]).unwrap();
+                ab.add::<TestConstraintSystem<Bn256>>(&cd)
+                    .unwrap()
+                    .red_mod(cs.namespace(|| "sum"), &nats[4])
+                    .unwrap();
+            }
+            assert!(cs.is_satisfied());
+            cs.num_constraints() - before
+        };
+        assert!(count(true) < count(false));
+    }
+
+    #[test]
+    fn mulmod_fused_matches_native() {
+        let m = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let factors = [
+            "123456789012345678901234567890123456",
+            "98765432109876543210987654321098765",
+            "31415926535897932384626433832795028",
+            "271828182845904523536028747135266249",
+            "161803398874989484820458683436563811",
+        ];
+        // With 64-bit limbs, the fourth factor would overflow the field, so there are two
+        // reductions.
+        for &n_factors in &[1, 3, 5] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: String, n: &Integer| {
+                let n = n.clone();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 64, 2).unwrap()
+            };
+            let fs: Vec<_> = factors[..n_factors]
+                .iter()
+                .enumerate()
+                .map(|(i, f)| nat(format!("factor {}", i), &Integer::from_str(f).unwrap()))
+                .collect();
+            let modulus = nat("m".to_owned(), &m);
+            let product = BigNat::mulmod_fused(cs.namespace(|| "product"), &fs, &modulus).unwrap();
+            let expected = fs
+                .iter()
+                .fold(Integer::from(1), |acc, f| acc * f.value.as_ref().unwrap() % &m);
+            assert_eq!(product.value, Some(expected));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn lazy_product_tree_is_cheaper_than_mult_mods() {
+        // The shape of the exponent aggregation in `SetBench`: a 128-bit challenge in 32-bit
+        // limbs, and reduced factors.
+        let l = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let count = |lazy: bool| {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: String, n: Integer| {
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4).unwrap()
+            };
+            let l_nat = nat("l".to_owned(), l.clone());
+            let factors: Vec<_> = (0..8)
+                .map(|i| nat(format!("factor {}", i), Integer::from(&l - (1000 * i + 1))))
+                .collect();
+            let before = cs.num_constraints();
+            let product = if lazy {
+                BigNat::product_tree_mod(cs.namespace(|| "tree"), &factors, &l_nat).unwrap()
+            } else {
+                let mut acc = factors[0].clone();
+                for (i, f) in factors.iter().enumerate().skip(1) {
+                    let name = format!("fold {}", i);
+                    acc = acc.mult_mod(cs.namespace(|| name), f, &l_nat).unwrap().1;
+                }
+                acc
+            };
+            let expected = factors
+                .iter()
+                .fold(Integer::from(1), |acc, f| acc * f.value.as_ref().unwrap() % &l);
+            assert_eq!(product.value, Some(expected));
+            assert!(cs.is_satisfied());
+            cs.num_constraints() - before
+        };
+        let lazy = count(true);
+        let eager = count(false);
+        println!("product of 8 mod 128b: {} constraints lazy, {} eager", lazy, eager);
+        assert!(lazy < eager);
+    }
+
+    #[test]
+    fn mulmod_fused_is_cheaper_than_mult_mods_2048() {
+        let count = |fused: bool| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let mut nats = Vec::new();
+            for i in 0..4 {
+                nats.push(
+                    BigNat::<Bn256>::alloc_from_nat(
+                        cs.namespace(|| format!("nat {}", i)),
+                        || Err(SynthesisError::AssignmentMissing),
+                        32,
+                        64,
+                    )
+                    .unwrap(),
+                );
+            }
+            let before = cs.num_constraints();
+            if fused {
+                BigNat::mulmod_fused(cs.namespace(|| "abc"), &nats[..3], &nats[3]).unwrap();
+            } else {
+                let (_, ab) = nats[0].mult_mod(cs.namespace(|| "ab"), &nats[1], &nats[3]).unwrap();
+                nats[2].mult_mod(cs.namespace(|| "abc"), &ab, &nats[3]).unwrap();
+            }
+            cs.num_constraints() - before
+        };
+        let fused = count(true);
+        let chained = count(false);
+        println!("2048b product of three: {} constraints fused, {} chained", fused, chained);
+        assert!(fused < chained);
+    }
+
+    #[test]
+    fn product_tree_mod_matches_native() {
+        let m = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let factors = [
+            "123456789012345678901234567890123456",
+            "98765432109876543210987654321098765",
+            "31415926535897932384626433832795028",
+            "271828182845904523536028747135266249",
+            "161803398874989484820458683436563811",
+        ];
+        for n_factors in 1..factors.len() + 1 {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: String, n: &Integer| {
+                let n = n.clone();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4).unwrap()
+            };
+            let fs: Vec<_> = factors[..n_factors]
+                .iter()
+                .enumerate()
+                .map(|(i, f)| nat(format!("factor {}", i), &Integer::from_str(f).unwrap()))
+                .collect();
+            let modulus = nat("m".to_owned(), &m);
+            let product =
+                BigNat::product_tree_mod(cs.namespace(|| "product"), &fs, &modulus).unwrap();
+            let expected = fs
+                .iter()
+                .fold(Integer::from(1), |acc, f| acc * f.value.as_ref().unwrap() % &m);
+            assert_eq!(product.value, Some(expected));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn lazy_mod_add_matches_native() {
+        let m = (Integer::from(1) << 249) - 91;
+        let a = (Integer::from(1) << 248) + 12345;
+        let b = (Integer::from(3) << 246) + 67890;
+        let c = (Integer::from(5) << 245) + 13579;
+        let d = (Integer::from(7) << 244) + 24680;
+        // With 125-bit limbs, the limbs of a product are nearly as wide as the field, so adding
+        // two products must first reduce one of them.
+        for &limb_width in &[32, 125] {
+            let n_limbs = (250 - 1) / limb_width + 1;
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let nats: Vec<BigNat<Bn256>> = [&a, &b, &c, &d, &m]
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let v = (*v).clone();
+                    BigNat::alloc_from_nat(
+                        cs.namespace(|| format!("nat {}", i)),
+                        || Ok(v),
+                        limb_width,
+                        n_limbs,
+                    )
+                    .unwrap()
+                })
+                .collect();
+            let lazy = |i: usize| LazyMod::new(nats[i].clone(), &nats[4]).unwrap();
+            let ab = lazy(0).mult(cs.namespace(|| "ab"), &lazy(1)).unwrap();
+            let cd = lazy(2).mult(cs.namespace(|| "cd"), &lazy(3)).unwrap();
+            let sum = ab
+                .add(cs.namespace(|| "ab + cd"), &cd)
+                .unwrap()
+                .add(cs.namespace(|| "ab + cd + a"), &lazy(0))
+                .unwrap();
+            let result = sum.finish(cs.namespace(|| "reduce")).unwrap();
+            let expected = (Integer::from(&a * &b) + Integer::from(&c * &d) + &a) % &m;
+            assert_eq!(result.value, Some(expected));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn eval_poly_mod_matches_native() {
+        let m = Integer::from_str("321521701247558003770554266015257202945").unwrap();
+        let x = Integer::from_str("98765432109876543210987654321098765").unwrap();
+        let coefficients = [
+            "123456789012345678901234567890123456",
+            "0",
+            "31415926535897932384626433832795028",
+            "271828182845904523536028747135266249",
+        ];
+        for n_coefficients in 1..=coefficients.len() {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let mut nat = |name: String, n: &Integer| {
+                let n = n.clone();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 32, 4).unwrap()
+            };
+            let coeffs: Vec<_> = coefficients[..n_coefficients]
+                .iter()
+                .enumerate()
+                .map(|(i, c)| nat(format!("c {}", i), &Integer::from_str(c).unwrap()))
+                .collect();
+            let point = nat("x".to_owned(), &x);
+            let modulus = nat("m".to_owned(), &m);
+            let value =
+                BigNat::eval_poly_mod(cs.namespace(|| "eval"), &coeffs, &point, &modulus).unwrap();
+            let expected = coefficients[..n_coefficients]
+                .iter()
+                .rev()
+                .fold(Integer::from(0), |acc, c| {
+                    (acc * &x + Integer::from_str(c).unwrap()) % &m
+                });
+            assert_eq!(value.value, Some(expected));
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct Crt {
+        residues: &'static [&'static str],
+        moduli: &'static [&'static str],
+        expected: &'static str,
+    }
+
+    impl<E: Engine> Circuit<E> for Crt {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let residues = self
+                .residues
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    let r = Integer::from_str(r).unwrap();
+                    BigNat::alloc_from_nat(cs.namespace(|| format!("r {}", i)), || Ok(r), 32, 2)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let moduli: Vec<Integer> = self
+                .moduli
+                .iter()
+                .map(|m| Integer::from_str(m).unwrap())
+                .collect();
+            let x = BigNat::crt_recompose(cs.namespace(|| "crt"), &residues, &moduli)?;
+            let expected = Integer::from_str(self.expected).unwrap();
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(expected),
+                32,
+                x.limbs.len(),
+            )?;
+            Gadget::assert_equal(cs.namespace(|| "eq"), &x, &expected)
+        }
+    }
+
+    circuit_tests! {
+        crt_small: (Crt {
+            residues: &["2", "3", "2"],
+            moduli: &["3", "5", "7"],
+            expected: "23",
+        }, true),
+        crt_unreduced: (Crt {
+            residues: &["11", "13", "100"],
+            moduli: &["3", "5", "7"],
+            expected: "23",
+        }, true),
+        crt_wide: (Crt {
+            residues: &["1234567890123456789", "987654321", "424242"],
+            moduli: &["2305843009213693951", "2147483647", "1000003"],
+            expected: "3540952104042744335818826567775693",
+        }, true),
+        crt_wrong: (Crt {
+            residues: &["2", "3", "2"],
+            moduli: &["3", "5", "7"],
+            expected: "24",
+        }, false),
+        crt_wrong_by_product: (Crt {
+            residues: &["2", "3", "2"],
+            moduli: &["3", "5", "7"],
+            expected: "128",
+        }, false),
+    }
+
+    #[test]
+    fn crt_requires_coprime_moduli() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let circuit = Crt { residues: &["1", "1"], moduli: &["4", "6"], expected: "1" };
+        assert!(circuit.synthesize(&mut cs).is_err());
+    }
+
+    pub struct MillerRabin32Inputs<'a> {
+        pub n: &'a str,
+        pub result: bool,
+    }
+
+    pub struct MillerRabin32<'a> {
+        inputs: Option<MillerRabin32Inputs<'a>>,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for MillerRabin32<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            use sapling_crypto::circuit::boolean::AllocatedBit;
+            let n = BigNat::alloc_from_nat(
+                cs.namespace(|| "n"),
+                || Ok(Integer::from_str(self.inputs.grab()?.n).unwrap()),
+                32,
+                1,
+            )?;
+            let expected_res = Boolean::Is(AllocatedBit::alloc(
+                cs.namespace(|| "bit"),
+                self.inputs.map(|o| o.result),
+            )?);
+            let actual_res = n.miller_rabin_32b(cs.namespace(|| "mr"))?;
+            Boolean::enforce_equal(cs.namespace(|| "eq"), &expected_res, &actual_res)?;
+            Ok(())
+        }
+    }
+    circuit_tests! {
+        mr_32: (
+                          MillerRabin32 {
+                              inputs: Some(MillerRabin32Inputs {
+                                  n: "251",
+                                  result: true,
+                              }),
+                          },
+                          true),
+    }
+
+    pub struct RelimbInputs<'a> {
+        pub n: &'a str,
+    }
+
+    pub struct RelimbParams {
+        pub from_width: usize,
+        pub from_limbs: usize,
+        pub to_width: usize,
+        pub to_limbs: usize,
+    }
+
+    pub struct Relimb<'a> {
+        inputs: Option<RelimbInputs<'a>>,
+        params: RelimbParams,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for Relimb<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let n = BigNat::alloc_from_nat(
+                cs.namespace(|| "n"),
+                || Ok(Integer::from_str(self.inputs.grab()?.n).unwrap()),
+                self.params.from_width,
+                self.params.from_limbs,
+            )?;
+            n.assert_well_formed(cs.namespace(|| "n wf"))?;
+            let limbs = n.to_allocated_limbs(
+                cs.namespace(|| "to"),
+                self.params.to_width,
+                self.params.to_limbs,
+            )?;
+            let m = BigNat::from_allocated_limbs(
+                cs.namespace(|| "from"),
+                &limbs,
+                self.params.to_width,
+                self.params.from_width,
+                self.params.from_limbs,
+            )?;
+            n.equal(cs.namespace(|| "round trip"), &m)
+        }
+    }
+
+    circuit_tests! {
+        relimb_32_to_64: (Relimb {
+            inputs: Some(RelimbInputs { n: "340282366920938463463374607431768211455" }),
+            params: RelimbParams { from_width: 32, from_limbs: 4, to_width: 64, to_limbs: 2 },
+        }, true),
+        relimb_32_to_48: (Relimb {
+            inputs: Some(RelimbInputs { n: "123456789012345678901234567890" }),
+            params: RelimbParams { from_width: 32, from_limbs: 4, to_width: 48, to_limbs: 3 },
+        }, true),
+        relimb_64_to_32: (Relimb {
+            inputs: Some(RelimbInputs { n: "123456789012345678901234567890" }),
+            params: RelimbParams { from_width: 64, from_limbs: 2, to_width: 32, to_limbs: 4 },
+        }, true),
+        relimb_overflow: (Relimb {
+            inputs: Some(RelimbInputs { n: "340282366920938463463374607431768211455" }),
+            params: RelimbParams { from_width: 32, from_limbs: 4, to_width: 64, to_limbs: 1 },
+        }, false),
+    }
+
+    pub struct RelimbSum {
+        a: &'static str,
+        b: &'static str,
+        /// `a + b`, in limbs of width `to_width`
+        sum: &'static str,
+        to_width: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for RelimbSum {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let nat = |cs: &mut CS, name: &str, n: &str, limb_width: usize, n_limbs: usize| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), limb_width, n_limbs)
+            };
+            let a = nat(cs, "a", self.a, 32, 4)?;
+            let b = nat(cs, "b", self.b, 32, 4)?;
+            let sum = nat(cs, "sum", self.sum, self.to_width, 128 / self.to_width + 1)?;
+            let relimbed = a
+                .add::<CS>(&b)?
+                .relimb(cs.namespace(|| "relimb"), self.to_width)?;
+            assert_eq!(relimbed.params.limb_width, self.to_width);
+            relimbed.equal(cs.namespace(|| "check"), &sum)?;
+            let back = relimbed.relimb(cs.namespace(|| "back"), 32)?;
+            back.equal_when_carried(cs.namespace(|| "round trip"), &a.add::<CS>(&b)?)
+        }
+    }
+
+    circuit_tests! {
+        relimb_sum_to_64: (RelimbSum {
+            a: "340282366920938463463374607431768211455",
+            b: "1",
+            sum: "340282366920938463463374607431768211456",
+            to_width: 64,
+        }, true),
+        relimb_sum_to_48: (RelimbSum {
+            a: "123456789012345678901234567890",
+            b: "98765432109876543210987654321",
+            sum: "222222221122222222112222222211",
+            to_width: 48,
+        }, true),
+        relimb_sum_wrong: (RelimbSum {
+            a: "123456789012345678901234567890",
+            b: "98765432109876543210987654321",
+            sum: "222222221122222222112222222212",
+            to_width: 48,
+        }, false),
+    }
+
+    #[test]
+    fn karatsuba_mult_matches_schoolbook() {
+        let a = Integer::from_str("311515013647097972396078794914139832177").unwrap();
+        let b = Integer::from_str("298937084241820869743410128427022097023").unwrap();
+        let mut counts = Vec::new();
+        for &threshold in &[usize::max_value(), 8, 3, 1] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let x = BigNat::alloc_from_nat(cs.namespace(|| "a"), || Ok(a.clone()), 16, 8).unwrap();
+            let y = BigNat::alloc_from_nat(cs.namespace(|| "b"), || Ok(b.clone()), 16, 9).unwrap();
+            let prod = x
+                .mult_karatsuba(cs.namespace(|| "mult"), &y, threshold)
+                .unwrap();
+            assert_eq!(prod.value, Some(Integer::from(&a * &b)));
+            assert!(cs.is_satisfied());
+            counts.push(cs.num_constraints());
+        }
+        // Operands of 8 limbs are not split at threshold 8.
+        assert_eq!(counts[0], counts[1]);
+        assert!(counts[2] > counts[1]);
+    }
+
+    #[test]
+    fn decompose_bounded_enforces_bound() {
+        for &(n, ok) in &[(200u32, true), (300u32, false)] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let a = BigNat::alloc_from_nat(
+                cs.namespace(|| "a"),
+                || Ok(Integer::from(n)),
+                4,
+                4,
+            )
+            .unwrap()
+            .with_max_bits(8);
+            let bits = a.decompose_bounded(cs.namespace(|| "bits")).unwrap();
+            assert_eq!(bits.bits.len(), 8);
+            assert_eq!(cs.is_satisfied(), ok);
+        }
+    }
+
+    #[test]
+    fn inputize_encoded_matches_nat_to_limbs_with() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let value = Integer::from_str("123456789012345").unwrap();
+        let msb_bits = Encoding {
+            bit_order: BitOrder::MsbFirst,
+            ..Encoding::EVM
+        };
+        for encoding in &[Encoding::NATIVE, Encoding::EVM, msb_bits] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let n =
+                BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(value.clone()), 16, 4).unwrap();
+            n.inputize_encoded(cs.namespace(|| "input"), encoding).unwrap();
+            assert!(cs.is_satisfied());
+            let expected = nat_to_limbs_with::<F>(&value, 16, 4, encoding).unwrap();
+            assert_eq!(limbs_to_nat_with(&expected, 16, encoding).unwrap(), value);
+            for (i, e) in expected.iter().enumerate() {
+                assert_eq!(cs.get_input(i + 1, &format!("input/limb {}/alloc", i)), *e);
+            }
+        }
+        assert_eq!(
+            nat_to_limbs_with::<F>(&value, 16, 4, &Encoding::NATIVE).unwrap(),
+            nat_to_limbs::<F>(&value, 16, 4).unwrap()
+        );
+    }
+
+    #[quickcheck]
+    fn nat_limbs_round_trip(n: u64, limb_width: u8, big_endian: bool) -> TestResult {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        if limb_width == 0 || limb_width > 70 {
+            return TestResult::discard();
+        }
+        let limb_width = limb_width as usize;
+        let n = Integer::from(n);
+        let n_limbs = (n.significant_bits() as usize).saturating_sub(1) / limb_width + 1;
+        let encoding = Encoding {
+            limb_order: if big_endian {
+                LimbOrder::MostSignificantFirst
+            } else {
+                LimbOrder::LeastSignificantFirst
+            },
+            ..Encoding::NATIVE
+        };
+        let limbs = nat_to_limbs_with::<F>(&n, limb_width, n_limbs, &encoding).unwrap();
+        TestResult::from_bool(limbs_to_nat_with(&limbs, limb_width, &encoding).unwrap() == n)
+    }
+
+    #[test]
+    fn nat_limb_conversions_reject_mismatched_widths() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let value = Integer::from(0x1_2345);
+        assert!(nat_to_limbs::<F>(&value, 8, 2).is_err());
+        assert!(nat_to_limbs::<F>(&Integer::from(-1), 8, 2).is_err());
+        assert!(nat_to_limbs::<F>(&value, 256, 1).is_err());
+        assert!(nat_to_limbs_with::<F>(&value, 256, 1, &Encoding::EVM).is_err());
+        assert_eq!(
+            limbs_to_nat_with(
+                &nat_to_limbs_with::<F>(&value, 128, 2, &Encoding::EVM).unwrap(),
+                128,
+                &Encoding::EVM
+            )
+            .unwrap(),
+            value
+        );
+        let limbs = nat_to_limbs::<F>(&value, 16, 2).unwrap();
+        assert!(limbs_to_nat_with(&limbs, 8, &Encoding::NATIVE).is_err());
+        assert_eq!(limbs_to_nat_with(&limbs, 16, &Encoding::NATIVE).unwrap(), value);
+    }
+
+    #[test]
+    fn public_inputs_for_matches_inputize_with() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let value = (Integer::from(1) << 1000) + 123456789;
+        for &packing in &[
+            InputPacking::Unpacked,
+            InputPacking::Dual { limb_width: 32 },
+            InputPacking::Max { limb_width: 32 },
+        ] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let n =
+                BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(value.clone()), 32, 32).unwrap();
+            Gadget::inputize_with(&n, cs.namespace(|| "input"), packing).unwrap();
+            assert!(cs.is_satisfied());
+            let expected = public_inputs_for::<F>(&value, 32, 32, packing).unwrap();
+            assert_eq!(cs.num_inputs(), expected.len() + 1);
+            for (i, e) in expected.iter().enumerate() {
+                assert_eq!(cs.get_input(i + 1, &format!("input/{}/in", i)), *e);
+            }
+        }
+        let misdeclared = InputPacking::Dual { limb_width: 16 };
+        assert!(public_inputs_for::<F>(&value, 32, 32, misdeclared).is_err());
+        assert!(public_inputs_for::<F>(&value, 32, 31, InputPacking::Unpacked).is_err());
+    }
+
+    #[test]
+    fn as_allocated_nums_follows_schema() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let value = Integer::from_str("123456789012345").unwrap();
+        let n = BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(value.clone()), 16, 4).unwrap();
+        let (nums, schema) = n.as_allocated_nums(cs.namespace(|| "lower")).unwrap();
+        assert_eq!(schema.limb_width, 16);
+        assert_eq!(schema.n_limbs, 4);
+        let expected = schema.limbs_of(&value).unwrap();
+        let actual: Vec<_> = nums.iter().map(|n| n.get_value().unwrap()).collect();
+        assert_eq!(actual, expected);
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn from_allocated_num_is_canonical() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let mut minus_one = F::zero();
+        minus_one.sub_assign(&F::one());
+        for (i, f) in [F::zero(), usize_to_f(12345), minus_one].iter().enumerate() {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let num =
+                AllocatedNum::alloc(cs.namespace(|| format!("num {}", i)), || Ok(*f)).unwrap();
+            let n = BigNat::from_allocated_num(cs.namespace(|| "nat"), &num, 32).unwrap();
+            assert_eq!(n.value, Some(f_to_nat(f)));
+            assert_eq!(n.params.n_limbs, (F::NUM_BITS as usize - 1) / 32 + 1);
+            assert!(cs.is_satisfied());
+        }
+    }
+
+    #[test]
+    fn reduce_to_field_matches_native() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let mut minus_one = F::zero();
+        minus_one.sub_assign(&F::one());
+        let p = f_to_nat(&minus_one) + 1;
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let a = (Integer::from(1) << 300) + 12345;
+        let b = Integer::from_str("98765432109876543210987654321098765").unwrap();
+        let a_nat = BigNat::alloc_from_nat(cs.namespace(|| "a"), || Ok(a.clone()), 32, 10).unwrap();
+        let b_nat = BigNat::alloc_from_nat(cs.namespace(|| "b"), || Ok(b.clone()), 32, 4).unwrap();
+        // The limbs of the product are uncarried.
+        let product = a_nat.mult_lazy(cs.namespace(|| "ab"), &b_nat).unwrap();
+        for (name, nat, value) in vec![("a", a_nat, a.clone()), ("ab", product, a * b)] {
+            let reduced = nat.reduce_to_field(cs.namespace(|| name)).unwrap();
+            assert_eq!(reduced.get_value(), nat_to_f(&(value % &p)));
+        }
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn recommended_limb_schema_multiplies() {
+        use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let schema = LimbSchema::recommend::<F>(2048).unwrap();
+        assert_eq!((schema.limb_width, schema.n_limbs), (123, 17));
+        assert!(LimbSchema::for_products::<F>(124, 2048).is_err());
+        assert!(LimbSchema::recommend::<F>(0).is_err());
+
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let m = (Integer::from(1) << 2047) + 1231;
+        let a = Integer::from(&m - 12345);
+        let b = Integer::from(&m >> 1);
+        let mut nat = |name: &str, n: &Integer| {
+            let n = n.clone();
+            BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), 123, 17).unwrap()
+        };
+        let (a_nat, b_nat, m_nat) = (nat("a", &a), nat("b", &b), nat("m", &m));
+        let (_, product) = a_nat.mult_mod(cs.namespace(|| "ab"), &b_nat, &m_nat).unwrap();
+        assert_eq!(product.value, Some(a * b % &m));
+        assert!(cs.is_satisfied());
     }
 }
 
@@ -2541,6 +6524,7 @@ impl Debug for BigNatParams {
             .field("limb_width", &self.limb_width)
             .field("n_limbs", &self.n_limbs)
             .field("min_bits", &self.min_bits)
+            .field("max_bits", &self.max_bits)
             .field("max_word", &format_args!("{}", &self.max_word))
             .finish()
     }
@@ -1,3 +1,9 @@
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, Namespace, SynthesisError};
+
+use util::bit::Bit;
+use util::gadget::Gadget;
+
 /// Computes the optimal window size, k, for a windowed (Bauer) exponentiation with an n-bit
 /// exponent.
 pub fn optimal_k(n: usize) -> usize {
@@ -11,3 +17,73 @@ pub fn optimal_k(n: usize) -> usize {
     }
     unreachable!()
 }
+
+/// Computes the product of `bases[i]^exps[i]`, given the bits of each exponent (low-order first),
+/// using simultaneous (Shamir's trick) exponentiation: the products of all subsets of the bases
+/// are tabulated, and then each exponent bit position costs one squaring and one multiplication,
+/// shared by all the bases. `mul` multiplies two values, and `one` is the empty product.
+///
+/// Returns `None` if the exponents have no bits. This backs both `BigNat::multi_pow_mod` and
+/// `CircuitSemiGroup::multi_power`.
+pub fn simultaneous_power<E, T, CS, F>(
+    mut cs: CS,
+    bases: &[T],
+    mut exp_bits: Vec<Vec<Bit<E>>>,
+    one: T,
+    mut mul: F,
+) -> Result<Option<T>, SynthesisError>
+where
+    E: Engine,
+    T: Gadget<E = E>,
+    CS: ConstraintSystem<E>,
+    F: for<'c> FnMut(Namespace<'c, E, CS::Root>, &T, &T) -> Result<T, SynthesisError>,
+{
+    if bases.len() != exp_bits.len() {
+        eprintln!(
+            "simultaneous_power got {} bases but {} exponents",
+            bases.len(),
+            exp_bits.len()
+        );
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    // table[mask] is the product of the bases whose index bits are set in mask.
+    let mut table = vec![one];
+    for mask in 1usize..(1 << bases.len()) {
+        let i = mask.trailing_zeros() as usize;
+        let rest = mask & (mask - 1);
+        let entry = if rest == 0 {
+            bases[i].clone()
+        } else {
+            mul(
+                cs.namespace(|| format!("table {}", mask)),
+                &table[rest],
+                &bases[i],
+            )?
+        };
+        table.push(entry);
+    }
+    let n_bits = exp_bits.iter().map(Vec::len).max().unwrap_or(0);
+    for bs in &mut exp_bits {
+        while bs.len() < n_bits {
+            bs.push(Bit {
+                bit: LinearCombination::zero(),
+                value: Some(false),
+            });
+        }
+    }
+    let mut acc: Option<T> = None;
+    for j in (0..n_bits).rev() {
+        let mut cs = cs.namespace(|| format!("bit {}", j));
+        // mux_tree consumes the most significant selector first.
+        let selector: Vec<Bit<E>> = exp_bits.iter().rev().map(|bs| bs[j].clone()).collect();
+        let term = Gadget::mux_tree(cs.namespace(|| "select"), selector.iter(), &table)?;
+        acc = Some(match acc {
+            None => term,
+            Some(a) => {
+                let sq = mul(cs.namespace(|| "square"), &a, &a)?;
+                mul(cs.namespace(|| "prod"), &sq, &term)?
+            }
+        });
+    }
+    Ok(acc)
+}
@@ -0,0 +1,393 @@
+//! The Jacobi symbol of a `BigNat` modulo an odd `BigNat`.
+//!
+//! The symbol is computed as by the Euclidean algorithm: each step writes `a = 2^k * odd`,
+//! accounts for `(2 / n)^k`, swaps `odd` and `n` by quadratic reciprocity, and reduces `n` modulo
+//! `odd`. The algorithm ends at `a = 0`, where `n` is the gcd of the inputs. A circuit cannot
+//! branch, so it runs a fixed number of steps, and those past the end leave the state unchanged.
+//!
+//! The number of twos, the odd part, the quotient and the remainder are witnesses, checked by
+//! multiplication, so a step does no division in-circuit. It still costs two full-width products
+//! and a few range checks, and inputs of `bits` bits need up to `3 * bits / 2 + 3` steps, so the
+//! gadget is expensive: prefer a native computation when the inputs are public.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::Field;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use super::bignat::BigNat;
+use util::bit::Bit;
+use util::convert::usize_to_f;
+use util::num::Num;
+use OptionExt;
+
+use std::cmp::max;
+
+/// Returns the Jacobi symbol `(a / n)`, as a number which is `-1`, `0` or `1`. Both inputs must
+/// be well-formed, and `n` is constrained to be odd.
+///
+/// The algorithm is run for `max_steps` steps, and must have finished by then: `3 * bits / 2 + 3`
+/// steps always suffice for inputs of at most `bits` bits.
+pub fn jacobi<E, CS>(
+    mut cs: CS,
+    a: &BigNat<E>,
+    n: &BigNat<E>,
+    max_steps: usize,
+) -> Result<Num<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    a.enforce_limb_width_agreement(n, "jacobi")?;
+    if a.params.limb_width < 3 || max_steps == 0 {
+        eprintln!(
+            "Jacobi symbol with a limb width of {} in {} steps",
+            a.params.limb_width, max_steps
+        );
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    if let Some(n) = n.value.as_ref() {
+        if n.is_even() {
+            eprintln!("Jacobi modulus {} is even", n);
+            return Err(SynthesisError::Unsatisfiable);
+        }
+    }
+    let n_limbs = max(a.params.n_limbs, n.params.n_limbs);
+    let mut a = a.with_n_limbs::<CS>(n_limbs);
+    let mut n = n.with_n_limbs::<CS>(n_limbs);
+    let mut negative = Bit::new_false::<CS>();
+    for i in 0..max_steps {
+        let (next_a, next_n, next_negative) =
+            step(cs.namespace(|| format!("step {}", i)), &a, &n, &negative)?;
+        a = next_a;
+        n = next_n;
+        negative = next_negative;
+    }
+    cs.enforce(
+        || "finished",
+        |lc| lc,
+        |lc| lc,
+        |_| limb_sum(&a),
+    );
+    // Since `n` is odd, its low limb is positive, and `n - 1` is zero iff its limb sum is.
+    let n_minus_one = limb_sum(&n) - CS::one();
+    let n_minus_one_value = limb_sum_value(&n).map(|mut s| {
+        s.sub_assign(&E::Fr::one());
+        s
+    });
+    let not_unit = is_nonzero(cs.namespace(|| "gcd is not one"), n_minus_one, n_minus_one_value)?;
+    let is_unit = Bit::from_sapling::<CS>(Boolean::from(not_unit).not());
+    let unit_and_negative = and(cs.namespace(|| "unit and negative"), &is_unit, &negative)?;
+    let value = is_unit.value.and_then(|u| {
+        negative.value.map(|neg| match (u, neg) {
+            (false, _) => E::Fr::zero(),
+            (true, false) => E::Fr::one(),
+            (true, true) => {
+                let mut minus_one = E::Fr::one();
+                minus_one.negate();
+                minus_one
+            }
+        })
+    });
+    Ok(Num::new(
+        value,
+        is_unit.bit - (usize_to_f(2), &unit_and_negative.bit),
+    ))
+}
+
+/// One step from `(a, n)` and the sign of the symbol so far. With `a = 2^k * odd` and
+/// `n = q * odd + r`, `(a / n) = (2 / n)^k * (r / odd) * (-1)^((odd - 1) * (n - 1) / 4)`.
+///
+/// The remainder is only constrained to be congruent to `n`, which is all the symbol depends on.
+fn step<E, CS>(
+    mut cs: CS,
+    a: &BigNat<E>,
+    n: &BigNat<E>,
+    negative: &Bit<E>,
+) -> Result<(BigNat<E>, BigNat<E>, Bit<E>), SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let limb_width = a.params.limb_width;
+    let n_limbs = a.params.n_limbs;
+    let width = limb_width * n_limbs;
+    let not_done = is_nonzero(
+        cs.namespace(|| "a is not zero"),
+        limb_sum(a),
+        limb_sum_value(a),
+    )?;
+    let done = Boolean::from(not_done.clone()).not();
+    // Once done, the step runs on `a = 1` instead, and its result is discarded.
+    let one = BigNat::one::<CS>(limb_width);
+    let a = BigNat::conditionally_select(cs.namespace(|| "a or one"), a, &one, &done)?;
+
+    let twos_value = a
+        .value
+        .as_ref()
+        .map(|a| a.find_one(0).unwrap_or(0) as usize);
+    let twos = AllocatedNum::alloc(cs.namespace(|| "twos"), || {
+        Ok(usize_to_f(*twos_value.grab()?))
+    })?;
+    let pow = BigNat::pow2(cs.namespace(|| "2^twos"), &twos, width - 1, limb_width)?;
+    let odd = BigNat::alloc_from_nat(
+        cs.namespace(|| "odd"),
+        || Ok(Integer::from(a.value.grab()? >> *twos_value.grab()? as u32)),
+        limb_width,
+        n_limbs,
+    )?;
+    odd.assert_well_formed(cs.namespace(|| "odd rangecheck"))?;
+    odd.mult(cs.namespace(|| "odd * 2^twos"), &pow)?
+        .equal_when_carried_regroup(cs.namespace(|| "odd part"), &a)?;
+
+    let twos_bits = Integer::from(width - 1).significant_bits() as usize;
+    let twos_num = Num::new(twos.get_value(), LinearCombination::zero() + twos.get_variable());
+    let twos_odd = low_bits(cs.namespace(|| "twos parity"), &twos_num, twos_bits, 1)?.remove(0);
+    let odd_bits = low_limb_bits(cs.namespace(|| "odd bits"), &odd, 2)?;
+    let modulus_bits = low_limb_bits(cs.namespace(|| "n bits"), n, 3)?;
+
+    // `(2 / n)` is `-1` iff `n` is 3 or 5 mod 8.
+    let n_1_and_2 = and(cs.namespace(|| "n bits 1 and 2"), &modulus_bits[1], &modulus_bits[2])?;
+    let two_is_negative = xor(&modulus_bits[1], &modulus_bits[2], &n_1_and_2);
+    let twos_flip = and(cs.namespace(|| "twos flip"), &twos_odd, &two_is_negative)?;
+    // Reciprocity flips the sign iff both numbers are 3 mod 4.
+    let reciprocity_flip = and(
+        cs.namespace(|| "reciprocity flip"),
+        &odd_bits[1],
+        &modulus_bits[1],
+    )?;
+    let both_flips = and(cs.namespace(|| "both flips"), &twos_flip, &reciprocity_flip)?;
+    let flip = xor(&twos_flip, &reciprocity_flip, &both_flips);
+    let flip = and(
+        cs.namespace(|| "flip unless done"),
+        &flip,
+        &Bit::from_sapling::<CS>(Boolean::from(not_done)),
+    )?;
+    let negative_and_flip = and(cs.namespace(|| "negative and flip"), negative, &flip)?;
+    let next_negative = xor(negative, &flip, &negative_and_flip);
+
+    let quotient_remainder = n.value.as_ref().and_then(|n| {
+        odd.value
+            .as_ref()
+            .map(|odd| n.clone().div_rem(odd.clone()))
+    });
+    let q = BigNat::alloc_from_nat(
+        cs.namespace(|| "q"),
+        || Ok(quotient_remainder.grab()?.0.clone()),
+        limb_width,
+        n_limbs,
+    )?;
+    q.assert_well_formed(cs.namespace(|| "q rangecheck"))?;
+    let r = BigNat::alloc_from_nat(
+        cs.namespace(|| "r"),
+        || Ok(quotient_remainder.grab()?.1.clone()),
+        limb_width,
+        n_limbs,
+    )?;
+    r.assert_well_formed(cs.namespace(|| "r rangecheck"))?;
+    q.mult(cs.namespace(|| "q * odd"), &odd)?
+        .add::<CS>(&r)?
+        .equal_when_carried_regroup(cs.namespace(|| "n = q * odd + r"), n)?;
+
+    let next_a = BigNat::conditionally_select(cs.namespace(|| "next a"), &r, &a, &done)?;
+    let next_n = BigNat::conditionally_select(cs.namespace(|| "next n"), &odd, n, &done)?;
+    Ok((next_a, next_n, next_negative))
+}
+
+/// The sum of the limbs, which for a well-formed number is zero iff the number is.
+fn limb_sum<E: Engine>(n: &BigNat<E>) -> LinearCombination<E> {
+    n.limbs
+        .iter()
+        .fold(LinearCombination::zero(), |lc, limb| lc + limb)
+}
+
+fn limb_sum_value<E: Engine>(n: &BigNat<E>) -> Option<E::Fr> {
+    n.limb_values.as_ref().map(|vs| {
+        vs.iter().fold(E::Fr::zero(), |mut sum, v| {
+            sum.add_assign(v);
+            sum
+        })
+    })
+}
+
+/// Allocates a bit which is set iff `x` is non-zero.
+fn is_nonzero<E, CS>(
+    mut cs: CS,
+    x: LinearCombination<E>,
+    value: Option<E::Fr>,
+) -> Result<AllocatedBit, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let bit = AllocatedBit::alloc(cs.namespace(|| "bit"), value.map(|v| !v.is_zero()))?;
+    let inverse = cs.alloc(
+        || "inverse",
+        || Ok(value.grab()?.inverse().unwrap_or_else(E::Fr::zero)),
+    )?;
+    // x * inverse = bit, and x * (1 - bit) = 0
+    cs.enforce(
+        || "set if non-zero",
+        |lc| lc + &x,
+        |lc| lc + inverse,
+        |lc| lc + bit.get_variable(),
+    );
+    cs.enforce(
+        || "unset if zero",
+        |lc| lc + &x,
+        |lc| lc + CS::one() - bit.get_variable(),
+        |lc| lc,
+    );
+    Ok(bit)
+}
+
+/// The low `k` bits of `num`, which is range-checked to `n_bits` bits.
+fn low_bits<E, CS>(
+    cs: CS,
+    num: &Num<E>,
+    n_bits: usize,
+    k: usize,
+) -> Result<Vec<Bit<E>>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut bits = num.decompose(cs, n_bits)?.into_bits();
+    bits.truncate(k);
+    Ok(bits)
+}
+
+/// The low `k` bits of an odd, well-formed number, the lowest of which is constrained to be set.
+fn low_limb_bits<E, CS>(mut cs: CS, n: &BigNat<E>, k: usize) -> Result<Vec<Bit<E>>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let low = Num::new(
+        n.limb_values.as_ref().map(|vs| vs[0]),
+        n.limbs[0].clone(),
+    );
+    let bits = low_bits(cs.namespace(|| "decomp"), &low, n.params.limb_width, k)?;
+    bits[0].constrain_value(cs.namespace(|| "odd"), true);
+    Ok(bits)
+}
+
+fn and<E, CS>(mut cs: CS, x: &Bit<E>, y: &Bit<E>) -> Result<Bit<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let value = x.value.and_then(|x| y.value.map(|y| x && y));
+    let var = cs.alloc(
+        || "and",
+        || {
+            Ok(if *value.grab()? {
+                E::Fr::one()
+            } else {
+                E::Fr::zero()
+            })
+        },
+    )?;
+    cs.enforce(
+        || "product",
+        |lc| lc + &x.bit,
+        |lc| lc + &y.bit,
+        |lc| lc + var,
+    );
+    Ok(Bit::new(LinearCombination::zero() + var, value))
+}
+
+/// `x xor y`, given `x and y`.
+fn xor<E: Engine>(x: &Bit<E>, y: &Bit<E>, x_and_y: &Bit<E>) -> Bit<E> {
+    Bit::new(
+        LinearCombination::zero() + &x.bit + &y.bit - (usize_to_f(2), &x_and_y.bit),
+        x.value.and_then(|x| y.value.map(|y| x != y)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::test_helpers::*;
+
+    use std::str::FromStr;
+
+    pub struct Jacobi {
+        a: &'static str,
+        n: &'static str,
+        /// The claimed symbol, which is checked against the gadget's
+        symbol: i32,
+        limb_width: usize,
+        n_limbs: usize,
+        max_steps: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for Jacobi {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let (limb_width, n_limbs) = (self.limb_width, self.n_limbs);
+            let a = Integer::from_str(self.a).unwrap();
+            let n = Integer::from_str(self.n).unwrap();
+            let field_symbol = |s: i32| {
+                let mut f = usize_to_f::<E::Fr>(s.abs() as usize);
+                if s < 0 {
+                    f.negate();
+                }
+                f
+            };
+            let native = field_symbol(a.jacobi(&n));
+            let a = BigNat::alloc_from_nat(cs.namespace(|| "a"), || Ok(a), limb_width, n_limbs)?;
+            a.assert_well_formed(cs.namespace(|| "a rangecheck"))?;
+            let n = BigNat::alloc_from_nat(cs.namespace(|| "n"), || Ok(n), limb_width, n_limbs)?;
+            n.assert_well_formed(cs.namespace(|| "n rangecheck"))?;
+            let symbol = jacobi(cs.namespace(|| "jacobi"), &a, &n, self.max_steps)?;
+            assert_eq!(symbol.value, Some(native));
+            let expected = field_symbol(self.symbol);
+            cs.enforce(
+                || "check",
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + &symbol.num - (expected, CS::one()),
+            );
+            Ok(())
+        }
+    }
+
+    circuit_tests! {
+        jacobi_5_21: (Jacobi {
+            a: "5", n: "21", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 6,
+        }, true),
+        jacobi_2_15: (Jacobi {
+            a: "2", n: "15", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 6,
+        }, true),
+        jacobi_2_11: (Jacobi {
+            a: "2", n: "11", symbol: -1, limb_width: 4, n_limbs: 2, max_steps: 6,
+        }, true),
+        jacobi_0_9: (Jacobi {
+            a: "0", n: "9", symbol: 0, limb_width: 4, n_limbs: 2, max_steps: 2,
+        }, true),
+        jacobi_0_1: (Jacobi {
+            a: "0", n: "1", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 2,
+        }, true),
+        jacobi_not_coprime: (Jacobi {
+            a: "6", n: "9", symbol: 0, limb_width: 4, n_limbs: 2, max_steps: 6,
+        }, true),
+        jacobi_a_above_n: (Jacobi {
+            a: "200", n: "33", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 8,
+        }, true),
+        jacobi_128: (Jacobi {
+            a: "123456789012345678901234567890123456",
+            n: "321521701247558003770554266015257202945",
+            symbol: -1,
+            limb_width: 32,
+            n_limbs: 4,
+            max_steps: 48,
+        }, true),
+        jacobi_wrong: (Jacobi {
+            a: "2", n: "11", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 6,
+        }, false),
+        jacobi_too_few_steps: (Jacobi {
+            a: "5", n: "21", symbol: 1, limb_width: 4, n_limbs: 2, max_steps: 1,
+        }, false),
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bigint;
 pub mod bignat;
 pub mod exp;
+pub mod jacobi;
+pub mod montgomery;
 mod poly;
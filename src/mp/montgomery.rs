@@ -0,0 +1,291 @@
+//! Montgomery-form modular multiplication against a fixed modulus.
+//!
+//! In Montgomery form, `x` is represented by `x * R % m`, for `R = 2^(limb_width * (n + 1))`
+//! where the modulus has `n` limbs. The product of two representatives is `a * b * R^-1 % m`,
+//! which is checked as `a * b + q * m = c * R` for a `q < R`: dividing by `R` only moves limbs,
+//! so the quotient is always `n + 1` limbs, however wide the operands are.
+//!
+//! Results are not fully reduced. They are congruent to the right value and, since `R > 4m`,
+//! below `2m` when the operands are, so a chain of products only reduces when it leaves
+//! Montgomery form.
+//!
+//! The fixed quotient is what makes this cheaper than `BigNat::mult_mod` for a modulus whose size
+//! is not known, which sizes its quotient for the widest possible product. The
+//! `montgomery_mult_is_cheaper_than_mult_mod_2048` test prints both costs.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
+
+use super::bignat::BigNat;
+use super::poly::Polynomial;
+use util::convert::{f_to_nat, nat_to_f};
+use util::num::Num;
+use OptionExt;
+
+use std::cmp::min;
+
+pub struct MontgomeryContext<E: Engine> {
+    modulus: BigNat<E>,
+    /// `-m^-1 mod R`, if the modulus is known
+    m_prime: Option<Integer>,
+}
+
+impl<E: Engine> MontgomeryContext<E> {
+    /// Sets up Montgomery multiplication modulo `modulus`, which must be well-formed. Enforces
+    /// that the modulus is odd, so that `R` is invertible modulo it.
+    pub fn new<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        modulus: &BigNat<E>,
+    ) -> Result<Self, SynthesisError> {
+        let limb_width = modulus.params.limb_width;
+        let r = Integer::from(1) << (limb_width * (modulus.params.n_limbs + 1)) as u32;
+        let m_prime = match modulus.value {
+            Some(ref m) => match m.clone().invert(&r) {
+                Ok(inverse) => Some(r - inverse),
+                Err(_) => {
+                    eprintln!("Montgomery modulus {} is even", m);
+                    return Err(SynthesisError::Unsatisfiable);
+                }
+            },
+            None => None,
+        };
+        // low limb = 2 * half + 1
+        let half = Num::alloc(cs.namespace(|| "half of low limb"), || {
+            let low = f_to_nat(&modulus.limb_values.grab()?[0]);
+            Ok(nat_to_f(&(low >> 1)).unwrap())
+        })?;
+        let half_bits = modulus.params.max_word.significant_bits() as usize - 1;
+        half.fits_in_bits(cs.namespace(|| "half rangecheck"), half_bits)?;
+        cs.enforce(
+            || "odd",
+            |lc| lc,
+            |lc| lc,
+            |lc| lc + &modulus.limbs[0] - &half.num - &half.num - CS::one(),
+        );
+        Ok(Self {
+            modulus: modulus.clone(),
+            m_prime,
+        })
+    }
+
+    pub fn modulus(&self) -> &BigNat<E> {
+        &self.modulus
+    }
+
+    /// The number of limbs of `R - 1`, and of values in Montgomery form.
+    fn r_limbs(&self) -> usize {
+        self.modulus.params.n_limbs + 1
+    }
+
+    /// Compute `x * R % m`, the Montgomery form of `x`.
+    pub fn to_montgomery<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        x: &BigNat<E>,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let r_bits = self.modulus.params.limb_width * self.r_limbs();
+        x.shl_const::<CS>(r_bits)
+            .red_mod(cs.namespace(|| "reduce"), &self.modulus)
+    }
+
+    /// Compute `a * b * R^-1 % m`, the Montgomery form of the product of the values `a` and `b`
+    /// are the Montgomery forms of. It is below `2m` if both `a` and `b` are.
+    pub fn mult<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        a: &BigNat<E>,
+        b: &BigNat<E>,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        self.redc(cs, a, b, self.r_limbs())
+    }
+
+    /// Compute `y * R^-1 % m`, the value `y` is the Montgomery form of. Since `y` is below `R`,
+    /// the result is at most `m`, and only `m` if `y` is a multiple of it.
+    pub fn from_montgomery<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        y: &BigNat<E>,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let one = BigNat::one::<CS>(self.modulus.params.limb_width);
+        self.redc(cs, y, &one, self.modulus.params.n_limbs)
+    }
+
+    /// Allocates `c` with `out_limbs` limbs, and enforces `a * b + q * m = c * R` for some
+    /// `q < R`.
+    fn redc<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        a: &BigNat<E>,
+        b: &BigNat<E>,
+        out_limbs: usize,
+    ) -> Result<BigNat<E>, SynthesisError> {
+        let m = &self.modulus;
+        a.enforce_limb_width_agreement(m, "montgomery, a")?;
+        b.enforce_limb_width_agreement(m, "montgomery, b")?;
+        let limb_width = m.params.limb_width;
+        let r_limbs = self.r_limbs();
+        let r_bits = (limb_width * r_limbs) as u32;
+        let product = || -> Result<Integer, SynthesisError> {
+            Ok(Integer::from(a.value.grab()? * b.value.grab()?))
+        };
+        let q = BigNat::alloc_from_nat(
+            cs.namespace(|| "q"),
+            || {
+                let mut q = product()? * self.m_prime.grab()?;
+                q.keep_bits_mut(r_bits);
+                Ok(q)
+            },
+            limb_width,
+            r_limbs,
+        )?;
+        q.assert_well_formed(cs.namespace(|| "q rangecheck"))?;
+        let out = BigNat::alloc_from_nat(
+            cs.namespace(|| "out"),
+            || {
+                let mut t = product()?;
+                t += Integer::from(q.value.grab()? * m.value.grab()?);
+                t >>= r_bits;
+                Ok(t)
+            },
+            limb_width,
+            out_limbs,
+        )?;
+        out.assert_well_formed(cs.namespace(|| "out rangecheck"))?;
+
+        let a_poly = Polynomial::from(a.clone());
+        let b_poly = Polynomial::from(b.clone());
+        let q_poly = Polynomial::from(q.clone());
+        let mod_poly = Polynomial::from(m.clone());
+        // a * b + q * m
+        let ab = a_poly.alloc_product(cs.namespace(|| "a * b"), &b_poly)?;
+        let qm = q_poly.alloc_product(cs.namespace(|| "q * m"), &mod_poly)?;
+        let left = ab.sum(&qm);
+        // c * R
+        let right = Polynomial::from(out.clone()).shift(r_limbs);
+
+        let left_max_word = {
+            let mut x = Integer::from(min(a.limbs.len(), b.limbs.len()));
+            x *= &a.params.max_word;
+            x *= &b.params.max_word;
+            let mut y = Integer::from(min(q.limbs.len(), m.limbs.len()));
+            y *= &q.params.max_word;
+            y *= &m.params.max_word;
+            x + y
+        };
+        let left_int = BigNat::from_poly(left, limb_width, left_max_word);
+        let right_int = BigNat::from_poly(right, limb_width, out.params.max_word.clone());
+        left_int.equal_when_carried_regroup(cs.namespace(|| "carry"), &right_int)?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::test_helpers::*;
+
+    use std::str::FromStr;
+
+    pub struct MontgomeryProduct {
+        m: &'static str,
+        factors: [&'static str; 3],
+        product: &'static str,
+        limb_width: usize,
+        n_limbs: usize,
+    }
+
+    impl<E: Engine> Circuit<E> for MontgomeryProduct {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let (limb_width, n_limbs) = (self.limb_width, self.n_limbs);
+            let nat = |cs: &mut CS, name: &str, n: &str| {
+                let n = Integer::from_str(n).unwrap();
+                BigNat::alloc_from_nat(cs.namespace(|| name), || Ok(n), limb_width, n_limbs)
+            };
+            let m = nat(cs, "m", self.m)?;
+            let ctx = MontgomeryContext::new(cs.namespace(|| "ctx"), &m)?;
+            let mut acc = None;
+            for (i, n) in self.factors.iter().enumerate() {
+                let f = nat(cs, &format!("factor {}", i), *n)?;
+                let f = ctx.to_montgomery(cs.namespace(|| format!("to {}", i)), &f)?;
+                acc = Some(match acc {
+                    None => f,
+                    Some(acc) => ctx.mult(cs.namespace(|| format!("mult {}", i)), &acc, &f)?,
+                });
+            }
+            let product = ctx.from_montgomery(cs.namespace(|| "from"), &acc.unwrap())?;
+            let expected = nat(cs, "expected", self.product)?;
+            product.equal_when_carried_regroup(cs.namespace(|| "check"), &expected)
+        }
+    }
+
+    #[test]
+    fn montgomery_mult_is_cheaper_than_mult_mod_2048() {
+        // An allocated modulus has no known lower bound, so `mult_mod` sizes its quotient for a
+        // modulus of 1, while `redc`'s is always `n + 1` limbs.
+        let count = |montgomery: bool| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let mut nat = |name: &str, n_limbs: usize| {
+                BigNat::<Bn256>::alloc_from_nat(
+                    cs.namespace(|| name),
+                    || Err(SynthesisError::AssignmentMissing),
+                    32,
+                    n_limbs,
+                )
+                .unwrap()
+            };
+            let m = nat("m", 64);
+            // Values in Montgomery form are below 2m, so they have an extra limb.
+            let operand_limbs = if montgomery { 65 } else { 64 };
+            let a = nat("a", operand_limbs);
+            let b = nat("b", operand_limbs);
+            if montgomery {
+                let ctx = MontgomeryContext::new(cs.namespace(|| "ctx"), &m).unwrap();
+                let before = cs.num_constraints();
+                ctx.mult(cs.namespace(|| "mult"), &a, &b).unwrap();
+                cs.num_constraints() - before
+            } else {
+                let before = cs.num_constraints();
+                a.mult_mod(cs.namespace(|| "mult"), &b, &m).unwrap();
+                cs.num_constraints() - before
+            }
+        };
+        let (montgomery, mult_mod) = (count(true), count(false));
+        println!(
+            "2048-bit product: {} constraints in Montgomery form, {} with mult_mod",
+            montgomery, mult_mod
+        );
+        assert!(montgomery < mult_mod);
+    }
+
+    circuit_tests! {
+        montgomery_small: (MontgomeryProduct {
+            m: "7",
+            factors: ["3", "5", "6"],
+            product: "6",
+            limb_width: 4,
+            n_limbs: 1,
+        }, true),
+        montgomery_128: (MontgomeryProduct {
+            m: "321521701247558003770554266015257202945",
+            factors: [
+                "123456789012345678901234567890123456",
+                "98765432109876543210987654321098765",
+                "31415926535897932384626433832795028",
+            ],
+            product: "255161014851574236331923459563938974105",
+            limb_width: 32,
+            n_limbs: 4,
+        }, true),
+        montgomery_128_wrong: (MontgomeryProduct {
+            m: "321521701247558003770554266015257202945",
+            factors: [
+                "123456789012345678901234567890123456",
+                "98765432109876543210987654321098765",
+                "31415926535897932384626433832795028",
+            ],
+            product: "255161014851574236331923459563938974106",
+            limb_width: 32,
+            n_limbs: 4,
+        }, false),
+    }
+}
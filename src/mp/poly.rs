@@ -2,16 +2,42 @@ use sapling_crypto::bellman::pairing::ff::Field;
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
 
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::fmt::{self, Debug, Formatter};
 
+use util::convert::usize_to_f;
 use OptionExt;
 
+/// How products of polynomials, and so of `BigNat`s, are checked in-circuit.
+///
+/// `Schoolbook` has the fewest constraints, and the others the fewest non-zero terms (see
+/// `Polynomial::alloc_product_karatsuba`), which is what the setup and the witness computation of
+/// very wide products are bound by. Where that trade-off pays depends on the prover, so there is no
+/// automatic choice: callers pick a strategy, and a threshold, from their own measurements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultStrategy {
+    /// `Polynomial::alloc_product`.
+    Schoolbook,
+    /// `Polynomial::alloc_product_karatsuba`.
+    Karatsuba { threshold: usize },
+    /// `Polynomial::alloc_product_toom3`.
+    Toom3 { threshold: usize },
+}
+
 pub struct Polynomial<E: Engine> {
     pub coefficients: Vec<LinearCombination<E>>,
     pub values: Option<Vec<E::Fr>>,
 }
 
+impl<E: Engine> Clone for Polynomial<E> {
+    fn clone(&self) -> Self {
+        Polynomial {
+            coefficients: self.coefficients.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
 impl<E: Engine> Debug for Polynomial<E> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Polynomial")
@@ -34,11 +60,73 @@ impl<E: Engine> Polynomial<E> {
             acc
         })
     }
+    /// The coefficients, if they are all constants: linear combinations of `CS::one()` alone.
+    pub fn constant_coefficients<CS: ConstraintSystem<E>>(&self) -> Option<Vec<E::Fr>> {
+        let one = CS::one().get_unchecked();
+        let mut constants = Vec::with_capacity(self.coefficients.len());
+        for c in &self.coefficients {
+            let mut constant = E::Fr::zero();
+            for &(v, ref coeff) in c.as_ref() {
+                if v.get_unchecked() != one {
+                    return None;
+                }
+                constant.add_assign(coeff);
+            }
+            constants.push(constant);
+        }
+        Some(constants)
+    }
+
+    /// The product of `self` and the polynomial with coefficients `constants`, which is linear in
+    /// the coefficients of `self`, so it costs no constraints.
+    fn scale_by(&self, constants: &[E::Fr]) -> Polynomial<E> {
+        let n_product_coeffs = self.coefficients.len() + constants.len() - 1;
+        let mut coefficients: Vec<LinearCombination<E>> =
+            std::iter::repeat_with(LinearCombination::zero)
+                .take(n_product_coeffs)
+                .collect();
+        for (i, c) in constants.iter().enumerate() {
+            if c.is_zero() {
+                continue;
+            }
+            for (j, lc) in self.coefficients.iter().enumerate() {
+                let acc = std::mem::replace(&mut coefficients[i + j], LinearCombination::zero());
+                coefficients[i + j] = acc + (*c, lc);
+            }
+        }
+        let values = self.values.as_ref().map(|vs| {
+            let mut values: Vec<E::Fr> = std::iter::repeat_with(E::Fr::zero)
+                .take(n_product_coeffs)
+                .collect();
+            for (i, c) in constants.iter().enumerate() {
+                for (j, v) in vs.iter().enumerate() {
+                    let mut v = v.clone();
+                    v.mul_assign(c);
+                    values[i + j].add_assign(&v);
+                }
+            }
+            values
+        });
+        Polynomial {
+            coefficients,
+            values,
+        }
+    }
+
+    /// The product of `self` and `other`, checked at one point per coefficient. If either is
+    /// constant (see `constant_coefficients`), the product is a linear combination instead, with
+    /// no constraints.
     pub fn alloc_product<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
         other: &Self,
     ) -> Result<Polynomial<E>, SynthesisError> {
+        if let Some(constants) = other.constant_coefficients::<CS>() {
+            return Ok(self.scale_by(&constants));
+        }
+        if let Some(constants) = self.constant_coefficients::<CS>() {
+            return Ok(other.scale_by(&constants));
+        }
         let n_product_coeffs = self.coefficients.len() + other.coefficients.len() - 1;
         let values = self.values.as_ref().and_then(|self_vs| {
             other.values.as_ref().map(|other_vs| {
@@ -100,6 +188,187 @@ impl<E: Engine> Polynomial<E> {
         Ok(product)
     }
 
+    /// The product of `self` and `other`, checked as `strategy` says.
+    pub fn alloc_product_with<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+        strategy: MultStrategy,
+    ) -> Result<Polynomial<E>, SynthesisError> {
+        let constant = self.constant_coefficients::<CS>().is_some()
+            || other.constant_coefficients::<CS>().is_some();
+        if constant {
+            return self.alloc_product(cs, other);
+        }
+        match strategy {
+            MultStrategy::Schoolbook => self.alloc_product(cs, other),
+            MultStrategy::Karatsuba { threshold } => {
+                self.alloc_product_karatsuba(cs, other, threshold)
+            }
+            MultStrategy::Toom3 { threshold } => self.alloc_product_toom3(cs, other, threshold),
+        }
+    }
+
+    /// Like `alloc_product`, but while both polynomials have more than `threshold` coefficients,
+    /// splits them in halves and computes the product from three half-size products
+    /// (Karatsuba): `a0 b0`, `a1 b1` and `(a0 + a1)(b0 + b1)`.
+    ///
+    /// `alloc_product` checks the product at one point per coefficient, so its constraint count is
+    /// already linear, but each constraint holds every coefficient of the factors: the number of
+    /// non-zero terms is quadratic. Splitting trades more constraints (about 1.5 times as many per
+    /// level) for sparser ones, with a number of terms growing as `n^1.58`.
+    pub fn alloc_product_karatsuba<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        threshold: usize,
+    ) -> Result<Polynomial<E>, SynthesisError> {
+        let n = min(self.coefficients.len(), other.coefficients.len());
+        if n <= max(threshold, 1) {
+            return self.alloc_product(cs, other);
+        }
+        let k = n / 2;
+        let (a0, a1) = self.split_at(k);
+        let (b0, b1) = other.split_at(k);
+        let low = a0.alloc_product_karatsuba(cs.namespace(|| "low"), &b0, threshold)?;
+        let high = a1.alloc_product_karatsuba(cs.namespace(|| "high"), &b1, threshold)?;
+        let mid =
+            a0.sum(&a1)
+                .alloc_product_karatsuba(cs.namespace(|| "mid"), &b0.sum(&b1), threshold)?;
+        // a0 b1 + a1 b0
+        let cross = mid.sum(&low.negate()).sum(&high.negate());
+        Ok(low.sum(&cross.shift(k)).sum(&high.shift(2 * k)))
+    }
+
+    /// Like `alloc_product`, but while both polynomials have more than `threshold` coefficients,
+    /// splits them in thirds and computes the product from five third-size products (Toom-3).
+    ///
+    /// Writing `a = a0 + a1 y + a2 y^2` for `y = x^k`, and likewise `b`, the product `r(y)` is
+    /// interpolated from its values at `y = 0, 1, -1, 2` and infinity. The interpolation divides by
+    /// 2 and 3, which is exact in the field. Each level costs about 5/3 times the constraints, and
+    /// the number of non-zero terms grows as `n^1.46`.
+    pub fn alloc_product_toom3<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+        threshold: usize,
+    ) -> Result<Polynomial<E>, SynthesisError> {
+        let n = min(self.coefficients.len(), other.coefficients.len());
+        if n <= max(threshold, 2) {
+            return self.alloc_product(cs, other);
+        }
+        let k = n / 3;
+        let f = |n: i64| {
+            let mut v = usize_to_f::<E::Fr>(n.abs() as usize);
+            if n < 0 {
+                v.negate();
+            }
+            v
+        };
+        // The values of a polynomial in `y` at 0, 1, -1, 2 and infinity.
+        let evaluate = |p: &Self| {
+            let (p0, rest) = p.split_at(k);
+            let (p1, p2) = rest.split_at(k);
+            let p02 = p0.sum(&p2);
+            [
+                p0.clone(),
+                p02.sum(&p1),
+                p02.sum(&p1.negate()),
+                p0.sum(&p1.scale(f(2))).sum(&p2.scale(f(4))),
+                p2,
+            ]
+        };
+        let (a, b) = (evaluate(self), evaluate(other));
+        let mut v = Vec::with_capacity(5);
+        for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+            v.push(a.alloc_product_toom3(
+                cs.namespace(|| format!("point {}", i)),
+                b,
+                threshold,
+            )?);
+        }
+        let half = f(2).inverse().expect("2 is invertible");
+        let sixth = f(6).inverse().expect("6 is invertible");
+        let (r0, r4) = (&v[0], &v[4]);
+        // r2 = (v(1) + v(-1)) / 2 - r0 - r4
+        let r2 = v[1]
+            .sum(&v[2])
+            .scale(half)
+            .sum(&r0.negate())
+            .sum(&r4.negate());
+        // r1 + r3 = (v(1) - v(-1)) / 2
+        let odd = v[1].sum(&v[2].negate()).scale(half);
+        // v(2) = r0 + 2 r1 + 4 r2 + 8 r3 + 16 r4, so 6 r3 = v(2) - r0 - 4 r2 - 16 r4 - 2 (r1 + r3)
+        let r3 = v[3]
+            .sum(&r0.negate())
+            .sum(&r2.scale(f(-4)))
+            .sum(&r4.scale(f(-16)))
+            .sum(&odd.scale(f(-2)))
+            .scale(sixth);
+        let r1 = odd.sum(&r3.negate());
+        Ok(r0
+            .sum(&r1.shift(k))
+            .sum(&r2.shift(2 * k))
+            .sum(&r3.shift(3 * k))
+            .sum(&r4.shift(4 * k)))
+    }
+
+    /// The low `k` coefficients, and the rest.
+    pub fn split_at(&self, k: usize) -> (Self, Self) {
+        let (low, high) = self.coefficients.split_at(k);
+        let values = self.values.as_ref().map(|vs| vs.split_at(k));
+        (
+            Polynomial {
+                coefficients: low.to_vec(),
+                values: values.map(|(l, _)| l.to_vec()),
+            },
+            Polynomial {
+                coefficients: high.to_vec(),
+                values: values.map(|(_, h)| h.to_vec()),
+            },
+        )
+    }
+
+    /// Multiplies by `x^k`.
+    pub fn shift(&self, k: usize) -> Self {
+        let mut coefficients = vec![LinearCombination::zero(); k];
+        coefficients.extend(self.coefficients.iter().cloned());
+        Polynomial {
+            coefficients,
+            values: self.values.as_ref().map(|vs| {
+                let mut values = vec![E::Fr::zero(); k];
+                values.extend(vs.iter().cloned());
+                values
+            }),
+        }
+    }
+
+    pub fn negate(&self) -> Self {
+        let mut minus_one = E::Fr::one();
+        minus_one.negate();
+        self.scale(minus_one)
+    }
+
+    /// Multiplies by the constant `c`.
+    pub fn scale(&self, c: E::Fr) -> Self {
+        Polynomial {
+            coefficients: self
+                .coefficients
+                .iter()
+                .map(|lc| LinearCombination::zero() + (c, lc))
+                .collect(),
+            values: self.values.as_ref().map(|vs| {
+                vs.iter()
+                    .map(|v| {
+                        let mut v = *v;
+                        v.mul_assign(&c);
+                        v
+                    })
+                    .collect()
+            }),
+        }
+    }
+
     pub fn sum(&self, other: &Self) -> Self {
         let n_coeffs = max(self.coefficients.len(), other.coefficients.len());
         let values = self.values.as_ref().and_then(|self_vs| {
@@ -197,4 +466,45 @@ mod tests {
             eprintln!("Error: {} is unsatisfied", token);
         }
     }
+
+    fn alloc_poly<CS: ConstraintSystem<Bn256>>(mut cs: CS, values: &[usize]) -> Polynomial<Bn256> {
+        let values: Vec<Fr> = values.iter().map(|v| usize_to_f(*v)).collect();
+        Polynomial {
+            coefficients: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let var = cs.alloc(|| format!("coeff {}", i), || Ok(*v)).unwrap();
+                    LinearCombination::zero() + var
+                })
+                .collect(),
+            values: Some(values),
+        }
+    }
+
+    #[test]
+    fn product_strategies_agree() {
+        let a: Vec<usize> = (0..27).map(|i| 3 * i + 1).collect();
+        let b: Vec<usize> = (0..29).map(|i| 5 * i + 2).collect();
+        let mut expected = None;
+        // The constraint counts of the products, which are regressions against schoolbook.
+        for &(strategy, n_constraints) in &[
+            (MultStrategy::Schoolbook, 55),
+            (MultStrategy::Karatsuba { threshold: 9 }, 123),
+            (MultStrategy::Toom3 { threshold: 9 }, 93),
+            (MultStrategy::Toom3 { threshold: 3 }, 157),
+        ] {
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            let pa = alloc_poly(cs.namespace(|| "a"), &a);
+            let pb = alloc_poly(cs.namespace(|| "b"), &b);
+            let prod = pa
+                .alloc_product_with(cs.namespace(|| "product"), &pb, strategy)
+                .unwrap();
+            assert!(cs.is_satisfied(), "{:?}", strategy);
+            assert_eq!(cs.num_constraints(), n_constraints, "{:?}", strategy);
+            assert_eq!(prod.coefficients.len(), a.len() + b.len() - 1);
+            let expected = expected.get_or_insert_with(|| prod.values.clone());
+            assert_eq!(&prod.values, expected, "{:?}", strategy);
+        }
+    }
 }
@@ -0,0 +1,303 @@
+//! Python bindings, enabled by the `python` feature, for scripting parameter sweeps and dataset
+//! generation.
+//!
+//! Items are lists of field elements (Bn256 scalars) given as decimal strings, and integers are
+//! decimal strings. Sets hash items with Poseidon. Build the extension module with
+//! `cargo rustc --release --features python-extension --crate-type cdylib`, and rename the
+//! library to `bellman_bignat.so`. The `python` feature alone links libpython, so the bindings
+//! can be tested with `cargo test --features python`.
+use pyo3::exceptions::{KeyError, ValueError};
+use pyo3::prelude::*;
+use pyo3::wrap_pyfunction;
+use rug::Integer;
+use sapling_crypto::bellman::pairing::bn256::Bn256;
+use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
+
+use driver::{Groth16, ProofSystem};
+use group::{RsaQuotientGroup, SemiGroup};
+use hash::division_intractable as di;
+use hash::hashes::Poseidon;
+use hash::Hasher;
+use set::int_set::exp::serial::SerialExp;
+use set::int_set::ExpSet;
+use set::merkle::MerkleSet;
+use set::rsa::{Set, SetBenchInputs};
+use set::GenSet;
+use util::convert::f_to_nat;
+
+use std::str::FromStr;
+
+type F = <Bn256 as ScalarEngine>::Fr;
+
+fn value_error(msg: String) -> PyErr {
+    PyErr::new::<ValueError, _>(msg)
+}
+
+fn parse_int(s: &str) -> PyResult<Integer> {
+    Integer::from_str(s).map_err(|e| value_error(format!("Bad integer {:?}: {}", s, e)))
+}
+
+fn parse_item(item: Vec<String>) -> PyResult<Vec<F>> {
+    item.iter()
+        .map(|s| F::from_str(s).ok_or_else(|| value_error(format!("Bad field element {:?}", s))))
+        .collect()
+}
+
+fn parse_items(items: Vec<Vec<String>>) -> PyResult<Vec<Vec<F>>> {
+    items.into_iter().map(parse_item).collect()
+}
+
+fn check_widths(n_bits_elem: usize, limb_width: usize) -> PyResult<()> {
+    if ![128, 512, 2048].contains(&n_bits_elem) {
+        return Err(value_error(format!(
+            "Unsupported element width {}",
+            n_bits_elem
+        )));
+    }
+    if limb_width == 0 || n_bits_elem % limb_width != 0 {
+        return Err(value_error(format!(
+            "Limb width {} does not divide element width {}",
+            limb_width, n_bits_elem
+        )));
+    }
+    Ok(())
+}
+
+/// The RSA group generated by `g` modulo `m`.
+#[pyclass(name = RsaGroup)]
+#[derive(Clone)]
+pub struct PyRsaGroup {
+    inner: RsaQuotientGroup,
+}
+
+#[pymethods]
+impl PyRsaGroup {
+    #[new]
+    fn new(g: &str, m: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: RsaQuotientGroup {
+                g: parse_int(g)?,
+                m: parse_int(m)?,
+            },
+        })
+    }
+
+    #[getter]
+    fn g(&self) -> String {
+        self.inner.g.to_string()
+    }
+
+    #[getter]
+    fn m(&self) -> String {
+        self.inner.m.to_string()
+    }
+
+    fn power(&self, b: &str, e: &str) -> PyResult<String> {
+        Ok(self.inner.power(&parse_int(b)?, &parse_int(e)?).to_string())
+    }
+}
+
+/// An RSA set of items, hashed to `n_bits_elem`-bit elements.
+#[pyclass(name = Set)]
+pub struct PySet {
+    inner: Set<Poseidon<Bn256>, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>>,
+}
+
+#[pymethods]
+impl PySet {
+    #[new]
+    fn new(
+        group: &PyRsaGroup,
+        n_bits_elem: usize,
+        limb_width: usize,
+        items: Vec<Vec<String>>,
+    ) -> PyResult<Self> {
+        check_widths(n_bits_elem, limb_width)?;
+        Ok(Self {
+            inner: Set::new_with(
+                group.inner.clone(),
+                di::offset(n_bits_elem),
+                Poseidon::default(),
+                n_bits_elem,
+                limb_width,
+                &parse_items(items)?,
+            ),
+        })
+    }
+
+    fn insert(&mut self, item: Vec<String>) -> PyResult<()> {
+        self.inner.insert(parse_item(item)?);
+        Ok(())
+    }
+
+    /// Removes `item`, returning whether it was present.
+    fn remove(&mut self, item: Vec<String>) -> PyResult<bool> {
+        Ok(self.inner.remove(&parse_item(item)?))
+    }
+
+    /// The integer `item` is represented by in the set.
+    fn hash_item(&self, item: Vec<String>) -> PyResult<String> {
+        Ok(self.inner.hash_item(&parse_item(item)?).to_string())
+    }
+
+    fn digest(&mut self) -> String {
+        self.inner.digest().to_string()
+    }
+}
+
+/// A Merkle set of `2 ** depth` items.
+#[pyclass(name = MerkleSet)]
+pub struct PyMerkleSet {
+    inner: MerkleSet<Poseidon<Bn256>>,
+}
+
+#[pymethods]
+impl PyMerkleSet {
+    #[new]
+    fn new(depth: usize, items: Vec<Vec<String>>) -> PyResult<Self> {
+        let items = parse_items(items)?;
+        Ok(Self {
+            inner: MerkleSet::new_with(
+                Poseidon::default(),
+                depth,
+                items.iter().map(Vec::as_slice),
+            ),
+        })
+    }
+
+    /// Replaces `old` with `new`.
+    fn swap(&mut self, old: Vec<String>, new: Vec<String>) -> PyResult<()> {
+        let old = parse_item(old)?;
+        let new = parse_item(new)?;
+        let key = self.inner.hasher.hash(&old).into_repr();
+        if !self.inner.leaf_indices.contains_key(&key) {
+            return Err(PyErr::new::<KeyError, _>("Item is not in the set"));
+        }
+        self.inner.swap(&old, new);
+        Ok(())
+    }
+
+    fn digest(&mut self) -> String {
+        f_to_nat(&self.inner.digest()).to_string()
+    }
+}
+
+/// Builds the initial and final digests of a `SetBench` in which `n_untouched` items are left
+/// alone, `n_removed` are removed and `n_inserted` are inserted.
+#[pyfunction]
+fn set_bench_digests(
+    group: &PyRsaGroup,
+    n_untouched: usize,
+    n_removed: usize,
+    n_inserted: usize,
+    item_len: usize,
+    n_bits_elem: usize,
+    limb_width: usize,
+) -> PyResult<(String, String)> {
+    check_widths(n_bits_elem, limb_width)?;
+    let mut inputs: SetBenchInputs<_, ExpSet<_, SerialExp<_>>> = SetBenchInputs::from_counts(
+        n_untouched,
+        n_removed,
+        n_inserted,
+        item_len,
+        Poseidon::<Bn256>::default(),
+        n_bits_elem,
+        limb_width,
+        group.inner.clone(),
+    );
+    Ok((
+        inputs.initial_state.digest().to_string(),
+        inputs.final_state.digest().to_string(),
+    ))
+}
+
+/// Verifies a serialized groth16 proof over Bn256 against a serialized verifying key.
+#[pyfunction]
+fn verify(vk: Vec<u8>, inputs: Vec<String>, proof: Vec<u8>) -> PyResult<bool> {
+    let vk = <Groth16 as ProofSystem<Bn256>>::read_verifying_key(&vk[..])
+        .map_err(|e| value_error(format!("Bad verifying key: {}", e)))?;
+    let proof = <Groth16 as ProofSystem<Bn256>>::read_proof(&proof[..])
+        .map_err(|e| value_error(format!("Bad proof: {}", e)))?;
+    let inputs = parse_item(inputs)?;
+    <Groth16 as ProofSystem<Bn256>>::verify(&vk, &proof, &inputs)
+        .map_err(|e| value_error(format!("Verification failed: {}", e)))
+}
+
+#[pymodule]
+fn bellman_bignat(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyRsaGroup>()?;
+    m.add_class::<PySet>()?;
+    m.add_class::<PyMerkleSet>()?;
+    m.add_wrapped(wrap_pyfunction!(set_bench_digests))?;
+    m.add_wrapped(wrap_pyfunction!(verify))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    fn item(s: &str) -> Vec<String> {
+        vec![s.to_owned()]
+    }
+
+    #[test]
+    fn set_matches_native() {
+        let group = PyRsaGroup::new("2", RSA_512).unwrap();
+        let mut set = PySet::new(&group, 128, 32, vec![item("1")]).unwrap();
+        set.insert(item("2")).unwrap();
+        assert!(set.remove(item("1")).unwrap());
+        assert!(!set.remove(item("3")).unwrap());
+
+        let mut expected: Set<_, ExpSet<_, SerialExp<_>>> = Set::new_with(
+            RsaQuotientGroup::from_strs("2", RSA_512),
+            di::offset(128),
+            Poseidon::<Bn256>::default(),
+            128,
+            32,
+            &vec![vec![F::from_str("2").unwrap()]],
+        );
+        assert_eq!(set.digest(), expected.digest().to_string());
+        assert_eq!(
+            set.hash_item(item("2")).unwrap(),
+            expected.hash_item(&[F::from_str("2").unwrap()]).to_string()
+        );
+    }
+
+    #[test]
+    fn bad_arguments_raise() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let group = PyRsaGroup::new("2", RSA_512).unwrap();
+        assert!(PyRsaGroup::new("two", RSA_512)
+            .err()
+            .unwrap()
+            .is_instance::<ValueError>(py));
+        for &(n_bits_elem, limb_width) in &[(100, 4), (128, 0), (128, 48)] {
+            assert!(PySet::new(&group, n_bits_elem, limb_width, vec![])
+                .err()
+                .unwrap()
+                .is_instance::<ValueError>(py));
+            assert!(set_bench_digests(&group, 1, 1, 1, 1, n_bits_elem, limb_width)
+                .unwrap_err()
+                .is_instance::<ValueError>(py));
+        }
+        let mut set = PySet::new(&group, 128, 32, vec![]).unwrap();
+        assert!(set
+            .insert(item("not a number"))
+            .unwrap_err()
+            .is_instance::<ValueError>(py));
+
+        let mut merkle = PyMerkleSet::new(2, vec![item("1")]).unwrap();
+        assert!(merkle.swap(item("1"), item("2")).is_ok());
+        assert!(merkle
+            .swap(item("1"), item("3"))
+            .unwrap_err()
+            .is_instance::<KeyError>(py));
+        assert!(verify(vec![0; 10], vec![], vec![])
+            .unwrap_err()
+            .is_instance::<ValueError>(py));
+    }
+}
@@ -0,0 +1,444 @@
+//! A protocol for computing the expensive native parts of a witness on another machine.
+//!
+//! Hashing items into the division-intractable domain, folding exponents into RSA digests and
+//! searching for Pocklington certificates dominate witness generation. A `WitnessClient` sends
+//! these as serialized `WitnessRequest`s over some `Transport`, and checks each response for
+//! consistency before handing it to synthesis:
+//!
+//!    * hashed items are spot-checked,
+//!    * folds come with a Wesolowski proof of exponentiation, for a Fiat-Shamir challenge, and
+//!    * Pocklington certificates are checked step by step.
+//!
+//! A server which claims that no certificate exists is believed, since checking that claim means
+//! doing the search. So a server cannot forge a certificate, but can withhold one; a caller which
+//! does not trust the server to answer should retry elsewhere, or search locally, on `None`.
+//!
+//! `WitnessServer` is a reference implementation which serves requests in-process.
+use rand::Rng;
+use rug::integer::Order;
+use rug::Integer;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+use std::io;
+
+use group::{RsaGroup, RsaQuotientGroup, SemiGroup};
+use hash::pocklington::helper::{
+    check_pocklington_certificate, hash_to_pocklington_prime, PocklingtonCertificate,
+};
+use hash::{division_intractable as di, HashDomain, Hasher};
+use util::convert::{f_to_nat, nat_to_f};
+
+/// Field elements are sent as integers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WitnessRequest {
+    /// Hash each item into the division-intractable `domain`.
+    HashItems {
+        items: Vec<Vec<Integer>>,
+        offset: Integer,
+        domain: HashDomain,
+        limb_width: usize,
+    },
+    /// Raise `base` to the product of `exponents`, modulo `modulus`. In the quotient group
+    /// (`x ~ -x`) if `quotient` is set.
+    Fold {
+        modulus: Integer,
+        quotient: bool,
+        base: Integer,
+        exponents: Vec<Integer>,
+    },
+    /// Find a Pocklington certificate for the hash of `inputs`.
+    Pocklington { inputs: Vec<Integer>, entropy: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WitnessResponse {
+    Items(Vec<Integer>),
+    /// The result of a fold, and `base` raised to the product divided by the challenge.
+    Fold { result: Integer, proof: Integer },
+    Pocklington(Option<PocklingtonCertificate>),
+    /// The server could not handle the request.
+    Error(String),
+}
+
+impl WitnessRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("requests are serializable")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RemoteError> {
+        bincode::deserialize(bytes).map_err(|e| RemoteError::Malformed(format!("{}", e)))
+    }
+}
+
+impl WitnessResponse {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("responses are serializable")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RemoteError> {
+        bincode::deserialize(bytes).map_err(|e| RemoteError::Malformed(format!("{}", e)))
+    }
+}
+
+#[derive(Debug)]
+pub enum RemoteError {
+    Io(io::Error),
+    /// The bytes received do not decode, or decode to the wrong kind of message.
+    Malformed(String),
+    /// The server reported an error.
+    Server(String),
+    /// The response does not match the request.
+    Inconsistent(&'static str),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoteError::Io(e) => write!(f, "transport error: {}", e),
+            RemoteError::Malformed(e) => write!(f, "malformed message: {}", e),
+            RemoteError::Server(e) => write!(f, "server error: {}", e),
+            RemoteError::Inconsistent(e) => write!(f, "inconsistent response: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for RemoteError {
+    fn from(e: io::Error) -> Self {
+        RemoteError::Io(e)
+    }
+}
+
+/// Carries a serialized request to a server, and its response back.
+pub trait Transport {
+    fn round_trip(&self, request: Vec<u8>) -> io::Result<Vec<u8>>;
+}
+
+/// The Fiat-Shamir challenge for a fold: a 128b prime derived from the request and its result.
+pub fn fold_challenge(request: &WitnessRequest, result: &Integer) -> Integer {
+    let mut hasher = Sha256::new();
+    hasher.input(&request.to_bytes());
+    hasher.input(&result.to_digits::<u8>(Order::Lsf));
+    let seed = Integer::from_digits(&hasher.result()[..16], Order::Lsf);
+    seed.next_prime()
+}
+
+/// Computes the result of a fold, and the proof that it is correct.
+fn prove_fold<G: SemiGroup<Elem = Integer>>(
+    group: &G,
+    request: &WitnessRequest,
+    base: &Integer,
+    exponents: &[Integer],
+) -> (Integer, Integer) {
+    let product = exponents
+        .iter()
+        .fold(Integer::from(1), |acc, x| acc * x);
+    let result = group.power(base, &product);
+    let challenge = fold_challenge(request, &result);
+    let proof = group.power(base, &Integer::from(&product / &challenge));
+    (result, proof)
+}
+
+/// Checks that `proof^challenge * base^(prod mod challenge) = result`.
+fn check_fold<G: SemiGroup<Elem = Integer>>(
+    group: &G,
+    base: &Integer,
+    exponents: &[Integer],
+    challenge: &Integer,
+    result: &Integer,
+    proof: &Integer,
+) -> bool {
+    let r = exponents
+        .iter()
+        .fold(Integer::from(1), |acc, x| acc * x % challenge);
+    group.op(&group.power(proof, challenge), &group.power(base, &r)) == *result
+}
+
+/// Serves witness requests in-process, using `hasher` for item hashing and Pocklington entropy.
+pub struct WitnessServer<H: Hasher> {
+    pub hasher: H,
+}
+
+impl<H: Hasher> WitnessServer<H> {
+    pub fn new(hasher: H) -> Self {
+        Self { hasher }
+    }
+
+    fn to_fs(&self, xs: &[Integer]) -> Option<Vec<H::F>> {
+        xs.iter().map(nat_to_f).collect()
+    }
+
+    pub fn handle(&self, request: &WitnessRequest) -> WitnessResponse {
+        match request {
+            WitnessRequest::HashItems {
+                items,
+                offset,
+                domain,
+                limb_width,
+            } => {
+                use rayon::prelude::*;
+                let items = match items
+                    .iter()
+                    .map(|i| self.to_fs(i))
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(items) => items,
+                    None => return WitnessResponse::Error("item out of range".to_owned()),
+                };
+                WitnessResponse::Items(
+                    items
+                        .par_iter()
+                        .map(|i| di::helper::di_hash(i, offset, domain, *limb_width, &self.hasher))
+                        .collect(),
+                )
+            }
+            WitnessRequest::Fold {
+                modulus,
+                quotient,
+                base,
+                exponents,
+            } => {
+                let (result, proof) = if *quotient {
+                    let g = RsaQuotientGroup {
+                        g: base.clone(),
+                        m: modulus.clone(),
+                    };
+                    prove_fold(&g, request, base, exponents)
+                } else {
+                    let g = RsaGroup {
+                        g: base.clone(),
+                        m: modulus.clone(),
+                    };
+                    prove_fold(&g, request, base, exponents)
+                };
+                WitnessResponse::Fold { result, proof }
+            }
+            WitnessRequest::Pocklington { inputs, entropy } => match self.to_fs(inputs) {
+                Some(inputs) => WitnessResponse::Pocklington(hash_to_pocklington_prime(
+                    &inputs,
+                    *entropy,
+                    &self.hasher,
+                )),
+                None => WitnessResponse::Error("input out of range".to_owned()),
+            },
+        }
+    }
+}
+
+impl<H: Hasher> Transport for WitnessServer<H> {
+    fn round_trip(&self, request: Vec<u8>) -> io::Result<Vec<u8>> {
+        let response = match WitnessRequest::from_bytes(&request) {
+            Ok(request) => self.handle(&request),
+            Err(e) => WitnessResponse::Error(format!("{}", e)),
+        };
+        Ok(response.to_bytes())
+    }
+}
+
+/// Sends witness computations to a server, and checks the results.
+pub struct WitnessClient<H: Hasher, T: Transport> {
+    pub hasher: H,
+    pub transport: T,
+    /// The number of hashed items to recompute locally, per request
+    pub spot_checks: usize,
+}
+
+impl<H: Hasher, T: Transport> WitnessClient<H, T> {
+    pub fn new(hasher: H, transport: T, spot_checks: usize) -> Self {
+        Self {
+            hasher,
+            transport,
+            spot_checks,
+        }
+    }
+
+    fn call(&self, request: &WitnessRequest) -> Result<WitnessResponse, RemoteError> {
+        let bytes = self.transport.round_trip(request.to_bytes())?;
+        match WitnessResponse::from_bytes(&bytes)? {
+            WitnessResponse::Error(e) => Err(RemoteError::Server(e)),
+            r => Ok(r),
+        }
+    }
+
+    /// Hashes `items` into the division-intractable `domain`, as `Set::hash_item` would for a set
+    /// with that domain.
+    pub fn hash_items<R: Rng>(
+        &self,
+        items: &[Vec<H::F>],
+        offset: &Integer,
+        domain: &HashDomain,
+        limb_width: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Integer>, RemoteError> {
+        let request = WitnessRequest::HashItems {
+            items: items
+                .iter()
+                .map(|i| i.iter().map(f_to_nat).collect())
+                .collect(),
+            offset: offset.clone(),
+            domain: domain.clone(),
+            limb_width,
+        };
+        let hashes = match self.call(&request)? {
+            WitnessResponse::Items(hashes) => hashes,
+            _ => return Err(RemoteError::Malformed("expected items".to_owned())),
+        };
+        if hashes.len() != items.len() {
+            return Err(RemoteError::Inconsistent("wrong number of hashes"));
+        }
+        for _ in 0..self.spot_checks.min(items.len()) {
+            let i = rng.gen_range(0, items.len());
+            if hashes[i] != di::helper::di_hash(&items[i], offset, domain, limb_width, &self.hasher)
+            {
+                return Err(RemoteError::Inconsistent("wrong hash"));
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Computes `base^(prod exponents)` in the RSA group, or quotient group, modulo `modulus`.
+    pub fn fold(
+        &self,
+        modulus: &Integer,
+        quotient: bool,
+        base: &Integer,
+        exponents: &[Integer],
+    ) -> Result<Integer, RemoteError> {
+        let request = WitnessRequest::Fold {
+            modulus: modulus.clone(),
+            quotient,
+            base: base.clone(),
+            exponents: exponents.to_vec(),
+        };
+        let (result, proof) = match self.call(&request)? {
+            WitnessResponse::Fold { result, proof } => (result, proof),
+            _ => return Err(RemoteError::Malformed("expected a fold".to_owned())),
+        };
+        let challenge = fold_challenge(&request, &result);
+        let ok = if quotient {
+            let g = RsaQuotientGroup {
+                g: base.clone(),
+                m: modulus.clone(),
+            };
+            check_fold(&g, base, exponents, &challenge, &result, &proof)
+        } else {
+            let g = RsaGroup {
+                g: base.clone(),
+                m: modulus.clone(),
+            };
+            check_fold(&g, base, exponents, &challenge, &result, &proof)
+        };
+        if ok {
+            Ok(result)
+        } else {
+            Err(RemoteError::Inconsistent("bad proof of exponentiation"))
+        }
+    }
+
+    /// Finds the Pocklington certificate for `inputs`, as `hash_to_pocklington_prime` would.
+    ///
+    /// A certificate is checked, but `None` is taken on trust (see the module docs).
+    pub fn pocklington(
+        &self,
+        inputs: &[H::F],
+        entropy: usize,
+    ) -> Result<Option<PocklingtonCertificate>, RemoteError> {
+        let request = WitnessRequest::Pocklington {
+            inputs: inputs.iter().map(f_to_nat).collect(),
+            entropy,
+        };
+        match self.call(&request)? {
+            WitnessResponse::Pocklington(Some(cert)) => {
+                if check_pocklington_certificate(inputs, entropy, &self.hasher, &cert) {
+                    Ok(Some(cert))
+                } else {
+                    Err(RemoteError::Inconsistent("bad pocklington certificate"))
+                }
+            }
+            WitnessResponse::Pocklington(None) => Ok(None),
+            _ => Err(RemoteError::Malformed("expected a certificate".to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hashes::Poseidon;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+    use util::test_helpers::*;
+
+    use rand::thread_rng;
+
+    /// Flips a bit in the result of every fold, and withholds every certificate.
+    struct Tamper<T>(T);
+
+    impl<T: Transport> Transport for Tamper<T> {
+        fn round_trip(&self, request: Vec<u8>) -> io::Result<Vec<u8>> {
+            let bytes = self.0.round_trip(request)?;
+            let response = match WitnessResponse::from_bytes(&bytes).unwrap() {
+                WitnessResponse::Fold { result, proof } => WitnessResponse::Fold {
+                    result: result ^ Integer::from(1),
+                    proof,
+                },
+                WitnessResponse::Pocklington(_) => WitnessResponse::Pocklington(None),
+                r => r,
+            };
+            Ok(response.to_bytes())
+        }
+    }
+
+    #[test]
+    fn in_process_round_trip() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let client = WitnessClient::new(hasher.clone(), WitnessServer::new(hasher.clone()), 2);
+        let items: Vec<Vec<F>> = (0..3)
+            .map(|i| vec![F::from_str(&format!("{}", i)).unwrap()])
+            .collect();
+        let offset = di::offset(128);
+        let domain = HashDomain {
+            n_bits: 128,
+            n_trailing_ones: 1,
+        };
+        let hashes = client
+            .hash_items(&items, &offset, &domain, 32, &mut thread_rng())
+            .unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(
+            hashes[2],
+            di::helper::di_hash(&items[2], &offset, &domain, 32, &hasher)
+        );
+
+        let m = Integer::from(1009 * 1013);
+        let exps = [Integer::from(5), Integer::from(7)];
+        let folded = client.fold(&m, false, &Integer::from(2), &exps).unwrap();
+        assert_eq!(folded, Integer::from(2).pow_mod(&Integer::from(35), &m).unwrap());
+
+        let cert = client.pocklington(&items[1], 128).unwrap().unwrap();
+        assert!(check_pocklington_certificate(&items[1], 128, &hasher, &cert));
+        assert!(!check_pocklington_certificate(&items[2], 128, &hasher, &cert));
+    }
+
+    #[test]
+    fn tampered_fold_rejected() {
+        let hasher = Poseidon::<Bn256>::default();
+        let client = WitnessClient::new(hasher.clone(), Tamper(WitnessServer::new(hasher)), 0);
+        let m = Integer::from(1009 * 1013);
+        let exps = [Integer::from(5), Integer::from(7)];
+        match client.fold(&m, false, &Integer::from(2), &exps) {
+            Err(RemoteError::Inconsistent(_)) => {}
+            r => panic!("expected an inconsistency, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn withheld_certificate_is_believed() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let client = WitnessClient::new(hasher.clone(), Tamper(WitnessServer::new(hasher)), 0);
+        let inputs = vec![F::from_str("1").unwrap()];
+        assert!(client.pocklington(&inputs, 128).unwrap().is_none());
+    }
+}
@@ -0,0 +1,319 @@
+use sapling_crypto::bellman::{Circuit, ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::num::AllocatedNum;
+use sapling_crypto::eddsa::PublicKey;
+use sapling_crypto::jubjub::{JubjubEngine, PrimeOrder};
+
+use group::{CircuitRsaGroupParams, CircuitRsaQuotientGroup, RsaQuotientGroup};
+use hash::circuit::{CircuitHasher, MaybeHashed};
+use hash::{pocklington, Hasher};
+use mp::bignat::BigNat;
+use rollup::rsa::{Accounts, RsaParams};
+use rollup::sig::allocate_point;
+use rollup::tx::circuit::CircuitAccount;
+use set::int_set::exp::serial::SerialExp;
+use set::int_set::ExpSet;
+use set::rsa::{CircuitSet, CircuitSetParams};
+use util::convert::usize_to_f;
+use util::gadget::Gadget;
+use CResult;
+use OptionExt;
+
+use std::sync::Arc;
+
+pub struct ExitInputs<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    /// The account state to exit from
+    pub accounts: Accounts<E, H>,
+    /// The owner of the exiting account
+    pub owner: PublicKey<E>,
+}
+
+pub struct ExitParams<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    pub jj_params: Arc<<E as JubjubEngine>::Params>,
+    pub set_params: RsaParams<H>,
+}
+
+/// Proves the removal of a single account from an RSA rollup state, so that its owner can
+/// withdraw without the cooperation of the operator.
+///
+/// Public inputs, in order: the owner (recipient) x and y coordinates, the withdrawn amount, the
+/// group, the initial state and the final state. The group and states are packed according to
+/// `set_params.packing`.
+pub struct Exit<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    pub input: Option<ExitInputs<E, H>>,
+    pub params: ExitParams<E, H>,
+}
+
+impl<E, H> Circuit<E> for Exit<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> CResult<()> {
+        let account_value = match self.input.as_ref() {
+            Some(i) => Some(i.accounts.get(&i.owner).ok_or_else(|| {
+                eprintln!("The exiting account does not exist");
+                SynthesisError::Unsatisfiable
+            })?),
+            None => None,
+        };
+        let id = allocate_point::<E, PrimeOrder, _>(
+            cs.namespace(|| "owner"),
+            account_value.map(|a| &a.id.0),
+            self.params.jj_params.as_ref(),
+        )?;
+        let amt = AllocatedNum::alloc(cs.namespace(|| "amt"), || {
+            Ok(usize_to_f(account_value.grab()?.amt as usize))
+        })?;
+        let next_tx_no = AllocatedNum::alloc(cs.namespace(|| "next_tx_no"), || {
+            Ok(usize_to_f(account_value.grab()?.next_tx_no as usize))
+        })?;
+        id.get_x().inputize(cs.namespace(|| "recipient x"))?;
+        id.get_y().inputize(cs.namespace(|| "recipient y"))?;
+        amt.inputize(cs.namespace(|| "amount"))?;
+        let account = CircuitAccount {
+            id,
+            amt,
+            next_tx_no,
+        };
+        let elems = account.as_elems();
+        let hash = self
+            .params
+            .set_params
+            .hasher
+            .allocate_hash(cs.namespace(|| "account hash"), &elems)?;
+        let item = MaybeHashed::new(elems, hash);
+
+        let limb_width = self.params.set_params.limb_width;
+        let n_limbs = self.params.set_params.n_bits_base / limb_width;
+        let digests = self.input.as_ref().map(|i| {
+            let mut accounts = i.accounts.clone();
+            let initial = accounts.digest();
+            accounts.remove(&i.owner);
+            (initial, accounts.digest())
+        });
+        let expected_initial_digest = BigNat::alloc_from_nat(
+            cs.namespace(|| "expected_initial_digest"),
+            || Ok(digests.grab()?.0.clone()),
+            limb_width,
+            n_limbs,
+        )?;
+        let expected_final_digest = BigNat::alloc_from_nat(
+            cs.namespace(|| "expected_final_digest"),
+            || Ok(digests.grab()?.1.clone()),
+            limb_width,
+            n_limbs,
+        )?;
+
+        let raw_group = self
+            .input
+            .as_ref()
+            .map(|s| s.accounts.set().group().clone());
+        let group = CircuitRsaQuotientGroup::alloc(
+            cs.namespace(|| "group"),
+            raw_group.as_ref(),
+            (),
+            &CircuitRsaGroupParams {
+                limb_width,
+                n_limbs,
+            },
+        )?;
+        group.inputize_with(cs.namespace(|| "group input"), self.params.set_params.packing)?;
+
+        // Binds the challenge to the modulus and generator, as `SetBench` does with `absorb_group`.
+        let group_nums = group.as_nums(cs.namespace(|| "group nums"))?;
+        let group_hash = self
+            .params
+            .set_params
+            .hasher
+            .allocate_hash(cs.namespace(|| "group hash"), &group_nums)?;
+        let mut to_hash_to_challenge: Vec<AllocatedNum<E>> = vec![group_hash];
+        to_hash_to_challenge.extend(
+            expected_initial_digest
+                .as_allocated_nums(cs.namespace(|| "initial digest hash"))?
+                .0,
+        );
+        to_hash_to_challenge.extend(
+            expected_final_digest
+                .as_allocated_nums(cs.namespace(|| "final digest hash"))?
+                .0,
+        );
+        to_hash_to_challenge.push(item.hash.clone().unwrap());
+        let challenge = pocklington::hash_to_prime(
+            cs.namespace(|| "challenge hash"),
+            &to_hash_to_challenge,
+            limb_width,
+            self.params.set_params.n_bits_challenge,
+            self.params.set_params.certificate,
+            &self.params.set_params.hasher,
+            None,
+        )?;
+
+        let set: CircuitSet<
+            E,
+            H,
+            CircuitRsaQuotientGroup<E>,
+            ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>,
+        > = CircuitSet::alloc(
+            cs.namespace(|| "set init"),
+            self.input.as_ref().map(|is| is.accounts.set()),
+            (group, challenge),
+            &CircuitSetParams {
+                hasher: self.params.set_params.hasher.clone(),
+                n_bits: self.params.set_params.n_bits_elem,
+                limb_width,
+            },
+        )?;
+        set.inputize_with(
+            cs.namespace(|| "initial_state input"),
+            self.params.set_params.packing,
+        )?;
+        set.inner.digest.equal(
+            cs.namespace(|| "initial digest matches"),
+            &expected_initial_digest,
+        )?;
+
+        let new_set = set.remove(cs.namespace(|| "exit"), &mut vec![item])?;
+        new_set
+            .inner
+            .digest
+            .equal(cs.namespace(|| "check"), &expected_final_digest)?;
+        new_set.inputize_with(
+            cs.namespace(|| "final_state input"),
+            self.params.set_params.packing,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // From my machine (openssl)
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    use super::*;
+    use rand::Rng;
+    use rug::Integer;
+    use sapling_crypto::alt_babyjubjub::AltJubjubBn256;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+    use sapling_crypto::eddsa::PrivateKey;
+    use sapling_crypto::jubjub::FixedGenerators;
+
+    use hash::hashes::Poseidon;
+    use hash::pocklington::CertificateKind;
+    use mp::bignat::nat_to_limbs;
+    use rollup::tx::Account;
+    use util::gadget::InputPacking;
+    use util::test_helpers::*;
+
+    type F = <Bn256 as ScalarEngine>::Fr;
+
+    fn params() -> ExitParams<Bn256, Poseidon<Bn256>> {
+        ExitParams {
+            jj_params: Arc::new(AltJubjubBn256::new()),
+            set_params: RsaParams {
+                group: RsaQuotientGroup::from_strs("2", RSA_512),
+                limb_width: 32,
+                n_bits_base: 512,
+                n_bits_elem: 128,
+                n_bits_challenge: 128,
+                certificate: CertificateKind::Pocklington,
+                hasher: Poseidon::default(),
+                packing: InputPacking::Unpacked,
+            },
+        }
+    }
+
+    /// The public inputs of an exit of `amt` to `owner`, between the given digests.
+    fn public_inputs(
+        params: &RsaParams<Poseidon<Bn256>>,
+        owner: &PublicKey<Bn256>,
+        amt: u64,
+        initial_digest: &Integer,
+        final_digest: &Integer,
+    ) -> Vec<F> {
+        let n_limbs = params.n_bits_base / params.limb_width;
+        let limbs = |n: &Integer| nat_to_limbs::<F>(n, params.limb_width, n_limbs).unwrap();
+        let (x, y) = owner.0.into_xy();
+        let mut inputs = vec![x, y, usize_to_f(amt as usize)];
+        inputs.extend(limbs(&params.group.g));
+        inputs.extend(limbs(&params.group.m));
+        inputs.extend(limbs(initial_digest));
+        inputs.extend(limbs(final_digest));
+        inputs
+    }
+
+    #[test]
+    fn exit_exposes_owner_amount_and_digests() {
+        let params = params();
+        let mut rng = rand::thread_rng();
+        let keys: Vec<PublicKey<Bn256>> = (0..3)
+            .map(|_| {
+                PublicKey::from_private(
+                    &PrivateKey(rng.gen()),
+                    FixedGenerators::SpendingKeyGenerator,
+                    params.jj_params.as_ref(),
+                )
+            })
+            .collect();
+        let mut accounts = Accounts::new(&params.set_params);
+        for (i, id) in keys[..2].iter().enumerate() {
+            accounts.insert(Account {
+                id: id.clone(),
+                amt: 5 + i as u64,
+                next_tx_no: 0,
+            });
+        }
+        let initial_digest = accounts.digest();
+        let final_digest = {
+            let mut accounts = accounts.clone();
+            accounts.remove(&keys[0]);
+            accounts.digest()
+        };
+
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        Exit {
+            input: Some(ExitInputs {
+                accounts: accounts.clone(),
+                owner: keys[0].clone(),
+            }),
+            params: self::params(),
+        }
+        .synthesize(&mut cs)
+        .unwrap();
+        assert!(cs.is_satisfied());
+        let set_params = &params.set_params;
+        let inputs = |owner: &PublicKey<Bn256>, amt: u64, final_digest: &Integer| {
+            public_inputs(set_params, owner, amt, &initial_digest, final_digest)
+        };
+        assert!(cs.verify(&inputs(&keys[0], 5, &final_digest)));
+        // Wrong amount
+        assert!(!cs.verify(&inputs(&keys[0], 6, &final_digest)));
+        // Wrong owner
+        assert!(!cs.verify(&inputs(&keys[1], 5, &final_digest)));
+        // Wrong digest: the state without the exit
+        assert!(!cs.verify(&inputs(&keys[0], 5, &initial_digest)));
+
+        // An owner without an account cannot exit.
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let exit = Exit {
+            input: Some(ExitInputs {
+                accounts,
+                owner: keys[2].clone(),
+            }),
+            params,
+        };
+        assert!(exit.synthesize(&mut cs).is_err());
+    }
+}
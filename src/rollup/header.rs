@@ -0,0 +1,340 @@
+use rug::integer::Order;
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use hash::circuit::CircuitHasher;
+use hash::Hasher;
+use mp::bignat::{nat_to_limbs, BigNat};
+use util::convert::{nat_to_f, usize_to_f};
+use util::encoding::Encoding;
+use util::gadget::Gadget;
+use util::num::Num;
+use CResult;
+use OptionExt;
+
+/// The header of a rollup batch. On-chain contracts track the hash of this header (its
+/// commitment) rather than the individual fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchHeader<F: PrimeField> {
+    /// The index of this batch in the chain of batches
+    pub batch_no: u64,
+    /// The commitment to the state digest before the batch
+    pub prev_digest: F,
+    /// The commitment to the state digest after the batch
+    pub new_digest: F,
+    /// The number of transactions in the batch
+    pub n_tx: u64,
+    /// The root of the deposits and withdrawals made by the batch
+    pub io_root: F,
+}
+
+impl<F: PrimeField> BatchHeader<F> {
+    pub fn as_elems(&self) -> Vec<F> {
+        vec![
+            usize_to_f(self.batch_no as usize),
+            self.prev_digest,
+            self.new_digest,
+            usize_to_f(self.n_tx as usize),
+            self.io_root,
+        ]
+    }
+
+    /// The commitment to this header, as exposed by `CircuitBatchHeader::inputize_hash`.
+    pub fn commitment<H: Hasher<F = F>>(&self, hasher: &H) -> F {
+        hasher.hash(&self.as_elems())
+    }
+}
+
+/// The commitment to an RSA digest: the hash of its limbs, as exposed by `inputize_hash`.
+pub fn digest_commitment<H: Hasher>(
+    hasher: &H,
+    digest: &Integer,
+    limb_width: usize,
+    n_limbs: usize,
+) -> Result<H::F, SynthesisError> {
+    Ok(hasher.hash(&nat_to_limbs(digest, limb_width, n_limbs)?))
+}
+
+/// Computes the commitment to `digest` in-circuit. Agrees with `digest_commitment`.
+pub fn allocate_digest_commitment<E, H, CS>(
+    mut cs: CS,
+    digest: &BigNat<E>,
+    hasher: &H,
+) -> CResult<AllocatedNum<E>>
+where
+    E: Engine,
+    H: CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+{
+    let (limbs, _) = digest.as_allocated_nums(cs.namespace(|| "limbs"))?;
+    hasher.allocate_hash(cs.namespace(|| "hash"), &limbs)
+}
+
+/// The length of the on-chain encoding of an RSA digest: eight big-endian `bytes32` words.
+pub const DIGEST_BLOB_BYTES: usize = 256;
+
+/// The number of field elements a blob is passed to circuits as: each `bytes32` word split into
+/// two 128-bit halves, so that they fit in the field.
+pub const DIGEST_BLOB_WORDS: usize = 16;
+
+const BLOB_WORD_BITS: usize = 128;
+
+/// Encodes `digest` as a zero-padded big-endian blob, as contracts storing it as `bytes32[8]`
+/// do. Fails if the digest has more than 2048 bits.
+pub fn digest_to_blob(digest: &Integer) -> Result<[u8; DIGEST_BLOB_BYTES], SynthesisError> {
+    let bytes = Encoding::EVM.encode_nat(digest, 256, DIGEST_BLOB_BYTES / 32)?;
+    let mut blob = [0u8; DIGEST_BLOB_BYTES];
+    blob.copy_from_slice(&bytes);
+    Ok(blob)
+}
+
+/// Decodes a digest from its big-endian blob.
+pub fn digest_from_blob(blob: &[u8; DIGEST_BLOB_BYTES]) -> Integer {
+    Integer::from_digits(&blob[..], Order::Msf)
+}
+
+/// The words of `blob`, as `assert_digest_matches_blob` takes them: its 128-bit halves, most
+/// significant first.
+pub fn blob_words<F: PrimeField>(blob: &[u8; DIGEST_BLOB_BYTES]) -> Result<Vec<F>, SynthesisError> {
+    Encoding::EVM
+        .split_nat(&digest_from_blob(blob), BLOB_WORD_BITS, DIGEST_BLOB_WORDS)?
+        .iter()
+        .map(nat_to_f)
+        .collect::<Option<Vec<F>>>()
+        .ok_or(SynthesisError::Unsatisfiable)
+}
+
+/// Checks that `words` (see `blob_words`), which are typically public inputs, encode `digest`.
+///
+/// Each word is constrained to be the recomposition of the digest limbs it covers, so the limb
+/// width of `digest` must divide 128. `digest` is checked to be well-formed, which bounds each
+/// recomposition below 2^128, so that the words are exactly the halves of the blob.
+pub fn assert_digest_matches_blob<E, CS>(
+    mut cs: CS,
+    digest: &BigNat<E>,
+    words: &[AllocatedNum<E>],
+) -> CResult<()>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let limb_width = digest.params.limb_width;
+    let limbs_per_word = BLOB_WORD_BITS / limb_width;
+    if words.len() != DIGEST_BLOB_WORDS
+        || BLOB_WORD_BITS % limb_width != 0
+        || digest.limbs.len() > DIGEST_BLOB_WORDS * limbs_per_word
+    {
+        eprintln!(
+            "A digest of {} limbs of width {} cannot be checked against {} blob words",
+            digest.limbs.len(),
+            limb_width,
+            words.len()
+        );
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    digest.assert_well_formed(cs.namespace(|| "digest well-formed"))?;
+    let grouped = digest.group_limbs(limbs_per_word);
+    // The words are most significant first, and the grouped limbs least significant first.
+    for (i, word) in words.iter().rev().enumerate() {
+        let limb = grouped
+            .limbs
+            .get(i)
+            .cloned()
+            .unwrap_or_else(LinearCombination::zero);
+        cs.enforce(
+            || format!("word {}", i),
+            |lc| lc,
+            |lc| lc,
+            |lc| lc + word.get_variable() - &limb,
+        );
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct CircuitBatchHeader<E: Engine> {
+    pub batch_no: AllocatedNum<E>,
+    pub prev_digest: AllocatedNum<E>,
+    pub new_digest: AllocatedNum<E>,
+    pub n_tx: AllocatedNum<E>,
+    pub io_root: AllocatedNum<E>,
+    pub value: Option<BatchHeader<E::Fr>>,
+}
+
+impl<E: Engine> CircuitBatchHeader<E> {
+    /// Assembles a header from already-allocated fields, checking that the counters fit in 64
+    /// bits.
+    pub fn from_parts<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        batch_no: AllocatedNum<E>,
+        prev_digest: AllocatedNum<E>,
+        new_digest: AllocatedNum<E>,
+        n_tx: AllocatedNum<E>,
+        io_root: AllocatedNum<E>,
+    ) -> CResult<Self> {
+        Num::from(batch_no.clone()).fits_in_bits(cs.namespace(|| "batch_no range"), 64)?;
+        Num::from(n_tx.clone()).fits_in_bits(cs.namespace(|| "n_tx range"), 64)?;
+        Ok(Self {
+            batch_no,
+            prev_digest,
+            new_digest,
+            n_tx,
+            io_root,
+            value: None,
+        })
+    }
+
+    pub fn as_elems(&self) -> Vec<AllocatedNum<E>> {
+        vec![
+            self.batch_no.clone(),
+            self.prev_digest.clone(),
+            self.new_digest.clone(),
+            self.n_tx.clone(),
+            self.io_root.clone(),
+        ]
+    }
+
+    /// Computes the commitment to this header, without exposing it.
+    pub fn commitment<CS: ConstraintSystem<E>, H: CircuitHasher<E = E>>(
+        &self,
+        cs: CS,
+        hasher: &H,
+    ) -> CResult<AllocatedNum<E>> {
+        hasher.allocate_hash(cs, &self.as_elems())
+    }
+}
+
+impl<E: Engine> Gadget for CircuitBatchHeader<E> {
+    type E = E;
+    type Value = BatchHeader<E::Fr>;
+    type Access = ();
+    type Params = ();
+    fn alloc<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: Option<&Self::Value>,
+        _access: (),
+        _params: &(),
+    ) -> Result<Self, SynthesisError> {
+        let elems = value.map(BatchHeader::as_elems);
+        let fields = (0..5)
+            .map(|i| {
+                AllocatedNum::alloc(cs.namespace(|| format!("field {}", i)), || {
+                    Ok(elems.grab()?[i])
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut header = Self::from_parts(
+            cs.namespace(|| "parts"),
+            fields[0].clone(),
+            fields[1].clone(),
+            fields[2].clone(),
+            fields[3].clone(),
+            fields[4].clone(),
+        )?;
+        header.value = value.cloned();
+        Ok(header)
+    }
+    fn wires(&self) -> Vec<LinearCombination<E>> {
+        self.as_elems()
+            .into_iter()
+            .map(|n| LinearCombination::zero() + n.get_variable())
+            .collect()
+    }
+    fn wire_values(&self) -> Option<Vec<<E as ScalarEngine>::Fr>> {
+        self.as_elems().iter().map(|n| n.get_value()).collect()
+    }
+    fn value(&self) -> Option<&Self::Value> {
+        self.value.as_ref()
+    }
+    fn access(&self) -> &() {
+        &()
+    }
+    fn params(&self) -> &() {
+        &()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hashes::Poseidon;
+    use util::test_helpers::*;
+
+    #[test]
+    fn header_commitment_matches() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let header = BatchHeader::<F> {
+            batch_no: 7,
+            prev_digest: F::from_str("11").unwrap(),
+            new_digest: F::from_str("12").unwrap(),
+            n_tx: 3,
+            io_root: F::from_str("13").unwrap(),
+        };
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let allocated =
+            CircuitBatchHeader::alloc(cs.namespace(|| "header"), Some(&header), (), &()).unwrap();
+        let commitment = allocated
+            .inputize_hash(cs.namespace(|| "commitment"), &hasher)
+            .unwrap();
+        assert_eq!(commitment.get_value(), Some(header.commitment(&hasher)));
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn digest_blob_round_trips() {
+        let digest = (Integer::from(1) << 2047) + 5;
+        let blob = digest_to_blob(&digest).unwrap();
+        assert_eq!(blob[0], 0x80);
+        assert_eq!(blob[DIGEST_BLOB_BYTES - 1], 5);
+        assert_eq!(digest_from_blob(&blob), digest);
+        let small = digest_to_blob(&Integer::from(0x1234)).unwrap();
+        assert_eq!(&small[DIGEST_BLOB_BYTES - 2..], &[0x12, 0x34]);
+        assert!(small[..DIGEST_BLOB_BYTES - 2].iter().all(|b| *b == 0));
+        assert!(digest_to_blob(&(Integer::from(1) << 2048)).is_err());
+    }
+
+    pub struct DigestBlob {
+        pub digest: Integer,
+        pub blob: Integer,
+    }
+
+    impl Circuit<Bn256> for DigestBlob {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let digest = BigNat::alloc_from_nat(
+                cs.namespace(|| "digest"),
+                || Ok(self.digest.clone()),
+                32,
+                64,
+            )?;
+            let blob = digest_to_blob(&self.blob)?;
+            let words = blob_words::<<Bn256 as ScalarEngine>::Fr>(&blob)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, w)| AllocatedNum::alloc(cs.namespace(|| format!("word {}", i)), || Ok(w)))
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_digest_matches_blob(cs.namespace(|| "check"), &digest, &words)
+        }
+    }
+
+    circuit_tests! {
+        digest_blob_matches: (DigestBlob {
+            digest: (Integer::from(3) << 2000) + 7,
+            blob: (Integer::from(3) << 2000) + 7,
+        }, true),
+        digest_blob_mismatch: (DigestBlob {
+            digest: (Integer::from(3) << 2000) + 7,
+            blob: (Integer::from(3) << 2000) + 8,
+        }, false),
+        digest_blob_mismatch_high_word: (DigestBlob {
+            digest: (Integer::from(3) << 2000) + 7,
+            blob: (Integer::from(1) << 2000) + 7,
+        }, false),
+    }
+}
@@ -161,12 +161,21 @@ where
 {
     /// Creates a benchmark where `t` coins are exchanged in a pool of size `c`.
     pub fn from_counts(c: usize, t: usize, p: &RollupBenchParams<E, H>) -> Self {
+        Self::from_counts_with_rng(c, t, p, &mut rand::thread_rng())
+    }
+
+    /// Like `from_counts`, but draws keys and signature randomness from `rng`.
+    pub fn from_counts_with_rng<R: Rng>(
+        c: usize,
+        t: usize,
+        p: &RollupBenchParams<E, H>,
+        rng: &mut R,
+    ) -> Self {
         let gens = FixedGenerators::SpendingKeyGenerator;
         let hasher = Pedersen::<E> {
             params: p.jj_params.clone(),
         };
         let mut sks = Vec::new();
-        let mut rng = rand::thread_rng();
         for _ in 0..c {
             sks.push(PrivateKey(rng.gen()));
         }
@@ -190,7 +199,7 @@ where
                 tx_no: (i / c) as u64,
             };
             transactions.push(action.sign(
-                &mut rng,
+                rng,
                 gens,
                 p.jj_params.as_ref(),
                 &hasher,
@@ -1,4 +1,7 @@
+pub mod exit;
+pub mod header;
 pub mod merkle;
+pub mod pool;
 pub mod rsa;
 pub mod sig;
 pub mod tx;
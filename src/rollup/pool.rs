@@ -0,0 +1,327 @@
+use sapling_crypto::eddsa::PublicKey;
+use sapling_crypto::jubjub::{FixedGenerators, JubjubEngine};
+
+use hash::circuit::CircuitHasher;
+use hash::hashes::Pedersen;
+use hash::Hasher;
+use rollup::rsa::{Accounts, RollupBenchInputs, RollupBenchParams};
+use rollup::tx::SignedTx;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Why a transaction was refused by a `TxPool`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PoolError {
+    /// The signature does not verify under the source key.
+    BadSignature,
+    /// The source account does not exist.
+    UnknownSource,
+    /// The destination account does not exist.
+    UnknownDestination,
+    /// The source and destination are the same account.
+    SelfTransfer,
+    /// The source account has already used this transaction number.
+    StaleNonce { expected: u64, got: u64 },
+    /// A pending transaction from the same source already has this transaction number.
+    DuplicateNonce(u64),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::BadSignature => write!(f, "invalid signature"),
+            PoolError::UnknownSource => write!(f, "unknown source account"),
+            PoolError::UnknownDestination => write!(f, "unknown destination account"),
+            PoolError::SelfTransfer => write!(f, "source and destination are the same"),
+            PoolError::StaleNonce { expected, got } => write!(
+                f,
+                "transaction number {} already used, next is {}",
+                got, expected
+            ),
+            PoolError::DuplicateNonce(n) => {
+                write!(f, "transaction number {} is already pending", n)
+            }
+        }
+    }
+}
+
+fn same_key<E: JubjubEngine>(a: &PublicKey<E>, b: &PublicKey<E>) -> bool {
+    a.0.into_xy() == b.0.into_xy()
+}
+
+/// Collects candidate transactions for an RSA rollup, and cuts them into batches which are
+/// valid against the current account state.
+///
+/// Transactions are checked on submission (signature, accounts, nonce), and again against the
+/// evolving state when batches are cut, since balances and nonces depend on earlier
+/// transactions. Transactions are included in arrival order, except that a transaction waits
+/// for any earlier transaction numbers from the same source.
+pub struct TxPool<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    state: Accounts<E, H>,
+    pending: Vec<SignedTx<E>>,
+    batch_size: usize,
+    jj_params: Arc<<E as JubjubEngine>::Params>,
+    sig_hasher: Pedersen<E>,
+    gen: FixedGenerators,
+}
+
+impl<E, H> TxPool<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    /// Creates a pool over `state`, producing batches for circuits with parameters `params`.
+    pub fn new(state: Accounts<E, H>, params: &RollupBenchParams<E, H>) -> Self {
+        Self {
+            state,
+            pending: Vec::new(),
+            batch_size: params.n_tx,
+            jj_params: params.jj_params.clone(),
+            sig_hasher: params.sig_hasher.clone(),
+            gen: params.gen,
+        }
+    }
+
+    /// The account state after all emitted batches.
+    pub fn state(&self) -> &Accounts<E, H> {
+        &self.state
+    }
+
+    /// The number of transactions waiting to be batched.
+    pub fn n_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Validates `tx` against the current state and queues it.
+    pub fn submit(&mut self, tx: SignedTx<E>) -> Result<(), PoolError> {
+        if !tx.verify(self.gen, self.jj_params.as_ref(), &self.sig_hasher) {
+            return Err(PoolError::BadSignature);
+        }
+        if same_key(&tx.tx.src, &tx.tx.action.dst) {
+            return Err(PoolError::SelfTransfer);
+        }
+        let src = self
+            .state
+            .get(&tx.tx.src)
+            .ok_or(PoolError::UnknownSource)?;
+        if self.state.get(&tx.tx.action.dst).is_none() {
+            return Err(PoolError::UnknownDestination);
+        }
+        if tx.tx.action.tx_no < src.next_tx_no {
+            return Err(PoolError::StaleNonce {
+                expected: src.next_tx_no,
+                got: tx.tx.action.tx_no,
+            });
+        }
+        if self.pending.iter().any(|p| {
+            same_key(&p.tx.src, &tx.tx.src) && p.tx.action.tx_no == tx.tx.action.tx_no
+        }) {
+            return Err(PoolError::DuplicateNonce(tx.tx.action.tx_no));
+        }
+        self.pending.push(tx);
+        Ok(())
+    }
+
+    /// Whether `tx` can be applied to `state` right now.
+    fn executable(state: &Accounts<E, H>, tx: &SignedTx<E>) -> bool {
+        match (state.get(&tx.tx.src), state.get(&tx.tx.action.dst)) {
+            (Some(src), Some(dst)) => {
+                src.next_tx_no == tx.tx.action.tx_no
+                    && src.amt >= tx.tx.action.amt
+                    && dst.amt.checked_add(tx.tx.action.amt).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Cuts the next batch of exactly `n_tx` transactions, advancing the pool's state past it.
+    ///
+    /// Returns `None` (leaving the pool unchanged) if not enough transactions are executable.
+    pub fn next_batch(&mut self) -> Option<RollupBenchInputs<E, H>> {
+        let mut scratch = self.state.clone();
+        let mut chosen = Vec::with_capacity(self.batch_size);
+        let mut remaining = self.pending.clone();
+        // Each pass may unlock the next transaction number of some sources.
+        loop {
+            let mut progress = false;
+            let mut deferred = Vec::new();
+            for tx in remaining {
+                if chosen.len() < self.batch_size && Self::executable(&scratch, &tx) {
+                    scratch
+                        .apply_tx(&tx.tx)
+                        .expect("transaction was checked to be executable");
+                    chosen.push(tx);
+                    progress = true;
+                } else {
+                    deferred.push(tx);
+                }
+            }
+            remaining = deferred;
+            if !progress || chosen.len() == self.batch_size {
+                break;
+            }
+        }
+        if chosen.len() < self.batch_size {
+            return None;
+        }
+        let initial = std::mem::replace(&mut self.state, scratch);
+        self.pending = remaining;
+        Some(RollupBenchInputs {
+            transactions: chosen,
+            accounts: initial,
+            final_digest: self.state.digest(),
+            epoch: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // From my machine (openssl)
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    use super::*;
+    use rand::Rng;
+    use sapling_crypto::alt_babyjubjub::AltJubjubBn256;
+    use sapling_crypto::eddsa::PrivateKey;
+
+    use group::RsaQuotientGroup;
+    use hash::hashes::Poseidon;
+    use hash::pocklington::CertificateKind;
+    use rollup::rsa::RsaParams;
+    use rollup::tx::{Account, Action};
+    use util::gadget::InputPacking;
+    use util::test_helpers::*;
+
+    type Pool = TxPool<Bn256, Poseidon<Bn256>>;
+
+    struct Fixture {
+        params: RollupBenchParams<Bn256, Poseidon<Bn256>>,
+        sks: Vec<PrivateKey<Bn256>>,
+        pks: Vec<PublicKey<Bn256>>,
+    }
+
+    impl Fixture {
+        /// Three accounts, whose balances and next transaction numbers are `accounts`, and a pool
+        /// cutting batches of two transactions.
+        fn new(accounts: &[(u64, u64)]) -> (Self, Pool) {
+            let jj_params = Arc::new(AltJubjubBn256::new());
+            let params = RollupBenchParams {
+                jj_params: jj_params.clone(),
+                sig_hasher: Pedersen { params: jj_params },
+                gen: FixedGenerators::SpendingKeyGenerator,
+                n_tx: 2,
+                set_params: RsaParams {
+                    group: RsaQuotientGroup::from_strs("2", RSA_512),
+                    limb_width: 32,
+                    n_bits_base: 512,
+                    n_bits_elem: 128,
+                    n_bits_challenge: 128,
+                    certificate: CertificateKind::Pocklington,
+                    hasher: Poseidon::default(),
+                    packing: InputPacking::Unpacked,
+                },
+                bind_epoch: false,
+            };
+            let mut rng = rand::thread_rng();
+            let sks: Vec<PrivateKey<Bn256>> =
+                accounts.iter().map(|_| PrivateKey(rng.gen())).collect();
+            let pks: Vec<PublicKey<Bn256>> = sks
+                .iter()
+                .map(|sk| PublicKey::from_private(sk, params.gen, params.jj_params.as_ref()))
+                .collect();
+            let mut state = Accounts::new(&params.set_params);
+            for (pk, &(amt, next_tx_no)) in pks.iter().zip(accounts) {
+                state.insert(Account {
+                    id: pk.clone(),
+                    amt,
+                    next_tx_no,
+                });
+            }
+            let pool = TxPool::new(state, &params);
+            (Self { params, sks, pks }, pool)
+        }
+
+        fn tx(&self, src: usize, dst: usize, amt: u64, tx_no: u64) -> SignedTx<Bn256> {
+            Action {
+                dst: self.pks[dst].clone(),
+                amt,
+                tx_no,
+            }
+            .sign(
+                &mut rand::thread_rng(),
+                self.params.gen,
+                self.params.jj_params.as_ref(),
+                &self.params.sig_hasher,
+                &self.sks[src],
+            )
+        }
+    }
+
+    #[test]
+    fn submit_rejects_bad_transactions() {
+        let (f, mut pool) = Fixture::new(&[(3, 1), (0, 0), (0, 0)]);
+        let mut tampered = f.tx(0, 1, 1, 1);
+        tampered.tx.action.amt = 2;
+        assert_eq!(pool.submit(tampered), Err(PoolError::BadSignature));
+        assert_eq!(
+            pool.submit(f.tx(0, 1, 1, 0)),
+            Err(PoolError::StaleNonce {
+                expected: 1,
+                got: 0
+            })
+        );
+        assert_eq!(pool.submit(f.tx(0, 0, 1, 1)), Err(PoolError::SelfTransfer));
+        assert_eq!(pool.submit(f.tx(0, 1, 1, 1)), Ok(()));
+        assert_eq!(
+            pool.submit(f.tx(0, 2, 1, 1)),
+            Err(PoolError::DuplicateNonce(1))
+        );
+        assert_eq!(pool.n_pending(), 1);
+    }
+
+    #[test]
+    fn next_batch_orders_by_transaction_number() {
+        let (f, mut pool) = Fixture::new(&[(3, 0), (0, 0), (0, 0)]);
+        // Submitted out of order: the first waits for the second.
+        pool.submit(f.tx(0, 1, 1, 1)).unwrap();
+        pool.submit(f.tx(0, 2, 1, 0)).unwrap();
+        let batch = pool.next_batch().expect("both transactions are executable");
+        let tx_nos: Vec<u64> = batch
+            .transactions
+            .iter()
+            .map(|t| t.tx.action.tx_no)
+            .collect();
+        assert_eq!(tx_nos, vec![0, 1]);
+        assert_eq!(pool.n_pending(), 0);
+        assert_eq!(batch.final_digest, pool.state().clone().digest());
+        let src = pool.state().get(&f.pks[0]).unwrap();
+        assert_eq!((src.amt, src.next_tx_no), (1, 2));
+    }
+
+    #[test]
+    fn next_batch_waits_for_balances() {
+        let (f, mut pool) = Fixture::new(&[(3, 0), (0, 0), (0, 0)]);
+        // Only executable once the second transaction credits the account, and even then the
+        // balance is insufficient.
+        pool.submit(f.tx(1, 2, 5, 0)).unwrap();
+        pool.submit(f.tx(0, 1, 1, 0)).unwrap();
+        let digest = pool.state().clone().digest();
+        assert!(pool.next_batch().is_none());
+        assert_eq!(pool.n_pending(), 2);
+        assert_eq!(pool.state().clone().digest(), digest);
+
+        // A credit earlier in the batch funds a later transaction.
+        let (f, mut pool) = Fixture::new(&[(3, 0), (0, 0), (0, 0)]);
+        pool.submit(f.tx(1, 2, 1, 0)).unwrap();
+        pool.submit(f.tx(0, 1, 1, 0)).unwrap();
+        let batch = pool.next_batch().expect("the credit funds the transfer");
+        assert!(same_key(&batch.transactions[0].tx.src, &f.pks[0]));
+        assert!(same_key(&batch.transactions[1].tx.src, &f.pks[1]));
+    }
+}
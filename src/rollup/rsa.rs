@@ -13,6 +13,7 @@ use group::{CircuitRsaGroupParams, CircuitRsaQuotientGroup, RsaQuotientGroup};
 use hash::circuit::CircuitHasher;
 use hash::hashes::Pedersen;
 use hash::{self, division_intractable as di, Hasher};
+use hash::pocklington::CertificateKind;
 use mp::bignat::BigNat;
 use rollup::sig::allocate_point;
 use rollup::tx::circuit::{CircuitAccount, CircuitSignedTx};
@@ -21,12 +22,14 @@ use set::int_set::ExpSet;
 use set::rsa::{CircuitSet, CircuitSetParams, Set};
 use set::int_set::exp::serial::SerialExp;
 use set::{CircuitGenSet, GenSet};
+use util::bench::{profile_memory, MemoryReport};
 use util::convert::usize_to_f;
-use util::gadget::Gadget;
+use util::gadget::{Gadget, InputPacking};
 use CResult;
 use OptionExt;
 
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 
 const RSA_2048: &str = "25195908475657893494027183240048398571429282126204032027777137836043662020707595556264018525880784406918290641249515082189298559149176184502808489120072844992687392807287776735971418347270261896375014971824691165077613379859095700097330459748808428401797429100642458691817195118746121515172654632282216869987549182422433637259085141865462043576798423387184774447920739934236584823824281198163815010674810451660377306056201619676256133844143603833904414952634432190114657544454178424020924616515723350778707749817125772467962926386356373289912154831438167899885040445364023527381951378636564391212010397122822120720357";
@@ -89,6 +92,11 @@ where
         self.set.digest()
     }
 
+    /// The set of account records backing these accounts.
+    pub fn set(&self) -> &Set<H, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>> {
+        &self.set
+    }
+
     /// Applies this transaction to the accounts:
     ///    * mutating the state and
     ///    * returning the changes made
@@ -183,6 +191,8 @@ where
     pub accounts: Accounts<E, H>,
     /// The expected final state
     pub final_digest: Integer,
+    /// The epoch the proof is bound to, if the circuit binds one
+    pub epoch: Option<E::Fr>,
 }
 
 impl<E, H> RollupBenchInputs<E, H>
@@ -192,12 +202,21 @@ where
 {
     /// Creates a benchmark where `t` coins are exchanged in a pool of size `c`.
     pub fn from_counts(c: usize, t: usize, p: &RollupBenchParams<E, H>) -> Self {
+        Self::from_counts_with_rng(c, t, p, &mut rand::thread_rng())
+    }
+
+    /// Like `from_counts`, but draws keys and signature randomness from `rng`.
+    pub fn from_counts_with_rng<R: Rng>(
+        c: usize,
+        t: usize,
+        p: &RollupBenchParams<E, H>,
+        rng: &mut R,
+    ) -> Self {
         let gens = FixedGenerators::SpendingKeyGenerator;
         let hasher = Pedersen::<E> {
             params: p.jj_params.clone(),
         };
         let mut sks = Vec::new();
-        let mut rng = rand::thread_rng();
         for _ in 0..c {
             sks.push(PrivateKey(rng.gen()));
         }
@@ -222,7 +241,7 @@ where
                 tx_no: (i / c) as u64,
             };
             transactions.push(action.sign(
-                &mut rng,
+                rng,
                 gens,
                 p.jj_params.as_ref(),
                 &hasher,
@@ -240,19 +259,27 @@ where
             transactions,
             accounts,
             final_digest,
+            epoch: None,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct RsaParams<H> {
     pub group: RsaQuotientGroup,
     pub limb_width: usize,
     pub n_bits_base: usize,
     pub n_bits_elem: usize,
     pub n_bits_challenge: usize,
+    /// How the challenge prime is certified
+    pub certificate: CertificateKind,
     pub hasher: H,
+    /// How the group and digests are exposed as public inputs
+    pub packing: InputPacking,
 }
 
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""))]
 pub struct RollupBenchParams<E, H>
 where
     E: JubjubEngine,
@@ -263,6 +290,9 @@ where
     pub gen: FixedGenerators,
     pub n_tx: usize,
     pub set_params: RsaParams<H>,
+    /// Whether the proof is bound to an epoch (see `set::transcript`), which the inputs must
+    /// carry. The epoch is the first public input.
+    pub bind_epoch: bool,
 }
 
 pub struct RollupBench<E, H>
@@ -299,14 +329,105 @@ where
                 n_bits_base: 2048,
                 n_bits_challenge: 256,
                 n_bits_elem: 2048,
+                certificate: CertificateKind::default(),
                 hasher: set_hash,
+                packing: InputPacking::Unpacked,
             },
+            bind_epoch: false,
         };
         Self {
             input: Some(RollupBenchInputs::from_counts(c, t, &params)),
             params,
         }
     }
+
+    /// Measures the circuit for `params` without building any inputs, to check before a long
+    /// setup or proof that the prover fits in memory.
+    pub fn estimate_memory(
+        params: &RollupBenchParams<E, H>,
+    ) -> Result<MemoryReport, SynthesisError> {
+        profile_memory(Self {
+            input: None,
+            params: params.clone(),
+        })
+    }
+}
+
+/// An inconsistency in the inputs of a `RollupBench`, which would make the circuit
+/// unsatisfiable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RollupInputError {
+    /// The circuit has no inputs.
+    MissingInputs,
+    /// The number of transactions differs from the circuit shape.
+    TxCount { expected: usize, got: usize },
+    /// The signature of this transaction does not verify.
+    BadSignature(usize),
+    /// This transaction cannot be applied (unknown account, wrong transaction number, or
+    /// insufficient funds).
+    InvalidTx(usize),
+    /// The expected final digest is not the result of the transactions.
+    DigestMismatch,
+    /// An epoch is present without `bind_epoch`, or vice versa.
+    Epoch,
+}
+
+impl fmt::Display for RollupInputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RollupInputError::MissingInputs => write!(f, "no inputs"),
+            RollupInputError::TxCount { expected, got } => {
+                write!(f, "expected {} transactions, got {}", expected, got)
+            }
+            RollupInputError::BadSignature(i) => write!(f, "transaction {} has a bad signature", i),
+            RollupInputError::InvalidTx(i) => write!(f, "transaction {} cannot be applied", i),
+            RollupInputError::DigestMismatch => {
+                write!(f, "the final digest does not follow from the transactions")
+            }
+            RollupInputError::Epoch => write!(f, "the epoch does not match the epoch binding"),
+        }
+    }
+}
+
+impl<E, H> RollupBench<E, H>
+where
+    E: JubjubEngine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+{
+    /// Checks natively that the transactions are well-signed, apply in order to the initial
+    /// accounts, and lead to the expected final digest.
+    pub fn check_inputs(&self) -> Result<(), RollupInputError> {
+        let input = self
+            .input
+            .as_ref()
+            .ok_or(RollupInputError::MissingInputs)?;
+        if input.transactions.len() != self.params.n_tx {
+            return Err(RollupInputError::TxCount {
+                expected: self.params.n_tx,
+                got: input.transactions.len(),
+            });
+        }
+        if input.epoch.is_some() != self.params.bind_epoch {
+            return Err(RollupInputError::Epoch);
+        }
+        let mut accounts = input.accounts.clone();
+        for (i, tx) in input.transactions.iter().enumerate() {
+            if !tx.verify(
+                self.params.gen,
+                self.params.jj_params.as_ref(),
+                &self.params.sig_hasher,
+            ) {
+                return Err(RollupInputError::BadSignature(i));
+            }
+            if accounts.apply_tx(&tx.tx).is_none() {
+                return Err(RollupInputError::InvalidTx(i));
+            }
+        }
+        if accounts.digest() != input.final_digest {
+            return Err(RollupInputError::DigestMismatch);
+        }
+        Ok(())
+    }
 }
 
 impl<E, H> Circuit<E> for RollupBench<E, H>
@@ -315,6 +436,15 @@ where
     H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
 {
     fn synthesize<CS: ConstraintSystem<E>>(mut self, cs: &mut CS) -> CResult<()> {
+        let epoch = if self.params.bind_epoch {
+            let epoch = AllocatedNum::alloc(cs.namespace(|| "epoch"), || {
+                Ok(*self.input.grab()?.epoch.grab()?)
+            })?;
+            epoch.inputize(cs.namespace(|| "epoch input"))?;
+            Some(epoch)
+        } else {
+            None
+        };
         let gen_value = self
             .params
             .jj_params
@@ -413,36 +543,27 @@ where
         )?;
 
         let mut to_hash_to_challenge: Vec<AllocatedNum<E>> = Vec::new();
+        to_hash_to_challenge.extend(epoch);
         to_hash_to_challenge.extend(
             expected_initial_digest
-                .as_limbs::<CS>()
-                .into_iter()
-                .enumerate()
-                .map(|(i, n)| {
-                    n.as_sapling_allocated_num(
-                        cs.namespace(|| format!("initial digest hash {}", i)),
-                    )
-                })
-                .collect::<Result<Vec<_>, _>>()?,
+                .as_allocated_nums(cs.namespace(|| "initial digest hash"))?
+                .0,
         );
         to_hash_to_challenge.extend(
             expected_final_digest
-                .as_limbs::<CS>()
-                .into_iter()
-                .enumerate()
-                .map(|(i, n)| {
-                    n.as_sapling_allocated_num(cs.namespace(|| format!("final digest hash {}", i)))
-                })
-                .collect::<Result<Vec<_>, _>>()?,
+                .as_allocated_nums(cs.namespace(|| "final digest hash"))?
+                .0,
         );
         to_hash_to_challenge.extend(insertions.iter().map(|i| i.hash.clone().unwrap()));
         to_hash_to_challenge.extend(removals.iter().map(|i| i.hash.clone().unwrap()));
-        let challenge = hash::pocklington::hash_to_pocklington_prime(
+        let challenge = hash::pocklington::hash_to_prime(
             cs.namespace(|| "challenge hash"),
             &to_hash_to_challenge,
             self.params.set_params.limb_width,
             self.params.set_params.n_bits_challenge,
+            self.params.set_params.certificate,
             &self.params.set_params.hasher,
+            None,
         )?;
 
         let raw_group = self.input.as_ref().map(|s| s.accounts.set.group().clone());
@@ -455,7 +576,7 @@ where
                 n_limbs: self.params.set_params.n_bits_base / self.params.set_params.limb_width,
             },
         )?;
-        group.inputize(cs.namespace(|| "group input"))?;
+        group.inputize_with(cs.namespace(|| "group input"), self.params.set_params.packing)?;
 
         let set: CircuitSet<E, H, CircuitRsaQuotientGroup<E>, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>> =
             CircuitSet::alloc(
@@ -468,7 +589,10 @@ where
                     limb_width: self.params.set_params.limb_width,
                 },
             )?;
-        set.inputize(cs.namespace(|| "initial_state input"))?;
+        set.inputize_with(
+            cs.namespace(|| "initial_state input"),
+            self.params.set_params.packing,
+        )?;
         set.inner.digest.equal(
             cs.namespace(|| "initial digest matches"),
             &expected_initial_digest,
@@ -480,7 +604,143 @@ where
             .inner
             .digest
             .equal(cs.namespace(|| "check"), &expected_final_digest)?;
-        new_set.inputize(cs.namespace(|| "final_state input"))?;
+        new_set.inputize_with(
+            cs.namespace(|| "final_state input"),
+            self.params.set_params.packing,
+        )?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    // From my machine (openssl)
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    use super::*;
+    use sapling_crypto::alt_babyjubjub::AltJubjubBn256;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::hashes::Poseidon;
+    use util::test_helpers::*;
+
+    type F = <Bn256 as ScalarEngine>::Fr;
+
+    fn params(bind_epoch: bool) -> RollupBenchParams<Bn256, Poseidon<Bn256>> {
+        let jj_params = Arc::new(AltJubjubBn256::new());
+        RollupBenchParams {
+            jj_params: jj_params.clone(),
+            sig_hasher: Pedersen { params: jj_params },
+            gen: FixedGenerators::SpendingKeyGenerator,
+            n_tx: 1,
+            set_params: RsaParams {
+                group: RsaQuotientGroup::from_strs("2", RSA_512),
+                limb_width: 32,
+                n_bits_base: 512,
+                n_bits_elem: 128,
+                n_bits_challenge: 128,
+                certificate: CertificateKind::Pocklington,
+                hasher: Poseidon::default(),
+                packing: InputPacking::Unpacked,
+            },
+            bind_epoch,
+        }
+    }
+
+    /// A bench of one transaction between two accounts, with consistent inputs.
+    fn bench() -> RollupBench<Bn256, Poseidon<Bn256>> {
+        let params = params(false);
+        let input = RollupBenchInputs::from_counts(2, 1, &params);
+        let bench = RollupBench {
+            input: Some(input),
+            params,
+        };
+        assert_eq!(bench.check_inputs(), Ok(()));
+        bench
+    }
+
+    #[test]
+    fn missing_inputs_are_caught() {
+        let bench = RollupBench {
+            input: None,
+            params: params(false),
+        };
+        assert_eq!(bench.check_inputs(), Err(RollupInputError::MissingInputs));
+    }
+
+    #[test]
+    fn tx_count_is_checked() {
+        let mut bench = bench();
+        bench.params.n_tx = 2;
+        assert_eq!(
+            bench.check_inputs(),
+            Err(RollupInputError::TxCount {
+                expected: 2,
+                got: 1
+            })
+        );
+    }
+
+    #[test]
+    fn bad_signature_is_caught() {
+        let mut bench = bench();
+        bench.input.as_mut().unwrap().transactions[0].tx.action.amt = 2;
+        assert_eq!(bench.check_inputs(), Err(RollupInputError::BadSignature(0)));
+    }
+
+    #[test]
+    fn inapplicable_tx_is_caught() {
+        let mut bench = bench();
+        let input = bench.input.as_mut().unwrap();
+        let src = input.transactions[0].tx.src.clone();
+        assert!(input.accounts.remove(&src).is_some());
+        assert_eq!(bench.check_inputs(), Err(RollupInputError::InvalidTx(0)));
+    }
+
+    #[test]
+    fn digest_mismatch_is_caught() {
+        let mut bench = bench();
+        bench.input.as_mut().unwrap().final_digest += 1;
+        assert_eq!(bench.check_inputs(), Err(RollupInputError::DigestMismatch));
+    }
+
+    #[test]
+    fn estimate_memory_matches_shape() {
+        let params = params(false);
+        let report = RollupBench::estimate_memory(&params).unwrap();
+        let mut counter = ::util::bench::ConstraintCounter::new();
+        RollupBench {
+            input: None,
+            params,
+        }
+        .synthesize(&mut counter)
+        .unwrap();
+        assert_eq!(report.n_constraints, counter.num_constraints());
+        assert!(report.n_inputs > 1);
+        assert!(report.estimated_prover_bytes::<Bn256>() > report.domain_size() * 32 * 3);
+    }
+
+    #[test]
+    fn epoch_is_bound() {
+        let params = params(true);
+        let input = RollupBenchInputs::from_counts(2, 1, &params);
+        let mut bench = RollupBench {
+            input: Some(input),
+            params,
+        };
+        assert_eq!(bench.check_inputs(), Err(RollupInputError::Epoch));
+        let epoch = F::from_str("7").unwrap();
+        bench.input.as_mut().unwrap().epoch = Some(epoch);
+        assert_eq!(bench.check_inputs(), Ok(()));
+
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        bench.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(cs.get_input(1, "epoch input/input variable"), epoch);
+        // The challenge absorbs the epoch, so another epoch needs another witness.
+        let other = F::from_str("8").unwrap();
+        cs.set("epoch/num", other);
+        cs.set("epoch input/input variable", other);
+        assert!(!cs.is_satisfied());
+    }
+}
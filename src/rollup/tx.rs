@@ -43,6 +43,14 @@ impl<E: JubjubEngine> Action<E> {
             usize_to_f(self.tx_no as usize),
         ]
     }
+    /// The message signed to authorize this action: the low bytes of its hash.
+    pub fn sig_message<H: Hasher<F = E::Fr>>(&self, hasher: &H) -> Vec<u8> {
+        let hash = hasher.hash(&self.as_elems());
+        let mut bytes = Vec::new();
+        hash.into_repr().write_le(&mut bytes).unwrap();
+        bytes.truncate((E::Fr::CAPACITY / 8) as usize);
+        bytes
+    }
     pub fn sign<R: Rng, H: Hasher<F = E::Fr>>(
         &self,
         rng: &mut R,
@@ -51,10 +59,7 @@ impl<E: JubjubEngine> Action<E> {
         hasher: &H,
         sk: &PrivateKey<E>,
     ) -> SignedTx<E> {
-        let hash = hasher.hash(&self.as_elems());
-        let mut bytes = Vec::new();
-        hash.into_repr().write_le(&mut bytes).unwrap();
-        bytes.truncate((E::Fr::CAPACITY / 8) as usize);
+        let bytes = self.sig_message(hasher);
         SignedTx {
             tx: Tx {
                 src: PublicKey::from_private(sk, p_g, params),
@@ -94,6 +99,24 @@ pub struct SignedTx<E: JubjubEngine> {
     pub sig: Signature<E>,
 }
 
+impl<E: JubjubEngine> SignedTx<E> {
+    /// Checks that the signature is valid for the action, under the source key.
+    pub fn verify<H: Hasher<F = E::Fr>>(
+        &self,
+        p_g: FixedGenerators,
+        params: &E::Params,
+        hasher: &H,
+    ) -> bool {
+        self.tx.src.verify_for_raw_message(
+            &self.tx.action.sig_message(hasher),
+            &self.sig,
+            p_g,
+            params,
+            (E::Fr::CAPACITY / 8) as usize,
+        )
+    }
+}
+
 pub mod circuit {
     use sapling_crypto::bellman::pairing::ff::ScalarEngine;
     use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
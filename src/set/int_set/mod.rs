@@ -6,7 +6,7 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 
 use group::{CircuitSemiGroup, SemiGroup};
-use mp::bignat::BigNat;
+use hash::pocklington::Challenge;
 use util::gadget::Gadget;
 use util::verbose::in_verbose_mode;
 use wesolowski::{proof_of_exp, Reduced};
@@ -32,6 +32,14 @@ pub trait IntSet: Sized + Clone + Eq + Debug {
     /// Gets the underlying RSA group
     fn group(&self) -> &Self::G;
 
+    /// The elements of the set, each repeated according to its multiplicity.
+    fn elements(&self) -> Vec<Integer>;
+
+    /// The multiplicity of `n` in the set.
+    fn count(&self, n: &Integer) -> usize {
+        self.elements().iter().filter(|e| *e == n).count()
+    }
+
     /// Add all of the `ns` to the set. Returns whether all items were absent
     fn insert_all<I: IntoIterator<Item = Integer>>(&mut self, ns: I) {
         for n in ns {
@@ -50,6 +58,20 @@ pub trait IntSet: Sized + Clone + Eq + Debug {
         }
         all_present
     }
+
+    /// Remove all of the `removals` and add all of the `insertions`, as a single operation.
+    /// Returns whether all removed items were present.
+    ///
+    /// Implementations may override this to avoid recomputing the digest twice.
+    fn swap<'a, I, J>(&mut self, removals: I, insertions: J) -> bool
+    where
+        I: IntoIterator<Item = &'a Integer>,
+        J: IntoIterator<Item = Integer>,
+        <Self::G as SemiGroup>::Elem: 'a,
+    {
+        self.insert_all(insertions);
+        self.remove_all(removals)
+    }
 }
 
 // ** ExpSet ** //
@@ -143,6 +165,17 @@ where
     fn group(&self) -> &G {
         &self.group
     }
+
+    fn elements(&self) -> Vec<Integer> {
+        self.elements
+            .iter()
+            .flat_map(|(elem, ct)| std::iter::repeat(elem.clone()).take(*ct))
+            .collect()
+    }
+
+    fn count(&self, n: &Integer) -> usize {
+        self.elements.get(n).cloned().unwrap_or(0)
+    }
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -217,7 +250,7 @@ where
     pub fn remove<'a, CS: ConstraintSystem<E>>(
         self,
         mut cs: CS,
-        challenge: &BigNat<E>,
+        challenge: &Challenge<E>,
         items: impl IntoIterator<Item = &'a Reduced<E>> + Clone,
     ) -> Result<Self, SynthesisError> {
         let value = self.value.clone().and_then(|mut set| {
@@ -242,7 +275,7 @@ where
             &new_set.group,
             &new_set.digest,
             items,
-            challenge,
+            challenge.nat(),
             &self.digest,
         )?;
         Ok(new_set)
@@ -251,7 +284,7 @@ where
     pub fn insert<CS: ConstraintSystem<E>>(
         self,
         mut cs: CS,
-        challenge: &BigNat<E>,
+        challenge: &Challenge<E>,
         items: &[Reduced<E>],
     ) -> Result<Self, SynthesisError> {
         let value = self.value.clone().and_then(|mut set| {
@@ -275,20 +308,86 @@ where
             &new_set.group,
             &self.digest,
             items,
-            challenge,
+            challenge.nat(),
             &new_set.digest,
         )?;
         Ok(new_set)
     }
+
+    /// Removes `removals` and inserts `insertions`, using a single challenge.
+    ///
+    /// Rather than proving the removal and the insertion separately, this allocates the common
+    /// "meeting point" `old^(prod insertions) = new^(prod removals)` and proves both exponentiations
+    /// into it.
+    pub fn swap<CS: ConstraintSystem<E>>(
+        self,
+        mut cs: CS,
+        challenge: &Challenge<E>,
+        removals: &[Reduced<E>],
+        insertions: &[Reduced<E>],
+    ) -> Result<Self, SynthesisError> {
+        let removed_values = removals
+            .iter()
+            .map(|i| i.raw.value.clone())
+            .collect::<Option<Vec<Integer>>>();
+        let inserted_values = insertions
+            .iter()
+            .map(|i| i.raw.value.clone())
+            .collect::<Option<Vec<Integer>>>();
+        let value = self.value.clone().and_then(|mut set| {
+            let rs = removed_values.as_ref()?;
+            let is = inserted_values.as_ref()?;
+            assert!(set.swap(rs.iter(), is.iter().cloned()));
+            Some(set)
+        });
+        let meet_value = self.group.group().and_then(|g| {
+            let d = self.digest.value()?;
+            let is = inserted_values.as_ref()?;
+            Some(is.iter().fold(d.clone(), |acc, i| g.power(&acc, i)))
+        });
+        let new_set = Self::alloc(
+            cs.namespace(|| "new"),
+            value.as_ref(),
+            self.group.clone(),
+            &(),
+        )?;
+        let meet: CG::Elem = <CG::Elem as Gadget>::alloc(
+            cs.namespace(|| "meet"),
+            meet_value.as_ref(),
+            (),
+            &CG::elem_params(self.group.params()),
+        )?;
+        proof_of_exp(
+            cs.namespace(|| "insertion proof"),
+            &self.group,
+            &self.digest,
+            insertions,
+            challenge.nat(),
+            &meet,
+        )?;
+        proof_of_exp(
+            cs.namespace(|| "removal proof"),
+            &self.group,
+            &new_set.digest,
+            removals,
+            challenge.nat(),
+            &meet,
+        )?;
+        Ok(new_set)
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::exp::serial::SerialExp;
     use super::*;
+    use mp::bignat::BigNat;
     use util::test_helpers::*;
 
-    use group::{CircuitRsaQuotientGroup, CircuitRsaGroupParams, RsaQuotientGroup};
+    use group::{
+        CircuitClassGroup, CircuitClassGroupParams, CircuitQuadForm, CircuitRsaGroupParams,
+        CircuitRsaQuotientGroup, ClassGroup, RsaQuotientGroup,
+    };
     use OptionExt;
 
     use std::str::FromStr;
@@ -316,12 +415,12 @@ pub mod tests {
 
     impl<'a, E: Engine> Circuit<E> for RsaRemoval<'a> {
         fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-            let challenge = BigNat::alloc_from_nat(
+            let challenge = Challenge::unchecked(BigNat::alloc_from_nat(
                 cs.namespace(|| "challenge"),
                 || Ok(Integer::from_str(self.inputs.grab()?.challenge).unwrap()),
                 self.params.limb_width,
                 self.params.n_limbs_b,
-            )?;
+            )?);
             let initial_items_vec: Vec<Integer> = self
                 .inputs
                 .grab()?
@@ -401,6 +500,167 @@ pub mod tests {
         }
     }
 
+    pub struct RsaSwapInputs<'a> {
+        pub g: &'a str,
+        pub m: &'a str,
+        pub initial_items: &'a [&'a str],
+        pub removed_items: &'a [&'a str],
+        pub inserted_items: &'a [&'a str],
+        pub challenge: &'a str,
+        pub final_digest: &'a str,
+    }
+
+    pub struct RsaSwap<'a> {
+        pub inputs: Option<RsaSwapInputs<'a>>,
+        pub params: RsaRemovalParams,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for RsaSwap<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let limb_width = self.params.limb_width;
+            let n_limbs_e = self.params.n_limbs_e;
+            let challenge = Challenge::unchecked(BigNat::alloc_from_nat(
+                cs.namespace(|| "challenge"),
+                || Ok(Integer::from_str(self.inputs.grab()?.challenge).unwrap()),
+                limb_width,
+                self.params.n_limbs_b,
+            )?);
+            let mut alloc_items = |name: &str, items: &[&str]| {
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        BigNat::alloc_from_nat(
+                            cs.namespace(|| format!("{} {}", name, i)),
+                            || Ok(Integer::from_str(e).unwrap()),
+                            limb_width,
+                            n_limbs_e,
+                        )
+                        .map(Reduced::from_raw)
+                    })
+                    .collect::<Result<Vec<Reduced<E>>, SynthesisError>>()
+            };
+            let removed = alloc_items("removed", self.inputs.grab()?.removed_items)?;
+            let inserted = alloc_items("inserted", self.inputs.grab()?.inserted_items)?;
+            let final_digest = BigNat::alloc_from_nat(
+                cs.namespace(|| "final_digest"),
+                || Ok(Integer::from_str(self.inputs.grab()?.final_digest).unwrap()),
+                limb_width,
+                self.params.n_limbs_b,
+            )?;
+            let raw_group =
+                RsaQuotientGroup::from_strs(self.inputs.grab()?.g, self.inputs.grab()?.m);
+            let group = CircuitRsaQuotientGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&raw_group),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width,
+                    n_limbs: self.params.n_limbs_b,
+                },
+            )?;
+            let initial_items = self
+                .inputs
+                .grab()?
+                .initial_items
+                .iter()
+                .map(|i| Integer::from_str(i).unwrap());
+            let initial_set: CircuitIntSet<
+                E,
+                CircuitRsaQuotientGroup<E>,
+                ExpSet<RsaQuotientGroup, SerialExp<_>>,
+            > = CircuitIntSet::alloc(
+                cs.namespace(|| "initial_set"),
+                Some(&ExpSet::new_with(raw_group, initial_items)),
+                group,
+                &(),
+            )?;
+            let final_set =
+                initial_set.swap(cs.namespace(|| "swap"), &challenge, &removed, &inserted)?;
+            final_set
+                .digest
+                .equal(cs.namespace(|| "final_eq"), &final_digest)
+        }
+    }
+
+    circuit_tests! {
+        swap_3_5_7_for_5_7_11: (
+            RsaSwap {
+                inputs: Some(RsaSwapInputs {
+                    g: "2",
+                    m: "143",
+                    initial_items: &["3", "5", "7"],
+                    removed_items: &["3"],
+                    inserted_items: &["11"],
+                    challenge: "223",
+                    final_digest: "54",
+                }),
+                params: RsaRemovalParams {
+                    limb_width: 4,
+                    n_limbs_e: 2,
+                    n_limbs_b: 2,
+                }
+            },
+            true
+        ),
+        swap_3_5_7_for_5_7_11_wrong: (
+            RsaSwap {
+                inputs: Some(RsaSwapInputs {
+                    g: "2",
+                    m: "143",
+                    initial_items: &["3", "5", "7"],
+                    removed_items: &["3"],
+                    inserted_items: &["11"],
+                    challenge: "223",
+                    final_digest: "34",
+                }),
+                params: RsaRemovalParams {
+                    limb_width: 4,
+                    n_limbs_e: 2,
+                    n_limbs_b: 2,
+                }
+            },
+            false
+        ),
+    }
+
+    shape_tests! {
+        swap_shape: (
+            RsaSwap {
+                inputs: Some(RsaSwapInputs {
+                    g: "2",
+                    m: "143",
+                    initial_items: &["3", "5", "7"],
+                    removed_items: &["3"],
+                    inserted_items: &["11"],
+                    challenge: "223",
+                    final_digest: "54",
+                }),
+                params: RsaRemovalParams {
+                    limb_width: 4,
+                    n_limbs_e: 2,
+                    n_limbs_b: 2,
+                }
+            },
+            RsaSwap {
+                inputs: Some(RsaSwapInputs {
+                    g: "2",
+                    m: "143",
+                    initial_items: &["3", "5", "7"],
+                    removed_items: &["5"],
+                    inserted_items: &["13"],
+                    challenge: "223",
+                    final_digest: "1",
+                }),
+                params: RsaRemovalParams {
+                    limb_width: 4,
+                    n_limbs_e: 2,
+                    n_limbs_b: 2,
+                }
+            },
+        ),
+    }
+
     circuit_tests! {
         removal_init_empty: (
                                 RsaRemoval {
@@ -521,4 +781,114 @@ pub mod tests {
                                                         true
                                                             ),
     }
+
+    fn alloc_items<E: Engine, CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        items: &[&str],
+    ) -> Result<Vec<Reduced<E>>, SynthesisError> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                BigNat::alloc_from_nat(
+                    cs.namespace(|| format!("item {}", i)),
+                    || Ok(Integer::from_str(e).unwrap()),
+                    4,
+                    2,
+                )
+                .map(Reduced::from_raw)
+            })
+            .collect()
+    }
+
+    /// Inserts and then removes items from a set whose digests are forms of discriminant -47, and
+    /// checks the result against the digest of `final_items`.
+    pub struct ClassGroupInsertRemove<'a> {
+        pub initial_items: &'a [&'a str],
+        pub inserted_items: &'a [&'a str],
+        pub removed_items: &'a [&'a str],
+        pub final_items: &'a [&'a str],
+    }
+
+    impl<'a, E: Engine> Circuit<E> for ClassGroupInsertRemove<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let ints = |items: &[&str]| -> Vec<Integer> {
+                items.iter().map(|i| Integer::from_str(i).unwrap()).collect()
+            };
+            let raw_group = ClassGroup::from_strs("-47", "2", "1");
+            let params = CircuitClassGroupParams::new(4, raw_group.discriminant.clone());
+            let group = CircuitClassGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(&raw_group),
+                (),
+                &params,
+            )?;
+            let challenge = Challenge::unchecked(BigNat::alloc_from_nat(
+                cs.namespace(|| "challenge"),
+                || Ok(Integer::from(13)),
+                4,
+                2,
+            )?);
+            let inserted = alloc_items(cs.namespace(|| "inserted"), self.inserted_items)?;
+            let removed = alloc_items(cs.namespace(|| "removed"), self.removed_items)?;
+            let initial_set: CircuitIntSet<
+                E,
+                CircuitClassGroup<E>,
+                ExpSet<ClassGroup, SerialExp<_>>,
+            > = CircuitIntSet::alloc(
+                cs.namespace(|| "initial_set"),
+                Some(&ExpSet::new_with(
+                    raw_group.clone(),
+                    ints(self.initial_items),
+                )),
+                group,
+                &(),
+            )?;
+            let set = initial_set.insert(cs.namespace(|| "insert"), &challenge, &inserted)?;
+            let final_set = set.remove(cs.namespace(|| "remove"), &challenge, &removed)?;
+            let mut expected_set: ExpSet<ClassGroup, SerialExp<_>> =
+                ExpSet::new_with(raw_group, ints(self.final_items));
+            let final_digest = CircuitQuadForm::alloc(
+                cs.namespace(|| "final_digest"),
+                Some(&expected_set.digest()),
+                (),
+                &params,
+            )?;
+            Gadget::assert_equal(
+                cs.namespace(|| "final_eq"),
+                &final_set.digest,
+                &final_digest,
+            )
+        }
+    }
+
+    circuit_tests! {
+        class_group_insert_3_11_remove_7: (
+            ClassGroupInsertRemove {
+                initial_items: &["7"],
+                inserted_items: &["3", "11"],
+                removed_items: &["7"],
+                final_items: &["3", "11"],
+            },
+            true
+        ),
+        class_group_insert_3_into_empty: (
+            ClassGroupInsertRemove {
+                initial_items: &[],
+                inserted_items: &["3"],
+                removed_items: &[],
+                final_items: &["3"],
+            },
+            true
+        ),
+        class_group_insert_3_11_remove_7_wrong_digest: (
+            ClassGroupInsertRemove {
+                initial_items: &["7"],
+                inserted_items: &["3", "11"],
+                removed_items: &["7"],
+                final_items: &["11"],
+            },
+            false
+        ),
+    }
 }
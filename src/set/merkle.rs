@@ -1,5 +1,5 @@
 use fnv::{FnvHashMap, FnvHashSet};
-use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, ScalarEngine};
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError};
 use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
@@ -7,14 +7,73 @@ use sapling_crypto::circuit::num::AllocatedNum;
 
 use std::collections::BTreeMap;
 use std::rc::Rc;
+use std::sync::Arc;
 
-use super::{CircuitGenSet, GenSet};
+use super::merkle_proof::MerkleProof;
+use super::{items_differ, CircuitGenSet, GenSet, Mutation, Observers};
+use hash::cache::WitnessCache;
 use hash::circuit::{CircuitHasher, MaybeHashed};
 use hash::Hasher;
 use util::convert::usize_to_f;
 use util::gadget::Gadget;
+use util::num::allocated_num::enforce_all_equal;
 use OptionExt;
 
+/// Storage for the nodes of a `MerkleSet`.
+#[derive(Clone)]
+pub enum MerkleNodes<F> {
+    /// Maps (level, idx in level) -> hash value, for the nodes which differ from the default.
+    /// Suited to sparse trees.
+    Sparse(FnvHashMap<(usize, usize), F>),
+    /// Every node, level by level: (level, idx in level) is at position 2 ** level - 1 + idx.
+    /// Suited to dense trees, where it avoids hashing and chasing pointers on each access.
+    Dense(Vec<F>),
+}
+
+impl<F: PrimeField> MerkleNodes<F> {
+    fn position(level: usize, index: usize) -> usize {
+        (1 << level) - 1 + index
+    }
+
+    pub fn get(&self, level: usize, index: usize) -> Option<&F> {
+        match self {
+            MerkleNodes::Sparse(m) => m.get(&(level, index)),
+            MerkleNodes::Dense(v) => v.get(Self::position(level, index)),
+        }
+    }
+
+    pub fn insert(&mut self, level: usize, index: usize, hash: F) {
+        match self {
+            MerkleNodes::Sparse(m) => {
+                m.insert((level, index), hash);
+            }
+            MerkleNodes::Dense(v) => v[Self::position(level, index)] = hash,
+        }
+    }
+
+    /// Resets a node to `default`, dropping it if the storage is sparse.
+    fn reset(&mut self, level: usize, index: usize, default: &F) {
+        match self {
+            MerkleNodes::Sparse(m) => {
+                m.remove(&(level, index));
+            }
+            MerkleNodes::Dense(v) => v[Self::position(level, index)] = default.clone(),
+        }
+    }
+
+    /// The number of nodes held in memory.
+    pub fn len(&self) -> usize {
+        match self {
+            MerkleNodes::Sparse(m) => m.len(),
+            MerkleNodes::Dense(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 /// Represents a merkle tree in which some prefix of the capacity is occupied.
 /// Unoccupied leaves are assumed to be zero. This allows nodes with no occupied children to have a
 /// pre-determined hash.
@@ -26,8 +85,7 @@ where
     pub hasher: H,
 
     /// Level i holds 2 ** i elements. Level 0 is the root.
-    /// Maps (level, idx in level) -> hash value
-    pub nodes: FnvHashMap<(usize, usize), H::F>,
+    pub nodes: MerkleNodes<H::F>,
 
     /// default[i] is the hash value for a node at level i which has no occupied descendents
     pub defaults: Vec<H::F>,
@@ -37,16 +95,42 @@ where
 
     /// Map from a leave to its index in the array of leaves
     pub leaf_indices: BTreeMap<<H::F as PrimeField>::Repr, usize>,
+
+    /// Notified of every swap
+    pub observers: Observers<H::F, H::F>,
+
+    /// If set, leaf hashes are looked up here rather than recomputed
+    pub cache: Option<Arc<WitnessCache<H::F>>>,
 }
 
 impl<H> MerkleSet<H>
 where
     H: Hasher,
 {
+    /// Builds a tree which stores only its non-default nodes.
     pub fn new_with<'b>(
         hasher: H,
         depth: usize,
         items: impl IntoIterator<Item = &'b [H::F]>,
+    ) -> Self {
+        Self::build(hasher, depth, items, false)
+    }
+
+    /// Builds a tree which stores all 2 ** (depth + 1) - 1 nodes in a flat array. Much faster for
+    /// trees which are mostly full, but allocates the whole tree up front.
+    pub fn new_dense_with<'b>(
+        hasher: H,
+        depth: usize,
+        items: impl IntoIterator<Item = &'b [H::F]>,
+    ) -> Self {
+        Self::build(hasher, depth, items, true)
+    }
+
+    fn build<'b>(
+        hasher: H,
+        depth: usize,
+        items: impl IntoIterator<Item = &'b [H::F]>,
+        dense: bool,
     ) -> Self {
         use rayon::prelude::*;
         let items: Vec<&'b [H::F]> = items.into_iter().collect();
@@ -58,10 +142,6 @@ where
             .map(|(i, e)| (e.into_repr(), i))
             .collect();
 
-        let mut nodes = FnvHashMap::default();
-        for (i, hash) in leaves.into_iter().enumerate() {
-            nodes.insert((depth, i), hash);
-        }
         let defaults = {
             let mut d = vec![usize_to_f::<H::F>(0)];
             while d.len() <= depth {
@@ -71,13 +151,27 @@ where
             d.reverse();
             d
         };
+        let nodes = if dense {
+            let mut v = Vec::with_capacity((1 << (depth + 1)) - 1);
+            for (level, default) in defaults.iter().enumerate() {
+                v.extend(std::iter::repeat(default.clone()).take(1 << level));
+            }
+            MerkleNodes::Dense(v)
+        } else {
+            MerkleNodes::Sparse(FnvHashMap::default())
+        };
         let mut this = Self {
             hasher,
             nodes,
             defaults,
             depth,
             leaf_indices,
+            observers: Observers::new(),
+            cache: None,
         };
+        for (i, hash) in leaves.into_iter().enumerate() {
+            this.set_node(depth, i, hash);
+        }
         this.update_hashes_from_leaf_indices(0..n);
         this
     }
@@ -86,9 +180,62 @@ impl<H> MerkleSet<H>
 where
     H: Hasher,
 {
+    /// Looks leaf hashes up in `cache` from now on. Clones of the set share the cache.
+    pub fn with_cache(self, cache: Arc<WitnessCache<H::F>>) -> Self {
+        Self {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    fn leaf_hash(&self, item: &[H::F]) -> H::F {
+        match &self.cache {
+            Some(cache) => cache.hash_item(&self.hasher, item),
+            None => self.hasher.hash(item),
+        }
+    }
+
+    /// Runs `callback` after every subsequent swap, with the new root.
+    pub fn subscribe(&mut self, callback: impl Fn(Mutation<H::F>, &H::F) + Send + Sync + 'static) {
+        self.observers.subscribe(callback);
+    }
+
+    /// The number of nodes held in memory.
+    pub fn n_stored_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Drops every stored node whose hash equals the default for its level, returning the number
+    /// dropped. Dense trees store every node, so this does nothing for them.
+    ///
+    /// Mutations through this type never store default nodes, so this is only needed when `nodes`
+    /// was populated directly (or by an older version of this type).
+    pub fn compact(&mut self) -> usize {
+        match &mut self.nodes {
+            MerkleNodes::Sparse(m) => {
+                let before = m.len();
+                let defaults = &self.defaults;
+                m.retain(|&(level, _), hash| *hash != defaults[level]);
+                m.shrink_to_fit();
+                before - m.len()
+            }
+            MerkleNodes::Dense(_) => 0,
+        }
+    }
+
+    /// Stores a node, or forgets it if it has the default hash, so that the memory of a sparse
+    /// tree stays proportional to the number of non-default nodes.
+    fn set_node(&mut self, level: usize, index: usize, hash: H::F) {
+        if hash == self.defaults[level] {
+            self.nodes.reset(level, index, &self.defaults[level]);
+        } else {
+            self.nodes.insert(level, index, hash);
+        }
+    }
+
     fn get_node(&self, level: usize, index: usize) -> &H::F {
         self.nodes
-            .get(&(level, index))
+            .get(level, index)
             .unwrap_or_else(|| &self.defaults[level])
     }
 
@@ -99,8 +246,8 @@ where
     }
 
     fn update_hash(&mut self, level: usize, index: usize) {
-        self.nodes
-            .insert((level, index), self.compute_hash(level, index));
+        let hash = self.compute_hash(level, index);
+        self.set_node(level, index, hash);
     }
 
     fn update_hashes_from_leaf_indices(&mut self, indices: impl Iterator<Item = usize>) {
@@ -112,7 +259,7 @@ where
                 .map(|i| (self.compute_hash(level, *i), *i))
                 .collect();
             for (h, i) in hashes_and_ixds {
-                self.nodes.insert((level, i), h);
+                self.set_node(level, i, h);
             }
             indices = indices.into_iter().map(|i| i / 2).collect();
         }
@@ -126,11 +273,28 @@ where
         }
     }
 
+    /// Returns a proof that `item` is in the set, if it is.
+    pub fn proof(&mut self, item: &[H::F]) -> Option<MerkleProof<H::F>> {
+        let index = *self.leaf_indices.get(&self.leaf_hash(item).into_repr())?;
+        let siblings = (1..=self.depth)
+            .rev()
+            .map(|level| {
+                self.get_node(level, (index >> (self.depth - level)) ^ 1)
+                    .clone()
+            })
+            .collect();
+        Some(MerkleProof {
+            index,
+            siblings,
+            root: self.digest(),
+        })
+    }
+
     /// Given an item, returns the witness that the item is in the set. The witness is a sequence
     /// of pairs (bit, hash), where bit is true if hash is a right child on the path to the item.
     /// The sequence starts at the top of the tree, going down.
     fn witness(&self, item: &[H::F]) -> Vec<(bool, H::F)> {
-        let o_r = self.hasher.hash(item).into_repr();
+        let o_r = self.leaf_hash(item).into_repr();
         let i = *self
             .leaf_indices
             .get(&o_r)
@@ -154,17 +318,21 @@ where
     type Digest = H::F;
 
     fn swap(&mut self, old: &[H::F], new: Vec<H::F>) {
-        let o_r = self.hasher.hash(old).into_repr();
-        let n = self.hasher.hash(&new);
+        let o_r = self.leaf_hash(old).into_repr();
+        let n = self.leaf_hash(&new);
         let n_r = n.into_repr();
         let i = *self
             .leaf_indices
             .get(&o_r)
             .expect("missing element in MerkleSet::swap");
-        self.nodes.insert((self.depth, i), n);
+        let depth = self.depth;
+        self.set_node(depth, i, n);
         self.leaf_indices.remove(&o_r);
         self.leaf_indices.insert(n_r, i);
         self.update_hashes_from_leaf_index(i);
+        let root = self.get_node(0, 0).clone();
+        self.observers
+            .notify(Mutation::Swap { old, new: &new }, || root);
     }
 
     /// The digest of the current elements (`g` to the product of the elements).
@@ -241,98 +409,141 @@ where
     }
 }
 
-impl<E, H, CH> CircuitGenSet for MerkleCircuitSet<E, H, CH>
+impl<E, H, CH> MerkleCircuitSet<E, H, CH>
 where
     E: Engine,
     H: Hasher<F = E::Fr>,
     CH: CircuitHasher<E = E>,
 {
-    type E = E;
-
-    fn swap_all<'b, CS: ConstraintSystem<Self::E>>(
-        mut self,
+    /// Replaces the leaf `key || old_value` with `key || new_value`.
+    ///
+    /// Both leaves are built from the same `key_fields` wires, so the key portion of the leaf is
+    /// unchanged by construction; only the value portion may differ.
+    pub fn update_value<CS: ConstraintSystem<E>>(
+        self,
         mut cs: CS,
-        removed_items: Vec<MaybeHashed<Self::E>>,
-        inserted_items: Vec<MaybeHashed<Self::E>>,
+        key_fields: &[AllocatedNum<E>],
+        old_value_fields: &[AllocatedNum<E>],
+        new_value_fields: &[AllocatedNum<E>],
     ) -> Result<Self, SynthesisError> {
-        for (j, (old, new)) in removed_items
-            .into_iter()
-            .zip(inserted_items.into_iter())
-            .enumerate()
-        {
-            let mut cs = cs.namespace(|| format!("swap {}", j));
+        if old_value_fields.len() != new_value_fields.len() {
+            eprintln!(
+                "update_value: old value has {} fields but new value has {}",
+                old_value_fields.len(),
+                new_value_fields.len()
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let leaf = |value_fields: &[AllocatedNum<E>]| {
+            MaybeHashed::from_values(
+                key_fields
+                    .iter()
+                    .chain(value_fields.iter())
+                    .cloned()
+                    .collect(),
+            )
+        };
+        self.swap_all(
+            cs.namespace(|| "update"),
+            vec![leaf(old_value_fields)],
+            vec![leaf(new_value_fields)],
+        )
+    }
 
-            // First, we allocate the path
-            let witness = self.value.as_ref().and_then(|v| {
+    /// Replaces `old` with `new` if `enabled`, and otherwise leaves the set unchanged without
+    /// checking that `old` is present.
+    fn swap_if<CS: ConstraintSystem<E>>(
+        &mut self,
+        mut cs: CS,
+        old: MaybeHashed<E>,
+        new: MaybeHashed<E>,
+        enabled: &Boolean,
+    ) -> Result<(), SynthesisError> {
+        // First, we allocate the path. A disabled swap may use any path.
+        let witness = match enabled.get_value() {
+            Some(false) => Some(vec![(false, E::Fr::zero()); self.depth]),
+            _ => self.value.as_ref().and_then(|v| {
                 old.values
                     .iter()
                     .map(|n| n.get_value())
                     .collect::<Option<Vec<E::Fr>>>()
                     .map(|x| v.witness(&x))
-            });
-            let path: Vec<(Boolean, AllocatedNum<E>)> = {
-                let mut cs = cs.namespace(|| "alloc path");
-                (0..self.depth)
-                    .map(|i| {
-                        let mut cs = cs.namespace(|| format!("{}", i));
-                        Ok((
-                            Boolean::from(AllocatedBit::alloc(
-                                cs.namespace(|| "direction"),
-                                witness.as_ref().map(|w| w[i].0),
-                            )?),
-                            AllocatedNum::alloc(cs.namespace(|| "hash"), || {
-                                Ok(witness.grab()?[i].1)
-                            })?,
-                        ))
-                    })
-                    .collect::<Result<Vec<(Boolean, AllocatedNum<E>)>, SynthesisError>>()?
-            };
+            }),
+        };
+        let path: Vec<(Boolean, AllocatedNum<E>)> = {
+            let mut cs = cs.namespace(|| "alloc path");
+            (0..self.depth)
+                .map(|i| {
+                    let mut cs = cs.namespace(|| format!("{}", i));
+                    Ok((
+                        Boolean::from(AllocatedBit::alloc(
+                            cs.namespace(|| "direction"),
+                            witness.as_ref().map(|w| w[i].0),
+                        )?),
+                        AllocatedNum::alloc(cs.namespace(|| "hash"), || Ok(witness.grab()?[i].1))?,
+                    ))
+                })
+                .collect::<Result<Vec<(Boolean, AllocatedNum<E>)>, SynthesisError>>()?
+        };
 
-            // Now, check the old item
-            {
-                let mut cs = cs.namespace(|| "check old");
-                let mut acc = self
-                    .hasher
-                    .allocate_hash(cs.namespace(|| "leaf hash"), &old.values)?;
-                for (i, (bit, hash)) in path.iter().enumerate().rev() {
-                    let mut cs = cs.namespace(|| format!("level {}", i));
-                    let (a, b) = AllocatedNum::conditionally_reverse(
-                        cs.namespace(|| "order"),
-                        &hash,
-                        &acc,
-                        &bit,
-                    )?;
-                    acc = self
-                        .hasher
-                        .allocate_hash(cs.namespace(|| "hash"), &[a, b])?;
-                }
-                let eq = AllocatedNum::equals(cs.namespace(|| "root check"), &acc, &self.digest)?;
-                Boolean::enforce_equal(
-                    cs.namespace(|| "root check passes"),
-                    &eq,
-                    &Boolean::constant(true),
+        // Now, check the old item
+        {
+            let mut cs = cs.namespace(|| "check old");
+            let mut acc = self
+                .hasher
+                .allocate_hash(cs.namespace(|| "leaf hash"), &old.values)?;
+            for (i, (bit, hash)) in path.iter().enumerate().rev() {
+                let mut cs = cs.namespace(|| format!("level {}", i));
+                let (a, b) = AllocatedNum::conditionally_reverse(
+                    cs.namespace(|| "order"),
+                    &hash,
+                    &acc,
+                    &bit,
                 )?;
+                acc = self
+                    .hasher
+                    .allocate_hash(cs.namespace(|| "hash"), &[a, b])?;
             }
+            let eq = AllocatedNum::equals(cs.namespace(|| "root check"), &acc, &self.digest)?;
+            let fails = Boolean::and(cs.namespace(|| "root check enabled"), &eq.not(), enabled)?;
+            Boolean::enforce_equal(
+                cs.namespace(|| "root check passes"),
+                &fails,
+                &Boolean::constant(false),
+            )?;
+        }
 
-            // Now, add the new item
-            {
-                let mut cs = cs.namespace(|| "add new");
-                let mut acc = self
+        // Now, add the new item
+        {
+            let mut cs = cs.namespace(|| "add new");
+            let mut acc = self
+                .hasher
+                .allocate_hash(cs.namespace(|| "leaf hash"), &new.values)?;
+            for (i, (bit, hash)) in path.into_iter().enumerate().rev() {
+                let mut cs = cs.namespace(|| format!("level {}", i));
+                let (a, b) = AllocatedNum::conditionally_reverse(
+                    cs.namespace(|| "order"),
+                    &hash,
+                    &acc,
+                    &bit,
+                )?;
+                acc = self
                     .hasher
-                    .allocate_hash(cs.namespace(|| "leaf hash"), &new.values)?;
-                for (i, (bit, hash)) in path.into_iter().enumerate().rev() {
-                    let mut cs = cs.namespace(|| format!("level {}", i));
-                    let (a, b) = AllocatedNum::conditionally_reverse(
-                        cs.namespace(|| "order"),
-                        &hash,
+                    .allocate_hash(cs.namespace(|| "hash"), &[a, b])?;
+            }
+            self.digest = match enabled {
+                Boolean::Constant(true) => acc,
+                _ => {
+                    AllocatedNum::conditionally_reverse(
+                        cs.namespace(|| "select"),
+                        &self.digest,
                         &acc,
-                        &bit,
-                    )?;
-                    acc = self
-                        .hasher
-                        .allocate_hash(cs.namespace(|| "hash"), &[a, b])?;
+                        enabled,
+                    )?
+                    .0
                 }
-                self.digest = acc;
+            };
+            if enabled.get_value() == Some(true) {
                 if let Some(v) = self.value.as_mut() {
                     let o = old
                         .values
@@ -350,6 +561,36 @@ where
                 }
             }
         }
+        Ok(())
+    }
+}
+
+impl<E, H, CH> CircuitGenSet for MerkleCircuitSet<E, H, CH>
+where
+    E: Engine,
+    H: Hasher<F = E::Fr>,
+    CH: CircuitHasher<E = E>,
+{
+    type E = E;
+
+    fn swap_all<'b, CS: ConstraintSystem<Self::E>>(
+        mut self,
+        mut cs: CS,
+        removed_items: Vec<MaybeHashed<Self::E>>,
+        inserted_items: Vec<MaybeHashed<Self::E>>,
+    ) -> Result<Self, SynthesisError> {
+        for (j, (old, new)) in removed_items
+            .into_iter()
+            .zip(inserted_items.into_iter())
+            .enumerate()
+        {
+            self.swap_if(
+                cs.namespace(|| format!("swap {}", j)),
+                old,
+                new,
+                &Boolean::constant(true),
+            )?;
+        }
         Ok(self)
     }
     fn verify_swap_all<CS: ConstraintSystem<Self::E>>(
@@ -360,13 +601,29 @@ where
         other: Self,
     ) -> Result<(), SynthesisError> {
         let new = self.swap_all(cs.namespace(|| "do swap"), removed_items, inserted_items)?;
-        let eq = AllocatedNum::equals(cs.namespace(|| "equal"), &new.digest, &other.digest)?;
+        enforce_all_equal(cs.namespace(|| "equal"), &[new.digest], &[other.digest])
+    }
+    fn assert_digest_changed<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        other: &Self,
+    ) -> Result<(), SynthesisError> {
+        let eq = AllocatedNum::equals(cs.namespace(|| "equal"), &self.digest, &other.digest)?;
         Boolean::enforce_equal(
-            cs.namespace(|| "equal is true"),
+            cs.namespace(|| "equal is false"),
             &eq,
-            &Boolean::constant(true),
-        )?;
-        Ok(())
+            &Boolean::constant(false),
+        )
+    }
+    fn swap_or_noop<CS: ConstraintSystem<Self::E>>(
+        mut self,
+        mut cs: CS,
+        old: MaybeHashed<Self::E>,
+        new: MaybeHashed<Self::E>,
+    ) -> Result<Self, SynthesisError> {
+        let differ = items_differ(cs.namespace(|| "differ"), &old, &new)?;
+        self.swap_if(cs.namespace(|| "swap"), old, new, &differ)?;
+        Ok(self)
     }
 }
 
@@ -444,6 +701,8 @@ pub struct MerkleSetBenchParams<H> {
     pub hash: H,
     pub depth: usize,
     pub verbose: bool,
+    /// Whether to reject batches which do not change the digest.
+    pub require_change: bool,
 }
 
 pub struct MerkleSetBench<H>
@@ -503,7 +762,7 @@ where
         if self.params.verbose {
             println!("Swapping elements");
         }
-        let new_set = set.swap_all(
+        let new_set = set.clone().swap_all(
             cs.namespace(|| "swap"),
             removals.into_iter().map(MaybeHashed::from_values).collect(),
             insertions
@@ -511,6 +770,9 @@ where
                 .map(MaybeHashed::from_values)
                 .collect(),
         )?;
+        if self.params.require_change {
+            set.assert_digest_changed(cs.namespace(|| "digest changed"), &new_set)?;
+        }
 
         if self.params.verbose {
             println!("Verifying resulting digest");
@@ -522,9 +784,274 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::{MerkleSetBench, MerkleSetBenchInputs, MerkleSetBenchParams};
+    use super::*;
     use hash::hashes::Poseidon;
     use util::test_helpers::*;
+
+    pub struct UpdateValue<'a> {
+        pub depth: usize,
+        pub key: &'a [&'a str],
+        pub old_value: &'a [&'a str],
+        pub new_value: &'a [&'a str],
+        /// The value of the updated leaf actually present in the final set.
+        pub final_value: &'a [&'a str],
+    }
+
+    impl<'a> Circuit<Bn256> for UpdateValue<'a> {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            type F = <Bn256 as ScalarEngine>::Fr;
+            let to_fs =
+                |strs: &[&str]| -> Vec<F> { strs.iter().map(|s| F::from_str(s).unwrap()).collect() };
+            let leaf = |value: &[&str]| -> Vec<F> {
+                let mut l = to_fs(self.key);
+                l.extend(to_fs(value));
+                l
+            };
+            let hasher = Poseidon::<Bn256>::default();
+            let initial = MerkleSet::new_with(
+                hasher.clone(),
+                self.depth,
+                vec![leaf(self.old_value)].iter().map(Vec::as_slice),
+            );
+            let expected = MerkleSet::new_with(
+                hasher.clone(),
+                self.depth,
+                vec![leaf(self.final_value)].iter().map(Vec::as_slice),
+            );
+            let mut alloc_all = |name: &str, fs: Vec<F>| {
+                fs.into_iter()
+                    .enumerate()
+                    .map(|(i, f)| {
+                        AllocatedNum::alloc(cs.namespace(|| format!("{} {}", name, i)), || Ok(f))
+                    })
+                    .collect::<Result<Vec<_>, SynthesisError>>()
+            };
+            let key = alloc_all("key", to_fs(self.key))?;
+            let old_value = alloc_all("old", to_fs(self.old_value))?;
+            let new_value = alloc_all("new", to_fs(self.new_value))?;
+            let set = MerkleCircuitSet::alloc(
+                cs.namespace(|| "initial"),
+                Some(&initial),
+                hasher.clone(),
+                &self.depth,
+            )?;
+            let expected = MerkleCircuitSet::alloc(
+                cs.namespace(|| "expected"),
+                Some(&expected),
+                hasher,
+                &self.depth,
+            )?;
+            let updated =
+                set.update_value(cs.namespace(|| "update"), &key, &old_value, &new_value)?;
+            Gadget::assert_equal(cs.namespace(|| "check"), &updated, &expected)
+        }
+    }
+
+    pub struct SwapOrNoop<'a> {
+        pub depth: usize,
+        pub initial: &'a [&'a str],
+        pub old: &'a str,
+        pub new: &'a str,
+        pub expected: &'a [&'a str],
+    }
+
+    impl<'a> Circuit<Bn256> for SwapOrNoop<'a> {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            type F = <Bn256 as ScalarEngine>::Fr;
+            let items = |strs: &[&str]| -> Vec<Vec<F>> {
+                strs.iter().map(|s| vec![F::from_str(s).unwrap()]).collect()
+            };
+            let hasher = Poseidon::<Bn256>::default();
+            let initial = MerkleSet::new_with(
+                hasher.clone(),
+                self.depth,
+                items(self.initial).iter().map(Vec::as_slice),
+            );
+            let expected = MerkleSet::new_with(
+                hasher.clone(),
+                self.depth,
+                items(self.expected).iter().map(Vec::as_slice),
+            );
+            let old = AllocatedNum::alloc(cs.namespace(|| "old"), || {
+                Ok(F::from_str(self.old).unwrap())
+            })?;
+            let new = AllocatedNum::alloc(cs.namespace(|| "new"), || {
+                Ok(F::from_str(self.new).unwrap())
+            })?;
+            let set = MerkleCircuitSet::alloc(
+                cs.namespace(|| "initial"),
+                Some(&initial),
+                hasher.clone(),
+                &self.depth,
+            )?;
+            let expected = MerkleCircuitSet::alloc(
+                cs.namespace(|| "expected"),
+                Some(&expected),
+                hasher,
+                &self.depth,
+            )?;
+            let result = set.swap_or_noop(
+                cs.namespace(|| "swap"),
+                MaybeHashed::from_values(vec![old]),
+                MaybeHashed::from_values(vec![new]),
+            )?;
+            Gadget::assert_equal(cs.namespace(|| "check"), &result, &expected)
+        }
+    }
+
+    #[test]
+    fn compact_drops_default_nodes() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let items: Vec<Vec<F>> = (0..3).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let mut set = MerkleSet::new_with(
+            Poseidon::<Bn256>::default(),
+            6,
+            items.iter().map(Vec::as_slice),
+        );
+        let stored = set.n_stored_nodes();
+        let digest = set.digest();
+        // Pad the map with explicit copies of default nodes in the empty right half.
+        for level in 1..=6 {
+            let default = set.defaults[level].clone();
+            set.nodes.insert(level, (1 << level) - 1, default);
+        }
+        assert_eq!(set.compact(), 6);
+        assert_eq!(set.n_stored_nodes(), stored);
+        assert_eq!(set.digest(), digest);
+        assert_eq!(set.compact(), 0);
+    }
+
+    #[test]
+    fn observers_see_swaps() {
+        use std::sync::Mutex;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let items: Vec<Vec<F>> = (0..2).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let mut set = MerkleSet::new_with(
+            Poseidon::<Bn256>::default(),
+            3,
+            items.iter().map(Vec::as_slice),
+        );
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_in = seen.clone();
+        set.subscribe(move |m, root| {
+            if let Mutation::Swap { new, .. } = m {
+                seen_in.lock().unwrap().push((new.to_vec(), root.clone()));
+            }
+        });
+        set.swap(&items[0], vec![usize_to_f(7)]);
+        let root = set.digest();
+        assert_eq!(*seen.lock().unwrap(), vec![(vec![usize_to_f(7)], root)]);
+    }
+
+    #[test]
+    fn clones_do_not_notify() {
+        use std::sync::Mutex;
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let items: Vec<Vec<F>> = (0..2).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let mut set = MerkleSet::new_with(
+            Poseidon::<Bn256>::default(),
+            3,
+            items.iter().map(Vec::as_slice),
+        );
+        let seen = Arc::new(Mutex::new(0));
+        let seen_in = seen.clone();
+        set.subscribe(move |_, _| *seen_in.lock().unwrap() += 1);
+        let mut copy = set.clone();
+        copy.swap(&items[0], vec![usize_to_f(7)]);
+        assert_eq!(*seen.lock().unwrap(), 0);
+        set.swap(&items[0], vec![usize_to_f(7)]);
+        assert_eq!(*seen.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn cached_leaf_hashes_agree() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let items: Vec<Vec<F>> = (0..3).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let hasher = Poseidon::<Bn256>::default();
+        let mut plain = MerkleSet::new_with(hasher.clone(), 3, items.iter().map(Vec::as_slice));
+        let cache = Arc::new(WitnessCache::new(16));
+        let mut cached = plain.clone().with_cache(cache.clone());
+        for set in &mut [&mut plain, &mut cached] {
+            set.swap(&items[0], vec![usize_to_f(7)]);
+            set.swap(&[usize_to_f(7)], vec![usize_to_f(8)]);
+        }
+        assert_eq!(plain.digest(), cached.digest());
+        // The second swap finds the hash of `7` from the first.
+        assert_eq!(cache.stats().0, 1);
+    }
+
+    #[test]
+    fn dense_matches_sparse() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let items: Vec<Vec<F>> = (0..5).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let hasher = Poseidon::<Bn256>::default();
+        let mut sparse = MerkleSet::new_with(hasher.clone(), 3, items.iter().map(Vec::as_slice));
+        let mut dense = MerkleSet::new_dense_with(hasher, 3, items.iter().map(Vec::as_slice));
+        assert_eq!(dense.n_stored_nodes(), 15);
+        assert_eq!(sparse.digest(), dense.digest());
+        sparse.swap(&items[2], vec![usize_to_f(9)]);
+        dense.swap(&items[2], vec![usize_to_f(9)]);
+        assert_eq!(sparse.digest(), dense.digest());
+        assert_eq!(
+            sparse.witness(&[usize_to_f(9)]),
+            dense.witness(&[usize_to_f(9)])
+        );
+    }
+
+    circuit_tests! {
+        update_value_depth_4: (UpdateValue {
+            depth: 4,
+            key: &["1", "2"],
+            old_value: &["3"],
+            new_value: &["4"],
+            final_value: &["4"],
+        }, true),
+        update_value_depth_4_wrong_final: (UpdateValue {
+            depth: 4,
+            key: &["1", "2"],
+            old_value: &["3"],
+            new_value: &["4"],
+            final_value: &["3"],
+        }, false),
+    }
+
+    circuit_tests! {
+        swap_or_noop_swaps: (SwapOrNoop {
+            depth: 3,
+            initial: &["1", "2"],
+            old: "2",
+            new: "3",
+            expected: &["1", "3"],
+        }, true),
+        swap_or_noop_equal_present: (SwapOrNoop {
+            depth: 3,
+            initial: &["1", "2"],
+            old: "2",
+            new: "2",
+            expected: &["1", "2"],
+        }, true),
+        swap_or_noop_equal_absent: (SwapOrNoop {
+            depth: 3,
+            initial: &["1", "2"],
+            old: "5",
+            new: "5",
+            expected: &["1", "2"],
+        }, true),
+        swap_or_noop_wrong_result: (SwapOrNoop {
+            depth: 3,
+            initial: &["1", "2"],
+            old: "2",
+            new: "3",
+            expected: &["1", "2"],
+        }, false),
+    }
+
     circuit_tests! {
         merkle_1_swap_3_depth: (MerkleSetBench {
             inputs: Some(MerkleSetBenchInputs::from_counts(
@@ -539,6 +1066,7 @@ mod test {
                         n_swaps: 1,
                         hash: Poseidon::default(),
                         verbose: true,
+                        require_change: false,
                         depth: 3,
                     },
         }, true),
@@ -555,6 +1083,7 @@ mod test {
                         n_swaps: 1,
                         hash: Poseidon::default(),
                         verbose: true,
+                        require_change: false,
                         depth: 10,
                     },
         }, true),
@@ -571,6 +1100,7 @@ mod test {
                         n_swaps: 1,
                         hash: Poseidon::default(),
                         verbose: true,
+                        require_change: false,
                         depth: 25,
                     },
         }, true),
@@ -587,6 +1117,7 @@ mod test {
                         n_swaps: 3,
                         hash: Poseidon::default(),
                         verbose: true,
+                        require_change: false,
                         depth: 25,
                     },
         }, true),
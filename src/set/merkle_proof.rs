@@ -0,0 +1,159 @@
+//! Merkle membership proofs in a layout external verifiers can consume.
+//!
+//! A proof is the leaf index, the sibling hashes from the leaf level up to (but excluding) the
+//! root, and the root. At each level, the low bit of the index says whether the running hash is
+//! a right child. Field elements are written as their canonical (non-Montgomery) byte
+//! representations, in the chosen byte order.
+//!
+//! The JSON layout is `{"index": 5, "siblings": ["0x..", ...], "root": "0x.."}`. The binary
+//! layout is a `u64` index, a `u32` sibling count, the siblings and the root, with the integers in
+//! the same byte order as the field elements.
+use sapling_crypto::bellman::pairing::ff::PrimeField;
+use serde::{Deserialize, Serialize};
+
+use hash::Hasher;
+use util::encoding::ByteOrder;
+
+use std::io::{self, Read, Write};
+
+/// A proof that a leaf is in a `MerkleSet`, as built by `MerkleSet::proof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof<F: PrimeField> {
+    pub index: usize,
+    /// Sibling hashes, from the leaf level up
+    pub siblings: Vec<F>,
+    pub root: F,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonProof {
+    index: u64,
+    siblings: Vec<String>,
+    root: String,
+}
+
+impl<F: PrimeField> MerkleProof<F> {
+    /// The root implied by the leaf hash `leaf` and this path.
+    pub fn implied_root<H: Hasher<F = F>>(&self, hasher: &H, leaf: F) -> F {
+        let mut acc = leaf;
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            // An index has no bits past its width, however deep the path is.
+            let bit = self.index.checked_shr(level as u32).unwrap_or(0) & 1;
+            acc = if bit == 0 {
+                hasher.hash2(acc, sibling.clone())
+            } else {
+                hasher.hash2(sibling.clone(), acc)
+            };
+        }
+        acc
+    }
+
+    /// Checks that `item` is at `self.index` in the tree with root `self.root`.
+    pub fn verify<H: Hasher<F = F>>(&self, hasher: &H, item: &[F]) -> bool {
+        self.index
+            .checked_shr(self.siblings.len() as u32)
+            .map_or(true, |high| high == 0)
+            && self.implied_root(hasher, hasher.hash(item)) == self.root
+    }
+
+    pub fn to_json(&self, encoding: ByteOrder) -> String {
+        let json = JsonProof {
+            index: self.index as u64,
+            siblings: self.siblings.iter().map(|s| encoding.f_to_hex(s)).collect(),
+            root: encoding.f_to_hex(&self.root),
+        };
+        serde_json::to_string(&json).expect("proofs are serializable")
+    }
+
+    pub fn from_json(s: &str, encoding: ByteOrder) -> Result<Self, String> {
+        let json: JsonProof = serde_json::from_str(s).map_err(|e| format!("Bad proof: {}", e))?;
+        Ok(Self {
+            index: json.index as usize,
+            siblings: json
+                .siblings
+                .iter()
+                .map(|s| encoding.f_from_hex(s))
+                .collect::<Result<Vec<F>, String>>()?,
+            root: encoding.f_from_hex(&json.root)?,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut w: W, encoding: ByteOrder) -> io::Result<()> {
+        encoding.write_u64(self.index as u64, &mut w)?;
+        encoding.write_u32(self.siblings.len() as u32, &mut w)?;
+        for s in &self.siblings {
+            encoding.write_f(s, &mut w)?;
+        }
+        encoding.write_f(&self.root, &mut w)
+    }
+
+    pub fn read<R: Read>(mut r: R, encoding: ByteOrder) -> io::Result<Self> {
+        let index = encoding.read_u64(&mut r)? as usize;
+        let n_siblings = encoding.read_u32(&mut r)?;
+        let siblings = (0..n_siblings)
+            .map(|_| encoding.read_f(&mut r))
+            .collect::<io::Result<Vec<F>>>()?;
+        let root = encoding.read_f(&mut r)?;
+        Ok(Self {
+            index,
+            siblings,
+            root,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::hashes::Poseidon;
+    use set::merkle::MerkleSet;
+    use set::GenSet;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    #[test]
+    fn proofs_verify_and_round_trip() {
+        let items: Vec<Vec<_>> = (0..5).map(|i| vec![usize_to_f(i + 1)]).collect();
+        let hasher = Poseidon::<Bn256>::default();
+        let mut set = MerkleSet::new_with(hasher.clone(), 4, items.iter().map(Vec::as_slice));
+        let proof = set.proof(&items[3]).unwrap();
+        assert_eq!(proof.index, 3);
+        assert_eq!(proof.siblings.len(), 4);
+        assert_eq!(proof.root, set.digest());
+        assert!(proof.verify(&hasher, &items[3]));
+        assert!(!proof.verify(&hasher, &items[2]));
+        assert_eq!(set.proof(&[usize_to_f(9)]), None);
+
+        for &encoding in &[ByteOrder::BigEndian, ByteOrder::LittleEndian] {
+            let json = proof.to_json(encoding);
+            assert_eq!(MerkleProof::from_json(&json, encoding), Ok(proof.clone()));
+            let mut bytes = Vec::new();
+            proof.write(&mut bytes, encoding).unwrap();
+            assert_eq!(bytes.len(), 8 + 4 + 5 * 32);
+            assert_eq!(MerkleProof::read(&bytes[..], encoding).unwrap(), proof);
+        }
+        let big = proof.to_json(ByteOrder::BigEndian);
+        assert_ne!(big, proof.to_json(ByteOrder::LittleEndian));
+        type F = <Bn256 as ScalarEngine>::Fr;
+        assert!(MerkleProof::<F>::from_json("{}", ByteOrder::BigEndian).is_err());
+    }
+
+    #[test]
+    fn deep_proofs_do_not_overflow() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let hasher = Poseidon::<Bn256>::default();
+        let leaf = [usize_to_f::<F>(1)];
+        let mut proof = MerkleProof {
+            index: usize::max_value(),
+            siblings: vec![usize_to_f(2); 70],
+            root: usize_to_f(0),
+        };
+        proof.root = proof.implied_root(&hasher, hasher.hash(&leaf));
+        assert!(proof.verify(&hasher, &leaf));
+        assert!(!proof.verify(&hasher, &[usize_to_f(3)]));
+        proof.siblings.truncate(10);
+        assert!(!proof.verify(&hasher, &leaf));
+    }
+}
@@ -1,13 +1,146 @@
-use sapling_crypto::bellman::pairing::ff::PrimeField;
+use sapling_crypto::bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
 use sapling_crypto::bellman::pairing::Engine;
-use sapling_crypto::bellman::ConstraintSystem;
+use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use std::io::{self, Read, Write};
 
 use hash::circuit::MaybeHashed;
 use CResult;
 
 pub mod int_set;
 pub mod merkle;
+pub mod merkle_proof;
+pub mod order;
+pub mod root_history;
 pub mod rsa;
+pub mod security;
+pub mod transcript;
+pub mod two_level;
+pub mod witness;
+
+/// A single operation on a set, as published in an operation log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetOp<F: PrimeField> {
+    Insert(Vec<F>),
+    Remove(Vec<F>),
+}
+
+impl<F: PrimeField> SetOp<F> {
+    const INSERT_TAG: u8 = 0;
+    const REMOVE_TAG: u8 = 1;
+
+    pub fn item(&self) -> &[F] {
+        match self {
+            SetOp::Insert(i) | SetOp::Remove(i) => i,
+        }
+    }
+
+    /// Writes the op as a tag byte, a little-endian `u32` item length, and the little-endian
+    /// representations of the item's field elements.
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let tag = match self {
+            SetOp::Insert(_) => Self::INSERT_TAG,
+            SetOp::Remove(_) => Self::REMOVE_TAG,
+        };
+        w.write_all(&[tag])?;
+        w.write_all(&(self.item().len() as u32).to_le_bytes())?;
+        for f in self.item() {
+            f.into_repr().write_le(&mut w)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut tag = [0u8; 1];
+        r.read_exact(&mut tag)?;
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)?;
+        let item = (0..u32::from_le_bytes(len))
+            .map(|_| {
+                let mut repr = F::Repr::default();
+                repr.read_le(&mut r)?;
+                F::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect::<io::Result<Vec<F>>>()?;
+        match tag[0] {
+            Self::INSERT_TAG => Ok(SetOp::Insert(item)),
+            Self::REMOVE_TAG => Ok(SetOp::Remove(item)),
+            t => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown set op tag {}", t),
+            )),
+        }
+    }
+}
+
+/// A mutation of a set, as reported to its observers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mutation<'a, F> {
+    Insert(&'a [F]),
+    Remove(&'a [F]),
+    /// Replacement of `old` by `new`, for sets which only support swaps.
+    Swap { old: &'a [F], new: &'a [F] },
+}
+
+impl<'a, F> Clone for Mutation<'a, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, F> Copy for Mutation<'a, F> {}
+
+/// Callbacks which are run after every mutation of a set, with the mutation and the new digest.
+///
+/// This lets indexers and light clients follow a set's digest as it evolves. Computing the digest
+/// may be expensive, so sets only do so when they have observers.
+///
+/// A clone starts with no observers, so that scratch copies of a set, such as those made to check
+/// or prove a batch, do not report their changes as the set's.
+pub struct Observers<F, D> {
+    callbacks: Vec<Box<dyn Fn(Mutation<F>, &D) + Send + Sync>>,
+}
+
+impl<F, D> Observers<F, D> {
+    pub fn new() -> Self {
+        Self {
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self, callback: impl Fn(Mutation<F>, &D) + Send + Sync + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    /// Reports `mutation` to every observer. `digest` is only called if there are any.
+    pub fn notify(&self, mutation: Mutation<F>, digest: impl FnOnce() -> D) {
+        if self.is_empty() {
+            return;
+        }
+        let digest = digest();
+        for callback in &self.callbacks {
+            callback(mutation, &digest);
+        }
+    }
+}
+
+impl<F, D> Default for Observers<F, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F, D> Clone for Observers<F, D> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
 
 pub trait GenSet<F>
 where
@@ -46,4 +179,41 @@ pub trait CircuitGenSet: Sized {
         inserted_items: Vec<MaybeHashed<Self::E>>,
         result: Self,
     ) -> CResult<()>;
+    /// Enforces that the digest of `other` differs from that of `self`, so that no-op batches
+    /// cannot be proven.
+    fn assert_digest_changed<CS: ConstraintSystem<Self::E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+    ) -> CResult<()>;
+    /// Swaps `old` for `new` if they differ, and otherwise leaves the set unchanged. In the latter
+    /// case `old` need not be in the set.
+    fn swap_or_noop<CS: ConstraintSystem<Self::E>>(
+        self,
+        cs: CS,
+        old: MaybeHashed<Self::E>,
+        new: MaybeHashed<Self::E>,
+    ) -> CResult<Self>;
+}
+
+/// Returns a bit which is set iff `a` and `b` differ in some field.
+pub fn items_differ<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    a: &MaybeHashed<E>,
+    b: &MaybeHashed<E>,
+) -> CResult<Boolean> {
+    if a.values.len() != b.values.len() {
+        eprintln!(
+            "items_differ: items have {} and {} fields",
+            a.values.len(),
+            b.values.len()
+        );
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let mut all_equal = Boolean::constant(true);
+    for (i, (x, y)) in a.values.iter().zip(&b.values).enumerate() {
+        let eq = AllocatedNum::equals(cs.namespace(|| format!("eq {}", i)), x, y)?;
+        all_equal = Boolean::and(cs.namespace(|| format!("and {}", i)), &all_equal, &eq)?;
+    }
+    Ok(all_equal.not())
 }
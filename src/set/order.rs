@@ -0,0 +1,223 @@
+//! Canonical ordering of the items in a batch, so that a commitment to a batch does not depend on
+//! the order its items are given in.
+//!
+//! Items are ordered by their hashes, compared as integers.
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use hash::circuit::{CircuitHasher, MaybeHashed};
+use hash::Hasher;
+use CResult;
+use OptionExt;
+
+/// Sorts `items` into canonical order.
+pub fn canonical_order<H: Hasher>(hasher: &H, items: &mut Vec<Vec<H::F>>) {
+    items.sort_by_cached_key(|i| hasher.hash(i).into_repr());
+}
+
+/// Returns the hashes of `items`, in canonical order.
+///
+/// The sorted hashes are witnessed. They are shown to be a permutation of the item hashes by
+/// comparing `prod (r - h)` over both lists, at a point `r` which is the hash of both lists.
+pub fn sorted_hashes<E, H, CS>(
+    mut cs: CS,
+    items: &mut [MaybeHashed<E>],
+    hasher: &H,
+) -> CResult<Vec<AllocatedNum<E>>>
+where
+    E: Engine,
+    H: CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+{
+    let hashes = items
+        .iter_mut()
+        .enumerate()
+        .map(|(i, item)| {
+            item.get_hash(|vs| hasher.allocate_hash(cs.namespace(|| format!("hash {}", i)), vs))
+        })
+        .collect::<CResult<Vec<_>>>()?;
+    if hashes.len() < 2 {
+        return Ok(hashes);
+    }
+
+    let sorted_values = hashes
+        .iter()
+        .map(AllocatedNum::get_value)
+        .collect::<Option<Vec<E::Fr>>>()
+        .map(|mut vs| {
+            vs.sort_by_key(|v| v.into_repr());
+            vs
+        });
+    let sorted = (0..hashes.len())
+        .map(|i| {
+            AllocatedNum::alloc(cs.namespace(|| format!("sorted {}", i)), || {
+                Ok(sorted_values.grab()?[i])
+            })
+        })
+        .collect::<CResult<Vec<_>>>()?;
+
+    let mut to_hash = hashes.clone();
+    to_hash.extend(sorted.iter().cloned());
+    let r = hasher.allocate_hash(cs.namespace(|| "permutation point"), &to_hash)?;
+    let lhs = product_of_differences(cs.namespace(|| "item product"), &r, &hashes)?;
+    let rhs = product_of_differences(cs.namespace(|| "sorted product"), &r, &sorted)?;
+    cs.enforce(
+        || "permutation",
+        |lc| lc,
+        |lc| lc,
+        |lc| lc + &lhs - &rhs,
+    );
+
+    // Each sorted hash is decomposed once, and shared by the comparisons on either side of it.
+    let sorted_bits = sorted
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s.into_bits_le_strict(cs.namespace(|| format!("sorted bits {}", i))))
+        .collect::<CResult<Vec<_>>>()?;
+    for (i, pair) in sorted_bits.windows(2).enumerate() {
+        enforce_bits_leq(cs.namespace(|| format!("order {}", i)), &pair[0], &pair[1])?;
+    }
+    Ok(sorted)
+}
+
+/// Returns `prod (r - x)` over the `xs`, which must be non-empty.
+pub fn product_of_differences<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    r: &AllocatedNum<E>,
+    xs: &[AllocatedNum<E>],
+) -> CResult<LinearCombination<E>> {
+    if xs.is_empty() {
+        eprintln!("product_of_differences of no numbers");
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let diff_value = |x: &AllocatedNum<E>| {
+        let mut d = r.get_value()?;
+        d.sub_assign(&x.get_value()?);
+        Some(d)
+    };
+    let mut acc = LinearCombination::zero() + r.get_variable() - xs[0].get_variable();
+    let mut acc_value = diff_value(&xs[0]);
+    for (i, x) in xs.iter().enumerate().skip(1) {
+        let value = acc_value.and_then(|mut a| {
+            a.mul_assign(&diff_value(x)?);
+            Some(a)
+        });
+        let product = AllocatedNum::alloc(cs.namespace(|| format!("product {}", i)), || {
+            Ok(*value.grab()?)
+        })?;
+        cs.enforce(
+            || format!("multiply {}", i),
+            |lc| lc + &acc,
+            |lc| lc + r.get_variable() - x.get_variable(),
+            |lc| lc + product.get_variable(),
+        );
+        acc = LinearCombination::zero() + product.get_variable();
+        acc_value = value;
+    }
+    Ok(acc)
+}
+
+/// Enforces that `a <= b`, as integers.
+pub fn enforce_leq<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>,
+) -> Result<(), SynthesisError> {
+    let a_bits = a.into_bits_le_strict(cs.namespace(|| "a bits"))?;
+    let b_bits = b.into_bits_le_strict(cs.namespace(|| "b bits"))?;
+    enforce_bits_leq(cs, &a_bits, &b_bits)
+}
+
+/// Enforces that `a_bits <= b_bits`, as little-endian integers of the same width.
+fn enforce_bits_leq<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    a_bits: &[Boolean],
+    b_bits: &[Boolean],
+) -> Result<(), SynthesisError> {
+    // Scanning from the top bit, `gt` is whether `a > b` has been decided, and `eq` whether the
+    // bits so far are all equal.
+    let mut gt = Boolean::constant(false);
+    let mut eq = Boolean::constant(true);
+    for (i, (a_bit, b_bit)) in a_bits.iter().zip(b_bits).enumerate().rev() {
+        let mut cs = cs.namespace(|| format!("bit {}", i));
+        let bit_gt = Boolean::and(cs.namespace(|| "bit gt"), a_bit, &b_bit.not())?;
+        let decides = Boolean::and(cs.namespace(|| "decides"), &eq, &bit_gt)?;
+        gt = Boolean::and(cs.namespace(|| "or"), &gt.not(), &decides.not())?.not();
+        let differ = Boolean::xor(cs.namespace(|| "differ"), a_bit, b_bit)?;
+        eq = Boolean::and(cs.namespace(|| "eq"), &eq, &differ.not())?;
+    }
+    Boolean::enforce_equal(cs.namespace(|| "not gt"), &gt, &Boolean::constant(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hashes::Poseidon;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    pub struct SortedHashes<'a> {
+        pub items: &'a [usize],
+        /// Whether to tamper with the second sorted hash
+        pub tamper: bool,
+    }
+
+    impl<'a> Circuit<Bn256> for SortedHashes<'a> {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let hasher = Poseidon::<Bn256>::default();
+            let mut native: Vec<Vec<_>> = self.items.iter().map(|i| vec![usize_to_f(*i)]).collect();
+            canonical_order(&hasher, &mut native);
+            let mut items = self
+                .items
+                .iter()
+                .enumerate()
+                .map(|(j, i)| {
+                    let n = AllocatedNum::alloc(cs.namespace(|| format!("item {}", j)), || {
+                        Ok(usize_to_f(*i))
+                    })?;
+                    Ok(MaybeHashed::from_values(vec![n]))
+                })
+                .collect::<CResult<Vec<_>>>()?;
+            let sorted = sorted_hashes(cs.namespace(|| "sort"), &mut items, &hasher)?;
+            for (i, (s, item)) in sorted.iter().zip(&native).enumerate() {
+                let mut expected = hasher.hash(item);
+                if self.tamper && i == 1 {
+                    expected.add_assign(&usize_to_f(1));
+                }
+                cs.enforce(
+                    || format!("check {}", i),
+                    |lc| lc,
+                    |lc| lc,
+                    |lc| lc + s.get_variable() - (expected, CS::one()),
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn canonical_order_ignores_input_order() {
+        let hasher = Poseidon::<Bn256>::default();
+        let mut a: Vec<Vec<_>> = (1..6).map(|i| vec![usize_to_f(i)]).collect();
+        let mut b = a.clone();
+        b.reverse();
+        canonical_order(&hasher, &mut a);
+        canonical_order(&hasher, &mut b);
+        assert_eq!(a, b);
+        let hashes: Vec<_> = a.iter().map(|i| hasher.hash(i).into_repr()).collect();
+        assert!(hashes.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    circuit_tests! {
+        sorted_hashes_one: (SortedHashes { items: &[1], tamper: false }, true),
+        sorted_hashes_three: (SortedHashes { items: &[3, 1, 2], tamper: false }, true),
+        sorted_hashes_repeated: (SortedHashes { items: &[2, 1, 2], tamper: false }, true),
+        sorted_hashes_tampered: (SortedHashes { items: &[3, 1, 2], tamper: true }, false),
+    }
+}
@@ -0,0 +1,274 @@
+//! A window of recent Merkle roots, so that a proof may be against slightly stale state.
+//!
+//! Natively, a `RootHistory` is a ring buffer of the last `capacity` roots, which can follow a
+//! `MerkleSet` as it changes. In-circuit, a `CircuitRootHistory` is the whole window, which is
+//! exposed either root-by-root (`inputize`) or as the hash of all the roots, oldest first
+//! (`inputize_hash`, matching `RootHistory::commitment`).
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, ScalarEngine};
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use super::merkle::MerkleSet;
+use super::order::product_of_differences;
+use super::GenSet;
+use hash::Hasher;
+use util::gadget::Gadget;
+use CResult;
+use OptionExt;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// The last `capacity` roots of a set, oldest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootHistory<F> {
+    roots: VecDeque<F>,
+}
+
+impl<F: Field> RootHistory<F> {
+    /// A window of `capacity` roots, all equal to `initial`, so that the window always has the
+    /// same size.
+    pub fn new(capacity: usize, initial: F) -> Self {
+        assert!(capacity > 0, "A root history must hold at least one root");
+        Self {
+            roots: std::iter::repeat(initial).take(capacity).collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Records a new root, evicting the oldest.
+    pub fn push(&mut self, root: F) {
+        self.roots.pop_front();
+        self.roots.push_back(root);
+    }
+
+    pub fn latest(&self) -> &F {
+        self.roots.back().expect("the window is never empty")
+    }
+
+    pub fn contains(&self, root: &F) -> bool {
+        self.roots.contains(root)
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &F> {
+        self.roots.iter()
+    }
+
+    /// The hash of the roots, oldest first.
+    pub fn commitment<H: Hasher<F = F>>(&self, hasher: &H) -> F {
+        hasher.hash(&self.roots.iter().cloned().collect::<Vec<_>>())
+    }
+}
+
+impl<F: PrimeField> RootHistory<F> {
+    /// Starts a window of `capacity` roots at the current root of `set`, which is kept up to date
+    /// as `set` changes.
+    pub fn follow<H: Hasher<F = F>>(set: &mut MerkleSet<H>, capacity: usize) -> Arc<Mutex<Self>> {
+        let history = Arc::new(Mutex::new(Self::new(capacity, set.digest())));
+        let observed = history.clone();
+        set.subscribe(move |_, root| observed.lock().unwrap().push(*root));
+        history
+    }
+}
+
+/// A window of allocated roots.
+#[derive(Clone)]
+pub struct CircuitRootHistory<E: Engine> {
+    pub roots: Vec<AllocatedNum<E>>,
+    pub value: Option<RootHistory<E::Fr>>,
+    /// The capacity of the window
+    pub capacity: usize,
+}
+
+impl<E: Engine> CircuitRootHistory<E> {
+    /// Enforces that `root` is one of the roots in the window, by showing that
+    /// `prod (root - r_i)` is zero.
+    pub fn assert_contains<CS: ConstraintSystem<E>>(
+        &self,
+        mut cs: CS,
+        root: &AllocatedNum<E>,
+    ) -> CResult<()> {
+        if self.roots.is_empty() {
+            eprintln!("A root history must hold at least one root");
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let acc = product_of_differences(cs.namespace(|| "differences"), root, &self.roots)?;
+        cs.enforce(|| "in window", |lc| lc, |lc| lc, |lc| lc + &acc);
+        Ok(())
+    }
+}
+
+impl<E: Engine> Gadget for CircuitRootHistory<E> {
+    type E = E;
+    type Value = RootHistory<E::Fr>;
+    type Access = ();
+    type Params = usize;
+    fn alloc<CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        value: Option<&Self::Value>,
+        _access: (),
+        capacity: &usize,
+    ) -> Result<Self, SynthesisError> {
+        if let Some(v) = value {
+            if v.capacity() != *capacity {
+                eprintln!(
+                    "Root history has capacity {}, expected {}",
+                    v.capacity(),
+                    capacity
+                );
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+        let roots = (0..*capacity)
+            .map(|i| {
+                AllocatedNum::alloc(cs.namespace(|| format!("root {}", i)), || {
+                    Ok(*value.grab()?.roots.get(i).grab()?)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            roots,
+            value: value.cloned(),
+            capacity: *capacity,
+        })
+    }
+    fn wires(&self) -> Vec<LinearCombination<E>> {
+        self.roots
+            .iter()
+            .map(|n| LinearCombination::zero() + n.get_variable())
+            .collect()
+    }
+    fn wire_values(&self) -> Option<Vec<<E as ScalarEngine>::Fr>> {
+        self.roots.iter().map(|n| n.get_value()).collect()
+    }
+    fn value(&self) -> Option<&Self::Value> {
+        self.value.as_ref()
+    }
+    fn access(&self) -> &() {
+        &()
+    }
+    fn params(&self) -> &usize {
+        &self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::hashes::Poseidon;
+    use set::merkle::{MerkleSetBench, MerkleSetBenchInputs, MerkleSetBenchParams};
+    use set::GenSet;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    type F = <Bn256 as ScalarEngine>::Fr;
+
+    #[test]
+    fn history_follows_set() {
+        let hasher = Poseidon::<Bn256>::default();
+        let items: Vec<Vec<F>> = (1..4).map(|i| vec![usize_to_f(i)]).collect();
+        let mut set = MerkleSet::new_with(hasher.clone(), 3, items.iter().map(Vec::as_slice));
+        let first = set.digest();
+        let history = RootHistory::follow(&mut set, 2);
+        set.swap(&items[0], vec![usize_to_f(10)]);
+        let second = set.digest();
+        assert!(history.lock().unwrap().contains(&first));
+        set.swap(&items[1], vec![usize_to_f(11)]);
+        let history = history.lock().unwrap();
+        assert!(!history.contains(&first));
+        assert!(history.contains(&second));
+        assert_eq!(*history.latest(), set.digest());
+        assert_eq!(history.roots().count(), 2);
+    }
+
+    #[test]
+    fn proofs_over_a_followed_set_leave_history_alone() {
+        let hasher = Poseidon::<Bn256>::default();
+        let items: Vec<Vec<F>> = (1..4).map(|i| vec![usize_to_f(i)]).collect();
+        let mut set = MerkleSet::new_with(hasher.clone(), 3, items.iter().map(Vec::as_slice));
+        let first = set.digest();
+        let history = RootHistory::follow(&mut set, 3);
+        let circuit = MerkleSetBench {
+            inputs: Some(MerkleSetBenchInputs {
+                initial_state: set.clone(),
+                to_remove: vec![items[0].clone()],
+                to_insert: vec![vec![usize_to_f(10)]],
+            }),
+            params: MerkleSetBenchParams {
+                item_size: 1,
+                n_swaps: 1,
+                hash: hasher,
+                depth: 3,
+                verbose: false,
+                require_change: false,
+            },
+        };
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        circuit.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+        assert!(history.lock().unwrap().roots().all(|r| *r == first));
+
+        set.swap(&items[0], vec![usize_to_f(10)]);
+        let second = set.digest();
+        let history = history.lock().unwrap();
+        assert_eq!(
+            history.roots().cloned().collect::<Vec<_>>(),
+            vec![first, first, second]
+        );
+    }
+
+    pub struct InWindow {
+        pub roots: Vec<usize>,
+        pub claimed: usize,
+    }
+
+    impl Circuit<Bn256> for InWindow {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let hasher = Poseidon::<Bn256>::default();
+            let mut history = RootHistory::new(self.roots.len(), F::zero());
+            for r in &self.roots {
+                history.push(usize_to_f(*r));
+            }
+            let window = CircuitRootHistory::alloc(
+                cs.namespace(|| "window"),
+                Some(&history),
+                (),
+                &self.roots.len(),
+            )?;
+            let commitment = window.inputize_hash(cs.namespace(|| "commit"), &hasher)?;
+            if commitment.get_value() != Some(history.commitment(&hasher)) {
+                eprintln!("Window commitment does not match");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            let claimed = AllocatedNum::alloc(cs.namespace(|| "claimed"), || {
+                Ok(usize_to_f(self.claimed))
+            })?;
+            window.assert_contains(cs.namespace(|| "contains"), &claimed)
+        }
+    }
+
+    #[test]
+    fn empty_window_is_rejected() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let window = CircuitRootHistory::<Bn256>::alloc(cs.namespace(|| "window"), None, (), &0)
+            .unwrap();
+        let claimed = AllocatedNum::alloc(cs.namespace(|| "claimed"), || Ok(F::zero())).unwrap();
+        assert!(window.assert_contains(cs.namespace(|| "contains"), &claimed).is_err());
+    }
+
+    circuit_tests! {
+        in_window_latest: (InWindow { roots: vec![3, 4, 5], claimed: 5 }, true),
+        in_window_oldest: (InWindow { roots: vec![3, 4, 5], claimed: 3 }, true),
+        in_window_single: (InWindow { roots: vec![3], claimed: 3 }, true),
+        in_window_missing: (InWindow { roots: vec![3, 4, 5], claimed: 6 }, false),
+    }
+}
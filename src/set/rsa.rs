@@ -1,20 +1,29 @@
 use rug::Integer;
-use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, ScalarEngine};
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError};
 use sapling_crypto::circuit::num::AllocatedNum;
 
+use std::collections::BTreeMap;
 use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
 
 use group::{
     CircuitRsaGroupParams, CircuitRsaQuotientGroup, CircuitSemiGroup, RsaQuotientGroup, SemiGroup,
 };
+use hash::cache::WitnessCache;
 use hash::circuit::{CircuitHasher, MaybeHashed};
 use hash::Hasher;
+use hash::pocklington::{CertificateKind, Challenge};
 use hash::{division_intractable as di, pocklington, HashDomain};
-use mp::bignat::BigNat;
+use mp::bignat::{nat_to_limbs, BigNat};
 use set::int_set::{CircuitIntSet, IntSet};
-use set::{CircuitGenSet, GenSet};
+use set::security::{SecurityConfig, SecurityError};
+use set::transcript::Transcript;
+use set::{items_differ, order, CircuitGenSet, GenSet, Mutation, Observers, SetOp};
+use util::bench::{profile_memory, MemoryReport};
+use util::bit::Bit;
+use util::cancel::CancelToken;
 use util::gadget::Gadget;
 use util::verbose::in_verbose_mode;
 use wesolowski::Reduced;
@@ -33,6 +42,10 @@ where
     pub hasher: H,
     pub hash_domain: HashDomain,
     pub limb_width: usize,
+    /// Notified of every insertion and removal
+    pub observers: Observers<H::F, <Inner::G as SemiGroup>::Elem>,
+    /// If set, item elements are looked up here rather than recomputed
+    pub cache: Option<Arc<WitnessCache<H::F>>>,
 }
 
 impl<H, Inner> Debug for Set<H, Inner>
@@ -76,35 +89,79 @@ impl<H: Hasher, Inner: IntSet> Set<H, Inner> {
             hash_domain,
             hasher,
             limb_width,
+            observers: Observers::new(),
+            cache: None,
         }
     }
 
+    /// Looks item elements up in `cache` from now on. Clones of the set share the cache.
+    pub fn with_cache(self, cache: Arc<WitnessCache<H::F>>) -> Self {
+        Self {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Runs `callback` after every subsequent insertion or removal, with the new digest.
+    pub fn subscribe(
+        &mut self,
+        callback: impl Fn(Mutation<H::F>, &<Inner::G as SemiGroup>::Elem) + Send + Sync + 'static,
+    ) {
+        self.observers.subscribe(callback);
+    }
+
     /// Gets the underlying RSA group
     pub fn group(&self) -> &Inner::G {
         self.inner.group()
     }
 
+    /// The integer that `n` is represented by in the underlying set.
+    pub fn hash_item(&self, n: &[H::F]) -> Integer {
+        // As `di::helper::di_hash`, and the circuit's `di::modded_di_hash`.
+        let expansion = di::Expansion::single::<H::F>();
+        match &self.cache {
+            Some(cache) => {
+                cache.element(&self.hasher, n, &self.offset, &self.hash_domain, &expansion)
+            }
+            None => di::helper::di_hash_with::<H>(
+                n,
+                &self.offset,
+                &self.hash_domain,
+                self.limb_width,
+                &expansion,
+                &self.hasher,
+            ),
+        }
+    }
+
+    /// The digest of the set, blinded by `h^r`.
+    pub fn blinded_digest(
+        &mut self,
+        h: &<Inner::G as SemiGroup>::Elem,
+        r: &Integer,
+    ) -> <Inner::G as SemiGroup>::Elem {
+        let digest = self.inner.digest();
+        let group = self.inner.group();
+        group.op(&digest, &group.power(h, r))
+    }
+
     /// Add `n` to the set.
     pub fn insert(&mut self, n: Vec<H::F>) {
-        let x = di::helper::di_hash::<H>(
-            &n,
-            &self.offset,
-            &self.hash_domain,
-            self.limb_width,
-            &self.hasher,
-        );
-        self.inner.insert(x)
+        let x = self.hash_item(&n);
+        self.inner.insert(x);
+        let inner = &mut self.inner;
+        self.observers
+            .notify(Mutation::Insert(&n), || inner.digest());
     }
     /// Remove `n` from the set, returning whether `n` was present.
     pub fn remove(&mut self, n: &[H::F]) -> bool {
-        let x = di::helper::di_hash::<H>(
-            &n,
-            &self.offset,
-            &self.hash_domain,
-            self.limb_width,
-            &self.hasher,
-        );
-        self.inner.remove(&x)
+        let x = self.hash_item(n);
+        let present = self.inner.remove(&x);
+        if present {
+            let inner = &mut self.inner;
+            self.observers.notify(Mutation::Remove(n), || inner.digest());
+        }
+        present
     }
 
     pub fn remove_all<'b, I: IntoIterator<Item = &'b [H::F]>>(&mut self, ns: I) -> bool
@@ -123,6 +180,136 @@ impl<H: Hasher, Inner: IntSet> Set<H, Inner> {
             self.insert(n);
         }
     }
+
+    /// Replays `ops` against the set, recording the digest after every `checkpoint_every` ops
+    /// (no checkpoints if it is zero).
+    ///
+    /// Returns the checkpoints, or the index of the first op which removes an absent item.
+    pub fn apply_log(
+        &mut self,
+        ops: &[SetOp<H::F>],
+        checkpoint_every: usize,
+    ) -> Result<Vec<<Inner::G as SemiGroup>::Elem>, usize> {
+        let mut checkpoints = Vec::new();
+        for (i, op) in ops.iter().enumerate() {
+            match op {
+                SetOp::Insert(item) => self.insert(item.clone()),
+                SetOp::Remove(item) => {
+                    if !self.remove(item) {
+                        return Err(i);
+                    }
+                }
+            }
+            if checkpoint_every != 0 && (i + 1) % checkpoint_every == 0 {
+                checkpoints.push(self.inner.digest());
+            }
+        }
+        Ok(checkpoints)
+    }
+}
+
+/// A problem with an operation staged into a `BatchStage`. Indices are positions among the
+/// staged removals (or insertions).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StageError {
+    /// Removal `second` removes the same element as removal `first`.
+    DoubleRemove { first: usize, second: usize },
+    /// Insertion `second` inserts the same element as insertion `first`.
+    DuplicateInsert { first: usize, second: usize },
+    /// Removal `index` removes an element which is not in the set.
+    NotPresent { index: usize },
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            StageError::DoubleRemove { first, second } => write!(
+                f,
+                "removal {} removes the same element as removal {}",
+                second, first
+            ),
+            StageError::DuplicateInsert { first, second } => write!(
+                f,
+                "insertion {} inserts the same element as insertion {}",
+                second, first
+            ),
+            StageError::NotPresent { index } => {
+                write!(f, "removal {} is not in the set", index)
+            }
+        }
+    }
+}
+
+/// A staging area for the operations of a single batch against a snapshot of a set.
+///
+/// Each operation is checked as it is staged, so that a bad batch is caught while building
+/// inputs rather than as an unsatisfied constraint after synthesis.
+pub struct BatchStage<'a, H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet,
+{
+    set: &'a Set<H, Inner>,
+    /// Maps the hash of each staged removal to its index.
+    removed: BTreeMap<Integer, usize>,
+    /// Maps the hash of each staged insertion to its index.
+    inserted: BTreeMap<Integer, usize>,
+    to_remove: Vec<Vec<H::F>>,
+    to_insert: Vec<Vec<H::F>>,
+}
+
+impl<'a, H, Inner> BatchStage<'a, H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet,
+{
+    pub fn new(set: &'a Set<H, Inner>) -> Self {
+        Self {
+            set,
+            removed: BTreeMap::new(),
+            inserted: BTreeMap::new(),
+            to_remove: Vec::new(),
+            to_insert: Vec::new(),
+        }
+    }
+
+    /// Stages the removal of `item`, which must be in the set and not already removed.
+    pub fn remove(&mut self, item: Vec<H::F>) -> Result<(), StageError> {
+        let index = self.to_remove.len();
+        let x = self.set.hash_item(&item);
+        if let Some(&first) = self.removed.get(&x) {
+            return Err(StageError::DoubleRemove {
+                first,
+                second: index,
+            });
+        }
+        if self.set.inner.count(&x) == 0 {
+            return Err(StageError::NotPresent { index });
+        }
+        self.removed.insert(x, index);
+        self.to_remove.push(item);
+        Ok(())
+    }
+
+    /// Stages the insertion of `item`, which must not already be staged for insertion.
+    pub fn insert(&mut self, item: Vec<H::F>) -> Result<(), StageError> {
+        let index = self.to_insert.len();
+        let x = self.set.hash_item(&item);
+        if let Some(&first) = self.inserted.get(&x) {
+            return Err(StageError::DuplicateInsert {
+                first,
+                second: index,
+            });
+        }
+        self.inserted.insert(x, index);
+        self.to_insert.push(item);
+        Ok(())
+    }
+
+    /// The staged removals and insertions, in the order they were staged.
+    pub fn into_ops(self) -> (Vec<Vec<H::F>>, Vec<Vec<H::F>>) {
+        (self.to_remove, self.to_insert)
+    }
 }
 
 impl<H, Inner> GenSet<H::F> for Set<H, Inner>
@@ -171,7 +358,7 @@ where
 {
     pub value: Option<Set<H, Inner>>,
     pub offset: Reduced<E>,
-    pub access: (CG, BigNat<E>),
+    pub access: (CG, Challenge<E>),
     pub inner: CircuitIntSet<E, CG, Inner>,
     pub params: CircuitSetParams<H>,
 }
@@ -187,7 +374,7 @@ where
     type E = E;
     type Value = Set<H, Inner>;
     /// Access is the circuit group and the challenge.
-    type Access = (CG, BigNat<E>);
+    type Access = (CG, Challenge<E>);
     type Params = CircuitSetParams<H>;
     fn alloc<CS: ConstraintSystem<Self::E>>(
         mut cs: CS,
@@ -206,7 +393,7 @@ where
             inner,
             offset: di::allocate_offset(
                 cs.namespace(|| "hash offset % l"),
-                &access.1,
+                access.1.nat(),
                 params.n_bits,
             )?,
             access,
@@ -238,12 +425,13 @@ where
     CG::Elem: Gadget<E = E, Value = <CG::Group as SemiGroup>::Elem, Access = ()>,
     Inner: IntSet<G = <CG as CircuitSemiGroup>::Group>,
 {
-    pub fn remove<'b, CS: ConstraintSystem<E>>(
-        self,
+    /// Hashes each item to a (challenge-reduced) set element.
+    fn hash_items<CS: ConstraintSystem<E>>(
+        &self,
         mut cs: CS,
         items: &mut Vec<MaybeHashed<E>>,
-    ) -> Result<Self, SynthesisError> {
-        let removals = items
+    ) -> Result<Vec<Reduced<E>>, SynthesisError> {
+        items
             .into_iter()
             .enumerate()
             .map(|(i, mut input)| -> Result<Reduced<E>, SynthesisError> {
@@ -253,25 +441,36 @@ where
                     self.params.limb_width,
                     &self.params.hash_domain(),
                     &self.offset,
-                    &self.access.1,
+                    self.access.1.nat(),
                     &self.params.hasher,
                 )
             })
-            .collect::<Result<Vec<Reduced<E>>, SynthesisError>>()?;
+            .collect()
+    }
+
+    fn item_values(items: &[MaybeHashed<E>]) -> Option<Vec<Vec<E::Fr>>> {
+        items
+            .iter()
+            .map(|i| {
+                i.values
+                    .iter()
+                    .map(|n| n.get_value())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect()
+    }
+
+    pub fn remove<'b, CS: ConstraintSystem<E>>(
+        self,
+        mut cs: CS,
+        items: &mut Vec<MaybeHashed<E>>,
+    ) -> Result<Self, SynthesisError> {
+        let removals = self.hash_items(&mut cs, items)?;
         let inner =
             self.inner
                 .remove(cs.namespace(|| "int removals"), &self.access.1, &removals)?;
         let value = self.value.as_ref().and_then(|v| {
-            let is: Option<Vec<Vec<E::Fr>>> = items
-                .into_iter()
-                .map(|i| {
-                    i.values
-                        .iter()
-                        .map(|n| n.get_value())
-                        .collect::<Option<Vec<_>>>()
-                })
-                .collect::<Option<Vec<_>>>();
-            is.map(|is| {
+            Self::item_values(items).map(|is| {
                 let mut v = v.clone();
                 assert!(v.remove_all(is.iter().map(Vec::as_slice)));
                 v
@@ -291,39 +490,16 @@ where
         mut cs: CS,
         items: &mut Vec<MaybeHashed<E>>,
     ) -> Result<Self, SynthesisError> {
-        let insertions = items
-            .into_iter()
-            .enumerate()
-            .map(|(i, mut slice)| -> Result<Reduced<E>, SynthesisError> {
-                di::modded_di_hash(
-                    cs.namespace(|| format!("hash {}", i)),
-                    &mut slice,
-                    self.params.limb_width,
-                    &self.params.hash_domain(),
-                    &self.offset,
-                    &self.access.1,
-                    &self.params.hasher,
-                )
-            })
-            .collect::<Result<Vec<Reduced<E>>, SynthesisError>>()?;
+        let insertions = self.hash_items(&mut cs, items)?;
         let inner = self.inner.insert(
             cs.namespace(|| "int insertions"),
             &self.access.1,
             &insertions,
         )?;
         let value = self.value.as_ref().and_then(|v| {
-            let is: Option<Vec<Vec<E::Fr>>> = items
-                .into_iter()
-                .map(|i| {
-                    i.values
-                        .iter()
-                        .map(|n| n.get_value())
-                        .collect::<Option<Vec<_>>>()
-                })
-                .collect::<Option<Vec<_>>>();
-            is.map(|is| {
+            Self::item_values(items).map(|is| {
                 let mut v = v.clone();
-                v.insert_all(is.into_iter());
+                v.insert_all(is);
                 v
             })
         });
@@ -352,9 +528,29 @@ where
         mut removed_items: Vec<MaybeHashed<Self::E>>,
         mut inserted_items: Vec<MaybeHashed<Self::E>>,
     ) -> CResult<Self> {
-        let with = self.insert(cs.namespace(|| "insert"), &mut inserted_items)?;
-        let without = with.remove(cs.namespace(|| "remove"), &mut removed_items)?;
-        Ok(without)
+        let removals = self.hash_items(cs.namespace(|| "hash removals"), &mut removed_items)?;
+        let insertions = self.hash_items(cs.namespace(|| "hash insertions"), &mut inserted_items)?;
+        let inner = self.inner.swap(
+            cs.namespace(|| "int swap"),
+            &self.access.1,
+            &removals,
+            &insertions,
+        )?;
+        let value = self.value.as_ref().and_then(|v| {
+            let rs = Self::item_values(&removed_items)?;
+            let is = Self::item_values(&inserted_items)?;
+            let mut v = v.clone();
+            v.insert_all(is.into_iter());
+            assert!(v.remove_all(rs.iter().map(Vec::as_slice)));
+            Some(v)
+        });
+        Ok(Self {
+            value,
+            inner,
+            params: self.params.clone(),
+            access: self.access.clone(),
+            offset: self.offset.clone(),
+        })
     }
     fn verify_swap_all<CS: ConstraintSystem<Self::E>>(
         self,
@@ -372,6 +568,60 @@ where
         )?;
         Ok(())
     }
+    fn assert_digest_changed<CS: ConstraintSystem<Self::E>>(
+        &self,
+        cs: CS,
+        other: &Self,
+    ) -> CResult<()> {
+        Gadget::assert_not_equal(cs, &self.inner.digest, &other.inner.digest)
+    }
+    /// When the items are equal, both are replaced by the element 1, so that the swap is a no-op
+    /// which holds whether or not the item is present.
+    fn swap_or_noop<CS: ConstraintSystem<Self::E>>(
+        self,
+        mut cs: CS,
+        old: MaybeHashed<Self::E>,
+        new: MaybeHashed<Self::E>,
+    ) -> CResult<Self> {
+        let differ = Bit::from_sapling::<CS>(items_differ(cs.namespace(|| "differ"), &old, &new)?);
+        let mut items = vec![old, new];
+        let elements = self.hash_items(cs.namespace(|| "hash items"), &mut items)?;
+        let gated = elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| -> CResult<Reduced<E>> {
+                let mut cs = cs.namespace(|| format!("gate {}", i));
+                let one = BigNat::one::<CS>(self.params.limb_width);
+                Ok(Reduced::new(
+                    Gadget::mux(cs.namespace(|| "raw"), &differ, &one, &e.raw)?,
+                    Gadget::mux(cs.namespace(|| "reduced"), &differ, &one, &e.reduced)?,
+                ))
+            })
+            .collect::<CResult<Vec<_>>>()?;
+        let inner = self.inner.swap(
+            cs.namespace(|| "int swap"),
+            &self.access.1,
+            &gated[..1],
+            &gated[1..],
+        )?;
+        let value = match differ.value {
+            Some(true) => self.value.as_ref().and_then(|v| {
+                let is = Self::item_values(&items)?;
+                let mut v = v.clone();
+                v.swap(&is[0], is[1].clone());
+                Some(v)
+            }),
+            Some(false) => self.value.clone(),
+            None => None,
+        };
+        Ok(Self {
+            value,
+            inner,
+            params: self.params.clone(),
+            access: self.access.clone(),
+            offset: self.offset.clone(),
+        })
+    }
 }
 
 pub struct SetBenchInputs<H, Inner>
@@ -386,6 +636,12 @@ where
     pub to_remove: Vec<Vec<H::F>>,
     /// The items to insert into the set
     pub to_insert: Vec<Vec<H::F>>,
+    /// The exponents blinding the initial and final digests, if the digests are blinded
+    pub blinders: Option<(Integer, Integer)>,
+    /// If set, the challenge certificate is looked up here
+    pub cache: Option<Arc<WitnessCache<H::F>>>,
+    /// The epoch the proof is bound to, if the circuit binds one
+    pub epoch: Option<H::F>,
 }
 
 impl<H, Inner> SetBenchInputs<H, Inner>
@@ -450,6 +706,34 @@ where
         limb_width: usize,
         group: RsaQuotientGroup,
     ) -> Self {
+        Self::try_new(
+            untouched_items,
+            removed_items,
+            inserted_items,
+            hasher,
+            n_bits_elem,
+            limb_width,
+            group,
+            &CancelToken::default(),
+        )
+        .expect("the default token is never cancelled")
+    }
+
+    /// Like `new`, but checks `cancel` between the (expensive) steps of building the states,
+    /// failing if it has been cancelled.
+    ///
+    /// The removals and insertions keep the order they are given in. Under
+    /// `SetBenchParams::canonical_order`, the circuit and `SetBench::transcript` sort them.
+    pub fn try_new(
+        untouched_items: Vec<Vec<String>>,
+        removed_items: Vec<Vec<String>>,
+        inserted_items: Vec<Vec<String>>,
+        hasher: H,
+        n_bits_elem: usize,
+        limb_width: usize,
+        group: RsaQuotientGroup,
+        cancel: &CancelToken,
+    ) -> Result<Self, SynthesisError> {
         use rayon::prelude::*;
         let untouched: Vec<Vec<H::F>> = untouched_items
             .par_iter()
@@ -464,22 +748,26 @@ where
             .map(|i| i.iter().map(|j| H::F::from_str(j).unwrap()).collect())
             .collect();
         let offset = di::offset(n_bits_elem);
+        cancel.check()?;
         if in_verbose_mode() {
             println!("Constructing common state");
         }
         let mut initial_state =
             Set::new_with(group, offset, hasher, n_bits_elem, limb_width, &untouched);
+        cancel.check()?;
         // We compute digests unecessarily to force evaluation.
         if in_verbose_mode() {
             println!("Computing common digest");
         }
         initial_state.digest();
+        cancel.check()?;
         if in_verbose_mode() {
             println!("Adding initial items");
         }
         let mut final_state = initial_state.clone();
         initial_state.insert_all(removed.clone());
         initial_state.digest();
+        cancel.check()?;
         if in_verbose_mode() {
             println!("Adding final items");
         }
@@ -488,11 +776,44 @@ where
         if in_verbose_mode() {
             println!("Done adding final items");
         }
-        SetBenchInputs {
+        Ok(SetBenchInputs {
             initial_state,
             final_state,
             to_remove: removed,
             to_insert: inserted,
+            blinders: None,
+            cache: None,
+            epoch: None,
+        })
+    }
+
+    /// Sets the exponents blinding the initial and final digests.
+    ///
+    /// When batches are chained, the initial blinder of each batch must be the final blinder of
+    /// the previous one, so that its blinded initial digest is the previous blinded final digest.
+    pub fn with_blinders(self, initial: Integer, fin: Integer) -> Self {
+        Self {
+            blinders: Some((initial, fin)),
+            ..self
+        }
+    }
+
+    /// Binds the proof to `epoch` (see `set::transcript`).
+    pub fn with_epoch(self, epoch: H::F) -> Self {
+        Self {
+            epoch: Some(epoch),
+            ..self
+        }
+    }
+
+    /// Shares `cache` with both states and the challenge derivation, so that consecutive batches
+    /// touching the same items reuse each other's work.
+    pub fn with_cache(self, cache: Arc<WitnessCache<H::F>>) -> Self {
+        Self {
+            initial_state: self.initial_state.with_cache(cache.clone()),
+            final_state: self.final_state.with_cache(cache.clone()),
+            cache: Some(cache),
+            ..self
         }
     }
 }
@@ -504,11 +825,80 @@ pub struct SetBenchParams<H> {
     pub n_bits_base: usize,
     pub n_bits_elem: usize,
     pub n_bits_challenge: usize,
+    /// How the challenge prime is certified
+    pub certificate: CertificateKind,
     pub item_size: usize,
     pub n_removes: usize,
     pub n_inserts: usize,
     pub hasher: H,
     pub verbose: bool,
+    /// Whether the challenge hash absorbs the hash of the group description (generator and
+    /// modulus). This should be on for all new parameter sets.
+    pub absorb_group: bool,
+    /// Whether the challenge hash absorbs the item hashes in canonical order (see `set::order`),
+    /// so that it does not depend on the order the items are given in.
+    pub canonical_order: bool,
+    /// Whether to reject batches which do not change the digest.
+    pub require_change: bool,
+    /// If set, the public digests are blinded, and the inputs must carry blinders.
+    pub blinding: Option<Blinding>,
+    /// Whether the proof is bound to an epoch (see `set::transcript`): a public input which the
+    /// challenge absorbs. If set, the inputs must carry an epoch.
+    pub bind_epoch: bool,
+    /// Checked before each item is hashed, and between the phases of synthesis. Once it is
+    /// cancelled, synthesis stops with an error at the next check: a phase which has started, such
+    /// as hashing to the challenge or checking the proofs of exponentiation, runs to its end first.
+    pub cancel: CancelToken,
+}
+
+/// Blinds a digest `d` as `d · h^r`, so that the public digest reveals nothing about the set.
+///
+/// Every batch should blind its final digest with a fresh `r`, drawn uniformly from `n_bits`
+/// bits, and the next batch must blind its initial digest with that same `r` (see
+/// `SetBenchInputs::with_blinders`). For statistical hiding, `n_bits` should exceed the modulus
+/// width by the security level. `n_bits` must be positive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blinding {
+    /// The blinding generator. No discrete-log relation between it and the set generator may be
+    /// known.
+    pub h: Integer,
+    pub n_bits: usize,
+}
+
+impl<H> SetBenchParams<H> {
+    /// Builds parameters for batches of `security.batch_size` insertions and removals of items
+    /// with `item_size` field elements, with widths chosen by `security`.
+    ///
+    /// Fails if the modulus of `group` is too small for the security level.
+    pub fn from_security(
+        security: &SecurityConfig,
+        group: RsaQuotientGroup,
+        limb_width: usize,
+        item_size: usize,
+        hasher: H,
+    ) -> Result<Self, SecurityError> {
+        let n_bits_base = group.m.significant_bits() as usize;
+        security.check_modulus(n_bits_base)?;
+        Ok(Self {
+            group,
+            limb_width,
+            n_bits_base,
+            n_bits_elem: security.n_bits_elem,
+            n_bits_challenge: security.n_bits_challenge,
+            certificate: CertificateKind::default(),
+            item_size,
+            n_removes: security.batch_size,
+            n_inserts: security.batch_size,
+            hasher,
+            verbose: false,
+            absorb_group: true,
+            canonical_order: false,
+            require_change: false,
+            blinding: None,
+            bind_epoch: false,
+            cancel: CancelToken::default(),
+        })
+    }
 }
 
 pub struct SetBench<H, Inner>
@@ -520,6 +910,379 @@ where
     pub params: SetBenchParams<H>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchPlanError {
+    /// The circuit shape cannot absorb the difference between the number of removals and
+    /// insertions using padding.
+    Unbalanced {
+        n_removes: usize,
+        n_inserts: usize,
+        n_batches: usize,
+    },
+    /// The item at this index of the removals is not in the set when it is removed.
+    MissingItem(usize),
+}
+
+impl fmt::Display for BatchPlanError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BatchPlanError::Unbalanced {
+                n_removes,
+                n_inserts,
+                n_batches,
+            } => write!(
+                f,
+                "cannot pad {} removals and {} insertions into {} batches",
+                n_removes, n_inserts, n_batches
+            ),
+            BatchPlanError::MissingItem(i) => write!(f, "removal {} is not in the set", i),
+        }
+    }
+}
+
+impl<H, Inner> SetBench<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet,
+{
+    /// Splits a batch which may exceed the capacity of the circuit described by `params` into
+    /// a sequence of circuits, in order, where the final state of each is the initial state of
+    /// the next.
+    ///
+    /// Unused slots are filled with `padding_item(params)`, which is inserted in some
+    /// sub-batch and removed in the same or a later one, so the final set contains no padding.
+    /// Each padding insertion must be matched by a padding removal, so when the capacities
+    /// differ this may use more sub-batches than the items need.
+    ///
+    /// If `params.bind_epoch`, each batch must then be given its epoch.
+    pub fn plan(
+        params: &SetBenchParams<H>,
+        initial_state: Set<H, Inner>,
+        to_remove: Vec<Vec<H::F>>,
+        to_insert: Vec<Vec<H::F>>,
+    ) -> Result<Vec<Self>, BatchPlanError> {
+        let (cap_r, cap_i) = (params.n_removes, params.n_inserts);
+        let (n_removes, n_inserts) = (to_remove.len(), to_insert.len());
+        let div_ceil = |n: usize, d: usize| if d == 0 { 0 } else { (n + d - 1) / d };
+        let min_batches = std::cmp::max(
+            std::cmp::max(div_ceil(n_removes, cap_r), div_ceil(n_inserts, cap_i)),
+            1,
+        );
+        let unbalanced = BatchPlanError::Unbalanced {
+            n_removes,
+            n_inserts,
+            n_batches: min_batches,
+        };
+        // `n_batches` must solve `n_batches * cap_i - n_inserts = n_batches * cap_r - n_removes`.
+        let n_batches = if cap_i == cap_r {
+            if n_inserts != n_removes {
+                return Err(unbalanced);
+            }
+            min_batches
+        } else {
+            let excess = n_inserts as isize - n_removes as isize;
+            let step = cap_i as isize - cap_r as isize;
+            if excess % step != 0 || excess / step < min_batches as isize {
+                return Err(unbalanced);
+            }
+            (excess / step) as usize
+        };
+        if n_batches * cap_i < n_inserts || n_batches * cap_r < n_removes {
+            return Err(unbalanced);
+        }
+        let padding = padding_item(params);
+        let mut removals = to_remove.into_iter().enumerate();
+        let mut insertions = to_insert.into_iter();
+        let mut remaining_inserts = n_inserts;
+        let mut pending_padding = 0;
+        let mut state = initial_state;
+        let mut batches = Vec::with_capacity(n_batches);
+        for batch in 0..n_batches {
+            let mut batch_removes = Vec::with_capacity(cap_r);
+            let mut removal_indices = Vec::with_capacity(cap_r);
+            for (i, r) in removals.by_ref().take(cap_r) {
+                removal_indices.push(Some(i));
+                batch_removes.push(r);
+            }
+            let padding_removes = cap_r - batch_removes.len();
+            // Padding removed in this batch must already be in the set, or inserted by it, and
+            // the insertions deferred must fit in the later batches.
+            let needed_padding = padding_removes.saturating_sub(pending_padding);
+            if needed_padding > cap_i {
+                return Err(unbalanced);
+            }
+            let n_real = std::cmp::min(remaining_inserts, cap_i - needed_padding);
+            if remaining_inserts - n_real > cap_i * (n_batches - batch - 1) {
+                return Err(unbalanced);
+            }
+            remaining_inserts -= n_real;
+            let mut batch_inserts: Vec<Vec<H::F>> = insertions.by_ref().take(n_real).collect();
+            while batch_inserts.len() < cap_i {
+                batch_inserts.push(padding.clone());
+                pending_padding += 1;
+            }
+            pending_padding -= padding_removes;
+            for _ in 0..padding_removes {
+                removal_indices.push(None);
+                batch_removes.push(padding.clone());
+            }
+            let mut final_state = state.clone();
+            final_state.insert_all(batch_inserts.iter().cloned());
+            for (r, i) in batch_removes.iter().zip(removal_indices) {
+                if !final_state.remove(r) {
+                    return Err(BatchPlanError::MissingItem(
+                        i.expect("padding was inserted before it is removed"),
+                    ));
+                }
+            }
+            state.digest();
+            final_state.digest();
+            let cache = state.cache.clone();
+            batches.push(SetBench {
+                inputs: Some(SetBenchInputs {
+                    initial_state: state,
+                    final_state: final_state.clone(),
+                    to_remove: batch_removes,
+                    to_insert: batch_inserts,
+                    blinders: None,
+                    cache,
+                    epoch: None,
+                }),
+                params: params.clone(),
+            });
+            state = final_state;
+        }
+        Ok(batches)
+    }
+}
+
+/// The value of `padding_item`'s tag, "set-padding" read as a big-endian integer.
+const PADDING_TAG: &str = "139505571366988752473386599";
+
+/// The item `SetBench::plan` uses to fill unused slots: element `j` is the hash of
+/// `[PADDING_TAG, j]`, so that it is not an item a user chose.
+pub fn padding_item<H: Hasher>(params: &SetBenchParams<H>) -> Vec<H::F> {
+    let tag = H::F::from_str(PADDING_TAG).unwrap();
+    (0..params.item_size)
+        .map(|j| {
+            let j = H::F::from_str(&j.to_string()).unwrap();
+            params.hasher.hash(&[tag, j])
+        })
+        .collect()
+}
+
+/// An inconsistency in the inputs of a `SetBench`, which would make the circuit unsatisfiable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputError {
+    /// The circuit has no inputs.
+    MissingInputs,
+    /// The initial state is over a different group than the parameters.
+    GroupMismatch,
+    /// The number of removals differs from the circuit shape.
+    RemovalCount { expected: usize, got: usize },
+    /// The number of insertions differs from the circuit shape.
+    InsertionCount { expected: usize, got: usize },
+    /// The item at this index of the removals has the wrong number of field elements.
+    RemovalSize { index: usize, expected: usize, got: usize },
+    /// The item at this index of the insertions has the wrong number of field elements.
+    InsertionSize { index: usize, expected: usize, got: usize },
+    /// The item at this index of the removals is not in the set (after the insertions).
+    MissingItem(usize),
+    /// The final state is not the initial state with the insertions and removals applied.
+    DigestMismatch,
+    /// Blinders are present without blinding parameters, or vice versa, or a blinder does not
+    /// fit in the blinding width (which must be positive).
+    Blinders,
+    /// An epoch is present without `bind_epoch`, or vice versa.
+    Epoch,
+    /// A public value, such as the modulus, does not fit in `n_bits_base` bits.
+    Width,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InputError::MissingInputs => write!(f, "no inputs"),
+            InputError::GroupMismatch => write!(f, "the initial state is over the wrong group"),
+            InputError::RemovalCount { expected, got } => {
+                write!(f, "expected {} removals, got {}", expected, got)
+            }
+            InputError::InsertionCount { expected, got } => {
+                write!(f, "expected {} insertions, got {}", expected, got)
+            }
+            InputError::RemovalSize {
+                index,
+                expected,
+                got,
+            } => write!(
+                f,
+                "removal {} has {} elements, expected {}",
+                index, got, expected
+            ),
+            InputError::InsertionSize {
+                index,
+                expected,
+                got,
+            } => write!(
+                f,
+                "insertion {} has {} elements, expected {}",
+                index, got, expected
+            ),
+            InputError::MissingItem(i) => write!(f, "removal {} is not in the set", i),
+            InputError::DigestMismatch => write!(
+                f,
+                "the final state does not follow from the initial state and the changes"
+            ),
+            InputError::Blinders => write!(f, "blinders do not match the blinding parameters"),
+            InputError::Epoch => write!(f, "the epoch does not match the epoch binding"),
+            InputError::Width => write!(f, "a public value does not fit in the base width"),
+        }
+    }
+}
+
+impl<H, Inner> SetBench<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    /// Checks natively that the inputs are consistent with each other and with the parameters.
+    ///
+    /// This is much cheaper than proving, and pinpoints the problem rather than leaving an
+    /// unsatisfied constraint.
+    pub fn check_inputs(&self) -> Result<(), InputError> {
+        let inputs = self.inputs.as_ref().ok_or(InputError::MissingInputs)?;
+        let params = &self.params;
+        if *inputs.initial_state.group() != params.group {
+            return Err(InputError::GroupMismatch);
+        }
+        if inputs.to_remove.len() != params.n_removes {
+            return Err(InputError::RemovalCount {
+                expected: params.n_removes,
+                got: inputs.to_remove.len(),
+            });
+        }
+        if inputs.to_insert.len() != params.n_inserts {
+            return Err(InputError::InsertionCount {
+                expected: params.n_inserts,
+                got: inputs.to_insert.len(),
+            });
+        }
+        if let Some((index, item)) = inputs
+            .to_remove
+            .iter()
+            .enumerate()
+            .find(|(_, i)| i.len() != params.item_size)
+        {
+            return Err(InputError::RemovalSize {
+                index,
+                expected: params.item_size,
+                got: item.len(),
+            });
+        }
+        if let Some((index, item)) = inputs
+            .to_insert
+            .iter()
+            .enumerate()
+            .find(|(_, i)| i.len() != params.item_size)
+        {
+            return Err(InputError::InsertionSize {
+                index,
+                expected: params.item_size,
+                got: item.len(),
+            });
+        }
+        if inputs.blinders.is_some() != params.blinding.is_some() {
+            return Err(InputError::Blinders);
+        }
+        if let (Some((r0, r1)), Some(blinding)) = (&inputs.blinders, &params.blinding) {
+            let fits = |r: &Integer| *r >= 0 && r.significant_bits() as usize <= blinding.n_bits;
+            if blinding.n_bits == 0 || !fits(r0) || !fits(r1) {
+                return Err(InputError::Blinders);
+            }
+        }
+        if inputs.epoch.is_some() != params.bind_epoch {
+            return Err(InputError::Epoch);
+        }
+        let mut state = inputs.initial_state.clone();
+        state.insert_all(inputs.to_insert.iter().cloned());
+        for (i, item) in inputs.to_remove.iter().enumerate() {
+            if !state.remove(item) {
+                return Err(InputError::MissingItem(i));
+            }
+        }
+        if state.digest() != inputs.final_state.clone().digest() {
+            return Err(InputError::DigestMismatch);
+        }
+        Ok(())
+    }
+
+    /// The transcript the challenge is derived from, computed natively.
+    pub fn transcript(&self) -> Result<Transcript<H>, InputError> {
+        let inputs = self.inputs.as_ref().ok_or(InputError::MissingInputs)?;
+        let params = &self.params;
+        let n_limbs = params.n_bits_base / params.limb_width;
+        let mut transcript = Transcript::new(&params.hasher, params.limb_width, n_limbs);
+        if params.absorb_group {
+            transcript = transcript.absorb_group(&params.group);
+        }
+        if params.bind_epoch {
+            transcript = transcript.absorb_epoch(inputs.epoch.ok_or(InputError::Epoch)?);
+        }
+        Ok(transcript
+            .absorb_digest(&inputs.initial_state.clone().digest())
+            .absorb_digest(&inputs.final_state.clone().digest())
+            .absorb_items(&inputs.to_insert, params.canonical_order)
+            .absorb_items(&inputs.to_remove, params.canonical_order))
+    }
+
+    /// The public inputs of the circuit, in the order it allocates them: the hash of the group,
+    /// the epoch (if bound), and the hashes of the initial and final digests or, if blinded, of
+    /// `h` and the blinded digests.
+    pub fn public_inputs(&self) -> Result<Vec<H::F>, InputError> {
+        let inputs = self.inputs.as_ref().ok_or(InputError::MissingInputs)?;
+        let params = &self.params;
+        let n_limbs = params.n_bits_base / params.limb_width;
+        let limbs = |n: &Integer| {
+            nat_to_limbs::<H::F>(n, params.limb_width, n_limbs).map_err(|_| InputError::Width)
+        };
+        let hash = |n: &Integer| limbs(n).map(|l| params.hasher.hash(&l));
+        let mut group = limbs(&params.group.g)?;
+        group.extend(limbs(&params.group.m)?);
+        let mut public = vec![params.hasher.hash(&group)];
+        if params.bind_epoch {
+            public.push(inputs.epoch.ok_or(InputError::Epoch)?);
+        }
+        let mut initial_state = inputs.initial_state.clone();
+        let mut final_state = inputs.final_state.clone();
+        match params.blinding.as_ref() {
+            None => {
+                public.push(hash(&initial_state.digest())?);
+                public.push(hash(&final_state.digest())?);
+            }
+            Some(blinding) => {
+                let (r0, r1) = inputs.blinders.as_ref().ok_or(InputError::Blinders)?;
+                public.push(hash(&blinding.h)?);
+                public.push(hash(&initial_state.blinded_digest(&blinding.h, r0))?);
+                public.push(hash(&final_state.blinded_digest(&blinding.h, r1))?);
+            }
+        }
+        Ok(public)
+    }
+
+    /// Measures the circuit for `params` without building any inputs, to check before a long
+    /// setup or proof that the prover fits in memory.
+    pub fn estimate_memory<E>(params: &SetBenchParams<H>) -> Result<MemoryReport, SynthesisError>
+    where
+        E: Engine,
+        H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    {
+        profile_memory(Self {
+            inputs: None,
+            params: params.clone(),
+        })
+    }
+}
+
 impl<E, Inner, H> Circuit<E> for SetBench<H, Inner>
 where
     E: Engine,
@@ -527,11 +1290,13 @@ where
     Inner: IntSet<G = RsaQuotientGroup>,
 {
     fn synthesize<CS: ConstraintSystem<E>>(mut self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let cancel = self.params.cancel.clone();
         if self.params.verbose {
             println!("Allocating Deletions...");
         }
-        let removals = (0..self.params.n_removes)
+        let mut removals = (0..self.params.n_removes)
             .map(|i| {
+                cancel.check()?;
                 let mut cs = cs.namespace(|| format!("init removals {}", i));
                 let values = (0..self.params.item_size)
                     .map(|j| {
@@ -551,8 +1316,9 @@ where
         if self.params.verbose {
             println!("Allocating Insertions...");
         }
-        let insertions = (0..self.params.n_inserts)
+        let mut insertions = (0..self.params.n_inserts)
             .map(|i| {
+                cancel.check()?;
                 let mut cs = cs.namespace(|| format!("init insertions {}", i));
                 let values = (0..self.params.item_size)
                     .map(|j| {
@@ -598,68 +1364,90 @@ where
             n_bits_base / limb_width,
         )?;
 
+        cancel.check()?;
+        if self.params.verbose {
+            println!("Constructing Group");
+        }
+        let (group, group_hash) = {
+            let raw_group = self
+                .inputs
+                .as_ref()
+                .map(|s| s.initial_state.group().clone());
+            let group = CircuitRsaQuotientGroup::alloc(
+                cs.namespace(|| "group"),
+                raw_group.as_ref(),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width,
+                    n_limbs: n_bits_base / limb_width,
+                },
+            )?;
+            let group_hash =
+                group.inputize_hash(cs.namespace(|| "group input"), &self.params.hasher)?;
+            (group, group_hash)
+        };
+
+        let epoch = if self.params.bind_epoch {
+            let epoch = AllocatedNum::alloc(cs.namespace(|| "epoch"), || {
+                Ok(*self.inputs.grab()?.epoch.grab()?)
+            })?;
+            epoch.inputize(cs.namespace(|| "epoch input"))?;
+            Some(epoch)
+        } else {
+            None
+        };
+
+        cancel.check()?;
         if self.params.verbose {
             println!("Constructing the challenge");
         }
 
         let challenge = {
             let mut to_hash_to_challenge: Vec<AllocatedNum<E>> = Vec::new();
+            if self.params.absorb_group {
+                // Binds the challenge to the modulus and generator, so that a proof for one group
+                // cannot be replayed against another.
+                to_hash_to_challenge.push(group_hash);
+            }
+            // Binds the proof to its chain context, so that it cannot be replayed in another.
+            to_hash_to_challenge.extend(epoch);
             to_hash_to_challenge.extend(
                 expected_initial_digest
-                    .as_limbs::<CS>()
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, n)| {
-                        n.as_sapling_allocated_num(
-                            cs.namespace(|| format!("initial digest hash {}", i)),
-                        )
-                    })
-                    .collect::<Result<Vec<_>, _>>()?,
+                    .as_allocated_nums(cs.namespace(|| "initial digest hash"))?
+                    .0,
             );
             to_hash_to_challenge.extend(
                 expected_final_digest
-                    .as_limbs::<CS>()
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, n)| {
-                        n.as_sapling_allocated_num(
-                            cs.namespace(|| format!("final digest hash {}", i)),
-                        )
-                    })
-                    .collect::<Result<Vec<_>, _>>()?,
+                    .as_allocated_nums(cs.namespace(|| "final digest hash"))?
+                    .0,
             );
-            to_hash_to_challenge.extend(insertions.iter().map(|i| i.hash.clone().unwrap()));
-            to_hash_to_challenge.extend(removals.iter().map(|i| i.hash.clone().unwrap()));
-            pocklington::hash_to_pocklington_prime(
+            if self.params.canonical_order {
+                to_hash_to_challenge.extend(order::sorted_hashes(
+                    cs.namespace(|| "sort insertions"),
+                    &mut insertions,
+                    &self.params.hasher,
+                )?);
+                to_hash_to_challenge.extend(order::sorted_hashes(
+                    cs.namespace(|| "sort removals"),
+                    &mut removals,
+                    &self.params.hasher,
+                )?);
+            } else {
+                to_hash_to_challenge.extend(insertions.iter().map(|i| i.hash.clone().unwrap()));
+                to_hash_to_challenge.extend(removals.iter().map(|i| i.hash.clone().unwrap()));
+            }
+            pocklington::hash_to_prime(
                 cs.namespace(|| "chash"),
                 &to_hash_to_challenge,
                 limb_width,
                 self.params.n_bits_challenge,
+                self.params.certificate,
                 &self.params.hasher,
+                self.inputs.as_ref().and_then(|i| i.cache.as_deref()),
             )?
         };
 
-        if self.params.verbose {
-            println!("Constructing Group");
-        }
-        let group = {
-            let raw_group = self
-                .inputs
-                .as_ref()
-                .map(|s| s.initial_state.group().clone());
-            let group = CircuitRsaQuotientGroup::alloc(
-                cs.namespace(|| "group"),
-                raw_group.as_ref(),
-                (),
-                &CircuitRsaGroupParams {
-                    limb_width,
-                    n_limbs: n_bits_base / limb_width,
-                },
-            )?;
-            group.inputize_hash(cs.namespace(|| "group input"), &self.params.hasher)?;
-            group
-        };
-
+        cancel.check()?;
         if self.params.verbose {
             println!("Constructing Sets");
         }
@@ -676,8 +1464,10 @@ where
                         limb_width,
                     },
                 )?;
-            initial_set
-                .inputize_hash(cs.namespace(|| "initial_state input"), &self.params.hasher)?;
+            if self.params.blinding.is_none() {
+                initial_set
+                    .inputize_hash(cs.namespace(|| "initial_state input"), &self.params.hasher)?;
+            }
             initial_set.inner.digest.equal(
                 cs.namespace(|| "initial digest matches"),
                 &expected_initial_digest,
@@ -685,6 +1475,7 @@ where
             initial_set
         };
 
+        cancel.check()?;
         let final_set = {
             let final_set: CircuitSet<E, H, CircuitRsaQuotientGroup<E>, Inner> = CircuitSet::alloc(
                 cs.namespace(|| "set final"),
@@ -696,7 +1487,10 @@ where
                     limb_width,
                 },
             )?;
-            final_set.inputize_hash(cs.namespace(|| "final_state input"), &self.params.hasher)?;
+            if self.params.blinding.is_none() {
+                final_set
+                    .inputize_hash(cs.namespace(|| "final_state input"), &self.params.hasher)?;
+            }
             final_set.inner.digest.equal(
                 cs.namespace(|| "final digest matches"),
                 &expected_final_digest,
@@ -704,9 +1498,54 @@ where
             final_set
         };
 
+        cancel.check()?;
+        if let Some(blinding) = self.params.blinding.as_ref() {
+            if self.params.verbose {
+                println!("Blinding digests");
+            }
+            if blinding.n_bits == 0 {
+                eprintln!("Blinders must have a positive width");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            let mut cs = cs.namespace(|| "blinding");
+            let h = BigNat::alloc_from_nat(
+                cs.namespace(|| "h"),
+                || Ok(blinding.h.clone()),
+                limb_width,
+                n_bits_base / limb_width,
+            )?;
+            h.inputize_hash(cs.namespace(|| "h input"), &self.params.hasher)?;
+            let blinders = self.inputs.as_ref().map(|i| i.blinders.as_ref());
+            for (i, (name, set)) in [("initial", &initial_set), ("final", &final_set)]
+                .iter()
+                .enumerate()
+            {
+                let mut cs = cs.namespace(|| *name);
+                let r = BigNat::alloc_from_nat(
+                    cs.namespace(|| "r"),
+                    || {
+                        let (r0, r1) = blinders.grab()?.grab()?;
+                        Ok(if i == 0 { r0.clone() } else { r1.clone() })
+                    },
+                    limb_width,
+                    (blinding.n_bits - 1) / limb_width + 1,
+                )?;
+                let mask = set.access.0.power(cs.namespace(|| "h^r"), &h, &r)?;
+                let blinded = set
+                    .access
+                    .0
+                    .op(cs.namespace(|| "blind"), &set.inner.digest, &mask)?;
+                blinded.inputize_hash(cs.namespace(|| "input"), &self.params.hasher)?;
+            }
+        }
+
+        cancel.check()?;
         if self.params.verbose {
             println!("Swapping elements");
         }
+        if self.params.require_change {
+            initial_set.assert_digest_changed(cs.namespace(|| "digest changed"), &final_set)?;
+        }
         initial_set.verify_swap_all(cs.namespace(|| "swap"), removals, insertions, final_set)?;
 
         if self.params.verbose {
@@ -733,6 +1572,622 @@ mod test {
 
     use util::test_helpers::*;
 
+    /// Parameters for single swaps of one-element items in a 512b group, which tests override as
+    /// needed.
+    fn base() -> SetBenchParams<Poseidon<Bn256>> {
+        SetBenchParams {
+            group: RsaQuotientGroup::from_strs("2", RSA_512),
+            limb_width: 32,
+            n_bits_elem: 128,
+            n_bits_challenge: 128,
+            certificate: CertificateKind::Pocklington,
+            n_bits_base: 512,
+            item_size: 1,
+            n_inserts: 1,
+            n_removes: 1,
+            hasher: Poseidon::<Bn256>::default(),
+            verbose: false,
+            absorb_group: true,
+            canonical_order: false,
+            require_change: false,
+            blinding: None,
+            bind_epoch: false,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    #[test]
+    fn apply_log_checkpoints() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![F::from_str(s).unwrap()];
+        let new_set = |items: &Vec<Vec<F>>| {
+            Set::<_, ExpSet<_, SerialExp<_>>>::new_with(
+                RsaQuotientGroup::from_strs("2", RSA_512),
+                di::offset(128),
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                items,
+            )
+        };
+        let ops = vec![
+            SetOp::Insert(item("1")),
+            SetOp::Insert(item("2")),
+            SetOp::Remove(item("1")),
+        ];
+        let mut encoded = Vec::new();
+        for op in &ops {
+            op.write(&mut encoded).unwrap();
+        }
+        let mut reader = &encoded[..];
+        let decoded = (0..ops.len())
+            .map(|_| SetOp::read(&mut reader).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ops, decoded);
+
+        let mut set = new_set(&vec![]);
+        let checkpoints = set.apply_log(&decoded, 1).unwrap();
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(checkpoints[2], new_set(&vec![item("2")]).digest());
+        assert_eq!(set.apply_log(&[SetOp::Remove(item("1"))], 0), Err(0));
+    }
+
+    #[test]
+    fn batch_stage_rejects_double_spends() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![F::from_str(s).unwrap()];
+        let set = Set::<_, ExpSet<_, SerialExp<_>>>::new_with(
+            RsaQuotientGroup::from_strs("2", RSA_512),
+            di::offset(128),
+            Poseidon::<Bn256>::default(),
+            128,
+            32,
+            &vec![item("1"), item("2")],
+        );
+        let mut stage = BatchStage::new(&set);
+        assert_eq!(stage.remove(item("1")), Ok(()));
+        assert_eq!(stage.remove(item("3")), Err(StageError::NotPresent { index: 1 }));
+        assert_eq!(stage.remove(item("2")), Ok(()));
+        assert_eq!(
+            stage.remove(item("1")),
+            Err(StageError::DoubleRemove {
+                first: 0,
+                second: 2
+            })
+        );
+        assert_eq!(stage.insert(item("4")), Ok(()));
+        assert_eq!(
+            stage.insert(item("4")),
+            Err(StageError::DuplicateInsert {
+                first: 0,
+                second: 1
+            })
+        );
+        let (removes, inserts) = stage.into_ops();
+        assert_eq!(removes, vec![item("1"), item("2")]);
+        assert_eq!(inserts, vec![item("4")]);
+    }
+
+    #[test]
+    fn check_inputs_finds_problems() {
+        let params = SetBenchParams {
+            item_size: 2,
+            ..base()
+        };
+        let bench = || SetBench::<_, ExpSet<_, SerialExp<_>>> {
+            inputs: Some(SetBenchInputs::from_counts(
+                2,
+                1,
+                1,
+                2,
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                RsaQuotientGroup::from_strs("2", RSA_512),
+            )),
+            params: params.clone(),
+        };
+        assert_eq!(bench().check_inputs(), Ok(()));
+
+        let mut b = bench();
+        b.inputs.as_mut().unwrap().to_insert[0].pop();
+        assert_eq!(
+            b.check_inputs(),
+            Err(InputError::InsertionSize {
+                index: 0,
+                expected: 2,
+                got: 1
+            })
+        );
+
+        let mut b = bench();
+        b.inputs.as_mut().unwrap().to_remove[0][0].double();
+        assert_eq!(b.check_inputs(), Err(InputError::MissingItem(0)));
+
+        let mut b = bench();
+        let extra = b.inputs.as_ref().unwrap().to_insert[0].clone();
+        b.inputs.as_mut().unwrap().final_state.insert(extra);
+        assert_eq!(b.check_inputs(), Err(InputError::DigestMismatch));
+
+        let mut b = bench();
+        b.params.n_removes = 2;
+        assert_eq!(
+            b.check_inputs(),
+            Err(InputError::RemovalCount {
+                expected: 2,
+                got: 1
+            })
+        );
+
+        assert!(bench().public_inputs().is_ok());
+        let mut b = bench();
+        b.params.n_bits_base = 256;
+        assert_eq!(b.public_inputs(), Err(InputError::Width));
+    }
+
+    #[test]
+    fn plan_pads_and_chains_batches() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![F::from_str(s).unwrap()];
+        let items = |ss: &[&str]| ss.iter().map(|s| item(s)).collect::<Vec<_>>();
+        let new_set = |ss: &[&str]| {
+            Set::<_, ExpSet<_, SerialExp<_>>>::new_with(
+                RsaQuotientGroup::from_strs("2", RSA_512),
+                di::offset(128),
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                &items(ss),
+            )
+        };
+        let params = SetBenchParams {
+            n_inserts: 2,
+            ..base()
+        };
+        let plan = |removes: &[&str], inserts: &[&str]| {
+            SetBench::plan(&params, new_set(&["1", "2"]), items(removes), items(inserts))
+        };
+        let padding = padding_item(&params);
+        assert_ne!(padding, item("0"));
+
+        let batches = plan(&["1"], &["3", "4", "5"]).unwrap();
+        assert_eq!(batches.len(), 2);
+        let mut expected_initial = new_set(&["1", "2"]).digest();
+        for b in &batches {
+            assert_eq!(b.check_inputs(), Ok(()));
+            let inputs = b.inputs.as_ref().unwrap();
+            assert_eq!(inputs.initial_state.clone().digest(), expected_initial);
+            expected_initial = inputs.final_state.clone().digest();
+        }
+        assert_eq!(expected_initial, new_set(&["2", "3", "4", "5"]).digest());
+        let last = batches[1].inputs.as_ref().unwrap();
+        assert_eq!(last.to_insert, vec![item("5"), padding.clone()]);
+        assert_eq!(last.to_remove, vec![padding.clone()]);
+
+        // Two insertions and no removals need two batches, each removing the padding it adds.
+        let batches = plan(&[], &["3", "4"]).unwrap();
+        assert_eq!(batches.len(), 2);
+        for b in &batches {
+            assert_eq!(b.check_inputs(), Ok(()));
+            assert_eq!(b.inputs.as_ref().unwrap().to_remove, vec![padding.clone()]);
+        }
+
+        assert_eq!(
+            plan(&["1", "2"], &[]).err(),
+            Some(BatchPlanError::Unbalanced {
+                n_removes: 2,
+                n_inserts: 0,
+                n_batches: 2
+            })
+        );
+        assert_eq!(
+            plan(&["1", "9"], &["3", "4", "5", "6"]).err(),
+            Some(BatchPlanError::MissingItem(1))
+        );
+    }
+
+    #[test]
+    fn blinding_needs_positive_width() {
+        let params = |n_bits: usize| SetBenchParams {
+            item_size: 2,
+            blinding: Some(Blinding {
+                h: Integer::from(3),
+                n_bits,
+            }),
+            ..base()
+        };
+        let bench = |n_bits: usize, r1: u32| SetBench::<_, ExpSet<_, SerialExp<_>>> {
+            inputs: Some(
+                SetBenchInputs::from_counts(
+                    2,
+                    1,
+                    1,
+                    2,
+                    Poseidon::<Bn256>::default(),
+                    128,
+                    32,
+                    RsaQuotientGroup::from_strs("2", RSA_512),
+                )
+                .with_blinders(Integer::from(5), Integer::from(r1)),
+            ),
+            params: params(n_bits),
+        };
+        assert_eq!(bench(8, 255).check_inputs(), Ok(()));
+        assert_eq!(bench(8, 256).check_inputs(), Err(InputError::Blinders));
+        assert_eq!(bench(0, 0).check_inputs(), Err(InputError::Blinders));
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        assert!(bench(0, 0).synthesize(&mut cs).is_err());
+    }
+
+    #[test]
+    fn canonical_order_fixes_the_challenge() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![s.to_owned()];
+        let bench = |canonical_order: bool, reverse: bool| {
+            let mut removed = vec![item("1"), item("2")];
+            let mut inserted = vec![item("3"), item("4")];
+            if reverse {
+                removed.reverse();
+                inserted.reverse();
+            }
+            SetBench::<_, ExpSet<_, SerialExp<_>>> {
+                inputs: Some(SetBenchInputs::new(
+                    vec![item("5")],
+                    removed,
+                    inserted,
+                    Poseidon::<Bn256>::default(),
+                    128,
+                    32,
+                    RsaQuotientGroup::from_strs("2", RSA_512),
+                )),
+                params: SetBenchParams {
+                    n_inserts: 2,
+                    n_removes: 2,
+                    canonical_order,
+                    ..base()
+                },
+            }
+        };
+        let challenge = |canonical_order: bool, reverse: bool| {
+            bench(canonical_order, reverse)
+                .transcript()
+                .unwrap()
+                .challenge(128, CertificateKind::Pocklington)
+                .unwrap()
+        };
+        // The inputs keep the order they are given in.
+        let reversed = bench(false, true).inputs.unwrap();
+        assert_eq!(reversed.to_remove[0], vec![F::from_str("2").unwrap()]);
+        assert_eq!(reversed.to_insert[0], vec![F::from_str("4").unwrap()]);
+        assert_eq!(challenge(true, false), challenge(true, true));
+        assert_ne!(challenge(false, false), challenge(false, true));
+    }
+
+    pub struct SwapOrNoop<'a> {
+        pub initial: &'a [&'a str],
+        pub old: &'a str,
+        pub new: &'a str,
+        pub expected: &'a [&'a str],
+    }
+
+    impl<'a> Circuit<Bn256> for SwapOrNoop<'a> {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            type F = <Bn256 as ScalarEngine>::Fr;
+            let item = |s: &str| vec![F::from_str(s).unwrap()];
+            let hasher = Poseidon::<Bn256>::default();
+            let new_set = |items: &[&str]| {
+                Set::<_, ExpSet<_, SerialExp<_>>>::new_with(
+                    RsaQuotientGroup::from_strs("2", RSA_512),
+                    di::offset(128),
+                    hasher.clone(),
+                    128,
+                    32,
+                    &items.iter().map(|s| item(s)).collect(),
+                )
+            };
+            let initial = new_set(self.initial);
+            let mut expected = new_set(self.expected);
+            let group = CircuitRsaQuotientGroup::alloc(
+                cs.namespace(|| "group"),
+                Some(initial.group()),
+                (),
+                &CircuitRsaGroupParams {
+                    limb_width: 32,
+                    n_limbs: 16,
+                },
+            )?;
+            let challenge = Challenge::unchecked(BigNat::alloc_from_nat(
+                cs.namespace(|| "challenge"),
+                || Ok((Integer::from(1) << 127) - 1),
+                32,
+                4,
+            )?);
+            let set: CircuitSet<_, _, _, ExpSet<_, SerialExp<_>>> = CircuitSet::alloc(
+                cs.namespace(|| "initial"),
+                Some(&initial),
+                (group, challenge),
+                &CircuitSetParams {
+                    hasher: hasher.clone(),
+                    n_bits: 128,
+                    limb_width: 32,
+                },
+            )?;
+            let old = AllocatedNum::alloc(cs.namespace(|| "old"), || Ok(item(self.old)[0]))?;
+            let new = AllocatedNum::alloc(cs.namespace(|| "new"), || Ok(item(self.new)[0]))?;
+            let result = set.swap_or_noop(
+                cs.namespace(|| "swap"),
+                MaybeHashed::from_values(vec![old]),
+                MaybeHashed::from_values(vec![new]),
+            )?;
+            let expected_digest = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(expected.digest()),
+                32,
+                16,
+            )?;
+            result
+                .inner
+                .digest
+                .equal(cs.namespace(|| "check"), &expected_digest)
+        }
+    }
+
+    circuit_tests! {
+        swap_or_noop_swaps: (SwapOrNoop {
+            initial: &["1", "2"],
+            old: "2",
+            new: "3",
+            expected: &["1", "3"],
+        }, true),
+        swap_or_noop_equal_absent: (SwapOrNoop {
+            initial: &["1", "2"],
+            old: "5",
+            new: "5",
+            expected: &["1", "2"],
+        }, true),
+        swap_or_noop_wrong_result: (SwapOrNoop {
+            initial: &["1", "2"],
+            old: "2",
+            new: "3",
+            expected: &["1", "2"],
+        }, false),
+    }
+
+    #[test]
+    fn estimate_memory_matches_shape() {
+        let params = SetBenchParams {
+            item_size: 2,
+            ..base()
+        };
+        let report =
+            SetBench::<_, ExpSet<_, SerialExp<_>>>::estimate_memory::<Bn256>(&params).unwrap();
+        let mut counter = ::util::bench::ConstraintCounter::new();
+        SetBench::<_, ExpSet<_, SerialExp<_>>> {
+            inputs: None,
+            params,
+        }
+        .synthesize(&mut counter)
+        .unwrap();
+        assert_eq!(report.n_constraints, counter.num_constraints());
+        assert!(report.n_aux > report.n_constraints / 2);
+        assert!(report.estimated_prover_bytes::<Bn256>() > report.domain_size() * 32 * 3);
+    }
+
+    #[test]
+    fn cancelled_synthesis_stops() {
+        let cancel = CancelToken::new();
+        let params = SetBenchParams {
+            cancel: cancel.clone(),
+            ..base()
+        };
+        let circuit = |params: &SetBenchParams<_>| SetBench::<_, ExpSet<_, SerialExp<_>>> {
+            inputs: None,
+            params: params.clone(),
+        };
+        let mut cs = ::util::bench::ConstraintCounter::new();
+        circuit(&params).synthesize(&mut cs).unwrap();
+
+        cancel.cancel();
+        let mut cs = ::util::bench::ConstraintCounter::new();
+        let err = circuit(&params).synthesize(&mut cs).unwrap_err();
+        assert!(::util::cancel::is_cancellation(&err));
+        let inputs = SetBenchInputs::<_, ExpSet<_, SerialExp<_>>>::try_new(
+            vec![],
+            vec![vec!["1".to_string()]],
+            vec![vec!["2".to_string()]],
+            Poseidon::<Bn256>::default(),
+            128,
+            32,
+            RsaQuotientGroup::from_strs("2", RSA_512),
+            &cancel,
+        );
+        assert!(inputs.is_err());
+    }
+
+    #[test]
+    fn epoch_binds_the_challenge() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let params = SetBenchParams {
+            bind_epoch: true,
+            ..base()
+        };
+        let bench = |epoch: Option<F>| {
+            let inputs = SetBenchInputs::new(
+                vec![],
+                vec![vec!["1".to_string()]],
+                vec![vec!["2".to_string()]],
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                RsaQuotientGroup::from_strs("2", RSA_512),
+            );
+            SetBench::<_, ExpSet<_, SerialExp<_>>> {
+                inputs: Some(match epoch {
+                    Some(e) => inputs.with_epoch(e),
+                    None => inputs,
+                }),
+                params: params.clone(),
+            }
+        };
+        assert_eq!(bench(None).check_inputs(), Err(InputError::Epoch));
+        let epoch = F::from_str("7").unwrap();
+        let bound = bench(Some(epoch));
+        assert_eq!(bound.check_inputs(), Ok(()));
+        let challenge = {
+            let transcript = bound.transcript().unwrap();
+            assert_eq!(transcript.inputs()[1], epoch);
+            transcript.challenge(128, CertificateKind::Pocklington)
+        };
+        let other = bench(Some(F::from_str("8").unwrap()));
+        assert_ne!(
+            challenge,
+            other
+                .transcript()
+                .unwrap()
+                .challenge(128, CertificateKind::Pocklington)
+        );
+
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        bound.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+    }
+
+    #[test]
+    fn lazy_aggregation_in_set_bench() {
+        let n_swaps = 8;
+        let mut cs = ::util::bench::ConstraintCounter::new();
+        SetBench::<_, ExpSet<_, SerialExp<_>>> {
+            inputs: None,
+            params: SetBenchParams {
+                group: RsaQuotientGroup::from_strs("2", RSA_2048),
+                n_bits_base: 2048,
+                item_size: 5,
+                n_inserts: n_swaps,
+                n_removes: n_swaps,
+                ..base()
+            },
+        }
+        .synthesize(&mut cs)
+        .unwrap();
+        let total = cs.num_constraints();
+        // The insertion and removal proofs each aggregate `n_swaps` factors modulo the 128-bit
+        // challenge, in 32-bit limbs.
+        let aggregation = |lazy: bool| {
+            let mut cs = ::util::bench::ConstraintCounter::new();
+            let mut nat = |name: String| {
+                BigNat::<Bn256>::alloc_from_nat(
+                    cs.namespace(|| name),
+                    || Err(SynthesisError::AssignmentMissing),
+                    32,
+                    4,
+                )
+                .unwrap()
+            };
+            let l = nat("l".to_owned());
+            let factors: Vec<_> = (0..n_swaps).map(|i| nat(format!("factor {}", i))).collect();
+            let before = cs.num_constraints();
+            if lazy {
+                BigNat::product_tree_mod(cs.namespace(|| "tree"), &factors, &l).unwrap();
+            } else {
+                let mut acc = factors[0].clone();
+                for (i, f) in factors.iter().enumerate().skip(1) {
+                    let name = format!("fold {}", i);
+                    acc = acc.mult_mod(cs.namespace(|| name), f, &l).unwrap().1;
+                }
+            }
+            cs.num_constraints() - before
+        };
+        let (lazy, eager) = (aggregation(true), aggregation(false));
+        let total_eager = total - 2 * lazy + 2 * eager;
+        println!(
+            "SetBench of {} swaps: {} constraints with lazy aggregation, about {} with mult_mods",
+            n_swaps, total, total_eager
+        );
+        assert!(total < total_eager);
+    }
+
+    #[test]
+    fn transcript_matches_circuit_challenge() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![s.to_owned()];
+        for &(absorb_group, canonical_order, bind_epoch) in
+            &[(true, false, false), (true, true, true), (false, true, false)]
+        {
+            let cache = Arc::new(WitnessCache::new(1 << 10));
+            let mut inputs = SetBenchInputs::new(
+                vec![item("5")],
+                vec![item("2"), item("1")],
+                vec![item("4"), item("3")],
+                Poseidon::<Bn256>::default(),
+                128,
+                32,
+                RsaQuotientGroup::from_strs("2", RSA_512),
+            )
+            .with_cache(cache.clone());
+            if bind_epoch {
+                inputs = inputs.with_epoch(F::from_str("7").unwrap());
+            }
+            let bench = SetBench::<_, ExpSet<_, SerialExp<_>>> {
+                inputs: Some(inputs),
+                params: SetBenchParams {
+                    n_inserts: 2,
+                    n_removes: 2,
+                    absorb_group,
+                    canonical_order,
+                    bind_epoch,
+                    ..base()
+                },
+            };
+            let transcript_inputs = bench.transcript().unwrap().inputs().to_vec();
+            let expected = bench
+                .transcript()
+                .unwrap()
+                .challenge(128, CertificateKind::Pocklington);
+
+            let mut cs = TestConstraintSystem::<Bn256>::new();
+            bench.synthesize(&mut cs).unwrap();
+            assert!(cs.is_satisfied());
+            // The circuit cached the certificate of what it hashed, so looking up the transcript
+            // hits only if the circuit hashed exactly the transcript.
+            let (hits, misses) = cache.stats();
+            let certificate = cache
+                .certificate(&Poseidon::<Bn256>::default(), &transcript_inputs, 128)
+                .unwrap();
+            assert_eq!(cache.stats(), (hits + 1, misses));
+            assert_eq!(Some(certificate.number().clone()), expected);
+        }
+    }
+
+    #[test]
+    fn absorb_group_changes_challenge() {
+        let item = |s: &str| vec![s.to_owned()];
+        let challenge = |absorb_group| {
+            let bench = SetBench::<_, ExpSet<_, SerialExp<_>>> {
+                inputs: Some(SetBenchInputs::new(
+                    vec![],
+                    vec![item("1")],
+                    vec![item("2")],
+                    Poseidon::<Bn256>::default(),
+                    128,
+                    32,
+                    RsaQuotientGroup::from_strs("2", RSA_512),
+                )),
+                params: SetBenchParams {
+                    absorb_group,
+                    ..base()
+                },
+            };
+            let transcript = bench.transcript().unwrap();
+            transcript
+                .challenge(128, CertificateKind::Pocklington)
+                .unwrap()
+        };
+        assert_ne!(challenge(true), challenge(false));
+    }
+
     circuit_tests! {
         small_rsa_1_swap_naive: (SetBench::<_, ExpSet<_, SerialExp<_>>>  {
             inputs: Some(SetBenchInputs::new(
@@ -749,17 +2204,103 @@ mod test {
                             RsaQuotientGroup::from_strs("2", RSA_512),
                     )),
                     params: SetBenchParams {
-                        group: RsaQuotientGroup::from_strs("2", RSA_512),
-                        limb_width: 32,
-                        n_bits_elem: 128,
-                        n_bits_challenge: 128,
-                        n_bits_base: 512,
                         item_size: 5,
-                        n_inserts: 1,
-                        n_removes: 1,
-                        hasher: Poseidon::default(),
                         verbose: true,
+                        ..base()
                     },
         }, true),
+        small_rsa_1_swap_lucas: (SetBench::<_, ExpSet<_, SerialExp<_>>>  {
+            inputs: Some(SetBenchInputs::new(
+                            [].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "4"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "5"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            Poseidon::default(),
+                            128,
+                            32,
+                            RsaQuotientGroup::from_strs("2", RSA_512),
+                    )),
+                    params: SetBenchParams {
+                        certificate: CertificateKind::Lucas,
+                        item_size: 5,
+                        ..base()
+                    },
+        }, true),
+        small_rsa_1_swap_unbound_group: (SetBench::<_, ExpSet<_, SerialExp<_>>>  {
+            inputs: Some(SetBenchInputs::new(
+                            [].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "4"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "5"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            Poseidon::default(),
+                            128,
+                            32,
+                            RsaQuotientGroup::from_strs("2", RSA_512),
+                    )),
+                    params: SetBenchParams {
+                        item_size: 5,
+                        absorb_group: false,
+                        ..base()
+                    },
+        }, true),
+        small_rsa_1_swap_blinded: (SetBench::<_, ExpSet<_, SerialExp<_>>>  {
+            inputs: Some(SetBenchInputs::new(
+                            [].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "4"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            [
+                            ["0", "1", "2", "3", "5"].iter().map(|s| s.to_string()).collect(),
+                            ].to_vec(),
+                            Poseidon::default(),
+                            128,
+                            32,
+                            RsaQuotientGroup::from_strs("2", RSA_512),
+                    ).with_blinders(Integer::from(12345), Integer::from(67890))),
+                    params: SetBenchParams {
+                        item_size: 5,
+                        verbose: true,
+                        blinding: Some(Blinding {
+                            h: Integer::from(3),
+                            n_bits: 64,
+                        }),
+                        ..base()
+                    },
+        }, true),
+        small_rsa_2_swap_canonical_any_order: ({
+            let mut inputs = SetBenchInputs::new(
+                [].to_vec(),
+                [
+                    ["0", "1", "2", "3", "4"].iter().map(|s| s.to_string()).collect(),
+                    ["0", "1", "2", "3", "6"].iter().map(|s| s.to_string()).collect(),
+                ].to_vec(),
+                [
+                    ["0", "1", "2", "3", "5"].iter().map(|s| s.to_string()).collect(),
+                    ["0", "1", "2", "3", "7"].iter().map(|s| s.to_string()).collect(),
+                ].to_vec(),
+                Poseidon::default(),
+                128,
+                32,
+                RsaQuotientGroup::from_strs("2", RSA_512),
+            );
+            // The circuit sorts the items itself.
+            inputs.to_remove.reverse();
+            SetBench::<_, ExpSet<_, SerialExp<_>>> {
+                inputs: Some(inputs),
+                params: SetBenchParams {
+                    item_size: 5,
+                    n_inserts: 2,
+                    n_removes: 2,
+                    canonical_order: true,
+                    ..base()
+                },
+            }
+        }, true),
     }
 }
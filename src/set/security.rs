@@ -0,0 +1,129 @@
+//! Mapping from a target security level to concrete set parameters.
+//!
+//! The knobs of an RSA set circuit (challenge width, element width, hash offset, modulus size)
+//! interact, so rather than setting them individually, callers can describe the security they
+//! want and let this module choose.
+use rug::Integer;
+
+use std::fmt;
+
+use hash::division_intractable as di;
+
+/// Element widths for which the division-intractable hash has a precomputed offset.
+const SUPPORTED_ELEM_BITS: [usize; 3] = [128, 512, 2048];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecurityError {
+    /// No supported division-intractable hash width is wide enough for this security level.
+    Unsupported { security_bits: usize },
+    /// The group modulus is too small for this security level.
+    WeakGroup {
+        modulus_bits: usize,
+        required_bits: usize,
+    },
+}
+
+impl fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecurityError::Unsupported { security_bits } => write!(
+                f,
+                "No supported element width gives {} bits of security",
+                security_bits
+            ),
+            SecurityError::WeakGroup {
+                modulus_bits,
+                required_bits,
+            } => write!(
+                f,
+                "A {}b modulus is too small, at least {}b is required",
+                modulus_bits, required_bits
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecurityConfig {
+    /// The target security level, in bits.
+    pub security_bits: usize,
+    /// The number of insertions (and removals) per batch.
+    pub batch_size: usize,
+    /// The width of the Fiat-Shamir challenge prime.
+    pub n_bits_challenge: usize,
+    /// The width of the division-intractable hash output.
+    pub n_bits_elem: usize,
+    /// The offset of the division-intractable hash, for `n_bits_elem`.
+    pub hash_offset: Integer,
+}
+
+impl SecurityConfig {
+    /// Chooses parameters for `security_bits` of security with batches of `batch_size`.
+    ///
+    /// The challenge is twice the security level (the usual Fiat-Shamir bound for proofs of
+    /// exponentiation), plus a bit per doubling of the batch, since any batch element colliding
+    /// with the challenge breaks soundness. The element width is the smallest supported
+    /// division-intractable hash width of at least 16 bits per bit of security (2048b at 128b).
+    pub fn new(security_bits: usize, batch_size: usize) -> Result<Self, SecurityError> {
+        let batch_bits = (batch_size.max(1) as f64).log2().ceil() as usize;
+        let n_bits_challenge = 2 * security_bits + batch_bits;
+        let n_bits_elem = *SUPPORTED_ELEM_BITS
+            .iter()
+            .find(|&&w| w >= 16 * security_bits)
+            .ok_or(SecurityError::Unsupported { security_bits })?;
+        Ok(Self {
+            security_bits,
+            batch_size,
+            n_bits_challenge,
+            n_bits_elem,
+            hash_offset: di::offset(n_bits_elem),
+        })
+    }
+
+    /// The smallest RSA modulus giving this security level, per NIST SP 800-57.
+    pub fn min_modulus_bits(&self) -> usize {
+        match self.security_bits {
+            0..=80 => 1024,
+            81..=112 => 2048,
+            113..=128 => 3072,
+            129..=192 => 7680,
+            _ => 15360,
+        }
+    }
+
+    /// Checks that a modulus of `modulus_bits` bits is large enough for this configuration.
+    pub fn check_modulus(&self, modulus_bits: usize) -> Result<(), SecurityError> {
+        if modulus_bits < self.min_modulus_bits() {
+            Err(SecurityError::WeakGroup {
+                modulus_bits,
+                required_bits: self.min_modulus_bits(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_128() {
+        let c = SecurityConfig::new(128, 1).unwrap();
+        assert_eq!(c.n_bits_challenge, 256);
+        assert_eq!(c.n_bits_elem, 2048);
+        let c = SecurityConfig::new(128, 1000).unwrap();
+        assert_eq!(c.n_bits_challenge, 266);
+        assert!(c.check_modulus(2048).is_err());
+        assert!(c.check_modulus(3072).is_ok());
+    }
+
+    #[test]
+    fn security_too_high() {
+        assert_eq!(
+            SecurityConfig::new(256, 1),
+            Err(SecurityError::Unsupported { security_bits: 256 })
+        );
+    }
+}
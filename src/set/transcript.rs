@@ -0,0 +1,86 @@
+//! The Fiat-Shamir transcripts of the RSA set circuits, computed natively.
+//!
+//! `SetBench` and `RollupBench` derive their challenge by hashing, in order: the hash of the group
+//! (`SetBench` with `absorb_group`), the epoch (if bound), the limbs of the initial and final
+//! digests, and the hashes of the inserted items, then of the removed ones. A `Transcript`
+//! absorbs the same values, so that verifiers and other implementations can recompute the
+//! challenge.
+//!
+//! The epoch is a field element naming the context a proof is for, such as a chain id and block
+//! height. It is also a public input, so a proof bound to one epoch cannot be replayed in another
+//! deployment which shares the parameters.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::PrimeField;
+
+use group::RsaQuotientGroup;
+use hash::pocklington::helper::hash_to_prime;
+use hash::pocklington::CertificateKind;
+use hash::Hasher;
+use mp::bignat::nat_to_limbs;
+
+pub struct Transcript<'a, H: Hasher> {
+    hasher: &'a H,
+    limb_width: usize,
+    n_limbs: usize,
+    inputs: Vec<H::F>,
+}
+
+impl<'a, H: Hasher> Transcript<'a, H> {
+    /// An empty transcript, for digests of `n_limbs` limbs of `limb_width` bits.
+    pub fn new(hasher: &'a H, limb_width: usize, n_limbs: usize) -> Self {
+        Self {
+            hasher,
+            limb_width,
+            n_limbs,
+            inputs: Vec::new(),
+        }
+    }
+
+    fn limbs(&self, n: &Integer) -> Vec<H::F> {
+        nat_to_limbs(n, self.limb_width, self.n_limbs).expect("values fit the modulus")
+    }
+
+    /// Absorbs the hash of the limbs of the generator and then the modulus, which is the hash
+    /// `Gadget::inputize_hash` exposes for the group.
+    pub fn absorb_group(mut self, group: &RsaQuotientGroup) -> Self {
+        let mut limbs = self.limbs(&group.g);
+        limbs.extend(self.limbs(&group.m));
+        self.inputs.push(self.hasher.hash(&limbs));
+        self
+    }
+
+    pub fn absorb_epoch(mut self, epoch: H::F) -> Self {
+        self.inputs.push(epoch);
+        self
+    }
+
+    pub fn absorb_digest(mut self, digest: &Integer) -> Self {
+        let limbs = self.limbs(digest);
+        self.inputs.extend(limbs);
+        self
+    }
+
+    /// Absorbs the hashes of `items`, sorted as `set::order` sorts them if `canonical`.
+    pub fn absorb_items(mut self, items: &[Vec<H::F>], canonical: bool) -> Self {
+        let mut hashes: Vec<H::F> = items.iter().map(|i| self.hasher.hash(i)).collect();
+        if canonical {
+            hashes.sort_by_key(|h| h.into_repr());
+        }
+        self.inputs.extend(hashes);
+        self
+    }
+
+    /// The values absorbed so far.
+    pub fn inputs(&self) -> &[H::F] {
+        &self.inputs
+    }
+
+    /// The challenge prime, certified as `certificate` says, as the circuits compute it.
+    pub fn challenge(
+        &self,
+        n_bits_challenge: usize,
+        certificate: CertificateKind,
+    ) -> Option<Integer> {
+        hash_to_prime(&self.inputs, n_bits_challenge, certificate, self.hasher)
+    }
+}
@@ -0,0 +1,597 @@
+//! A two-level accumulator: RSA sets of items, sharded, under an RSA set of the shard digests.
+//!
+//! Each item lives in the shard selected by the low `shard_bits` bits of its hash. The top-level
+//! set holds one entry per shard: the shard index followed by the limbs of the shard's digest.
+//! Updating a shard replaces its entry, so the top-level digest commits to every item while each
+//! batch only touches one shard. Shards can be maintained (and their batches proven) by
+//! different provers, which only need to agree on the top level.
+//!
+//! `ShardUpdate` proves a batch against one shard together with the resulting update of the
+//! top-level digest. Its public inputs are the hash of the group, and the hashes of the initial
+//! and final top-level digests.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField};
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::num::AllocatedNum;
+
+use std::fmt::{self, Formatter};
+
+use group::{CircuitRsaGroupParams, CircuitRsaQuotientGroup, RsaQuotientGroup};
+use hash::circuit::{CircuitHasher, MaybeHashed};
+use hash::{low_k_bits, pocklington, Hasher};
+use mp::bignat::{nat_to_limbs, BigNat};
+use set::int_set::IntSet;
+use set::rsa::{CircuitSet, CircuitSetParams, Set};
+use set::{CircuitGenSet, GenSet};
+use util::convert::{f_to_nat, usize_to_f};
+use util::gadget::Gadget;
+use CResult;
+use OptionExt;
+
+/// The shard of the item with hash `hash`.
+pub fn shard_of_hash<F: PrimeField>(hash: &F, shard_bits: usize) -> usize {
+    low_k_bits(&f_to_nat(hash), shard_bits)
+        .to_usize()
+        .expect("shard indices fit in a usize")
+}
+
+/// The top-level entry of shard `shard` with digest `digest`.
+pub fn shard_entry<F: PrimeField>(
+    shard: usize,
+    digest: &Integer,
+    limb_width: usize,
+    n_limbs: usize,
+) -> Vec<F> {
+    let limbs = nat_to_limbs::<F>(digest, limb_width, n_limbs).expect("digests fit the modulus");
+    let mut entry = vec![usize_to_f(shard)];
+    entry.extend(limbs);
+    entry
+}
+
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct TwoLevelSet<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    pub shards: Vec<Set<H, Inner>>,
+    pub top: Set<H, Inner>,
+    /// The number of low bits of an item's hash which select its shard
+    pub shard_bits: usize,
+    /// The number of limbs of a digest in a top-level entry
+    pub n_limbs: usize,
+}
+
+/// A problem with a batch staged against one shard of a `TwoLevelSet`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShardError {
+    /// The batch has no items, so it selects no shard.
+    Empty,
+    /// The items of the batch belong to different shards.
+    MixedShards { first: usize, other: usize },
+    /// Removal `index` removes an item which is not in its shard.
+    NotPresent { index: usize },
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ShardError::Empty => write!(f, "the batch is empty"),
+            ShardError::MixedShards { first, other } => write!(
+                f,
+                "the batch touches shards {} and {}, but may only touch one",
+                first, other
+            ),
+            ShardError::NotPresent { index } => {
+                write!(f, "removal {} is not in the set", index)
+            }
+        }
+    }
+}
+
+impl<H, Inner> TwoLevelSet<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    /// A set of `items` split across `2^shard_bits` shards. The shards and the top level share
+    /// the group, hasher and element parameters.
+    pub fn new_with(
+        group: RsaQuotientGroup,
+        offset: Integer,
+        hasher: H,
+        element_bits: usize,
+        limb_width: usize,
+        shard_bits: usize,
+        items: &Vec<Vec<H::F>>,
+    ) -> Self {
+        let n_limbs = (group.m.significant_bits() as usize - 1) / limb_width + 1;
+        let mut sharded: Vec<Vec<Vec<H::F>>> = vec![Vec::new(); 1 << shard_bits];
+        for item in items {
+            sharded[shard_of_hash(&hasher.hash(item), shard_bits)].push(item.clone());
+        }
+        let mut shards: Vec<Set<H, Inner>> = sharded
+            .iter()
+            .map(|items| {
+                Set::new_with(
+                    group.clone(),
+                    offset.clone(),
+                    hasher.clone(),
+                    element_bits,
+                    limb_width,
+                    items,
+                )
+            })
+            .collect();
+        let entries = shards
+            .iter_mut()
+            .enumerate()
+            .map(|(i, s)| shard_entry(i, &s.digest(), limb_width, n_limbs))
+            .collect();
+        let top = Set::new_with(group, offset, hasher, element_bits, limb_width, &entries);
+        Self {
+            shards,
+            top,
+            shard_bits,
+            n_limbs,
+        }
+    }
+
+    pub fn n_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard `item` belongs to.
+    pub fn shard_of(&self, item: &[H::F]) -> usize {
+        shard_of_hash(&self.top.hasher.hash(item), self.shard_bits)
+    }
+
+    /// The current top-level entry of shard `shard`.
+    pub fn entry(&mut self, shard: usize) -> Vec<H::F> {
+        let digest = self.shards[shard].digest();
+        shard_entry(shard, &digest, self.top.limb_width, self.n_limbs)
+    }
+
+    /// Applies `f` to shard `shard`, and replaces its top-level entry.
+    fn update<T>(&mut self, shard: usize, f: impl FnOnce(&mut Set<H, Inner>) -> T) -> T {
+        let old = self.entry(shard);
+        let result = f(&mut self.shards[shard]);
+        let new = self.entry(shard);
+        if old != new {
+            self.top.swap(&old, new);
+        }
+        result
+    }
+
+    /// Add `item` to its shard.
+    pub fn insert(&mut self, item: Vec<H::F>) {
+        let shard = self.shard_of(&item);
+        self.update(shard, |s| s.insert(item))
+    }
+
+    /// Remove `item` from its shard, returning whether it was present.
+    pub fn remove(&mut self, item: &[H::F]) -> bool {
+        let shard = self.shard_of(item);
+        self.update(shard, |s| s.remove(item))
+    }
+
+    /// Applies a batch which touches a single shard, returning the witness of a `ShardUpdate`.
+    /// On failure, the set is unchanged.
+    pub fn update_shard(
+        &mut self,
+        to_remove: Vec<Vec<H::F>>,
+        to_insert: Vec<Vec<H::F>>,
+    ) -> Result<ShardUpdateInputs<H, Inner>, ShardError> {
+        let mut routes = to_remove
+            .iter()
+            .chain(to_insert.iter())
+            .map(|item| self.shard_of(item));
+        let shard = routes.next().ok_or(ShardError::Empty)?;
+        if let Some(other) = routes.find(|s| *s != shard) {
+            return Err(ShardError::MixedShards {
+                first: shard,
+                other,
+            });
+        }
+        // Checked on a copy, so that a failure leaves the shard (and its observers) untouched.
+        let mut final_shard = self.shards[shard].clone();
+        final_shard.insert_all(to_insert.iter().cloned());
+        for (index, item) in to_remove.iter().enumerate() {
+            if !final_shard.remove(item) {
+                return Err(ShardError::NotPresent { index });
+            }
+        }
+        let initial_shard = self.shards[shard].clone();
+        let initial_top = self.top.clone();
+        self.update(shard, |s| {
+            s.insert_all(to_insert.iter().cloned());
+            s.remove_all(to_remove.iter().map(Vec::as_slice));
+        });
+        Ok(ShardUpdateInputs {
+            shard,
+            initial_shard,
+            final_shard,
+            initial_top,
+            final_top: self.top.clone(),
+            to_remove,
+            to_insert,
+        })
+    }
+}
+
+impl<H, Inner> GenSet<H::F> for TwoLevelSet<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    type Digest = Integer;
+
+    fn swap(&mut self, old: &[H::F], new: Vec<H::F>) {
+        self.insert(new);
+        self.remove(old);
+    }
+
+    /// The top-level digest.
+    fn digest(&mut self) -> Integer {
+        self.top.digest()
+    }
+}
+
+pub struct ShardUpdateInputs<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    /// The index of the updated shard
+    pub shard: usize,
+    pub initial_shard: Set<H, Inner>,
+    pub final_shard: Set<H, Inner>,
+    pub initial_top: Set<H, Inner>,
+    pub final_top: Set<H, Inner>,
+    pub to_remove: Vec<Vec<H::F>>,
+    pub to_insert: Vec<Vec<H::F>>,
+}
+
+#[derive(Clone)]
+pub struct ShardUpdateParams<H> {
+    pub group: RsaQuotientGroup,
+    pub limb_width: usize,
+    /// The width of the modulus, which must be a multiple of `limb_width`
+    pub n_bits_base: usize,
+    pub n_bits_elem: usize,
+    pub n_bits_challenge: usize,
+    pub item_size: usize,
+    pub shard_bits: usize,
+    pub n_removes: usize,
+    pub n_inserts: usize,
+    pub hasher: H,
+}
+
+pub struct ShardUpdate<H, Inner>
+where
+    H: Hasher,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    pub inputs: Option<ShardUpdateInputs<H, Inner>>,
+    pub params: ShardUpdateParams<H>,
+}
+
+/// The shard and top-level states of a `ShardUpdate`, in the order they are allocated.
+const STATES: [&str; 4] = ["initial shard", "final shard", "initial top", "final top"];
+
+/// Allocates `n` items of `item_size` elements, with their hashes.
+fn alloc_items<E, H, CS>(
+    mut cs: CS,
+    items: Option<&Vec<Vec<E::Fr>>>,
+    n: usize,
+    item_size: usize,
+    hasher: &H,
+) -> CResult<Vec<MaybeHashed<E>>>
+where
+    E: Engine,
+    H: CircuitHasher<E = E>,
+    CS: ConstraintSystem<E>,
+{
+    (0..n)
+        .map(|i| {
+            let mut cs = cs.namespace(|| format!("item {}", i));
+            let values = (0..item_size)
+                .map(|j| {
+                    AllocatedNum::alloc(cs.namespace(|| format!("alloc {}", j)), || {
+                        Ok(*items.grab()?.get(i).grab()?.get(j).grab()?)
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let hash = hasher.allocate_hash(cs.namespace(|| "hash"), &values)?;
+            Ok(MaybeHashed::new(values, hash))
+        })
+        .collect()
+}
+
+/// Enforces that the low `shard_bits` bits of `hash` are `shard`.
+fn assert_routed<E, CS>(
+    mut cs: CS,
+    hash: &AllocatedNum<E>,
+    shard: &AllocatedNum<E>,
+    shard_bits: usize,
+) -> CResult<()>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let bits = hash.into_bits_le_strict(cs.namespace(|| "bits"))?;
+    let mut packed = LinearCombination::zero();
+    let mut coeff = E::Fr::one();
+    for bit in &bits[..shard_bits] {
+        packed = packed + &bit.lc(CS::one(), coeff);
+        coeff.double();
+    }
+    cs.enforce(
+        || "routed",
+        |lc| lc,
+        |lc| lc,
+        |lc| lc + &packed - shard.get_variable(),
+    );
+    Ok(())
+}
+
+impl<E, Inner, H> Circuit<E> for ShardUpdate<H, Inner>
+where
+    E: Engine,
+    H: Hasher<F = E::Fr> + CircuitHasher<E = E>,
+    Inner: IntSet<G = RsaQuotientGroup>,
+{
+    fn synthesize<CS: ConstraintSystem<E>>(mut self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let limb_width = self.params.limb_width;
+        let n_limbs = self.params.n_bits_base / limb_width;
+        if let Some(inputs) = self.inputs.as_ref() {
+            if inputs.initial_top.limb_width != limb_width {
+                eprintln!(
+                    "The set has limb width {}, but the circuit {}",
+                    inputs.initial_top.limb_width, limb_width
+                );
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            if *inputs.initial_top.group() != self.params.group {
+                eprintln!("The set and the circuit have different groups");
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
+        let hasher = self.params.hasher.clone();
+        let mut removals = alloc_items(
+            cs.namespace(|| "removals"),
+            self.inputs.as_ref().map(|i| &i.to_remove),
+            self.params.n_removes,
+            self.params.item_size,
+            &hasher,
+        )?;
+        let mut insertions = alloc_items(
+            cs.namespace(|| "insertions"),
+            self.inputs.as_ref().map(|i| &i.to_insert),
+            self.params.n_inserts,
+            self.params.item_size,
+            &hasher,
+        )?;
+
+        let shard = AllocatedNum::alloc(cs.namespace(|| "shard"), || {
+            Ok(usize_to_f(self.inputs.grab()?.shard))
+        })?;
+        for (i, item) in removals.iter().chain(insertions.iter()).enumerate() {
+            assert_routed(
+                cs.namespace(|| format!("route {}", i)),
+                item.hash.as_ref().unwrap(),
+                &shard,
+                self.params.shard_bits,
+            )?;
+        }
+
+        let mut digests = Vec::new();
+        for (i, name) in STATES.iter().enumerate() {
+            let mut cs = cs.namespace(|| format!("{} digest", name));
+            let digest = BigNat::alloc_from_nat(
+                cs.namespace(|| "alloc"),
+                || {
+                    let inputs = self.inputs.grab_mut()?;
+                    Ok(match i {
+                        0 => inputs.initial_shard.digest(),
+                        1 => inputs.final_shard.digest(),
+                        2 => inputs.initial_top.digest(),
+                        _ => inputs.final_top.digest(),
+                    })
+                },
+                limb_width,
+                n_limbs,
+            )?;
+            digest.assert_well_formed(cs.namespace(|| "well formed"))?;
+            let (nums, _) = digest.as_allocated_nums(cs.namespace(|| "nums"))?;
+            digests.push((digest, nums));
+        }
+
+        let group = CircuitRsaQuotientGroup::alloc(
+            cs.namespace(|| "group"),
+            Some(&self.params.group),
+            (),
+            &CircuitRsaGroupParams { limb_width, n_limbs },
+        )?;
+        let group_hash = group.inputize_hash(cs.namespace(|| "group input"), &hasher)?;
+
+        // The challenge binds the whole statement: the shard entries are determined by the shard
+        // index and digests.
+        let challenge = {
+            let mut to_hash = vec![group_hash, shard.clone()];
+            for (_, nums) in &digests {
+                to_hash.extend(nums.iter().cloned());
+            }
+            to_hash.extend(insertions.iter().map(|i| i.hash.clone().unwrap()));
+            to_hash.extend(removals.iter().map(|i| i.hash.clone().unwrap()));
+            pocklington::hash_to_pocklington_prime(
+                cs.namespace(|| "chash"),
+                &to_hash,
+                limb_width,
+                self.params.n_bits_challenge,
+                &hasher,
+            )?
+        };
+
+        let set_params = CircuitSetParams {
+            hasher: hasher.clone(),
+            n_bits: self.params.n_bits_elem,
+            limb_width,
+        };
+        let mut sets: Vec<CircuitSet<E, H, CircuitRsaQuotientGroup<E>, Inner>> = Vec::new();
+        for (i, (name, (digest, _))) in STATES.iter().zip(&digests).enumerate() {
+            let mut cs = cs.namespace(|| format!("set {}", name));
+            let value = self.inputs.as_ref().map(|inputs| match i {
+                0 => &inputs.initial_shard,
+                1 => &inputs.final_shard,
+                2 => &inputs.initial_top,
+                _ => &inputs.final_top,
+            });
+            let set = CircuitSet::alloc(
+                cs.namespace(|| "alloc"),
+                value,
+                (group.clone(), challenge.clone()),
+                &set_params,
+            )?;
+            set.inner
+                .digest
+                .equal(cs.namespace(|| "digest matches"), digest)?;
+            sets.push(set);
+        }
+        let final_top = sets.pop().unwrap();
+        let initial_top = sets.pop().unwrap();
+        let final_shard = sets.pop().unwrap();
+        let initial_shard = sets.pop().unwrap();
+        initial_top.inputize_hash(cs.namespace(|| "initial top input"), &hasher)?;
+        final_top.inputize_hash(cs.namespace(|| "final top input"), &hasher)?;
+
+        initial_shard.verify_swap_all(
+            cs.namespace(|| "shard swap"),
+            removals,
+            insertions,
+            final_shard,
+        )?;
+
+        let entry = |digest: usize| {
+            let mut values = vec![shard.clone()];
+            values.extend(digests[digest].1.iter().cloned());
+            MaybeHashed::from_values(values)
+        };
+        let (old_entry, new_entry) = (entry(0), entry(1));
+        initial_top.verify_swap_all(
+            cs.namespace(|| "top swap"),
+            vec![old_entry],
+            vec![new_entry],
+            final_top,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+
+    use hash::division_intractable as di;
+    use hash::hashes::Poseidon;
+    use set::int_set::exp::serial::SerialExp;
+    use set::int_set::ExpSet;
+    use util::test_helpers::*;
+
+    const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+    type F = <Bn256 as ScalarEngine>::Fr;
+    type Inner = ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>;
+    type Sharded = TwoLevelSet<Poseidon<Bn256>, Inner>;
+
+    fn new_set(items: &Vec<Vec<F>>) -> Sharded {
+        TwoLevelSet::new_with(
+            RsaQuotientGroup::from_strs("2", RSA_512),
+            di::offset(128),
+            Poseidon::<Bn256>::default(),
+            128,
+            32,
+            1,
+            items,
+        )
+    }
+
+    /// Items of shard `shard`, which is 0 or 1.
+    fn items_in(set: &Sharded, shard: usize, n: usize) -> Vec<Vec<F>> {
+        (1..)
+            .map(|i| vec![usize_to_f(i)])
+            .filter(|item| set.shard_of(item) == shard)
+            .take(n)
+            .collect()
+    }
+
+    #[test]
+    fn updates_follow_the_top_level() {
+        let empty = new_set(&vec![]);
+        let items = items_in(&empty, 0, 2);
+        let other = items_in(&empty, 1, 1);
+        let mut set = new_set(&vec![items[0].clone(), other[0].clone()]);
+        let mut expected = new_set(&vec![items[0].clone(), items[1].clone(), other[0].clone()]);
+        set.insert(items[1].clone());
+        assert_eq!(set.digest(), expected.digest());
+        assert!(set.remove(&other[0]));
+        assert!(!set.remove(&other[0]));
+        assert_ne!(set.digest(), expected.digest());
+
+        assert_eq!(
+            set.update_shard(vec![items[0].clone()], other.clone()).err(),
+            Some(ShardError::MixedShards { first: 0, other: 1 })
+        );
+        assert_eq!(
+            set.update_shard(other.clone(), vec![]).err(),
+            Some(ShardError::NotPresent { index: 0 })
+        );
+        assert_eq!(set.update_shard(vec![], vec![]).err(), Some(ShardError::Empty));
+        let before = set.digest();
+        let update = set.update_shard(vec![], other.clone()).unwrap();
+        assert_eq!(update.shard, 1);
+        assert_eq!(update.initial_top.clone().digest(), before);
+        assert_eq!(set.digest(), expected.digest());
+    }
+
+    fn update_circuit(
+        remove: usize,
+        insert: usize,
+        shard: usize,
+    ) -> ShardUpdate<Poseidon<Bn256>, Inner> {
+        let empty = new_set(&vec![]);
+        let items = items_in(&empty, shard, 2);
+        let mut set = new_set(&items[..remove].to_vec());
+        let inputs = set
+            .update_shard(items[..remove].to_vec(), items[remove..remove + insert].to_vec())
+            .unwrap();
+        ShardUpdate {
+            inputs: Some(inputs),
+            params: ShardUpdateParams {
+                group: RsaQuotientGroup::from_strs("2", RSA_512),
+                limb_width: 32,
+                n_bits_base: 512,
+                n_bits_elem: 128,
+                n_bits_challenge: 128,
+                item_size: 1,
+                shard_bits: 1,
+                n_removes: remove,
+                n_inserts: insert,
+                hasher: Poseidon::default(),
+            },
+        }
+    }
+
+    circuit_tests! {
+        shard_update_swap: (update_circuit(1, 1, 0), true),
+        shard_update_insert_other_shard: (update_circuit(0, 1, 1), true),
+        shard_update_wrong_shard: ({
+            let mut c = update_circuit(1, 1, 0);
+            c.inputs.as_mut().unwrap().shard = 1;
+            c
+        }, false),
+    }
+}
@@ -0,0 +1,206 @@
+//! Bulk computation of membership witnesses for RSA sets.
+//!
+//! The membership witness for `x` in a set with digest `g^(prod S)` is `g^(prod S / x)`. Computing
+//! these one at a time costs O(n) exponentiations each, so we use the divide-and-conquer
+//! ("root factor") algorithm, which computes all witnesses for n elements using O(n log n)
+//! multiplications in the exponent.
+use rug::Integer;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use group::SemiGroup;
+use hash::Hasher;
+use set::int_set::IntSet;
+use set::rsa::Set;
+use util::verbose::in_verbose_mode;
+
+fn product(xs: &[Integer]) -> Integer {
+    use rayon::prelude::*;
+    xs.par_iter()
+        .fold(|| Integer::from(1), |acc, x| acc * x)
+        .reduce(|| Integer::from(1), |a, b| a * b)
+}
+
+fn root_factor<G, P>(
+    group: &G,
+    base: G::Elem,
+    elements: &[Integer],
+    done: &AtomicUsize,
+    progress: &P,
+    total: usize,
+) -> Vec<G::Elem>
+where
+    G: SemiGroup + Sync,
+    G::Elem: Send + Sync,
+    P: Fn(usize, usize) + Sync,
+{
+    if elements.len() <= 1 {
+        let n = done.fetch_add(elements.len(), Ordering::SeqCst) + elements.len();
+        progress(n, total);
+        return elements.iter().map(|_| base.clone()).collect();
+    }
+    let (left, right) = elements.split_at(elements.len() / 2);
+    let (mut ws, right_ws) = rayon::join(
+        || {
+            let left_base = group.power(&base, &product(right));
+            root_factor(group, left_base, left, done, progress, total)
+        },
+        || {
+            let right_base = group.power(&base, &product(left));
+            root_factor(group, right_base, right, done, progress, total)
+        },
+    );
+    ws.extend(right_ws);
+    ws
+}
+
+/// Computes the membership witness of each of `requested` in a set (with generator `g`)
+/// containing `others` and `requested`.
+///
+/// `progress` is called with (witnesses done, witnesses total) as witnesses are completed.
+pub fn membership_witnesses<G, P>(
+    group: &G,
+    others: &[Integer],
+    requested: &[Integer],
+    progress: P,
+) -> Vec<G::Elem>
+where
+    G: SemiGroup + Sync,
+    G::Elem: Send + Sync,
+    P: Fn(usize, usize) + Sync,
+{
+    if in_verbose_mode() {
+        println!("Folding {} untouched elements into the base", others.len());
+    }
+    let base = group.power(group.generator(), &product(others));
+    if in_verbose_mode() {
+        println!("Computing {} witnesses", requested.len());
+    }
+    root_factor(
+        group,
+        base,
+        requested,
+        &AtomicUsize::new(0),
+        &progress,
+        requested.len(),
+    )
+}
+
+/// Item `second` of a request for witnesses is the same item as item `first`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateItem {
+    pub first: usize,
+    pub second: usize,
+}
+
+impl fmt::Display for DuplicateItem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "item {} repeats item {}", self.second, self.first)
+    }
+}
+
+/// Computes membership witnesses for each of `items` in `set`.
+///
+/// Returns `None` for any item which is not present in the set, and an error if an item is
+/// requested twice.
+pub fn set_witnesses<H, Inner, P>(
+    set: &Set<H, Inner>,
+    items: &[Vec<H::F>],
+    progress: P,
+) -> Result<Vec<Option<<Inner::G as SemiGroup>::Elem>>, DuplicateItem>
+where
+    H: Hasher,
+    Inner: IntSet,
+    Inner::G: Sync,
+    <Inner::G as SemiGroup>::Elem: Send + Sync,
+    P: Fn(usize, usize) + Sync,
+{
+    let mut others = set.inner.elements();
+    others.sort();
+    // Pull one copy of each requested element out of the set.
+    let mut requested = Vec::new();
+    let mut positions = Vec::new();
+    let mut seen = BTreeMap::new();
+    for (second, item) in items.iter().enumerate() {
+        let x = set.hash_item(item);
+        if let Some(&first) = seen.get(&x) {
+            return Err(DuplicateItem { first, second });
+        }
+        seen.insert(x.clone(), second);
+        match others.binary_search(&x) {
+            Ok(i) => {
+                others.remove(i);
+                positions.push(Some(requested.len()));
+                requested.push(x);
+            }
+            Err(_) => positions.push(None),
+        }
+    }
+    let witnesses = membership_witnesses(set.group(), &others, &requested, progress);
+    Ok(positions
+        .into_iter()
+        .map(|p| p.map(|i| witnesses[i].clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sapling_crypto::bellman::pairing::ff::{PrimeField, ScalarEngine};
+
+    use group::RsaQuotientGroup;
+    use hash::division_intractable as di;
+    use hash::hashes::Poseidon;
+    use set::int_set::exp::serial::SerialExp;
+    use set::int_set::ExpSet;
+    use set::GenSet;
+    use util::test_helpers::*;
+
+    #[test]
+    fn witnesses_exponentiate_to_digest() {
+        let group = RsaQuotientGroup::from_strs("2", "143");
+        let others: Vec<Integer> = vec![Integer::from(3), Integer::from(5)];
+        let requested: Vec<Integer> = vec![7, 11, 13, 17, 19]
+            .into_iter()
+            .map(Integer::from)
+            .collect();
+        let all = others.iter().chain(&requested).cloned().collect::<Vec<_>>();
+        let digest = group.power(group.generator(), &product(&all));
+        let ws = membership_witnesses(&group, &others, &requested, |_, _| ());
+        assert_eq!(ws.len(), requested.len());
+        for (w, x) in ws.iter().zip(&requested) {
+            assert_eq!(group.power(w, x), digest);
+        }
+    }
+
+    #[test]
+    fn set_witnesses_reject_duplicates() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let item = |s: &str| vec![F::from_str(s).unwrap()];
+        let mut set = Set::<_, ExpSet<_, SerialExp<_>>>::new_with(
+            RsaQuotientGroup::from_strs("2", "143"),
+            di::offset(128),
+            Poseidon::<Bn256>::default(),
+            128,
+            32,
+            &vec![item("1"), item("2")],
+        );
+        let ws = set_witnesses(&set, &[item("2"), item("1"), item("3")], |_, _| ()).unwrap();
+        let digest = set.digest();
+        for (w, i) in ws.iter().zip(&["2", "1"]) {
+            let w = w.as_ref().unwrap();
+            assert_eq!(set.group().power(w, &set.hash_item(&item(*i))), digest);
+        }
+        assert!(ws[2].is_none());
+        assert_eq!(
+            set_witnesses(&set, &[item("1"), item("3"), item("1")], |_, _| ()).err(),
+            Some(DuplicateItem {
+                first: 0,
+                second: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Canonical test vectors for implementations of the RSA set outside this crate.
+//!
+//! Each vector inserts one item into a fixed set, and records the integer the item hashes to, the
+//! digests before and after the insertion, and the challenge prime for the insertion (as computed
+//! by `SetBench` with `absorb_group` off, and the same `certificate`). Vectors are written one per line as
+//! space-separated `key=value` pairs, with integers and field elements in decimal.
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::PrimeField;
+
+use group::RsaQuotientGroup;
+use hash::division_intractable as di;
+use hash::pocklington::CertificateKind;
+use hash::Hasher;
+use set::int_set::exp::serial::SerialExp;
+use set::int_set::{ExpSet, IntSet};
+use set::rsa::Set;
+use set::transcript::Transcript;
+use util::convert::{f_to_nat, nat_to_f, usize_to_f};
+
+use std::fmt;
+use std::str::FromStr;
+
+const RSA_512: &str = "11834783464130424096695514462778870280264989938857328737807205623069291535525952722847913694296392927890261736769191982212777933726583565708193466779811767";
+
+/// The parameters the vectors are generated for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorParams {
+    pub group: RsaQuotientGroup,
+    pub limb_width: usize,
+    pub n_bits_elem: usize,
+    pub n_bits_challenge: usize,
+    /// How the challenge prime is certified
+    pub certificate: CertificateKind,
+    /// The number of items in the set before the insertion
+    pub n_initial_items: usize,
+    /// The number of field elements in each item
+    pub item_len: usize,
+}
+
+impl VectorParams {
+    /// The parameters of the frozen vectors: a 512-bit group with generator 2, 32-bit limbs and
+    /// 128-bit Pocklington challenges.
+    pub fn standard() -> Self {
+        Self {
+            group: RsaQuotientGroup::from_strs("2", RSA_512),
+            limb_width: 32,
+            n_bits_elem: 512,
+            n_bits_challenge: 128,
+            certificate: CertificateKind::Pocklington,
+            n_initial_items: 3,
+            item_len: 2,
+        }
+    }
+
+    /// The `i`th item: `[i * item_len + 1, ..., (i + 1) * item_len]`.
+    fn item<F: PrimeField>(&self, i: usize) -> Vec<F> {
+        (0..self.item_len)
+            .map(|j| usize_to_f(i * self.item_len + j + 1))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    pub item: Vec<Integer>,
+    /// The integer the item hashes to
+    pub element: Integer,
+    pub initial_digest: Integer,
+    pub final_digest: Integer,
+    pub challenge: Integer,
+}
+
+impl fmt::Display for TestVector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let item: Vec<String> = self.item.iter().map(Integer::to_string).collect();
+        write!(
+            f,
+            "item={} element={} initial_digest={} final_digest={} challenge={}",
+            item.join(","),
+            self.element,
+            self.initial_digest,
+            self.final_digest,
+            self.challenge
+        )
+    }
+}
+
+impl FromStr for TestVector {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut fields = std::collections::HashMap::new();
+        for pair in s.split_whitespace() {
+            let mut kv = pair.splitn(2, '=');
+            let k = kv.next().unwrap();
+            let v = kv.next().ok_or_else(|| format!("Missing value for {}", k))?;
+            fields.insert(k, v);
+        }
+        let int = |k: &str| -> Result<Integer, String> {
+            let v = fields.get(k).ok_or_else(|| format!("Missing field {}", k))?;
+            Integer::from_str(v).map_err(|e| format!("Bad {}: {}", k, e))
+        };
+        let item = fields
+            .get("item")
+            .ok_or_else(|| "Missing field item".to_owned())?
+            .split(',')
+            .map(|v| Integer::from_str(v).map_err(|e| format!("Bad item: {}", e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            item,
+            element: int("element")?,
+            initial_digest: int("initial_digest")?,
+            final_digest: int("final_digest")?,
+            challenge: int("challenge")?,
+        })
+    }
+}
+
+/// Computes the vector for inserting `item` into the set of `initial_items`.
+pub fn generate<H: Hasher>(
+    params: &VectorParams,
+    hasher: &H,
+    initial_items: &Vec<Vec<H::F>>,
+    item: Vec<H::F>,
+) -> TestVector {
+    let mut set: Set<H, ExpSet<RsaQuotientGroup, SerialExp<RsaQuotientGroup>>> = Set::new_with(
+        params.group.clone(),
+        di::offset(params.n_bits_elem),
+        hasher.clone(),
+        params.n_bits_elem,
+        params.limb_width,
+        initial_items,
+    );
+    let element = set.hash_item(&item);
+    let initial_digest = set.inner.digest();
+    set.insert(item.clone());
+    let final_digest = set.inner.digest();
+
+    let n_limbs = (params.group.m.significant_bits() as usize - 1) / params.limb_width + 1;
+    let challenge = Transcript::new(hasher, params.limb_width, n_limbs)
+        .absorb_digest(&initial_digest)
+        .absorb_digest(&final_digest)
+        .absorb_items(&[item.clone()], false)
+        .challenge(params.n_bits_challenge, params.certificate)
+        .expect("challenge generation failed");
+
+    TestVector {
+        item: item.iter().map(f_to_nat).collect(),
+        element,
+        initial_digest,
+        final_digest,
+        challenge,
+    }
+}
+
+/// The vectors for `params`: each of the items following the initial ones, inserted into the set
+/// of initial items.
+pub fn vectors<H: Hasher>(params: &VectorParams, hasher: &H, n_vectors: usize) -> Vec<TestVector> {
+    let initial_items: Vec<Vec<H::F>> = (0..params.n_initial_items)
+        .map(|i| params.item(i))
+        .collect();
+    (0..n_vectors)
+        .map(|i| {
+            generate(
+                params,
+                hasher,
+                &initial_items,
+                params.item(params.n_initial_items + i),
+            )
+        })
+        .collect()
+}
+
+/// Checks that this crate reproduces `vector`, returning the first mismatched field.
+pub fn check<H: Hasher>(
+    params: &VectorParams,
+    hasher: &H,
+    vector: &TestVector,
+) -> Result<(), &'static str> {
+    let initial_items: Vec<Vec<H::F>> = (0..params.n_initial_items)
+        .map(|i| params.item(i))
+        .collect();
+    let item = vector
+        .item
+        .iter()
+        .map(nat_to_f)
+        .collect::<Option<Vec<H::F>>>()
+        .ok_or("item")?;
+    let actual = generate(params, hasher, &initial_items, item);
+    if actual.element != vector.element {
+        Err("element")
+    } else if actual.initial_digest != vector.initial_digest {
+        Err("initial_digest")
+    } else if actual.final_digest != vector.final_digest {
+        Err("final_digest")
+    } else if actual.challenge != vector.challenge {
+        Err("challenge")
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hashes::Poseidon;
+    use util::test_helpers::*;
+
+    use std::fs;
+
+    const FROZEN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testvectors/rsa_512_poseidon_bn256.txt"
+    );
+
+    #[test]
+    fn display_round_trip() {
+        let v = TestVector {
+            item: vec![Integer::from(1), Integer::from(2)],
+            element: Integer::from(3),
+            initial_digest: Integer::from(4),
+            final_digest: Integer::from(5),
+            challenge: Integer::from(7),
+        };
+        assert_eq!(TestVector::from_str(&v.to_string()), Ok(v));
+        assert!(TestVector::from_str("item=1 element=3").is_err());
+    }
+
+    #[test]
+    fn generated_vectors_check() {
+        let params = VectorParams::standard();
+        let hasher = Poseidon::<Bn256>::default();
+        let vs = vectors(&params, &hasher, 2);
+        assert_ne!(vs[0], vs[1]);
+        for v in &vs {
+            assert_eq!(check(&params, &hasher, v), Ok(()));
+        }
+        let mut tampered = vs[0].clone();
+        tampered.challenge += 2;
+        assert_eq!(check(&params, &hasher, &tampered), Err("challenge"));
+    }
+
+    #[test]
+    fn frozen_vectors() {
+        let contents = fs::read_to_string(FROZEN).unwrap_or_else(|e| {
+            panic!(
+                "No frozen vectors at {} ({}); run `write_vectors` to create them",
+                FROZEN, e
+            )
+        });
+        let params = VectorParams::standard();
+        let hasher = Poseidon::<Bn256>::default();
+        let lines: Vec<&str> = contents.lines().filter(|l| !l.is_empty()).collect();
+        assert!(!lines.is_empty(), "{} has no vectors", FROZEN);
+        for (i, line) in lines.into_iter().enumerate() {
+            let v = TestVector::from_str(line).unwrap();
+            if let Err(field) = check(&params, &hasher, &v) {
+                panic!("Vector {} has a different {}", i, field);
+            }
+        }
+    }
+
+    /// Regenerates the frozen vectors. Only run this when the hash or set construction changes
+    /// on purpose.
+    #[test]
+    #[ignore]
+    fn write_vectors() {
+        let params = VectorParams::standard();
+        let hasher = Poseidon::<Bn256>::default();
+        let lines: Vec<String> = vectors(&params, &hasher, 4)
+            .iter()
+            .map(TestVector::to_string)
+            .collect();
+        fs::create_dir_all(concat!(env!("CARGO_MANIFEST_DIR"), "/testvectors")).unwrap();
+        fs::write(FROZEN, lines.join("\n") + "\n").unwrap();
+    }
+}
@@ -3,12 +3,18 @@ pub use sapling_crypto::bellman::{
     ConstraintSystem, Index, LinearCombination, SynthesisError, Variable,
 };
 
+use sapling_crypto::bellman::Circuit;
+
 use std::io::Error;
 use std::io::Write;
+use std::mem::size_of;
 use std::time::{Duration, Instant};
 
+/// Counts the variables and constraints of a circuit, without computing any witness.
 pub struct ConstraintCounter {
     n_constraints: usize,
+    n_inputs: usize,
+    n_aux: usize,
 }
 
 impl ConstraintCounter {
@@ -16,7 +22,21 @@ impl ConstraintCounter {
         self.n_constraints
     }
     pub fn new() -> Self {
-        Self { n_constraints: 0 }
+        Self {
+            n_constraints: 0,
+            // The constant `ONE` is input 0.
+            n_inputs: 1,
+            n_aux: 0,
+        }
+    }
+
+    /// The size of the circuit counted so far.
+    pub fn report(&self) -> MemoryReport {
+        MemoryReport {
+            n_inputs: self.n_inputs,
+            n_aux: self.n_aux,
+            n_constraints: self.n_constraints,
+        }
     }
 }
 
@@ -28,7 +48,8 @@ impl<E: Engine> ConstraintSystem<E> for ConstraintCounter {
         A: FnOnce() -> AR,
         AR: Into<String>,
     {
-        Ok(Variable::new_unchecked(Index::Aux(0)))
+        self.n_aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.n_aux - 1)))
     }
     fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
     where
@@ -36,7 +57,8 @@ impl<E: Engine> ConstraintSystem<E> for ConstraintCounter {
         A: FnOnce() -> AR,
         AR: Into<String>,
     {
-        Ok(Variable::new_unchecked(Index::Input(0)))
+        self.n_inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.n_inputs - 1)))
     }
 
     fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, _a: LA, _b: LB, _c: LC)
@@ -61,6 +83,44 @@ impl<E: Engine> ConstraintSystem<E> for ConstraintCounter {
     }
 }
 
+/// The size of a circuit, and an estimate of the memory needed to prove it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub n_inputs: usize,
+    pub n_aux: usize,
+    pub n_constraints: usize,
+}
+
+impl MemoryReport {
+    /// The size of the evaluation domain used by groth16.
+    pub fn domain_size(&self) -> usize {
+        (self.n_constraints + self.n_inputs).next_power_of_two()
+    }
+
+    /// An estimate of the peak memory of a groth16 prover, in bytes: the proving key, the
+    /// evaluations of the three QAP polynomials over the domain, and the assignment.
+    pub fn estimated_prover_bytes<E: Engine>(&self) -> usize {
+        let n_vars = self.n_inputs + self.n_aux;
+        let domain = self.domain_size();
+        let g1 = size_of::<E::G1Affine>();
+        let g2 = size_of::<E::G2Affine>();
+        let fr = size_of::<E::Fr>();
+        // The A and B_G1 queries span all variables, L the auxiliary ones, and H the domain.
+        let params = g1 * (2 * n_vars + self.n_aux + domain) + g2 * n_vars;
+        params + 3 * domain * fr + n_vars * fr
+    }
+}
+
+/// Synthesizes the shape of `circuit` to measure it. Run this with an input-free circuit before
+/// a long setup or proof, to check that the prover fits in memory.
+pub fn profile_memory<E: Engine, C: Circuit<E>>(
+    circuit: C,
+) -> Result<MemoryReport, SynthesisError> {
+    let mut counter = ConstraintCounter::new();
+    circuit.synthesize(&mut counter)?;
+    Ok(counter.report())
+}
+
 mod interner {
     use fnv::FnvHashMap;
     use std::collections::HashMap;
@@ -0,0 +1,65 @@
+//! Cooperative cancellation of long-running synthesis and witness preparation.
+//!
+//! A `CancelToken` is shared between the job and whoever may abort it: a deadline, or a service
+//! which has received a newer batch. The job checks the token between phases, and bails out with
+//! `SynthesisError::IoError` of kind `Interrupted`, which drops everything allocated so far. A
+//! phase is not interrupted once it has started, so the latency of a cancellation is that of the
+//! longest phase: for `SetBench`, checking the proofs of exponentiation.
+use sapling_crypto::bellman::SynthesisError;
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A handle through which a job can be cancelled. Clones share the same flag.
+///
+/// The default token is never cancelled unless `cancel` is called.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token which is also cancelled once `deadline` passes.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            flag: Arc::new(AtomicBool::new(false)),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Cancels the job, and every other holder of a clone of this token.
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) || self.deadline.map_or(false, |d| Instant::now() >= d)
+    }
+
+    /// Fails with an interruption error if the job has been cancelled.
+    pub fn check(&self) -> Result<(), SynthesisError> {
+        if self.is_cancelled() {
+            Err(SynthesisError::IoError(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "cancelled",
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Whether `e` is the error returned by `CancelToken::check`.
+pub fn is_cancellation(e: &SynthesisError) -> bool {
+    match e {
+        SynthesisError::IoError(e) => e.kind() == io::ErrorKind::Interrupted,
+        _ => false,
+    }
+}
@@ -1,5 +1,5 @@
 use rug::{integer::Order, Integer};
-use sapling_crypto::bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, PrimeFieldRepr};
 
 /// Convert a field element to a natural number
 pub fn f_to_nat<F: PrimeField>(f: &F) -> Integer {
@@ -20,6 +20,22 @@ pub fn usize_to_f<F: PrimeField>(n: usize) -> F {
     F::from_repr(<F::Repr as From<u64>>::from(n as u64)).expect("decoding problem")
 }
 
+/// Packs consecutive runs of `limbs_per_input` limbs (low limb first) of `limb_width` bits each
+/// into single field elements.
+pub fn pack_limbs<F: PrimeField>(limbs: &[F], limb_width: usize, limbs_per_input: usize) -> Vec<F> {
+    let base = usize_to_f::<F>(2).pow(&[limb_width as u64]);
+    limbs
+        .chunks(limbs_per_input)
+        .map(|chunk| {
+            chunk.iter().rev().fold(F::zero(), |mut acc, l| {
+                acc.mul_assign(&base);
+                acc.add_assign(l);
+                acc
+            })
+        })
+        .collect()
+}
+
 /// Convert a `usize` to a field element.
 /// Panics if the field is too small.
 pub fn f_to_usize<F: PrimeField>(n: &F) -> usize {
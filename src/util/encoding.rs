@@ -0,0 +1,269 @@
+//! Conventions for laying out integers as limbs, bits and bytes.
+//!
+//! An `Encoding` fixes the order of the limbs of an integer, the order of the bits inside each
+//! limb, and the order of the bytes when limbs (or field elements) are serialized. The crate's
+//! own convention is `Encoding::NATIVE`: least significant limb first, bits in their natural
+//! order, little-endian bytes. `nat_to_limbs` and `BigNat::inputize` lay limbs out this way;
+//! `nat_to_limbs_with` and `BigNat::inputize_encoded` take any `Encoding`. The FFI and the Python
+//! bindings take none: they pass integers as decimal strings.
+//!
+//! `Encoding::EVM` is the convention of EVM contracts: most significant limb first and big-endian
+//! bytes. `encode_nat` with 256-bit limbs lays a 2048-bit digest out as `bytes32[8]`, as
+//! `rollup::header::digest_to_blob` does. Limbs which become field elements must fit in the
+//! field, so `nat_to_limbs_with` and `inputize_encoded` reject 256-bit limbs: EVM public inputs
+//! use narrower limbs, e.g. 128 bits.
+use rug::integer::Order;
+use rug::Integer;
+use sapling_crypto::bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
+use sapling_crypto::bellman::SynthesisError;
+
+use std::io::{self, Read, Write};
+
+/// The order of the limbs of an integer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimbOrder {
+    LeastSignificantFirst,
+    MostSignificantFirst,
+}
+
+/// The order of the bits inside a limb. With `MsbFirst`, a limb's value is its bits reversed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    LsbFirst,
+    MsbFirst,
+}
+
+/// The order in which the bytes of integers and field elements are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Most significant byte first, as EVM contracts expect
+    BigEndian,
+    LittleEndian,
+}
+
+/// A layout of integers. The limb and bit orders fix the limbs, as bytes or as field elements;
+/// the byte order only matters when they are serialized, by `encode_nat` and `decode_nat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encoding {
+    pub limb_order: LimbOrder,
+    pub bit_order: BitOrder,
+    pub byte_order: ByteOrder,
+}
+
+impl Encoding {
+    /// The crate's convention.
+    pub const NATIVE: Encoding = Encoding {
+        limb_order: LimbOrder::LeastSignificantFirst,
+        bit_order: BitOrder::LsbFirst,
+        byte_order: ByteOrder::LittleEndian,
+    };
+
+    /// The convention of EVM contracts.
+    pub const EVM: Encoding = Encoding {
+        limb_order: LimbOrder::MostSignificantFirst,
+        bit_order: BitOrder::LsbFirst,
+        byte_order: ByteOrder::BigEndian,
+    };
+
+    /// The value of a `limb_width`-bit limb under this bit order. This is an involution.
+    pub fn encode_limb(&self, limb: &Integer, limb_width: usize) -> Integer {
+        match self.bit_order {
+            BitOrder::LsbFirst => limb.clone(),
+            BitOrder::MsbFirst => {
+                let mut r = Integer::from(0);
+                for i in 0..limb_width {
+                    if limb.get_bit(i as u32) {
+                        r.set_bit((limb_width - 1 - i) as u32, true);
+                    }
+                }
+                r
+            }
+        }
+    }
+
+    /// Splits `nat` into `n_limbs` encoded limbs of `limb_width` bits, in limb order.
+    pub fn split_nat(
+        &self,
+        nat: &Integer,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Vec<Integer>, SynthesisError> {
+        if *nat < 0 || nat.significant_bits() as usize > limb_width * n_limbs {
+            eprintln!(
+                "nat {} does not fit in {} limbs of width {}",
+                nat, n_limbs, limb_width
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let mut limbs: Vec<Integer> = (0..n_limbs)
+            .map(|i| {
+                let mut limb = Integer::from(nat >> (i * limb_width) as u32);
+                limb.keep_bits_mut(limb_width as u32);
+                self.encode_limb(&limb, limb_width)
+            })
+            .collect();
+        if self.limb_order == LimbOrder::MostSignificantFirst {
+            limbs.reverse();
+        }
+        Ok(limbs)
+    }
+
+    /// Joins encoded limbs, in limb order, into the integer they represent.
+    pub fn join_nat(&self, limbs: &[Integer], limb_width: usize) -> Integer {
+        let decode = |acc: Integer, limb: &Integer| {
+            (acc << limb_width as u32) + self.encode_limb(limb, limb_width)
+        };
+        match self.limb_order {
+            LimbOrder::LeastSignificantFirst => limbs.iter().rev().fold(Integer::from(0), decode),
+            LimbOrder::MostSignificantFirst => limbs.iter().fold(Integer::from(0), decode),
+        }
+    }
+
+    /// Serializes `nat` as `n_limbs` limbs, each of `ceil(limb_width / 8)` bytes.
+    pub fn encode_nat(
+        &self,
+        nat: &Integer,
+        limb_width: usize,
+        n_limbs: usize,
+    ) -> Result<Vec<u8>, SynthesisError> {
+        let limb_bytes = (limb_width + 7) / 8;
+        let mut out = Vec::with_capacity(limb_bytes * n_limbs);
+        for limb in self.split_nat(nat, limb_width, n_limbs)? {
+            let mut bytes = limb.to_digits::<u8>(Order::Lsf);
+            bytes.resize(limb_bytes, 0);
+            if self.byte_order == ByteOrder::BigEndian {
+                bytes.reverse();
+            }
+            out.extend(bytes);
+        }
+        Ok(out)
+    }
+
+    /// Parses the output of `encode_nat`.
+    pub fn decode_nat(&self, bytes: &[u8], limb_width: usize) -> Result<Integer, SynthesisError> {
+        let limb_bytes = (limb_width + 7) / 8;
+        if bytes.len() % limb_bytes != 0 {
+            eprintln!(
+                "{} bytes is not a whole number of {}-byte limbs",
+                bytes.len(),
+                limb_bytes
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let order = match self.byte_order {
+            ByteOrder::BigEndian => Order::Msf,
+            ByteOrder::LittleEndian => Order::Lsf,
+        };
+        let limbs = bytes
+            .chunks(limb_bytes)
+            .map(|c| Integer::from_digits(c, order))
+            .collect::<Vec<_>>();
+        if limbs.iter().any(|l| l.significant_bits() as usize > limb_width) {
+            eprintln!("A limb has more than {} bits", limb_width);
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        Ok(self.join_nat(&limbs, limb_width))
+    }
+}
+
+impl ByteOrder {
+    pub fn write_f<F: PrimeField, W: Write>(&self, f: &F, w: W) -> io::Result<()> {
+        match self {
+            ByteOrder::BigEndian => f.into_repr().write_be(w),
+            ByteOrder::LittleEndian => f.into_repr().write_le(w),
+        }
+    }
+
+    pub fn read_f<F: PrimeField, R: Read>(&self, r: R) -> io::Result<F> {
+        let mut repr = F::Repr::default();
+        match self {
+            ByteOrder::BigEndian => repr.read_be(r)?,
+            ByteOrder::LittleEndian => repr.read_le(r)?,
+        }
+        F::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes `f` as `0x`-prefixed hex.
+    pub fn f_to_hex<F: PrimeField>(&self, f: &F) -> String {
+        let mut bytes = Vec::new();
+        self.write_f(f, &mut bytes)
+            .expect("writing to a vector cannot fail");
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    pub fn f_from_hex<F: PrimeField>(&self, s: &str) -> Result<F, String> {
+        let digits = s.trim_start_matches("0x");
+        let bytes = hex::decode(digits).map_err(|e| format!("Bad hex {:?}: {}", s, e))?;
+        if bytes.len() != F::Repr::default().as_ref().len() * 8 {
+            return Err(format!("{:?} has {} bytes", s, bytes.len()));
+        }
+        self.read_f(&bytes[..])
+            .map_err(|e| format!("{:?} is not a field element: {}", s, e))
+    }
+
+    pub fn write_u64<W: Write>(&self, n: u64, mut w: W) -> io::Result<()> {
+        match self {
+            ByteOrder::BigEndian => w.write_all(&n.to_be_bytes()),
+            ByteOrder::LittleEndian => w.write_all(&n.to_le_bytes()),
+        }
+    }
+
+    pub fn read_u64<R: Read>(&self, mut r: R) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        r.read_exact(&mut bytes)?;
+        Ok(match self {
+            ByteOrder::BigEndian => u64::from_be_bytes(bytes),
+            ByteOrder::LittleEndian => u64::from_le_bytes(bytes),
+        })
+    }
+
+    pub fn write_u32<W: Write>(&self, n: u32, mut w: W) -> io::Result<()> {
+        match self {
+            ByteOrder::BigEndian => w.write_all(&n.to_be_bytes()),
+            ByteOrder::LittleEndian => w.write_all(&n.to_le_bytes()),
+        }
+    }
+
+    pub fn read_u32<R: Read>(&self, mut r: R) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        r.read_exact(&mut bytes)?;
+        Ok(match self {
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rollup::header::digest_to_blob;
+
+    #[test]
+    fn encodings_round_trip() {
+        let nat = Integer::from_str_radix("123456789abcdef0fedcba987654321", 16).unwrap();
+        let msb_bits = Encoding {
+            bit_order: BitOrder::MsbFirst,
+            ..Encoding::NATIVE
+        };
+        for encoding in &[Encoding::NATIVE, Encoding::EVM, msb_bits] {
+            let limbs = encoding.split_nat(&nat, 20, 7).unwrap();
+            assert_eq!(encoding.join_nat(&limbs, 20), nat);
+            let bytes = encoding.encode_nat(&nat, 20, 7).unwrap();
+            assert_eq!(bytes.len(), 21);
+            assert_eq!(encoding.decode_nat(&bytes, 20).unwrap(), nat);
+        }
+        assert!(Encoding::NATIVE.split_nat(&nat, 20, 6).is_err());
+        assert_eq!(
+            msb_bits.encode_limb(&Integer::from(0b0011), 4),
+            Integer::from(0b1100)
+        );
+    }
+
+    #[test]
+    fn evm_encoding_matches_digest_blob() {
+        let digest = (Integer::from(5) << 2000) + 17;
+        let bytes = Encoding::EVM.encode_nat(&digest, 256, 8).unwrap();
+        assert_eq!(&bytes[..], &digest_to_blob(&digest).unwrap()[..]);
+    }
+}
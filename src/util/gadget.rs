@@ -1,13 +1,42 @@
-use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, ScalarEngine};
 use sapling_crypto::bellman::pairing::Engine;
 use sapling_crypto::bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use sapling_crypto::circuit::boolean::Boolean;
 use sapling_crypto::circuit::num::AllocatedNum;
 
 use hash::circuit::CircuitHasher;
 
+use std::cmp::max;
+
 use super::bit::Bit;
+use super::convert::{pack_limbs, usize_to_f};
+use super::num::Num;
 use OptionExt;
 
+/// How the wires of a gadget are exposed as public inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputPacking {
+    /// One input per wire.
+    Unpacked,
+    /// One input per pair of wires, each of which is a limb of `limb_width` bits. Halves the
+    /// number of inputs, for a range check on each wire.
+    Dual { limb_width: usize },
+    /// As many wires per input as the field's capacity allows, each a limb of `limb_width` bits.
+    /// With 32-bit limbs in a 253-bit field, a 2048-bit number takes ten inputs rather than 64.
+    Max { limb_width: usize },
+}
+
+impl InputPacking {
+    /// How many wires share one input, in the field `F`.
+    pub fn wires_per_input<F: PrimeField>(&self) -> usize {
+        match *self {
+            InputPacking::Unpacked => 1,
+            InputPacking::Dual { .. } => 2,
+            InputPacking::Max { limb_width } => max(1, F::CAPACITY as usize / limb_width),
+        }
+    }
+}
+
 pub trait Gadget: Sized + Clone {
     type E: Engine;
     type Value: Clone;
@@ -34,6 +63,60 @@ pub trait Gadget: Sized + Clone {
         Ok(())
     }
 
+    fn inputize_with<CS: ConstraintSystem<Self::E>>(
+        &self,
+        cs: CS,
+        packing: InputPacking,
+    ) -> Result<(), SynthesisError> {
+        let wires_per_input = packing.wires_per_input::<<Self::E as ScalarEngine>::Fr>();
+        match packing {
+            InputPacking::Unpacked => self.inputize(cs),
+            InputPacking::Dual { limb_width } | InputPacking::Max { limb_width } => {
+                self.inputize_packed(cs, limb_width, wires_per_input)
+            }
+        }
+    }
+
+    /// Exposes the wires, which must be `limb_width`-bit limbs, `limbs_per_input` at a time, as
+    /// `pack_limbs` would.
+    ///
+    /// Each wire is range-checked, so that the packing is injective.
+    fn inputize_packed<CS: ConstraintSystem<Self::E>>(
+        &self,
+        mut cs: CS,
+        limb_width: usize,
+        limbs_per_input: usize,
+    ) -> Result<(), SynthesisError> {
+        let capacity = <<Self::E as ScalarEngine>::Fr as PrimeField>::CAPACITY as usize;
+        if limbs_per_input * limb_width > capacity {
+            eprintln!(
+                "Cannot pack {} limbs of {} bits into one input",
+                limbs_per_input, limb_width
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let values = self.wire_values();
+        let packed_values = values
+            .as_ref()
+            .map(|vs| pack_limbs(vs, limb_width, limbs_per_input));
+        let base = usize_to_f::<<Self::E as ScalarEngine>::Fr>(2).pow(&[limb_width as u64]);
+        for (i, chunk) in self.wires().chunks(limbs_per_input).enumerate() {
+            let mut cs = cs.namespace(|| format!("{}", i));
+            let mut packed = LinearCombination::zero();
+            let mut shift = <Self::E as ScalarEngine>::Fr::one();
+            for (j, w) in chunk.iter().enumerate() {
+                let value = values.as_ref().map(|vs| vs[i * limbs_per_input + j]);
+                Num::new(value, w.clone())
+                    .fits_in_bits(cs.namespace(|| format!("range {}", j)), limb_width)?;
+                packed = packed + (shift, w);
+                shift.mul_assign(&base);
+            }
+            let in_ = cs.alloc_input(|| "in", || Ok(packed_values.as_ref().grab()?[i]))?;
+            cs.enforce(|| "eq", |lc| lc, |lc| lc, |lc| lc + in_ - &packed);
+        }
+        Ok(())
+    }
+
     fn inputize_hash<CS: ConstraintSystem<Self::E>, H: CircuitHasher<E = Self::E>>(
         &self,
         mut cs: CS,
@@ -171,4 +254,30 @@ pub trait Gadget: Sized + Clone {
         }
         Ok(())
     }
+
+    /// Enforces that some wire of `i0` differs from the corresponding wire of `i1`.
+    ///
+    /// This is only a value inequality if wires are a canonical representation of the value, so
+    /// gadgets whose wires are not (such as `BigNat`, whose limbs may be uncarried) override it.
+    fn assert_not_equal<CS: ConstraintSystem<Self::E>>(
+        mut cs: CS,
+        i0: &Self,
+        i1: &Self,
+    ) -> Result<(), SynthesisError> {
+        let i0_nums = i0.as_nums(cs.namespace(|| "i0 nums"))?;
+        let i1_nums = i1.as_nums(cs.namespace(|| "i1 nums"))?;
+        if i0_nums.len() != i1_nums.len() {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let mut all_equal = Boolean::constant(true);
+        for (i, (a, b)) in i0_nums.iter().zip(&i1_nums).enumerate() {
+            let eq = AllocatedNum::equals(cs.namespace(|| format!("eq {}", i)), a, b)?;
+            all_equal = Boolean::and(cs.namespace(|| format!("and {}", i)), &all_equal, &eq)?;
+        }
+        Boolean::enforce_equal(
+            cs.namespace(|| "not all equal"),
+            &all_equal,
+            &Boolean::constant(false),
+        )
+    }
 }
@@ -1,6 +1,8 @@
 pub mod bench;
 pub mod bit;
+pub mod cancel;
 pub mod convert;
+pub mod encoding;
 pub mod gadget;
 pub mod lazy;
 pub mod num;
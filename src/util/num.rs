@@ -44,6 +44,9 @@ impl<E: Engine> Num<E> {
         })
     }
 
+    /// Enforces that the value is less than `2^n_bits`, in `n_bits` constraints and `n_bits - 1`
+    /// variables: bits `1` through `n_bits - 1` are allocated, and the low bit is implied, by
+    /// requiring what the other bits leave of the value to be boolean.
     pub fn fits_in_bits<CS: ConstraintSystem<E>>(
         &self,
         mut cs: CS,
@@ -216,6 +219,32 @@ pub mod allocated_num {
         );
         Ok(res)
     }
+
+    /// Enforces that `xs` and `ys` are equal, element-wise, under one namespace, in one linear
+    /// constraint per element.
+    pub fn enforce_all_equal<E: Engine, CS: ConstraintSystem<E>>(
+        mut cs: CS,
+        xs: &[AllocatedNum<E>],
+        ys: &[AllocatedNum<E>],
+    ) -> Result<(), SynthesisError> {
+        if xs.len() != ys.len() {
+            eprintln!(
+                "enforce_all_equal: {} elements against {}",
+                xs.len(),
+                ys.len()
+            );
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        for (i, (x, y)) in xs.iter().zip(ys).enumerate() {
+            cs.enforce(
+                || format!("equal {}", i),
+                |lc| lc,
+                |lc| lc,
+                |lc| lc + x.get_variable() - y.get_variable(),
+            );
+        }
+        Ok(())
+    }
 }
 
 impl<E: Engine> From<AllocatedNum<E>> for Num<E> {
@@ -223,3 +252,53 @@ impl<E: Engine> From<AllocatedNum<E>> for Num<E> {
         Self::new(a.get_value(), LinearCombination::zero() + a.get_variable())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::allocated_num::enforce_all_equal;
+    use super::*;
+    use util::convert::usize_to_f;
+    use util::test_helpers::*;
+
+    pub struct AllEqual<'a> {
+        pub xs: &'a [usize],
+        pub ys: &'a [usize],
+    }
+
+    impl<'a> Circuit<Bn256> for AllEqual<'a> {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let mut alloc = |name: &str, vs: &[usize]| {
+                vs.iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        AllocatedNum::alloc(cs.namespace(|| format!("{} {}", name, i)), || {
+                            Ok(usize_to_f(*v))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            let xs = alloc("x", self.xs)?;
+            let ys = alloc("y", self.ys)?;
+            enforce_all_equal(cs.namespace(|| "equal"), &xs, &ys)
+        }
+    }
+
+    #[test]
+    fn fits_in_bits_cost() {
+        let mut cs = TestConstraintSystem::<Bn256>::new();
+        let x = Num::alloc(cs.namespace(|| "x"), || Ok(usize_to_f(0xdead_beef))).unwrap();
+        let before = cs.num_constraints();
+        x.fits_in_bits(cs.namespace(|| "range"), 32).unwrap();
+        assert_eq!(cs.num_constraints() - before, 32);
+        assert!(cs.is_satisfied());
+    }
+
+    circuit_tests! {
+        all_equal: (AllEqual { xs: &[1, 2, 3], ys: &[1, 2, 3] }, true),
+        all_equal_mismatch: (AllEqual { xs: &[1, 2, 3], ys: &[1, 4, 3] }, false),
+        all_equal_first: (AllEqual { xs: &[7, 2], ys: &[1, 2] }, false),
+    }
+}
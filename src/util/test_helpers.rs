@@ -4,6 +4,130 @@ pub use sapling_crypto::bellman::pairing::ff::PrimeField;
 pub use sapling_crypto::bellman::Circuit;
 pub use sapling_crypto::circuit::test::TestConstraintSystem;
 
+use sapling_crypto::bellman::pairing::Engine;
+use sapling_crypto::bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+
+/// One side of a constraint, as (variable, coefficient) pairs.
+type Terms<E> = Vec<(Index, <E as sapling_crypto::bellman::pairing::ff::ScalarEngine>::Fr)>;
+
+/// A constraint system which records the structure of each constraint, but no witness values.
+pub struct ShapeRecorder<E: Engine> {
+    n_inputs: usize,
+    n_aux: usize,
+    namespace: Vec<String>,
+    constraints: Vec<(String, Terms<E>, Terms<E>, Terms<E>)>,
+}
+
+impl<E: Engine> ShapeRecorder<E> {
+    pub fn new() -> Self {
+        Self {
+            // The constant `ONE` is input 0.
+            n_inputs: 1,
+            n_aux: 0,
+            namespace: Vec::new(),
+            constraints: Vec::new(),
+        }
+    }
+
+    fn terms(lc: LinearCombination<E>) -> Terms<E> {
+        lc.as_ref()
+            .iter()
+            .map(|(v, c)| (v.get_unchecked(), *c))
+            .collect()
+    }
+
+    /// Describes the first difference between the shapes of `self` and `other`, if any.
+    pub fn first_difference(&self, other: &Self) -> Option<String> {
+        if self.n_inputs != other.n_inputs {
+            return Some(format!(
+                "{} inputs vs {} inputs",
+                self.n_inputs, other.n_inputs
+            ));
+        }
+        if self.n_aux != other.n_aux {
+            return Some(format!("{} aux vs {} aux", self.n_aux, other.n_aux));
+        }
+        for (a, b) in self.constraints.iter().zip(&other.constraints) {
+            if a != b {
+                return Some(format!("constraint {} vs constraint {}", a.0, b.0));
+            }
+        }
+        if self.constraints.len() != other.constraints.len() {
+            return Some(format!(
+                "{} constraints vs {} constraints",
+                self.constraints.len(),
+                other.constraints.len()
+            ));
+        }
+        None
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for ShapeRecorder<E> {
+    type Root = Self;
+    fn alloc<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.n_aux += 1;
+        Ok(Variable::new_unchecked(Index::Aux(self.n_aux - 1)))
+    }
+    fn alloc_input<F, A, AR>(&mut self, _annotation: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.n_inputs += 1;
+        Ok(Variable::new_unchecked(Index::Input(self.n_inputs - 1)))
+    }
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        let mut path = self.namespace.clone();
+        path.push(annotation().into());
+        self.constraints.push((
+            path.join("/"),
+            Self::terms(a(LinearCombination::zero())),
+            Self::terms(b(LinearCombination::zero())),
+            Self::terms(c(LinearCombination::zero())),
+        ));
+    }
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.namespace.push(name_fn().into());
+    }
+    fn pop_namespace(&mut self) {
+        self.namespace.pop();
+    }
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Synthesizes `a` and `b`, which should be the same circuit with different witnesses, and
+/// panics if the resulting constraint systems differ in shape. A difference means that the
+/// structure of the circuit depends on the witness, which breaks the proving key.
+pub fn assert_same_shape<E: Engine, A: Circuit<E>, B: Circuit<E>>(a: A, b: B) {
+    let mut cs_a = ShapeRecorder::<E>::new();
+    a.synthesize(&mut cs_a).expect("synthesis failed");
+    let mut cs_b = ShapeRecorder::<E>::new();
+    b.synthesize(&mut cs_b).expect("synthesis failed");
+    if let Some(diff) = cs_a.first_difference(&cs_b) {
+        panic!("Circuit shape depends on the witness: {}", diff);
+    }
+}
+
 macro_rules! circuit_tests {
     ($($name:ident: $value:expr,)*) => {
         $(
@@ -28,6 +152,18 @@ macro_rules! circuit_tests {
     }
 }
 
+macro_rules! shape_tests {
+    ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (a, b) = $value;
+                assert_same_shape::<Bn256, _, _>(a, b);
+            }
+        )*
+    }
+}
+
 macro_rules! circuit_benches {
     ($($name:ident: $value:expr,)*) => {
         $(
@@ -0,0 +1,461 @@
+//! Verification of a chain of batch proofs, submitted by (possibly competing) operators.
+//!
+//! Each batch proves a transition from one state commitment to the next. The `Verifier` keeps
+//! the canonical chain of commitments, starting at a genesis commitment, and extends it with the
+//! first valid batch which builds on its tip.
+use rand::Rng;
+use sapling_crypto::bellman::groth16::{
+    prepare_verifying_key, verify_proof, PreparedVerifyingKey, Proof, VerifyingKey,
+};
+use sapling_crypto::bellman::pairing::ff::{Field, PrimeField, PrimeFieldRepr};
+use sapling_crypto::bellman::pairing::{CurveAffine, CurveProjective, Engine};
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+fn write_f<F: PrimeField, W: Write>(f: &F, w: W) -> io::Result<()> {
+    f.into_repr().write_le(w)
+}
+
+fn read_f<F: PrimeField, R: Read>(r: R) -> io::Result<F> {
+    let mut repr = F::Repr::default();
+    repr.read_le(r)?;
+    F::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A proof that the state moved from commitment `prev` to commitment `next`.
+#[derive(Clone)]
+pub struct Batch<E: Engine> {
+    pub prev: E::Fr,
+    pub next: E::Fr,
+    pub proof: Proof<E>,
+}
+
+impl<E: Engine> Batch<E> {
+    /// Writes the batch as the little-endian representations of `prev` and `next`, followed by
+    /// the proof.
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write_f(&self.prev, &mut w)?;
+        write_f(&self.next, &mut w)?;
+        self.proof.write(&mut w)
+    }
+
+    pub fn read<R: Read>(mut r: R) -> io::Result<Self> {
+        let prev = read_f(&mut r)?;
+        let next = read_f(&mut r)?;
+        let proof = Proof::read(&mut r)?;
+        Ok(Self { prev, next, proof })
+    }
+}
+
+/// How to check the proofs of queued batches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// One pairing check per proof.
+    Individual,
+    /// A single randomized pairing check for a whole chain of proofs, falling back to individual
+    /// checks if it fails.
+    Batched,
+}
+
+/// The result of a round of verification.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Batches appended to the chain
+    pub accepted: usize,
+    /// Batches with invalid proofs
+    pub invalid: usize,
+    /// Batches which build on a commitment that has since been superseded
+    pub stale: usize,
+}
+
+pub struct Verifier<E: Engine> {
+    vk: VerifyingKey<E>,
+    pvk: PreparedVerifyingKey<E>,
+    /// Public inputs preceding `prev` and `next` (e.g. the hash of the group)
+    fixed_inputs: Vec<E::Fr>,
+    chain: Vec<E::Fr>,
+    queue: VecDeque<Batch<E>>,
+}
+
+impl<E: Engine> Verifier<E> {
+    /// Creates a verifier for proofs whose public inputs are `fixed_inputs`, followed by the
+    /// previous and next state commitments.
+    pub fn new(vk: VerifyingKey<E>, fixed_inputs: Vec<E::Fr>, genesis: E::Fr) -> Self {
+        Self {
+            pvk: prepare_verifying_key(&vk),
+            vk,
+            fixed_inputs,
+            chain: vec![genesis],
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// The latest verified state commitment.
+    pub fn latest(&self) -> E::Fr {
+        *self.chain.last().unwrap()
+    }
+
+    /// All verified state commitments, starting with the genesis.
+    pub fn chain(&self) -> &[E::Fr] {
+        &self.chain
+    }
+
+    pub fn n_queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Queues a batch for verification.
+    pub fn submit(&mut self, batch: Batch<E>) {
+        self.queue.push_back(batch);
+    }
+
+    /// Deserializes and queues a batch.
+    pub fn submit_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let batch = Batch::read(bytes)?;
+        self.submit(batch);
+        Ok(())
+    }
+
+    fn inputs(&self, batch: &Batch<E>) -> Vec<E::Fr> {
+        let mut inputs = self.fixed_inputs.clone();
+        inputs.push(batch.prev);
+        inputs.push(batch.next);
+        inputs
+    }
+
+    fn verify_one(&self, batch: &Batch<E>) -> bool {
+        verify_proof(&self.pvk, &batch.proof, &self.inputs(batch)).unwrap_or(false)
+    }
+
+    /// Checks all of `batches` with one randomized pairing product:
+    ///
+    /// prod e(r_i A_i, B_i) = e(sum r_i alpha, beta) e(sum r_i acc_i, gamma) e(sum r_i C_i, delta)
+    ///
+    /// where `acc_i` is the linear combination of the input query for batch `i`. A forged proof
+    /// passes with probability about 2^-128.
+    fn verify_all<R: Rng>(&self, batches: &[&Batch<E>], rng: &mut R) -> bool {
+        if self.fixed_inputs.len() + 3 != self.vk.ic.len()
+            || batches
+                .iter()
+                .any(|b| b.proof.a.is_zero() || b.proof.b.is_zero())
+        {
+            return false;
+        }
+        let mut alpha_coeff = E::Fr::zero();
+        let mut acc = E::G1::zero();
+        let mut c = E::G1::zero();
+        let mut pairs = Vec::with_capacity(batches.len() + 3);
+        for batch in batches {
+            // Random 128-bit coefficients suffice, and are cheaper to multiply by.
+            let r = {
+                let mut repr = <E::Fr as PrimeField>::Repr::from(rng.gen::<u64>());
+                repr.shl(64);
+                repr.add_nocarry(&<E::Fr as PrimeField>::Repr::from(rng.gen::<u64>()));
+                E::Fr::from_repr(repr).unwrap()
+            };
+            alpha_coeff.add_assign(&r);
+            let mut batch_acc = self.vk.ic[0].into_projective();
+            for (input, base) in self.inputs(batch).iter().zip(self.vk.ic.iter().skip(1)) {
+                batch_acc.add_assign(&base.mul(input.into_repr()));
+            }
+            batch_acc.mul_assign(r.into_repr());
+            acc.add_assign(&batch_acc);
+            c.add_assign(&batch.proof.c.mul(r.into_repr()));
+            pairs.push((
+                batch.proof.a.mul(r.into_repr()).into_affine().prepare(),
+                batch.proof.b.prepare(),
+            ));
+        }
+        let neg = |g: &E::G2Affine| {
+            let mut g = g.clone();
+            g.negate();
+            g.prepare()
+        };
+        pairs.push((
+            self.vk.alpha_g1.mul(alpha_coeff.into_repr()).into_affine().prepare(),
+            neg(&self.vk.beta_g2),
+        ));
+        pairs.push((acc.into_affine().prepare(), neg(&self.vk.gamma_g2)));
+        pairs.push((c.into_affine().prepare(), neg(&self.vk.delta_g2)));
+        let refs: Vec<_> = pairs.iter().map(|(a, b)| (a, b)).collect();
+        match E::final_exponentiation(&E::miller_loop(refs.iter())) {
+            Some(r) => r == E::Fqk::one(),
+            None => false,
+        }
+    }
+
+    /// Verifies queued batches, extending the chain as far as possible.
+    ///
+    /// Batches building on the tip are considered in the order they were submitted; the first
+    /// valid one wins. Batches building on superseded commitments are dropped as stale, and
+    /// batches building on unknown commitments stay queued.
+    pub fn process<R: Rng>(&mut self, mode: VerifyMode, rng: &mut R) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        loop {
+            // Greedily pick the chain of first-submitted extensions of the tip.
+            let mut picked = Vec::new();
+            let mut tip = self.latest();
+            while let Some(i) = self
+                .queue
+                .iter()
+                .enumerate()
+                .position(|(i, b)| b.prev == tip && !picked.contains(&i))
+            {
+                tip = self.queue[i].next;
+                picked.push(i);
+                if mode == VerifyMode::Individual {
+                    break;
+                }
+            }
+            if picked.is_empty() {
+                break;
+            }
+            let all_valid = mode == VerifyMode::Batched && {
+                let batches: Vec<&Batch<E>> = picked.iter().map(|&i| &self.queue[i]).collect();
+                self.verify_all(&batches, rng)
+            };
+            // Accept the valid prefix of the picked chain, and drop its first invalid batch.
+            let mut n_ok = 0;
+            for &i in &picked {
+                if all_valid || self.verify_one(&self.queue[i]) {
+                    n_ok += 1;
+                } else {
+                    break;
+                }
+            }
+            for &i in &picked[..n_ok] {
+                let next = self.queue[i].next;
+                self.chain.push(next);
+            }
+            report.accepted += n_ok;
+            let mut to_drop: Vec<usize> = picked[..n_ok].to_vec();
+            if n_ok < picked.len() {
+                to_drop.push(picked[n_ok]);
+                report.invalid += 1;
+            }
+            to_drop.sort_unstable_by(|a, b| b.cmp(a));
+            for i in to_drop {
+                self.queue.remove(i);
+            }
+        }
+        // Anything building on a commitment before the tip has lost the race.
+        let tip = self.latest();
+        let superseded = &self.chain[..self.chain.len() - 1];
+        let before = self.queue.len();
+        self.queue
+            .retain(|b| b.prev == tip || !superseded.contains(&b.prev));
+        report.stale += before - self.queue.len();
+        report
+    }
+
+    /// Moves the verifier onto a background thread, which verifies batches as they arrive.
+    pub fn spawn<R>(mut self, mode: VerifyMode, mut rng: R) -> VerifierHandle<E>
+    where
+        R: Rng + Send + 'static,
+    {
+        let (tx, rx) = channel::<Vec<u8>>();
+        let latest = Arc::new(RwLock::new(self.latest()));
+        let latest_out = latest.clone();
+        let thread = thread::spawn(move || {
+            for bytes in rx {
+                if self.submit_bytes(&bytes).is_err() {
+                    continue;
+                }
+                self.process(mode, &mut rng);
+                *latest.write().unwrap() = self.latest();
+            }
+            self
+        });
+        VerifierHandle {
+            sender: tx,
+            latest: latest_out,
+            thread,
+        }
+    }
+}
+
+/// A handle to a verifier running on a background thread.
+pub struct VerifierHandle<E: Engine> {
+    sender: Sender<Vec<u8>>,
+    latest: Arc<RwLock<E::Fr>>,
+    thread: JoinHandle<Verifier<E>>,
+}
+
+impl<E: Engine> VerifierHandle<E> {
+    /// Submits a serialized batch. Returns false if the verifier has stopped.
+    pub fn submit(&self, bytes: Vec<u8>) -> bool {
+        self.sender.send(bytes).is_ok()
+    }
+
+    /// The latest verified state commitment.
+    pub fn latest(&self) -> E::Fr {
+        *self.latest.read().unwrap()
+    }
+
+    /// Stops the verifier once all submitted batches are processed, and returns it.
+    pub fn shutdown(self) -> Verifier<E> {
+        drop(self.sender);
+        self.thread.join().expect("verifier thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sapling_crypto::bellman::groth16::{
+        create_random_proof, generate_random_parameters, Parameters,
+    };
+    use sapling_crypto::bellman::pairing::ff::ScalarEngine;
+    use sapling_crypto::bellman::{ConstraintSystem, SynthesisError};
+    use util::test_helpers::*;
+    use OptionExt;
+
+    type F = <Bn256 as ScalarEngine>::Fr;
+
+    /// Proves that `next = prev + step`. The public inputs are `step` (fixed for a chain), `prev`
+    /// and `next`.
+    struct Step {
+        step: Option<F>,
+        prev: Option<F>,
+    }
+
+    impl Circuit<Bn256> for Step {
+        fn synthesize<CS: ConstraintSystem<Bn256>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let step = cs.alloc_input(|| "step", || Ok(*self.step.grab()?))?;
+            let prev = cs.alloc_input(|| "prev", || Ok(*self.prev.grab()?))?;
+            let next = cs.alloc_input(
+                || "next",
+                || {
+                    let mut next = *self.prev.grab()?;
+                    next.add_assign(self.step.grab()?);
+                    Ok(next)
+                },
+            )?;
+            cs.enforce(
+                || "next = prev + step",
+                |lc| lc + prev + step,
+                |lc| lc + CS::one(),
+                |lc| lc + next,
+            );
+            Ok(())
+        }
+    }
+
+    fn f(n: usize) -> F {
+        F::from_str(&n.to_string()).unwrap()
+    }
+
+    fn batch<R: Rng>(params: &Parameters<Bn256>, prev: usize, rng: &mut R) -> Batch<Bn256> {
+        let circuit = Step {
+            step: Some(f(2)),
+            prev: Some(f(prev)),
+        };
+        Batch {
+            prev: f(prev),
+            next: f(prev + 2),
+            proof: create_random_proof(circuit, params, rng).unwrap(),
+        }
+    }
+
+    fn setup<R: Rng>(rng: &mut R) -> (Parameters<Bn256>, Verifier<Bn256>) {
+        let circuit = Step {
+            step: None,
+            prev: None,
+        };
+        let params = generate_random_parameters(circuit, rng).unwrap();
+        let verifier = Verifier::new(params.vk.clone(), vec![f(2)], f(1));
+        (params, verifier)
+    }
+
+    #[test]
+    fn verify_all_rejects_forgeries() {
+        let mut rng = rand::thread_rng();
+        let (params, verifier) = setup(&mut rng);
+        let first = batch(&params, 1, &mut rng);
+        let second = batch(&params, 3, &mut rng);
+        assert!(verifier.verify_one(&first));
+        assert!(verifier.verify_all(&[&first, &second], &mut rng));
+        // A valid proof, claimed for a different transition
+        let forged = Batch {
+            next: f(6),
+            ..second.clone()
+        };
+        assert!(!verifier.verify_one(&forged));
+        assert!(!verifier.verify_all(&[&first, &forged], &mut rng));
+        assert!(!verifier.verify_all(&[&forged], &mut rng));
+    }
+
+    #[test]
+    fn process_extends_the_chain() {
+        for &mode in &[VerifyMode::Individual, VerifyMode::Batched] {
+            let mut rng = rand::thread_rng();
+            let (params, mut verifier) = setup(&mut rng);
+            let first = batch(&params, 1, &mut rng);
+            let rival = batch(&params, 1, &mut rng);
+            let second = batch(&params, 3, &mut rng);
+            let forged = Batch {
+                prev: f(5),
+                next: f(8),
+                proof: second.proof.clone(),
+            };
+            verifier.submit(forged);
+            verifier.submit(second);
+            verifier.submit(first);
+            verifier.submit(batch(&params, 7, &mut rng));
+            assert_eq!(
+                verifier.process(mode, &mut rng),
+                VerifyReport {
+                    accepted: 2,
+                    invalid: 1,
+                    stale: 0,
+                }
+            );
+            assert_eq!(verifier.chain(), &[f(1), f(3), f(5)][..]);
+            // The batch building on 7 waits for a batch reaching 7.
+            assert_eq!(verifier.n_queued(), 1);
+
+            let mut bytes = Vec::new();
+            rival.write(&mut bytes).unwrap();
+            verifier.submit_bytes(&bytes).unwrap();
+            verifier.submit(batch(&params, 5, &mut rng));
+            assert_eq!(
+                verifier.process(mode, &mut rng),
+                VerifyReport {
+                    accepted: 2,
+                    invalid: 0,
+                    stale: 1,
+                }
+            );
+            assert_eq!(verifier.latest(), f(9));
+            assert_eq!(verifier.n_queued(), 0);
+        }
+    }
+
+    #[test]
+    fn batch_round_trip() {
+        type F = <Bn256 as ScalarEngine>::Fr;
+        let batch = Batch::<Bn256> {
+            prev: F::from_str("11").unwrap(),
+            next: F::from_str("12").unwrap(),
+            proof: Proof {
+                a: <Bn256 as Engine>::G1Affine::one(),
+                b: <Bn256 as Engine>::G2Affine::one(),
+                c: <Bn256 as Engine>::G1Affine::one(),
+            },
+        };
+        let mut bytes = Vec::new();
+        batch.write(&mut bytes).unwrap();
+        let read = Batch::<Bn256>::read(&bytes[..]).unwrap();
+        assert_eq!(read.prev, batch.prev);
+        assert_eq!(read.next, batch.next);
+        assert!(read.proof == batch.proof);
+        assert!(Batch::<Bn256>::read(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
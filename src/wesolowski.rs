@@ -44,6 +44,28 @@ pub fn base_to_product<'a, G: SemiGroup, I: Iterator<Item = &'a Integer>>(
     g.power(b, &acc)
 }
 
+/// Multiplies `factors` into a single exponent `r = prod(factors) % l`, where `l` is the
+/// challenge.
+///
+/// This is what lets a PoE for a batch of `k` elements check one exponentiation by `r`, rather
+/// than `k`. Each factor contributes its `reduced` form, so factors hashed straight to residues
+/// modulo `l` cost one modular multiplication each, arranged as a product tree (see
+/// `BigNat::product_tree_mod`).
+pub fn aggregate_exponent<'a, E: Engine, CS: ConstraintSystem<E>>(
+    cs: CS,
+    factors: impl IntoIterator<Item = &'a Reduced<E>>,
+    challenge: &BigNat<E>,
+) -> Result<BigNat<E>, SynthesisError> {
+    let factors: Vec<BigNat<E>> = factors.into_iter().map(|f| f.reduced.clone()).collect();
+    let acc = if factors.is_empty() {
+        BigNat::one::<CS>(challenge.params.limb_width)
+    } else {
+        BigNat::product_tree_mod(cs, &factors, challenge)?
+    };
+    // r < l, so sharing a ladder with `Q^l` does not lengthen it.
+    Ok(acc.with_max_bits(challenge.bit_bound()))
+}
+
 /// \exists q s.t. q^l \times base^r = result
 pub fn proof_of_exp<'a, E: Engine, G: CircuitSemiGroup<E = E>, CS: ConstraintSystem<E>>(
     mut cs: CS,
@@ -69,29 +91,15 @@ where
             })
         })
     };
-    let r = {
-        let mut acc = BigNat::one::<CS>(challenge.params.limb_width);
-        for (i, f) in pf.into_iter().enumerate() {
-            acc = acc
-                .mult_mod(
-                    cs.namespace(|| format!("fold {}", i)),
-                    &f.reduced,
-                    challenge,
-                )?
-                .1;
-        }
-        acc
-    };
+    let r = aggregate_exponent(cs.namespace(|| "aggregate"), pf, challenge)?;
     let q = <G::Elem as Gadget>::alloc(
         cs.namespace(|| "Q"),
         q_value.as_ref(),
         base.access().clone(),
         <G::Elem as Gadget>::params(base),
     )?;
-    let ql = group.power(cs.namespace(|| "Q^l"), &q, &challenge)?;
-    let br = group.power(cs.namespace(|| "b^r"), &base, &r)?;
-    let left = group.op(cs.namespace(|| "Q^l b^r"), &ql, &br)?;
-    <G::Elem as Gadget>::assert_equal(cs.namespace(|| "Q^l b^r == res"), &left, &result)
+    // Q^l and b^r share their squarings.
+    group.verify_reduced_poe(cs.namespace(|| "check"), base, &r, result, &q, challenge)
 }
 
 #[cfg(test)]
@@ -311,6 +319,51 @@ mod tests {
         }
     }
 
+    pub struct Aggregate<'a> {
+        pub exps: &'a [&'a str],
+        pub l: &'a str,
+        pub expected: &'a str,
+    }
+
+    impl<'a, E: Engine> Circuit<E> for Aggregate<'a> {
+        fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let exps = self
+                .exps
+                .iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    Ok(Reduced::from_raw(BigNat::alloc_from_nat(
+                        cs.namespace(|| format!("e {}", i)),
+                        || Ok(Integer::from_str(e).unwrap()),
+                        4,
+                        2,
+                    )?))
+                })
+                .collect::<Result<Vec<Reduced<E>>, SynthesisError>>()?;
+            let l = BigNat::alloc_from_nat(
+                cs.namespace(|| "l"),
+                || Ok(Integer::from_str(self.l).unwrap()),
+                4,
+                2,
+            )?;
+            let expected = BigNat::alloc_from_nat(
+                cs.namespace(|| "expected"),
+                || Ok(Integer::from_str(self.expected).unwrap()),
+                4,
+                2,
+            )?;
+            let r = aggregate_exponent(cs.namespace(|| "aggregate"), &exps, &l)?;
+            r.equal(cs.namespace(|| "check"), &expected)
+        }
+    }
+
+    circuit_tests! {
+        aggregate_none: (Aggregate { exps: &[], l: "13", expected: "1" }, true),
+        aggregate_three: (Aggregate { exps: &["5", "7", "11"], l: "13", expected: "8" }, true),
+        aggregate_unreduced: (Aggregate { exps: &["200", "100"], l: "13", expected: "6" }, true),
+        aggregate_wrong: (Aggregate { exps: &["5", "7", "11"], l: "13", expected: "4" }, false),
+    }
+
     #[test]
     fn base_to_product_0() {
         let b = Integer::from(2usize);